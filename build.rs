@@ -0,0 +1,16 @@
+//! Records the version of iroh the benchmark is built against, reported with every run.
+
+use std::{env, fs, path::Path};
+
+fn main() {
+    let lock = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("Cargo.lock");
+    println!("cargo:rerun-if-changed={}", lock.display());
+    let version = fs::read_to_string(&lock)
+        .ok()
+        .and_then(|lock| {
+            let (_, rest) = lock.split_once("\nname = \"iroh\"\nversion = \"")?;
+            rest.split_once('"').map(|(version, _)| version.to_string())
+        })
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=IROH_VERSION={version}");
+}
@@ -0,0 +1,107 @@
+//! Aggregation of results from many clients into one database.
+//!
+//! The `aggregate` binary serves a [`ResultStore`] on [`RESULTS_ALPN`]. Clients started with
+//! `--aggregate <ticket>` [`upload`] the reports of every run to it, so that the results of a
+//! measurement campaign across many machines end up in one place without copying files around.
+//!
+//! Only the nodes on the service's [`Allowlist`] may upload, everyone if it has none.
+//!
+//! Each upload is the JSON array of the reports of one run on a bidirectional stream of its own.
+//! The service answers with the number of reports it stored as a big-endian `u64`.
+
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use iroh::{
+    Endpoint, NodeAddr,
+    endpoint::{Connecting, ConnectionError},
+    protocol::ProtocolHandler,
+};
+use n0_future::boxed::BoxFuture;
+use tracing::info;
+
+use crate::protocol::RESULTS_ALPN;
+use crate::report::BenchmarkReport;
+use crate::server::Allowlist;
+use crate::store::ResultStore;
+
+/// Largest upload accepted, enough for thousands of reports with all their samples.
+const MAX_UPLOAD_LEN: usize = 64 * 1024 * 1024;
+
+/// Stores the reports uploaded on [`RESULTS_ALPN`] by the nodes on the allowlist.
+#[derive(Clone)]
+pub struct Aggregator {
+    pub store: Arc<Mutex<ResultStore>>,
+    pub allow: Allowlist,
+}
+
+impl Aggregator {
+    pub fn new(store: ResultStore, allow: Allowlist) -> Self {
+        Self { store: Arc::new(Mutex::new(store)), allow }
+    }
+}
+
+impl std::fmt::Debug for Aggregator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Aggregator").finish_non_exhaustive()
+    }
+}
+
+impl ProtocolHandler for Aggregator {
+    /// Stores every upload of the connection, until the client closes it.
+    fn accept(&self, connecting: Connecting) -> BoxFuture<Result<()>> {
+        let (store, allow) = (self.store.clone(), self.allow.clone());
+        Box::pin(async move {
+            let connection = connecting.await?;
+            let Some(node_id) = allow.admit(&connection, false)? else {
+                return Ok(());
+            };
+            loop {
+                let (mut send, mut recv) = match connection.accept_bi().await {
+                    Ok(stream) => stream,
+                    Err(ConnectionError::ApplicationClosed(_)) => return Ok(()),
+                    Err(err) => return Err(err.into()),
+                };
+                let upload = recv.read_to_end(MAX_UPLOAD_LEN).await?;
+                let reports: Vec<BenchmarkReport> = serde_json::from_slice(&upload)?;
+                let run = reports.first().map_or("-".into(), |report| report.run.run_id.clone());
+                let count = reports.len();
+                // SQLite blocks, so the reports are stored off the async workers
+                let store = store.clone();
+                tokio::task::spawn_blocking(move || {
+                    let mut store = store.lock().expect("poisoned");
+                    reports.iter().try_for_each(|report| store.append(report))
+                })
+                .await??;
+                info!("Stored {count} reports of run {run} from {node_id}");
+                send.write_all(&(count as u64).to_be_bytes()).await?;
+                send.finish()?;
+            }
+        })
+    }
+}
+
+/// Uploads `reports` to the aggregation service at `aggregator`, returning once it stored them.
+pub async fn upload(
+    endpoint: &Endpoint,
+    aggregator: NodeAddr,
+    reports: &[BenchmarkReport],
+) -> Result<()> {
+    let connection = endpoint
+        .connect(aggregator, RESULTS_ALPN)
+        .await
+        .context("Failed to connect to the aggregation service")?;
+    let (mut send, mut recv) = connection.open_bi().await?;
+    send.write_all(&serde_json::to_vec(reports)?).await?;
+    send.finish()?;
+    let mut count = [0u8; 8];
+    recv.read_exact(&mut count).await?;
+    connection.close(0u32.into(), b"done");
+    let count = u64::from_be_bytes(count);
+    anyhow::ensure!(
+        count == reports.len() as u64,
+        "The aggregation service stored {count} of {} reports",
+        reports.len()
+    );
+    Ok(())
+}
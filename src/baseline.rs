@@ -0,0 +1,463 @@
+//! Baselines iroh's throughput is compared against with `client --baseline`, to show how much
+//! of the bandwidth iroh's encryption, hole punching and relaying cost.
+//!
+//! The TCP baseline repeats the transfers of the benchmark over plain TCP connections to the
+//! server's `--tcp-baseline` listener. The listener shares the port number of the server's UDP
+//! socket, so the client finds it at the direct address iroh used, unless `--tcp-addr` is given.
+//! Every TCP connection carries one stream of a transfer, started with the same [`Request`] as
+//! iroh's streams.
+//!
+//! The QUIC baseline runs the very same transfers as iroh over a plain connection of quinn, the
+//! QUIC implementation iroh is built on, to the server's `--quic-baseline` endpoint. It isolates
+//! what iroh's magicsock and relay layer add from what QUIC itself costs. The server presents a
+//! self-signed certificate which the client does not verify, as only throughput matters here.
+
+use std::{net::SocketAddr, sync::Arc};
+
+use anyhow::Result;
+use iroh::{Endpoint, NodeId, endpoint::ConnectionType};
+use rustls::{
+    DigitallySignedStruct, SignatureScheme,
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{CryptoProvider, WebPkiSupportedAlgorithms},
+    pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName, UnixTime},
+};
+use n0_future::try_join_all;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{
+        TcpStream,
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+    },
+    time::{Duration, Instant, timeout_at},
+};
+
+use crate::client::{
+    BenchmarkConfig, Command, Transport, bandwidth, benchmark_timed, benchmark_transfer, stripe,
+};
+use crate::config::SocketBuffers;
+use crate::pacing::Pacer;
+use crate::payload::Payload;
+use crate::progress::Counters;
+use crate::protocol::{ACK, ALPN, Direction, Request, Response, Transfer, UNBOUNDED};
+use crate::report::{Benchmark, Sample};
+use crate::units::format_size;
+
+/// UDP port of the server's QUIC baseline endpoint unless configured otherwise.
+pub const QUIC_PORT: u16 = 4433;
+
+/// Server name of the QUIC baseline's self-signed certificate.
+const QUIC_SERVER_NAME: &str = "localhost";
+
+/// Transport iroh is compared against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Baseline {
+    /// Plain TCP connections to the server's `--tcp-baseline` listener
+    Tcp,
+    /// Plain QUIC connections, without iroh, to the server's `--quic-baseline` endpoint
+    Quic,
+}
+
+impl Baseline {
+    fn name(self) -> &'static str {
+        match self {
+            Baseline::Tcp => "TCP",
+            Baseline::Quic => "QUIC",
+        }
+    }
+
+    /// Where the server's baseline listener is expected: at the IP of the direct path iroh last
+    /// used to reach `node_id`, on the port of that path for TCP and on [`QUIC_PORT`] for QUIC.
+    /// `None` if the connection only went through the relay.
+    pub fn default_addr(self, endpoint: &Endpoint, node_id: NodeId) -> Option<SocketAddr> {
+        let addr = match endpoint.conn_type(node_id).ok()?.get().ok()? {
+            ConnectionType::Direct(addr) | ConnectionType::Mixed(addr, _) => addr,
+            ConnectionType::Relay(_) | ConnectionType::None => return None,
+        };
+        match self {
+            Baseline::Tcp => Some(addr),
+            Baseline::Quic => Some(SocketAddr::new(addr.ip(), QUIC_PORT)),
+        }
+    }
+}
+
+/// Bandwidth of iroh next to the baseline for the same transfers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BaselineResult {
+    pub transport: Baseline,
+    /// Payload size of bandwidth benchmarks, absent for timed ones.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Average bandwidth of iroh's transfers.
+    pub iroh: Sample,
+    /// Average bandwidth of the baseline's transfers.
+    pub baseline: Sample,
+}
+
+/// Fails unless the benchmark selected by `config` and `command` can be repeated over a
+/// baseline: bandwidth or timed transfers over streams, without echo or verification.
+pub fn check(config: &BenchmarkConfig, command: Option<&Command>) -> Result<()> {
+    anyhow::ensure!(
+        command.is_none() && config.transport == Transport::Stream && config.soak.is_none(),
+        "Baselines can only be compared to bandwidth and timed benchmarks over streams"
+    );
+    anyhow::ensure!(
+        config.direction != Direction::Echo && !config.verify,
+        "Baselines do not support echo transfers or --verify"
+    );
+    Ok(())
+}
+
+/// Repeats the transfers of `benchmark` over the `baseline` transport to `addr`: every payload
+/// size for as many iterations, or streaming for as long.
+pub async fn run(
+    baseline: Baseline,
+    addr: SocketAddr,
+    config: &BenchmarkConfig,
+    benchmark: &Benchmark,
+) -> Result<Vec<BaselineResult>> {
+    config.status(format!("\nRunning the {} baseline against {addr}", baseline.name()));
+    let transfer = async |size, duration| match baseline {
+        Baseline::Tcp => tcp_transfer(addr, config, size, duration).await,
+        Baseline::Quic => quic_transfer(addr, config, size, duration).await,
+    };
+    let mut results = Vec::new();
+    match benchmark {
+        Benchmark::Bandwidth { results: sizes, .. } => {
+            for result in sizes {
+                let mut samples = Vec::new();
+                for _ in 0..config.iterations {
+                    samples.push(transfer(Some(result.size), None).await?);
+                }
+                results.push(BaselineResult {
+                    transport: baseline,
+                    size: Some(result.size),
+                    iroh: Sample {
+                        upload: result.upload.map(|s| s.average),
+                        download: result.download.map(|s| s.average),
+                    },
+                    baseline: average(&samples),
+                });
+            }
+        }
+        Benchmark::Timed { total, duration_secs, .. } => {
+            let duration = Duration::from_secs_f64(*duration_secs);
+            results.push(BaselineResult {
+                transport: baseline,
+                size: None,
+                iroh: *total,
+                baseline: transfer(None, Some(duration)).await?,
+            });
+        }
+        _ => anyhow::bail!("Baselines can only be compared to bandwidth and timed benchmarks"),
+    }
+    Ok(results)
+}
+
+/// Prints iroh's bandwidth next to the baseline's, and iroh's share of it.
+pub fn print_results(results: &[BaselineResult]) {
+    println!("\nBaseline comparison (Mbit/s):");
+    println!("  {:<25} {:>10} {:>10} {:>10}", "Transfer", "iroh", "baseline", "iroh/base");
+    for result in results {
+        let size = result.size.map(format_size).unwrap_or_else(|| "timed".to_string());
+        let size = format!("{} {size}", result.transport.name());
+        let directions = [
+            ("upload", result.iroh.upload, result.baseline.upload),
+            ("download", result.iroh.download, result.baseline.download),
+        ];
+        for (direction, iroh, baseline) in directions {
+            let (Some(iroh), Some(baseline)) = (iroh, baseline) else {
+                continue;
+            };
+            let label = format!("{size} {direction}");
+            let share = 100.0 * iroh / baseline;
+            println!("  {label:<25} {iroh:>10.2} {baseline:>10.2} {share:>9.1}%");
+        }
+    }
+}
+
+/// Average bandwidth of every direction measured in `samples`.
+fn average(samples: &[Sample]) -> Sample {
+    let mean = |values: Vec<f64>| {
+        (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+    };
+    Sample {
+        upload: mean(samples.iter().filter_map(|s| s.upload).collect()),
+        download: mean(samples.iter().filter_map(|s| s.download).collect()),
+    }
+}
+
+/// Bytes moved by one TCP stream, and when each direction completed.
+#[derive(Debug, Default, Clone, Copy)]
+struct StreamResult {
+    sent: u64,
+    upload: Option<Duration>,
+    received: u64,
+    download: Option<Duration>,
+}
+
+/// Transfers `size` bytes striped across `--streams` TCP connections, or streams for `duration`
+/// if there is no size, and returns the bandwidth of each direction.
+async fn tcp_transfer(
+    addr: SocketAddr,
+    config: &BenchmarkConfig,
+    size: Option<u64>,
+    duration: Option<Duration>,
+) -> Result<Sample> {
+    let sizes: Vec<u64> = match size {
+        Some(size) => stripe(size as usize, config.streams).map(|n| n as u64).collect(),
+        None => vec![UNBOUNDED; config.streams],
+    };
+    let transfers = sizes.into_iter().map(|size| Transfer {
+        direction: config.direction,
+        size,
+        chunk_size: config.chunk_size as u32,
+        streams: config.streams as u32,
+        verify: false,
+        pattern: config.pattern,
+        seed: config.seed,
+        bitrate: config.paced_bitrate() / config.streams as u64,
+        report: false,
+    });
+    let streams = try_join_all(transfers.map(|transfer| async move {
+        let mut stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(true)?;
+        Request::Transfer(transfer).write(&mut stream).await?;
+        Response::read(&mut stream).await?;
+        anyhow::Ok((stream, transfer))
+    }))
+    .await?;
+
+    let start = Instant::now();
+    let deadline = duration.map(|duration| start + duration);
+    let results = try_join_all(
+        streams
+            .into_iter()
+            .map(|(stream, transfer)| tcp_stream(stream, transfer, start, deadline)),
+    )
+    .await?;
+
+    // All streams share the time window, so a direction completes with its slowest stream
+    let elapsed = |f: fn(&StreamResult) -> Option<Duration>| results.iter().filter_map(f).max();
+    let sent = results.iter().map(|r| r.sent).sum::<u64>() as usize;
+    let received = results.iter().map(|r| r.received).sum::<u64>() as usize;
+    Ok(Sample {
+        upload: elapsed(|r| r.upload).map(|elapsed| bandwidth(sent, elapsed)),
+        download: elapsed(|r| r.download).map(|elapsed| bandwidth(received, elapsed)),
+    })
+}
+
+/// Runs one stream of a transfer on its TCP connection.
+async fn tcp_stream(
+    stream: TcpStream,
+    transfer: Transfer,
+    start: Instant,
+    deadline: Option<Instant>,
+) -> Result<StreamResult> {
+    let (mut recv, mut send) = stream.into_split();
+    let mut result = StreamResult::default();
+    match transfer.direction {
+        Direction::Upload => {
+            result.sent = send_tcp(&mut send, transfer, deadline).await?;
+            // Uploads complete once the server acknowledged them
+            let mut ack = [0u8; ACK.len()];
+            recv.read_exact(&mut ack).await?;
+            result.upload = Some(start.elapsed());
+        }
+        Direction::Download => {
+            result.received = receive_tcp(&mut recv, transfer, deadline).await?;
+            result.download = Some(start.elapsed());
+        }
+        Direction::Both | Direction::Echo => {
+            let upload = async {
+                let sent = send_tcp(&mut send, transfer, deadline).await?;
+                anyhow::Ok((sent, start.elapsed()))
+            };
+            let download = async {
+                let received = receive_tcp(&mut recv, transfer, deadline).await?;
+                anyhow::Ok((received, start.elapsed()))
+            };
+            let ((sent, upload), (received, download)) = tokio::try_join!(upload, download)?;
+            result = StreamResult {
+                sent,
+                upload: Some(upload),
+                received,
+                download: Some(download),
+            };
+        }
+    }
+    Ok(result)
+}
+
+/// Writes the payload of `transfer` until its size or the deadline is reached, then shuts down
+/// the sending side. Returns the number of bytes written.
+async fn send_tcp(
+    send: &mut OwnedWriteHalf,
+    transfer: Transfer,
+    deadline: Option<Instant>,
+) -> Result<u64> {
+    let chunk_size = transfer.chunk_size as usize;
+    let mut payload = Payload::new(transfer.pattern, transfer.seed);
+    let mut pacer = Pacer::new(transfer.bitrate, chunk_size);
+    let mut chunk = vec![0u8; chunk_size];
+    let mut sent = 0;
+    while sent < transfer.size && deadline.is_none_or(|deadline| Instant::now() < deadline) {
+        let n = (transfer.size - sent).min(chunk_size as u64) as usize;
+        payload.fill(&mut chunk[..n]);
+        pacer.wait(n).await;
+        send.write_all(&chunk[..n]).await?;
+        sent += n as u64;
+    }
+    send.shutdown().await?;
+    Ok(sent)
+}
+
+/// Reads the server's payload until it shuts down its side or the deadline is reached. Returns
+/// the number of bytes read.
+async fn receive_tcp(
+    recv: &mut OwnedReadHalf,
+    transfer: Transfer,
+    deadline: Option<Instant>,
+) -> Result<u64> {
+    let mut buf = vec![0u8; transfer.chunk_size as usize];
+    let mut received = 0;
+    loop {
+        let read = recv.read(&mut buf);
+        let n = match deadline {
+            Some(deadline) => match timeout_at(deadline, read).await {
+                Ok(n) => n?,
+                Err(_) => return Ok(received),
+            },
+            None => read.await?,
+        };
+        if n == 0 {
+            return Ok(received);
+        }
+        received += n as u64;
+    }
+}
+
+/// Transfers `size` bytes striped across `--streams` streams of a new plain QUIC connection, or
+/// streams for `duration` if there is no size, and returns the bandwidth of each direction.
+async fn quic_transfer(
+    addr: SocketAddr,
+    config: &BenchmarkConfig,
+    size: Option<u64>,
+    duration: Option<Duration>,
+) -> Result<Sample> {
+    let (endpoint, conn) = quic_connect(addr, &config.endpoint.buffers).await?;
+    let transfer = Transfer {
+        direction: config.direction,
+        size: size.unwrap_or(UNBOUNDED),
+        chunk_size: config.chunk_size as u32,
+        streams: config.streams as u32,
+        verify: false,
+        pattern: config.pattern,
+        seed: config.seed,
+        bitrate: config.paced_bitrate(),
+        report: false,
+    };
+    let counters = Counters::default();
+    let sample = match (size, duration) {
+        (Some(size), _) => benchmark_transfer(&conn, transfer, &counters)
+            .await?
+            .to_sample(size as usize),
+        (None, Some(duration)) => benchmark_timed(&conn, duration, transfer, &counters).await?,
+        (None, None) => unreachable!("transfers have a size or a duration"),
+    };
+    conn.close(0u32.into(), b"bye!");
+    endpoint.wait_idle().await;
+    Ok(sample)
+}
+
+/// Connects a new plain QUIC endpoint with the socket `buffers` to the server's QUIC baseline
+/// endpoint at `addr`.
+pub(crate) async fn quic_connect(
+    addr: SocketAddr,
+    buffers: &SocketBuffers,
+) -> Result<(quinn::Endpoint, quinn::Connection)> {
+    let (mut endpoint, _) = buffers.quic_endpoint(unspecified(addr), None)?;
+    endpoint.set_default_client_config(quic_client_config()?);
+    let conn = endpoint.connect(addr, QUIC_SERVER_NAME)?.await?;
+    Ok((endpoint, conn))
+}
+
+/// Any port on all interfaces of the address family of `addr`, to reach it from.
+pub(crate) fn unspecified(addr: SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V4(_) => SocketAddr::from(([0, 0, 0, 0], 0)),
+        SocketAddr::V6(_) => SocketAddr::from(([0u16; 8], 0)),
+    }
+}
+
+/// Configuration of the server's QUIC baseline endpoint, with a fresh self-signed certificate.
+pub fn quic_server_config() -> Result<quinn::ServerConfig> {
+    let cert = rcgen::generate_simple_self_signed(vec![QUIC_SERVER_NAME.to_string()])?;
+    let key = PrivatePkcs8KeyDer::from(cert.key_pair.serialize_der());
+    let mut crypto = rustls::ServerConfig::builder_with_provider(provider())
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .with_no_client_auth()
+        .with_single_cert(vec![cert.cert.der().clone()], PrivateKeyDer::Pkcs8(key))?;
+    crypto.alpn_protocols = vec![ALPN.to_vec()];
+    let crypto = quinn::crypto::rustls::QuicServerConfig::try_from(crypto)?;
+    Ok(quinn::ServerConfig::with_crypto(Arc::new(crypto)))
+}
+
+/// Configuration of the client's QUIC baseline connections, accepting any certificate.
+fn quic_client_config() -> Result<quinn::ClientConfig> {
+    let provider = provider();
+    let verifier = AnyCertificate(provider.signature_verification_algorithms);
+    let mut crypto = rustls::ClientConfig::builder_with_provider(provider)
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+    crypto.alpn_protocols = vec![ALPN.to_vec()];
+    let crypto = quinn::crypto::rustls::QuicClientConfig::try_from(crypto)?;
+    Ok(quinn::ClientConfig::new(Arc::new(crypto)))
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+/// Accepts the server's self-signed certificate, only checking the handshake signatures.
+#[derive(Debug)]
+struct AnyCertificate(WebPkiSupportedAlgorithms);
+
+impl ServerCertVerifier for AnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.0)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.0)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.supported_schemes()
+    }
+}
@@ -0,0 +1,97 @@
+//! Aggregation service that stores the results uploaded by benchmark clients in one database.
+//!
+//! The service is implemented in [`p2p::aggregate`], this binary binds the endpoint and prints
+//! the ticket clients upload to with `--aggregate <ticket>`.
+//!
+//! ## Usage
+//!
+//!     cargo run --bin aggregate -- --db <results.db> [--relay-url <url>...] [--port <port>]
+//!         [--secret-key-file <path>] [--discovery n0|local... | --no-discovery]
+//!         [--allow <node-id>...] [--allow-file <path>] [-v | -vv] [--log-file <path>]
+
+use std::{
+    net::{Ipv4Addr, SocketAddrV4},
+    path::PathBuf,
+};
+
+use anyhow::Result;
+use clap::Parser;
+use iroh::{Endpoint, NodeId, RelayUrl, protocol::Router};
+use iroh_base::ticket::NodeTicket;
+use p2p::aggregate::Aggregator;
+use p2p::config::{Discovery, discovery, relay_mode, secret_key};
+use p2p::logging::LogConfig;
+use p2p::protocol::RESULTS_ALPN;
+use p2p::server::Allowlist;
+use p2p::store::ResultStore;
+use tracing::level_filters::LevelFilter;
+
+/// CLI arguments
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// SQLite database to append the uploaded reports to, created if needed
+    #[arg(long)]
+    db: PathBuf,
+
+    /// Relay server to use instead of n0's default relays, can be repeated
+    #[arg(long = "relay-url")]
+    relay_urls: Vec<RelayUrl>,
+
+    /// File with the secret key of the node, generated if missing, to keep its node ID and
+    /// ticket across restarts
+    #[arg(long)]
+    secret_key_file: Option<PathBuf>,
+
+    /// UDP port to listen on, random if not set
+    #[arg(long)]
+    port: Option<u16>,
+
+    /// Discovery mechanism used to publish the node's addresses, can be repeated
+    #[arg(long, value_enum, default_value = "n0")]
+    discovery: Vec<Discovery>,
+
+    /// Do not publish the node's addresses via any discovery mechanism
+    #[arg(long, conflicts_with = "discovery")]
+    no_discovery: bool,
+
+    /// Only store reports uploaded by this client node ID, can be repeated
+    #[arg(long)]
+    allow: Vec<NodeId>,
+
+    /// Only store reports uploaded by the client node IDs in this file, one per line
+    #[arg(long)]
+    allow_file: Option<PathBuf>,
+
+    #[command(flatten)]
+    log: LogConfig,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    args.log.init(env!("CARGO_CRATE_NAME"), LevelFilter::INFO)?;
+    let store = ResultStore::open(&args.db)?;
+    let mut builder = Endpoint::builder().relay_mode(relay_mode(&args.relay_urls)?);
+    if !args.no_discovery {
+        builder = discovery(builder, &args.discovery);
+    }
+    if let Some(path) = &args.secret_key_file {
+        builder = builder.secret_key(secret_key(path)?);
+    }
+    if let Some(port) = args.port {
+        builder = builder.bind_addr_v4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port));
+    }
+    let endpoint = builder.bind().await?;
+    let allow = Allowlist::load(&args.allow, args.allow_file.as_deref())?;
+    let aggregator = Aggregator::new(store, allow);
+    let router = Router::builder(endpoint).accept(RESULTS_ALPN, aggregator).spawn().await?;
+    let ticket = NodeTicket::new(router.endpoint().node_addr().await?);
+    println!("Storing results in {}", args.db.display());
+    println!("Ticket: {ticket}");
+    println!("Upload with: client --aggregate {ticket} ...");
+
+    tokio::signal::ctrl_c().await?;
+    router.shutdown().await?;
+    Ok(())
+}
@@ -0,0 +1,1079 @@
+//! Benchmark client that measures the connections to one or more benchmark servers.
+//!
+//! The benchmarks live in [`p2p::client`], this binary parses the options, connects to the
+//! servers and prints, saves and compares the reports.
+//!
+//! ## Usage
+//!
+//!     cargo run --bin client -- (--public-key <public-key> | --ticket <ticket>)...
+//!         [--addr <ip:port>...] [--discovery n0|local... | --no-discovery]
+//!         [--secret-key-file <path>] [--stream-receive-window <size>] [--receive-window <size>]
+//!         [--max-concurrent-streams <n>] [--idle-timeout <duration>]
+//!         [--congestion cubic|bbr|newreno] [--send-buffer <size>] [--recv-buffer <size>]
+//!         [--initial-mtu <bytes>] [--max-mtu <bytes>] [--keylog]
+//!         [--direction upload|download|both|echo] [--sizes 1M,16M,128M | --sweep 64K..1G]
+//!         [--iterations <n>]
+//!         [--warmup <n>] [--streams <n> | --stream-sweep 64] [--connections <n>]
+//!         [--reuse-connection]
+//!         [--chunk-size 64K | --chunk-sweep 4K..4M] [--pattern zeros|random|seq] [--seed <n>]
+//!         [--verify]
+//!         [--bitrate 50Mbps]
+//!         [--time 10s | --soak 6h [--soak-interval 1m] | --traffic burst:256Kx20@500ms
+//!         | --target-duration 5s] [--interval 1s]
+//!         [--trim-outliers [--outlier-rule iqr|mad]]
+//!         [--transport stream|datagram [--rate <Mbit/s>] [--datagram-size <bytes>]]
+//!         [--relay-url <url>...] [--relay-only | --direct-only [--direct-timeout 5s]]
+//!         [--settle 10s] [--connect-timeout 10s] [--transfer-timeout 1m] [--retries <n>]
+//!         [--clock-sync]
+//!         [--output text|json|csv|markdown] [--output-file <path>] [--results-dir <dir>]
+//!         [--label <text>]
+//!         [--scenario <bench.toml> [--resume <run-id>]] [--min-bandwidth 200Mbps]
+//!         [--max-latency 50ms] [--tui]
+//!         [--no-charts] [--machine-ceiling] [--baseline tcp|quic]
+//!         [--tcp-addr <ip:port>] [--quic-addr <ip:port>] [--every 15m] [--db <results.db>]
+//!         [--influx <url|file> [--influx-token <token>]]
+//!         [--webhook-url <url> [--webhook-on completion|breach]] [--aggregate <ticket>]
+//!         [-v | -vv] [--log-file <path>]
+//!     cargo run --bin client -- --ticket <ticket> ping [--count <n>] [--size <bytes>]
+//!     cargo run --bin client -- --public-key <public-key> discover [--trials <n>]
+//!     cargo run --bin client -- --ticket <ticket> connect [--trials <n>]
+//!     cargo run --bin client -- --ticket <ticket> holepunch [--trials <n>] [--timeout 10s]
+//!     cargo run --bin client -- --ticket <ticket> streams [--count <n>]
+//!     cargo run --bin client -- --ticket <ticket> resume [--trials <n>] [--cold | --warm]
+//!     cargo run --bin client -- --ticket <ticket> [--sizes <sizes>] [--iterations <n>] blobs
+//!     cargo run --bin client -- --ticket <ticket> gossip [--peers <n>] [--count <n>]
+//!         [--size <bytes>] [--interval 1ms]
+//!     cargo run --bin client -- --ticket <ticket> [--direction <direction>] bufferbloat
+//!         [--idle 3s] [--duration 10s] [--probe-interval 50ms] [--size <bytes>]
+//!     cargo run --bin client -- --ticket <ticket> rpc [--count <n>] [--request-size <bytes>]
+//!         [--response-size <bytes>] [--depth <n>]
+//!     cargo run --bin client -- --ticket <ticket> messages [--count <n>] [--size <bytes>]
+//!     cargo run --bin client -- --ticket <ticket> delay [--count <n>] [--interval 10ms]
+//!         [--synced-clocks]
+//!     cargo run --bin client -- --ticket <ticket> [--direction <direction>] migrate
+//!         [--duration 10s] [--at 5s] [--sample-interval 100ms] [--quic <ip:port>]
+//!     cargo run --bin client -- compare <baseline.json> <current.json> [--threshold <percent>]
+//!     cargo run --bin client -- report <results.json>... [--out report.html]
+//!     cargo run --bin client -- --db <results.db> history <node-id> [--metric <name>]
+//!         [--limit <n>]
+//!     cargo run --bin client -- [--scenario <bench.toml>] selftest [--via loopback|relay]
+//!     cargo run --bin client -- mesh --nodes <n> [--wait 5m] [--duration 5s] [--pings <n>]
+//!     cargo run --bin client -- --ticket <agent> remote --to <ticket> [--duration 5s]
+//!         [--pings <n>]
+//!     cargo run --bin client -- [--relay-url <url>...] relays [--count <n>] [--timeout 5s]
+//!     cargo run --bin client -- --ticket <ticket> [--relay-url <url>...] --per-relay
+//!         [<benchmark>]
+
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Result};
+use clap::{ArgGroup, Parser, Subcommand};
+use iroh::{Endpoint, NodeAddr, PublicKey};
+use iroh_base::ticket::NodeTicket;
+use p2p::baseline::Baseline;
+use p2p::client::{self, BenchmarkConfig, Command, Interrupted, NotDirect, wait_for_direct};
+use p2p::compare::{Thresholds, print_comparison};
+use p2p::config::{EndpointConfig, SocketBuffers, relay_mode};
+use p2p::counters::{CounterSnapshot, IrohCounters};
+use p2p::influx::{InfluxExporter, Tags};
+use p2p::logging::LogConfig;
+use p2p::mesh::{MeshConfig, MeshReport, RemoteConfig};
+use p2p::relays::RelaysConfig;
+use p2p::progress::Progress;
+use p2p::protocol::ALPN;
+use p2p::report::{Benchmark, BenchmarkReport, RunMetadata, millis, path_label, timestamp};
+use p2p::scenario::Scenario;
+use p2p::selftest::Via;
+use p2p::store::{HistoryEntry, ResultStore};
+use p2p::units::{format_size, parse_bandwidth};
+use p2p::webhook::{RunSummary, Webhook, WebhookOn};
+use serde::Serialize;
+use tokio::sync::watch;
+use tracing::{error, level_filters::LevelFilter, warn};
+
+/// CLI arguments
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+#[command(group(
+    ArgGroup::new("target").required(true).multiple(true).args(["public_key", "ticket"])
+))]
+#[command(subcommand_negates_reqs = true)]
+struct Args {
+    /// Public key in hex format, can be repeated to benchmark several servers in turn
+    #[arg(short, long)]
+    public_key: Vec<String>,
+
+    /// Node ticket printed by the server, carrying its relay URL and direct addresses, can be
+    /// repeated to benchmark several servers in turn
+    #[arg(long)]
+    ticket: Vec<NodeTicket>,
+
+    /// Direct socket address of the server (`ip:port`), can be repeated
+    #[arg(long = "addr")]
+    addrs: Vec<SocketAddr>,
+
+    #[command(flatten)]
+    config: BenchmarkConfig,
+
+    /// How long to wait for a direct path before giving up with `--direct-only`
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "5s")]
+    direct_timeout: Duration,
+
+    /// Format of the benchmark results
+    #[arg(short, long, value_enum, default_value_t = Output::Text)]
+    output: Output,
+
+    /// File to write structured (non-text) results to instead of stdout
+    #[arg(long)]
+    output_file: Option<PathBuf>,
+
+    /// Label recorded with every result of the run, e.g. the network or the change under test
+    #[arg(long)]
+    label: Option<String>,
+
+    /// Directory to save the JSON results of every run to, as `<run id>.json`
+    #[arg(long)]
+    results_dir: Option<PathBuf>,
+
+    /// Continue the scenario run with this ID where it stopped, reusing the tests its checkpoint
+    /// in the `--results-dir` records as completed instead of running them again
+    #[arg(long, requires_all = ["scenario", "results_dir"], conflicts_with = "every")]
+    resume: Option<String>,
+
+    /// SQLite database to append every report and its samples to, created if needed
+    #[arg(long)]
+    db: Option<PathBuf>,
+
+    /// Upload the reports of every run to the aggregation service with this ticket, started with
+    /// `aggregate --db <path>`
+    #[arg(long)]
+    aggregate: Option<NodeTicket>,
+
+    /// Export the throughput of every interval and the headline metrics to InfluxDB: an HTTP
+    /// write URL (e.g. `http://localhost:8086/api/v2/write?org=iroh&bucket=bench`), or a file to
+    /// append line protocol to
+    #[arg(long)]
+    influx: Option<String>,
+
+    /// API token for `--influx`
+    #[arg(long, requires = "influx")]
+    influx_token: Option<String>,
+
+    /// POST a JSON summary of every run to this URL, e.g. to alert when monitoring with `--every`
+    #[arg(long)]
+    webhook_url: Option<String>,
+
+    /// Notify the webhook after every run, or only when a threshold is breached or a server
+    /// could not be benchmarked
+    #[arg(long, value_enum, default_value_t = WebhookOn::Completion, requires = "webhook_url")]
+    webhook_on: WebhookOn,
+
+    /// Exit with code 4 if any bandwidth falls below this (e.g. `200Mbps`, `1Gbps`)
+    #[arg(long, value_parser = parse_bandwidth)]
+    min_bandwidth: Option<f64>,
+
+    /// Exit with code 4 if any median latency exceeds this (e.g. `50ms`)
+    #[arg(long, value_parser = humantime::parse_duration)]
+    max_latency: Option<Duration>,
+
+    /// Run the suite of benchmarks described by a TOML scenario file, one report per run
+    #[arg(long)]
+    scenario: Option<PathBuf>,
+
+    /// Show a live dashboard of the running benchmark instead of progress output, and a summary
+    /// once it completes
+    #[arg(long)]
+    tui: bool,
+
+    /// Do not chart the throughput of every iteration or the latency distribution after the
+    /// text results
+    #[arg(long)]
+    no_charts: bool,
+
+    /// Repeat the transfers over another transport after the benchmark and compare iroh's
+    /// bandwidth to it, needs a server started with `--tcp-baseline` or `--quic-baseline`
+    #[arg(long, value_enum)]
+    baseline: Option<Baseline>,
+
+    /// Address of the server's TCP baseline listener, by default the direct address iroh used
+    #[arg(long, requires = "baseline")]
+    tcp_addr: Option<SocketAddr>,
+
+    /// Address of the server's QUIC baseline endpoint, by default port 4433 at the IP of the
+    /// direct path iroh used
+    #[arg(long, requires = "baseline")]
+    quic_addr: Option<SocketAddr>,
+
+    /// Measure the throughput over loopback first and report it as the machine ceiling, to tell
+    /// limits of this machine's CPU and crypto from limits of the network
+    #[arg(long)]
+    machine_ceiling: bool,
+
+    /// Run the benchmark once per relay of the relay map, forced through that relay, and compare
+    /// the relays, needs a build with `--features relay-only`. Every run binds a fresh endpoint,
+    /// so the server's `--allow` list cannot apply, and the server needs `--per-relay` to be
+    /// reachable via every relay
+    #[arg(long, conflicts_with_all = ["scenario", "direct_only", "baseline"])]
+    per_relay: bool,
+
+    /// Keep running as a monitor, benchmarking every server again at this interval (e.g. `15m`)
+    /// and saving every run to the `--results-dir`
+    #[arg(long, value_parser = humantime::parse_duration)]
+    every: Option<Duration>,
+
+    #[command(flatten)]
+    log: LogConfig,
+
+    /// Benchmark to run, defaults to the bandwidth test
+    #[command(subcommand)]
+    command: Option<CliCommand>,
+}
+
+#[derive(Subcommand, Debug)]
+enum CliCommand {
+    #[command(flatten)]
+    Benchmark(Command),
+    /// Compare two saved result files and highlight regressions
+    Compare(CompareArgs),
+    /// Render saved result files as a standalone HTML page with charts
+    Report(ReportArgs),
+    /// Show the past results of a server from the `--db` database
+    History(HistoryArgs),
+    /// Benchmark a server running in this process, over loopback or via the relay, with the full
+    /// suite or the `--scenario`
+    Selftest(SelftestArgs),
+    /// Coordinate servers started with `--coordinator <ticket>`, measuring every pair of them
+    Mesh(MeshConfig),
+    /// Have the server given by `--ticket`, started with `--agent`, measure the path to another
+    /// node
+    Remote(RemoteConfig),
+    /// Measure the latency to every relay of the relay map, and which one iroh picks as home
+    Relays(RelaysConfig),
+}
+
+#[derive(clap::Args, Debug)]
+struct CompareArgs {
+    /// Results to compare against, e.g. of the previous iroh version
+    baseline: PathBuf,
+
+    /// Results to compare
+    current: PathBuf,
+
+    /// Relative change in percent beyond which a metric counts as regressed or improved
+    #[arg(long, default_value_t = 5.0)]
+    threshold: f64,
+}
+
+#[derive(clap::Args, Debug)]
+struct ReportArgs {
+    /// Result files to include, each plotted as its own series
+    #[arg(required = true)]
+    results: Vec<PathBuf>,
+
+    /// Path of the HTML page to write
+    #[arg(long, default_value = "report.html")]
+    out: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+struct HistoryArgs {
+    /// Node ID of the server, or a prefix of it
+    node_id: String,
+
+    /// Only show metrics whose name contains this, e.g. `upload` or `p50`
+    #[arg(long)]
+    metric: Option<String>,
+
+    /// Number of latest reports to show
+    #[arg(long, default_value_t = 20)]
+    limit: usize,
+}
+
+#[derive(clap::Args, Debug)]
+struct SelftestArgs {
+    /// Path between the client and the in-process server
+    #[arg(long, value_enum, default_value_t = Via::Loopback)]
+    via: Via,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Output {
+    /// Human-readable statistics
+    Text,
+    /// A JSON document with all samples, summaries and run metadata
+    Json,
+    /// One CSV row per sample
+    Csv,
+    /// A summary table to paste into GitHub issues
+    Markdown,
+}
+
+/// Exit code used when `--direct-only` is set but the connection is relayed.
+const EXIT_NOT_DIRECT: i32 = 3;
+
+/// Exit code used when a result violates `--min-bandwidth` or `--max-latency`.
+const EXIT_THRESHOLD: i32 = 4;
+
+/// Exit code used when the benchmark was interrupted with Ctrl-C, like shells report SIGINT.
+const EXIT_INTERRUPTED: i32 = 130;
+
+/// Error returned when results violate the thresholds, after all results were written.
+#[derive(Debug)]
+struct ThresholdsViolated(usize);
+
+impl std::fmt::Display for ThresholdsViolated {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} result(s) violated the thresholds", self.0)
+    }
+}
+
+impl std::error::Error for ThresholdsViolated {}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    args.log.init(env!("CARGO_CRATE_NAME"), LevelFilter::WARN)?;
+    match run(args).await {
+        Err(err) if err.is::<NotDirect>() => {
+            eprintln!("Error: {err:#}");
+            std::process::exit(EXIT_NOT_DIRECT);
+        }
+        Err(err) if err.is::<ThresholdsViolated>() => {
+            eprintln!("Error: {err:#}");
+            std::process::exit(EXIT_THRESHOLD);
+        }
+        Err(err) if err.is::<Interrupted>() => {
+            eprintln!("{err:#}");
+            std::process::exit(EXIT_INTERRUPTED);
+        }
+        res => res,
+    }
+}
+
+async fn run(mut args: Args) -> Result<()> {
+    let command = match &args.command {
+        Some(CliCommand::Compare(compare)) => {
+            let baseline = BenchmarkReport::load(&compare.baseline)?;
+            let current = BenchmarkReport::load(&compare.current)?;
+            for (label, path, reports) in [
+                ("Baseline", &compare.baseline, &baseline),
+                ("Current", &compare.current, &current),
+            ] {
+                if let Some(report) = reports.first() {
+                    let run = &report.run;
+                    let path = path.display();
+                    println!("{label}: {path} (run {}, iroh {})", run.run_id, run.iroh_version);
+                }
+            }
+            let comparisons = p2p::compare::compare(&baseline, &current, compare.threshold);
+            print_comparison(&comparisons);
+            return Ok(());
+        }
+        Some(CliCommand::Report(report)) => {
+            let runs = report
+                .results
+                .iter()
+                .map(|path| {
+                    let name = path.file_name().unwrap_or(path.as_os_str());
+                    Ok((name.to_string_lossy().into_owned(), BenchmarkReport::load(path)?))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            std::fs::write(&report.out, p2p::html::render(&runs))?;
+            eprintln!("Report written to {}", report.out.display());
+            return Ok(());
+        }
+        Some(CliCommand::History(history)) => {
+            let db = args.db.as_deref().context("History needs the database given by --db")?;
+            let store = ResultStore::open(db)?;
+            let metric = history.metric.as_deref();
+            let entries = store.history(&history.node_id, metric, history.limit)?;
+            print_history(&entries);
+            return Ok(());
+        }
+        Some(CliCommand::Mesh(mesh)) => {
+            let print_results = args.output == Output::Text;
+            let report = p2p::mesh::run_mesh(&args.config.endpoint, mesh, print_results).await?;
+            write_mesh_report(&args, report)?;
+            return Ok(());
+        }
+        Some(CliCommand::Remote(remote)) => {
+            let [agent] = &args.ticket[..] else {
+                anyhow::bail!("remote needs the --ticket of a single agent");
+            };
+            let agent = agent.node_addr().clone();
+            let print_results = args.output == Output::Text;
+            let report =
+                p2p::mesh::run_remote(&args.config.endpoint, agent, remote, print_results).await?;
+            write_mesh_report(&args, report)?;
+            return Ok(());
+        }
+        Some(CliCommand::Relays(relays)) => {
+            let print_results = args.output == Output::Text;
+            let mut report =
+                p2p::relays::run_relays(&args.config.endpoint, relays, print_results).await?;
+            report.run.label = args.label.clone();
+            save_results(&args, &report.run, &report)?;
+            match args.output {
+                Output::Text => {}
+                Output::Json => {
+                    write_output(&args, &(serde_json::to_string_pretty(&report)? + "\n"))?
+                }
+                Output::Csv => write_output(&args, &report.to_csv())?,
+                Output::Markdown => write_output(&args, &report.to_markdown())?,
+            }
+            return Ok(());
+        }
+        Some(CliCommand::Benchmark(command)) => Some(command.clone()),
+        Some(CliCommand::Selftest(_)) | None => None,
+    };
+    let mut scenario = args.scenario.as_deref().map(Scenario::load).transpose()?;
+    // Keeps serving the self-test until the client is done
+    let mut _selftest = None;
+    let mut targets: Vec<NodeAddr> =
+        args.ticket.iter().map(|ticket| ticket.node_addr().clone()).collect();
+    if let Some(CliCommand::Selftest(selftest)) = &args.command {
+        let via = selftest.via;
+        let (router, node_addr) = p2p::selftest::serve(&args.config.endpoint, via).await?;
+        eprintln!("Self-test server {} via {via:?}", node_addr.node_id);
+        args.config.endpoint.no_discovery = true;
+        args.config.endpoint.relay_only = via == Via::Relay;
+        scenario.get_or_insert_with(|| p2p::selftest::suite(via));
+        targets.push(node_addr);
+        _selftest = Some(router);
+    }
+    for public_key in &args.public_key {
+        // Decode the hex string into raw bytes
+        let pk_bytes = hex::decode(public_key)?;
+        let pk_array: [u8; 32] = pk_bytes[..].try_into()
+            .map_err(|_| anyhow::anyhow!("Invalid public key length - expected 32 bytes"))?;
+
+        // Create public key and node address
+        targets.push(NodeAddr::new(PublicKey::from_bytes(&pk_array)?));
+    }
+    anyhow::ensure!(!targets.is_empty(), "Either --public-key or --ticket is required");
+    anyhow::ensure!(
+        args.addrs.is_empty() || targets.len() == 1,
+        "--addr can only be used with a single server"
+    );
+    args.config.print_results = args.output == Output::Text && !args.tui;
+    if args.tui {
+        args.config.progress = Some(Arc::new(Progress::default()));
+    } else {
+        // The dashboard reads Ctrl-C as a key press and aborts on it instead
+        let (interrupt, interrupted) = watch::channel(false);
+        args.config.interrupt = Some(interrupted);
+        tokio::spawn(interrupt_on_ctrl_c(interrupt));
+    }
+    for node_addr in &mut targets {
+        node_addr.direct_addresses.extend(&args.addrs);
+        eprintln!("Node Address: {:?}", node_addr);
+    }
+    // A single benchmark of a single server produces one report, everything else a list
+    anyhow::ensure!(
+        cfg!(feature = "relay-only") || !args.per_relay,
+        "--per-relay needs a build with `--features relay-only`"
+    );
+    let single = targets.len() == 1 && scenario.is_none() && !args.per_relay;
+    if !single {
+        anyhow::ensure!(
+            args.output != Output::Csv,
+            "Reports of several benchmarks or servers cannot share a CSV table"
+        );
+    }
+    if scenario.is_some() {
+        anyhow::ensure!(command.is_none(), "Scenarios cannot be combined with a subcommand");
+    } else if args.baseline.is_some() {
+        p2p::baseline::check(&args.config, command.as_ref())?;
+    }
+    let mut store = args.db.as_deref().map(ResultStore::open).transpose()?;
+    let webhook =
+        args.webhook_url.clone().map(|url| Webhook::new(url, args.webhook_on)).transpose()?;
+    p2p::counters::init();
+    let mut resumed = match &args.resume {
+        Some(run_id) => Some(Checkpoint::load(&args, run_id)?),
+        None => None,
+    };
+    // A resumed run keeps the ceiling it measured before
+    let ceiling = match args.machine_ceiling && resumed.is_none() {
+        true => {
+            eprintln!("Measuring the machine ceiling over loopback");
+            let ceiling = p2p::selftest::machine_ceiling(&args.config).await?;
+            if args.config.print_results {
+                println!("Machine ceiling (loopback): {}", ceiling.describe());
+            }
+            Some(ceiling)
+        }
+        false => None,
+    };
+    let endpoint = args.config.endpoint.keyed_builder()?.bind().await?;
+    for sizes in SocketBuffers::iroh_sizes(&endpoint) {
+        eprintln!("{sizes}");
+    }
+
+    loop {
+        let started = tokio::time::Instant::now();
+        let mut checkpoint = match resumed.take() {
+            Some(checkpoint) => checkpoint,
+            None => Checkpoint::new(
+                &args,
+                RunMetadata {
+                    label: args.label.clone(),
+                    machine_ceiling: ceiling,
+                    ..RunMetadata::new(endpoint.node_id(), SystemTime::now())
+                },
+            ),
+        };
+        let run = checkpoint.run.clone();
+        eprintln!("Run ID: {}", run.run_id);
+        let mut reports = Vec::new();
+        let mut failures = Vec::new();
+        for node_addr in &targets {
+            let res = match &scenario {
+                Some(scenario) => {
+                    run_scenario(&endpoint, node_addr, &args, scenario, &mut checkpoint).await
+                }
+                None if args.per_relay => {
+                    run_per_relay(node_addr, &args, &run, command.as_ref()).await
+                }
+                None => {
+                    let (config, command) = (&args.config, command.as_ref());
+                    run_benchmark(&endpoint, node_addr, &args, &run, None, config, command)
+                        .await
+                        .map(|report| vec![report])
+                }
+            };
+            match res {
+                Ok(target_reports) => reports.extend(target_reports),
+                // Monitoring goes on when a server is unreachable for a while
+                Err(err) if args.every.is_some() => {
+                    let failure = format!("Benchmark of {} failed: {err:#}", node_addr.node_id);
+                    error!("{failure}");
+                    failures.push(failure);
+                }
+                Err(err) => return Err(err),
+            }
+            if args.config.interrupted() {
+                break;
+            }
+        }
+
+        if let Some(store) = &mut store {
+            for report in &reports {
+                store.append(report)?;
+            }
+        }
+        if let Some(ticket) = args.aggregate.as_ref().filter(|_| !reports.is_empty()) {
+            let aggregator = ticket.node_addr().clone();
+            match p2p::aggregate::upload(&endpoint, aggregator, &reports).await {
+                Ok(()) => eprintln!("Uploaded {} reports for aggregation", reports.len()),
+                Err(err) => warn!("Uploading the results failed: {err:#}"),
+            }
+        }
+        match (single, reports.as_slice()) {
+            (true, [report]) => {
+                save_results(&args, &run, report)?;
+                match args.output {
+                    // Text results are printed while the benchmark runs
+                    Output::Text => {}
+                    Output::Json => {
+                        write_output(&args, &(serde_json::to_string_pretty(report)? + "\n"))?
+                    }
+                    Output::Csv => write_output(&args, &report.to_csv())?,
+                    Output::Markdown => write_output(&args, &report.to_markdown())?,
+                }
+            }
+            _ if reports.is_empty() => {}
+            _ => {
+                save_results(&args, &run, &reports)?;
+                match args.output {
+                    Output::Json => {
+                        write_output(&args, &(serde_json::to_string_pretty(&reports)? + "\n"))?
+                    }
+                    Output::Markdown => {
+                        let tables: Vec<_> =
+                            reports.iter().map(BenchmarkReport::to_markdown).collect();
+                        write_output(&args, &tables.join("\n"))?;
+                    }
+                    Output::Text | Output::Csv => {}
+                }
+            }
+        }
+        let violations = thresholds(&args).violations(&reports);
+        if let Some(webhook) = &webhook {
+            let summary = RunSummary::new(&run, &reports, violations.clone(), failures);
+            if let Err(err) = webhook.notify(&summary).await {
+                warn!("Notifying the webhook failed: {err:#}");
+            }
+        }
+        if args.config.interrupted() {
+            if checkpoint.saved() {
+                eprintln!("Continue this run with --resume {}", run.run_id);
+            }
+            return Err(Interrupted.into());
+        }
+        checkpoint.remove()?;
+        let res = check_thresholds(&violations);
+        let Some(every) = args.every else {
+            return res;
+        };
+        if let Err(err) = res {
+            error!("{err:#}");
+        }
+        let next = started + every;
+        let wait = next.saturating_duration_since(tokio::time::Instant::now());
+        let wait = Duration::from_secs(wait.as_secs());
+        eprintln!("\nNext run in {}", humantime::format_duration(wait));
+        tokio::select! {
+            _ = tokio::time::sleep_until(next) => {}
+            _ = args.config.until_interrupted() => return Err(Interrupted.into()),
+        }
+    }
+}
+
+/// Runs every test of the scenario against one server, with one report per repetition, and
+/// records every completed repetition in the `checkpoint`.
+///
+/// Repetitions the checkpoint already records are not run again, their reports are reused.
+async fn run_scenario(
+    endpoint: &Endpoint,
+    node_addr: &NodeAddr,
+    args: &Args,
+    scenario: &Scenario,
+    checkpoint: &mut Checkpoint,
+) -> Result<Vec<BenchmarkReport>> {
+    let run = &checkpoint.run.clone();
+    let server = node_addr.node_id.to_string();
+    let mut reports = Vec::new();
+    for test in &scenario.tests {
+        let mut completed = checkpoint.completed(&server, &test.name).into_iter();
+        let config = BenchmarkConfig {
+            endpoint: args.config.endpoint.clone(),
+            print_results: args.config.print_results,
+            progress: args.config.progress.clone(),
+            interrupt: args.config.interrupt.clone(),
+            ..test.config.clone()
+        };
+        for i in 0..test.repetitions {
+            if config.interrupted() {
+                return Ok(reports);
+            }
+            let header = format!("=== {} ({}/{}) ===", test.name, i + 1, test.repetitions);
+            if let Some(report) = completed.next() {
+                eprintln!("\n{header} completed before, skipping");
+                reports.push(report);
+                continue;
+            }
+            match args.output {
+                Output::Text => println!("\n{header}"),
+                _ if args.tui => {}
+                _ => eprintln!("\n{header}"),
+            }
+            let command = test.command.as_ref();
+            let name = Some(test.name.as_str());
+            let report =
+                run_benchmark(endpoint, node_addr, args, run, name, &config, command).await?;
+            // An interrupted repetition is reported but has to run again when resuming
+            if !report.interrupted {
+                checkpoint.push(&report)?;
+            }
+            reports.push(report);
+        }
+    }
+    Ok(reports)
+}
+
+/// Runs the benchmark once per relay of the relay map, each time from a new endpoint that only
+/// knows that relay and only sends via relays, and prints a table comparing the relays.
+///
+/// A relay the benchmark fails through is reported in the table instead of failing the run.
+async fn run_per_relay(
+    node_addr: &NodeAddr,
+    args: &Args,
+    run: &RunMetadata,
+    command: Option<&Command>,
+) -> Result<Vec<BenchmarkReport>> {
+    let urls: Vec<_> =
+        relay_mode(&args.config.endpoint.relay_urls)?.relay_map().urls().cloned().collect();
+    let mut results = Vec::new();
+    for url in urls {
+        if args.config.interrupted() {
+            break;
+        }
+        let header = format!("=== via {url} ===");
+        match args.output {
+            Output::Text => println!("\n{header}"),
+            _ if args.tui => {}
+            _ => eprintln!("\n{header}"),
+        }
+        let config = BenchmarkConfig {
+            endpoint: EndpointConfig {
+                relay_urls: vec![url.clone()],
+                relay_only: true,
+                // Discovery would add the server's home relay, which is not the one under test
+                no_discovery: true,
+                ..args.config.endpoint.clone()
+            },
+            ..args.config.clone()
+        };
+        // The server is dialed via the relay under test only
+        let node_addr = NodeAddr::new(node_addr.node_id).with_relay_url(url.clone());
+        let res = async {
+            let endpoint = config.endpoint.builder()?.bind().await?;
+            let name = Some(url.as_str());
+            let res =
+                run_benchmark(&endpoint, &node_addr, args, run, name, &config, command).await;
+            endpoint.close().await;
+            res
+        };
+        let res = res.await.map_err(|err| {
+            eprintln!("Benchmark via {url} failed: {err:#}");
+            format!("{err:#}")
+        });
+        results.push((url.to_string(), res));
+    }
+    if args.config.print_results {
+        print_relay_comparison(&results);
+    }
+    Ok(results.into_iter().filter_map(|(_, res)| res.ok()).collect())
+}
+
+/// Prints the headline metrics of every relay side by side, one row per relay.
+fn print_relay_comparison(results: &[(String, Result<BenchmarkReport, String>)]) {
+    // The columns are the metrics of the first relay the benchmark succeeded through
+    let first = results.iter().find_map(|(_, res)| res.as_ref().ok());
+    let columns: Vec<String> = first
+        .map(|report| report.benchmark.metrics())
+        .unwrap_or_default()
+        .iter()
+        .map(|metric| format!("{} ({})", metric.name, metric.unit))
+        .collect();
+    let width = results.iter().map(|(url, _)| url.len()).max().unwrap_or(0);
+    println!("\nComparison of the relays:");
+    let header: Vec<_> = columns.iter().map(|column| format!("{column:>12}")).collect();
+    println!("  {:<width$} {}", "Relay", header.join(" "));
+    for (url, res) in results {
+        let line = match res {
+            Ok(report) => {
+                let metrics = report.benchmark.metrics();
+                let values = columns.iter().map(|column| {
+                    let metric = metrics
+                        .iter()
+                        .find(|metric| format!("{} ({})", metric.name, metric.unit) == *column);
+                    let value = metric.map_or("-".to_string(), |m| format!("{:.2}", m.value));
+                    format!("{value:>width$}", width = column.len().max(12))
+                });
+                values.collect::<Vec<_>>().join(" ")
+            }
+            Err(err) => format!("failed: {err}"),
+        };
+        println!("  {url:<width$} {line}");
+    }
+}
+
+/// Scenario tests completed so far in a run, saved as `<run id>.checkpoint.json` in the
+/// `--results-dir` after every repetition, so that `--resume` can continue the run.
+struct Checkpoint {
+    run: RunMetadata,
+    path: Option<PathBuf>,
+    reports: Vec<BenchmarkReport>,
+}
+
+impl Checkpoint {
+    /// An empty checkpoint of a new run.
+    fn new(args: &Args, run: RunMetadata) -> Self {
+        let path = args.results_dir.as_ref().map(|dir| Self::path(dir, &run.run_id));
+        Self { run, path, reports: Vec::new() }
+    }
+
+    /// Loads the checkpoint of the run with ID `run_id` from the `--results-dir`.
+    fn load(args: &Args, run_id: &str) -> Result<Self> {
+        let dir = args.results_dir.as_ref().context("Resuming needs the --results-dir")?;
+        let path = Self::path(dir, run_id);
+        anyhow::ensure!(
+            path.exists(),
+            "There is no checkpoint of run {run_id} in {}",
+            dir.display()
+        );
+        let reports = BenchmarkReport::load(&path)?;
+        let run = reports.first().context("The checkpoint is empty")?.run.clone();
+        eprintln!("Resuming run {run_id} after {} completed tests", reports.len());
+        Ok(Self { run, path: Some(path), reports })
+    }
+
+    fn path(dir: &Path, run_id: &str) -> PathBuf {
+        dir.join(format!("{run_id}.checkpoint.json"))
+    }
+
+    /// Reports of the repetitions of the scenario test `test` completed against `server`.
+    fn completed(&self, server: &str, test: &str) -> Vec<BenchmarkReport> {
+        let reports = self.reports.iter().filter(|report| {
+            report.server_node_id == server && report.name.as_deref() == Some(test)
+        });
+        reports.cloned().collect()
+    }
+
+    /// Records a completed repetition and saves the checkpoint.
+    fn push(&mut self, report: &BenchmarkReport) -> Result<()> {
+        self.reports.push(report.clone());
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        std::fs::create_dir_all(path.parent().expect("checkpoints are in a directory"))?;
+        std::fs::write(path, serde_json::to_string_pretty(&self.reports)? + "\n")
+            .with_context(|| format!("Failed to write checkpoint {}", path.display()))
+    }
+
+    /// Whether the checkpoint was saved and the run can be resumed.
+    fn saved(&self) -> bool {
+        self.path.as_ref().is_some_and(|path| path.exists())
+    }
+
+    /// Deletes the saved checkpoint once the run completed.
+    fn remove(&self) -> Result<()> {
+        match &self.path {
+            Some(path) if path.exists() => Ok(std::fs::remove_file(path)?),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The limits given by `--min-bandwidth` and `--max-latency`.
+fn thresholds(args: &Args) -> Thresholds {
+    Thresholds {
+        min_bandwidth: args.min_bandwidth,
+        max_latency_ms: args.max_latency.map(millis),
+    }
+}
+
+/// Fails with [`ThresholdsViolated`] if there are any threshold violations, listing them on
+/// stderr.
+fn check_thresholds(violations: &[String]) -> Result<()> {
+    for violation in violations {
+        eprintln!("Threshold violated: {violation}");
+    }
+    if !violations.is_empty() {
+        return Err(ThresholdsViolated(violations.len()).into());
+    }
+    Ok(())
+}
+
+/// Runs a single benchmark and wraps its results in a report named `name`, together with iroh's
+/// counters of the traffic during the benchmark.
+async fn run_benchmark(
+    endpoint: &Endpoint,
+    node_addr: &NodeAddr,
+    args: &Args,
+    run: &RunMetadata,
+    name: Option<&str>,
+    config: &BenchmarkConfig,
+    command: Option<&Command>,
+) -> Result<BenchmarkReport> {
+    config.validate()?;
+    let public_key = node_addr.node_id;
+    if config.direct_only {
+        let conn = endpoint.connect(node_addr.clone(), ALPN).await?;
+        let direct = wait_for_direct(endpoint, public_key, args.direct_timeout).await?;
+        conn.close(0u32.into(), b"bye!");
+        if !direct {
+            let path = path_label(&endpoint.conn_type(public_key)?.get()?);
+            return Err(NotDirect(path.to_string()).into());
+        }
+        eprintln!("Direct path established");
+    }
+
+    let relays = relay_mode(&config.endpoint.relay_urls)?
+        .relay_map()
+        .urls()
+        .map(|url| url.to_string())
+        .collect();
+    // Export the throughput of every interval while the benchmark runs
+    let influx =
+        args.influx.as_deref().map(|url| InfluxExporter::new(url, args.influx_token.clone()));
+    let mut tags = Tags {
+        run_id: run.run_id.clone(),
+        server: public_key.to_string(),
+        mode: String::new(),
+        test: name.unwrap_or_default().to_string(),
+        host: run.hostname.clone(),
+        label: run.label.clone().unwrap_or_default(),
+    };
+    let mut config = config.clone();
+    let export = influx.clone().map(|influx| {
+        let (sink, samples) = tokio::sync::mpsc::unbounded_channel();
+        config.interval_sink = Some(sink);
+        tokio::spawn(influx.forward(tags.clone(), samples))
+    });
+
+    let started_at = SystemTime::now();
+    let before = CounterSnapshot::now();
+    let benchmark = client::run_command(endpoint, node_addr.clone(), &config, command);
+    let benchmark = match &config.progress {
+        Some(progress) => {
+            let title = format!("run {}", run.run_id);
+            p2p::tui::run(endpoint, public_key, &title, progress.clone(), benchmark).await?
+        }
+        None => benchmark.await?,
+    };
+    let counters = CounterSnapshot::now().since(&before);
+    // Closing the sink lets the export finish once it has sent the last intervals
+    config.interval_sink = None;
+    if let Some(export) = export {
+        export.await?;
+    }
+    let config = &config;
+    let summarize = config.progress.is_some() && args.output == Output::Text;
+    if summarize {
+        print_summary(&benchmark);
+    }
+    if (config.print_results || summarize) && !args.no_charts {
+        print!("{}", p2p::chart::charts(&benchmark));
+    }
+    if config.print_results || summarize {
+        print_counters(&counters);
+    }
+    // Scenario tests the baseline does not apply to, and interrupted ones, are compared to nothing
+    let mut baselines = Vec::new();
+    let baseline = args
+        .baseline
+        .filter(|_| !config.interrupted() && p2p::baseline::check(config, command).is_ok());
+    if let Some(baseline) = baseline {
+        let addr = match baseline {
+            Baseline::Tcp => args.tcp_addr,
+            Baseline::Quic => args.quic_addr,
+        };
+        match addr.or_else(|| baseline.default_addr(endpoint, public_key)) {
+            Some(addr) => match p2p::baseline::run(baseline, addr, config, &benchmark).await {
+                Ok(results) => baselines = results,
+                Err(err) => warn!("The {baseline:?} baseline failed: {err:#}"),
+            },
+            None => eprintln!("Skipping the baseline, there is no direct path to the server"),
+        }
+        if (config.print_results || summarize) && !baselines.is_empty() {
+            p2p::baseline::print_results(&baselines);
+        }
+    }
+    let report = BenchmarkReport {
+        name: name.map(str::to_string),
+        run: run.clone(),
+        client_node_id: endpoint.node_id().to_string(),
+        server_node_id: public_key.to_string(),
+        started_at: timestamp(started_at),
+        finished_at: timestamp(SystemTime::now()),
+        relays,
+        relay_only: config.endpoint.relay_only,
+        congestion: config.endpoint.transport.congestion.unwrap_or_default(),
+        iroh_counters: Some(counters),
+        baselines,
+        interrupted: config.interrupted(),
+        benchmark,
+    };
+    if let Some(influx) = &influx {
+        tags.mode = report.benchmark.mode().to_string();
+        if let Err(err) = influx.write_report(&tags, &report).await {
+            warn!("Exporting to InfluxDB failed: {err:#}");
+        }
+    }
+    Ok(report)
+}
+
+/// Interrupts the benchmark on the first Ctrl-C, and exits right away on the second.
+async fn interrupt_on_ctrl_c(interrupt: watch::Sender<bool>) {
+    if tokio::signal::ctrl_c().await.is_err() {
+        return;
+    }
+    eprintln!("\nInterrupted, finishing with the results so far (Ctrl-C again to quit)");
+    interrupt.send_replace(true);
+    if tokio::signal::ctrl_c().await.is_ok() {
+        std::process::exit(EXIT_INTERRUPTED);
+    }
+}
+
+/// Prints the headline metrics of a benchmark that ran with the dashboard, which replaces the
+/// detailed results printed while it runs.
+fn print_summary(benchmark: &Benchmark) {
+    println!("\nResults ({}):", benchmark.mode());
+    for metric in benchmark.metrics() {
+        println!("  {}: {:.3} {}", metric.name, metric.value, metric.unit);
+    }
+}
+
+/// Prints how much of the traffic took which path, according to iroh.
+fn print_counters(counters: &IrohCounters) {
+    println!("\nPaths (as counted by iroh):");
+    println!(
+        "  Sent: {} direct, {} via relay",
+        format_size(counters.sent_direct_bytes),
+        format_size(counters.sent_relay_bytes)
+    );
+    println!(
+        "  Received: {} direct, {} via relay",
+        format_size(counters.received_direct_bytes),
+        format_size(counters.received_relay_bytes)
+    );
+    println!("  Relayed: {:.1}% of all bytes", counters.relay_percent);
+    println!(
+        "  Hole punch attempts: {}, connections that became direct: {} of {}",
+        counters.holepunch_attempts, counters.became_direct, counters.connections
+    );
+}
+
+/// Prints the history of every metric as a chart over the runs, oldest first.
+fn print_history(entries: &[HistoryEntry]) {
+    if entries.is_empty() {
+        println!("No results for this server");
+        return;
+    }
+    let mut series: Vec<(String, Vec<(String, f64)>)> = Vec::new();
+    for entry in entries {
+        let name = format!("{} {} ({})", entry.test, entry.metric, entry.unit);
+        let row = (format!("{} {}", entry.started_at, &entry.server_node_id[..8]), entry.value);
+        match series.iter_mut().find(|(series, _)| *series == name) {
+            Some((_, rows)) => rows.push(row),
+            None => series.push((name, vec![row])),
+        }
+    }
+    for (name, rows) in series {
+        println!("\n{name}:");
+        print!("{}", p2p::chart::bars(&rows));
+    }
+}
+
+/// Saves the results of the run as JSON in the `--results-dir`, if one was given.
+fn save_results(args: &Args, run: &RunMetadata, results: &impl Serialize) -> Result<()> {
+    let Some(dir) = &args.results_dir else {
+        return Ok(());
+    };
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{}.json", run.run_id));
+    std::fs::write(&path, serde_json::to_string_pretty(results)? + "\n")?;
+    eprintln!("Results saved to {}", path.display());
+    Ok(())
+}
+
+/// Labels the results of `mesh` and `remote`, saves them and writes them in the `--output` format.
+fn write_mesh_report(args: &Args, mut report: MeshReport) -> Result<()> {
+    report.run.label = args.label.clone();
+    let report = &report;
+    save_results(args, &report.run, report)?;
+    match args.output {
+        Output::Text => {}
+        Output::Json => write_output(args, &(serde_json::to_string_pretty(report)? + "\n"))?,
+        Output::Csv => write_output(args, &report.to_csv())?,
+        Output::Markdown => write_output(args, &report.to_markdown())?,
+    }
+    Ok(())
+}
+
+/// Writes structured output to the `--output-file`, or to stdout if none was given.
+fn write_output(args: &Args, output: &str) -> Result<()> {
+    match &args.output_file {
+        Some(path) => std::fs::write(path, output)?,
+        None => print!("{output}"),
+    }
+    Ok(())
+}
@@ -0,0 +1,313 @@
+//! Benchmark server that serves the workloads requested by the client.
+//!
+//! The workloads are served by the protocol handlers in [`p2p::server`], this binary binds the
+//! endpoint and prints the ticket clients connect with.
+//!
+//! ## Usage
+//!
+//!     cargo run --bin server -- [--relay-url <url>...] [--port <port>]
+//!         [--secret-key-file <path>] [--discovery n0|local... | --no-discovery]
+//!         [--metrics-addr <ip:port>]
+//!         [--tcp-baseline] [--quic-baseline [<port>]] [--coordinator <ticket>] [--agent] [--qr]
+//!         [--blobs [--max-blob-size <size>] [--max-blobs-per-client <n>]] [--gossip]
+//!         [--allow <node-id>...] [--allow-file <path>] [--per-relay] [--max-connections <n>]
+//!         [--max-bytes-per-conn <size>] [--stream-receive-window <size>]
+//!         [--receive-window <size>] [--max-concurrent-streams <n>] [--idle-timeout <duration>]
+//!         [--congestion cubic|bbr|newreno] [--send-buffer <size>] [--recv-buffer <size>]
+//!         [--initial-mtu <bytes>] [--max-mtu <bytes>] [--keylog] [-v | -vv] [--log-file <path>]
+
+use std::{
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use iroh::{Endpoint, NodeAddr, NodeId, RelayUrl, protocol::Router};
+use iroh_base::ticket::NodeTicket;
+use p2p::config::{
+    Discovery, SocketBuffers, TransportTuning, discovery, keylog, relay_mode, secret_key,
+};
+use p2p::logging::LogConfig;
+use p2p::metrics::ServerMetrics;
+use p2p::server::{Allowlist, BlobLimits, Limits, ServeOptions};
+use p2p::units::parse_size;
+use qrcode::{QrCode, render::unicode::Dense1x2};
+use tokio::net::TcpListener;
+use tracing::{error, info, level_filters::LevelFilter};
+
+/// CLI arguments
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Relay server to use instead of n0's default relays, can be repeated
+    #[arg(long = "relay-url")]
+    relay_urls: Vec<RelayUrl>,
+
+    /// File with the secret key of the node, generated if missing, to keep its node ID and
+    /// ticket across restarts
+    #[arg(long)]
+    secret_key_file: Option<PathBuf>,
+
+    /// Write the TLS session keys to the file named by `SSLKEYLOGFILE`, to decrypt captured
+    /// traffic in Wireshark
+    #[arg(long)]
+    keylog: bool,
+
+    /// UDP port to listen on, random if not set
+    #[arg(long)]
+    port: Option<u16>,
+
+    /// Discovery mechanism used to publish the node's addresses, can be repeated
+    #[arg(long, value_enum, default_value = "n0")]
+    discovery: Vec<Discovery>,
+
+    /// Do not publish the node's addresses via any discovery mechanism
+    #[arg(long, conflicts_with = "discovery")]
+    no_discovery: bool,
+
+    /// Also serve transfers over plain TCP on the port of the UDP socket, for clients comparing
+    /// iroh to TCP with `--baseline tcp`. Unauthenticated and unlimited, so it cannot be combined
+    /// with an allowlist or limits
+    #[arg(long, conflicts_with_all = ["allow", "allow_file", "max_connections", "max_bytes_per_conn"])]
+    tcp_baseline: bool,
+
+    /// Also serve transfers over plain QUIC, without iroh, on this UDP port (4433 if not given),
+    /// for clients comparing iroh to QUIC with `--baseline quic`. Unauthenticated and unlimited,
+    /// so it cannot be combined with an allowlist or limits
+    #[arg(long, num_args = 0..=1, default_missing_value = "4433", conflicts_with_all = ["allow", "allow_file", "max_connections", "max_bytes_per_conn"])]
+    quic_baseline: Option<u16>,
+
+    /// Register with the mesh coordinator started by `client mesh` with this ticket and run the
+    /// measurements it asks for
+    #[arg(long)]
+    coordinator: Option<NodeTicket>,
+
+    /// Run measurements to other nodes when instructed by `client remote`. Anyone who has the
+    /// node ID can then make this node connect to and upload to any other node
+    #[arg(long)]
+    agent: bool,
+
+    /// Serve the blob benchmark, importing the blobs clients request into an in-memory store that
+    /// iroh-blobs serves to anyone who knows their hashes
+    #[arg(long)]
+    blobs: bool,
+
+    /// Largest blob a client may request with `--blobs` (e.g. `128M`, at most `1G`)
+    #[arg(long, value_parser = parse_size, default_value = "128M", requires = "blobs")]
+    max_blob_size: u64,
+
+    /// Blobs a client may have the server hold at once with `--blobs`
+    #[arg(long, default_value_t = 4, requires = "blobs")]
+    max_blobs_per_client: u64,
+
+    /// Serve the gossip benchmark, joining the topics clients request. Any node can then join
+    /// those topics through this node, as iroh-gossip knows no allowlist
+    #[arg(long)]
+    gossip: bool,
+
+    /// Only accept benchmarks from this client node ID, can be repeated
+    #[arg(long)]
+    allow: Vec<NodeId>,
+
+    /// Only accept benchmarks from the client node IDs in this file, one per line
+    #[arg(long)]
+    allow_file: Option<PathBuf>,
+
+    /// Be reachable via every relay of the relay map at once, for clients comparing the relays
+    /// with `--per-relay`. The node's own endpoint only uses the first relay, and an endpoint
+    /// with the same node ID, and without discovery, is bound for each of the others
+    #[arg(long)]
+    per_relay: bool,
+
+    /// Reject connections beyond this many open at once
+    #[arg(long)]
+    max_connections: Option<usize>,
+
+    /// Close connections once they sent and received this many bytes (e.g. `10G`), counting
+    /// QUIC overhead
+    #[arg(long, value_parser = parse_size)]
+    max_bytes_per_conn: Option<u64>,
+
+    /// Also print the ticket as a QR code, to scan it from a phone or another machine's camera
+    #[arg(long)]
+    qr: bool,
+
+    #[command(flatten)]
+    transport: TransportTuning,
+
+    #[command(flatten)]
+    buffers: SocketBuffers,
+
+    /// Serve Prometheus metrics on `http://<ip:port>/metrics`, e.g. 127.0.0.1:9090
+    #[arg(long)]
+    metrics_addr: Option<SocketAddr>,
+
+    #[command(flatten)]
+    log: LogConfig,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    args.log.init(env!("CARGO_CRATE_NAME"), LevelFilter::INFO)?;
+    let metrics = Arc::new(ServerMetrics::default());
+    let (router, relay_routers) = accept_side(&args, metrics.clone()).await?;
+    let node_addr = router.endpoint().node_addr().await?;
+    println!("Listening on {:?}", node_addr.node_id.to_string());
+    print_ticket(node_addr.clone(), args.qr)?;
+    let (endpoint, qr) = (router.endpoint().clone(), args.qr);
+    tokio::spawn(async move {
+        if let Err(err) = reprint_ticket(endpoint, node_addr, qr).await {
+            error!("Watching the addresses failed: {err:#}");
+        }
+    });
+
+    if let Some(addr) = args.metrics_addr {
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("Failed to bind the metrics server to {addr}"))?;
+        let endpoint = router.endpoint().clone();
+        tokio::spawn(async move {
+            if let Err(err) = p2p::metrics::serve(listener, metrics, endpoint).await {
+                error!("Metrics server failed: {err:#}");
+            }
+        });
+        println!("Metrics on http://{addr}/metrics");
+    }
+
+    if args.tcp_baseline {
+        let (v4, v6) = router.endpoint().bound_sockets();
+        for addr in std::iter::once(v4).chain(v6) {
+            let listener = TcpListener::bind(addr)
+                .await
+                .with_context(|| format!("Failed to bind the TCP baseline to {addr}"))?;
+            tokio::spawn(async move {
+                if let Err(err) = p2p::server::serve_tcp_baseline(listener, false).await {
+                    error!("TCP baseline failed: {err:#}");
+                }
+            });
+            println!("TCP baseline on {addr}");
+        }
+    }
+
+    if let Some(port) = args.quic_baseline {
+        let addr = SocketAddr::from((Ipv4Addr::UNSPECIFIED, port));
+        let config = p2p::baseline::quic_server_config()?;
+        let (endpoint, sizes) = args
+            .buffers
+            .quic_endpoint(addr, Some(config))
+            .with_context(|| format!("Failed to bind the QUIC baseline to {addr}"))?;
+        tokio::spawn(p2p::server::serve_quic_baseline(endpoint, false));
+        println!("QUIC baseline on {addr}");
+        println!("{sizes}");
+    }
+
+    if let Some(ticket) = &args.coordinator {
+        let (endpoint, coordinator) = (router.endpoint().clone(), ticket.node_addr().clone());
+        tokio::spawn(async move {
+            match p2p::mesh::join(endpoint, coordinator).await {
+                Ok(()) => info!("The mesh coordinator is done"),
+                Err(err) => error!("Mesh coordination failed: {err:#}"),
+            }
+        });
+    }
+
+    tokio::signal::ctrl_c().await?;
+    for router in std::iter::once(router).chain(relay_routers) {
+        router.shutdown().await?;
+    }
+    Ok(())
+}
+
+/// Prints the ticket of `node_addr` and a client command benchmarking it, and with `qr` the
+/// ticket as a QR code.
+fn print_ticket(node_addr: NodeAddr, qr: bool) -> Result<()> {
+    let ticket = NodeTicket::new(node_addr);
+    if qr {
+        let code = QrCode::new(ticket.to_string())?;
+        // Light text on a dark terminal background draws the light modules
+        let code = code.render().dark_color(Dense1x2::Light).light_color(Dense1x2::Dark).build();
+        println!("{code}");
+    }
+    println!("Ticket: {ticket}");
+    println!("Benchmark with: cargo run --bin client -- --ticket {ticket}");
+    Ok(())
+}
+
+/// Prints the ticket again whenever the direct addresses or the home relay of the endpoint
+/// change, as clients dialing the previous ticket may no longer reach the server directly.
+async fn reprint_ticket(endpoint: Endpoint, mut printed: NodeAddr, qr: bool) -> Result<()> {
+    let mut direct = endpoint.direct_addresses();
+    let mut relay = endpoint.home_relay();
+    loop {
+        tokio::select! {
+            res = direct.updated() => res.map(drop)?,
+            res = relay.updated() => res.map(drop)?,
+        }
+        let addrs = direct.get()?.unwrap_or_default().into_iter().map(|addr| addr.addr);
+        let node_addr = NodeAddr::from_parts(endpoint.node_id(), relay.get()?, addrs);
+        if node_addr != printed {
+            println!("Addresses changed");
+            print_ticket(node_addr.clone(), qr)?;
+            printed = node_addr;
+        }
+    }
+}
+
+/// Binds the endpoint and serves the benchmarks on it, and with `--per-relay` on one more
+/// endpoint per relay beyond the first, whose routers are returned second.
+async fn accept_side(args: &Args, metrics: Arc<ServerMetrics>) -> Result<(Router, Vec<Router>)> {
+    let relays: Vec<RelayUrl> =
+        relay_mode(&args.relay_urls)?.relay_map().urls().cloned().collect();
+    // A relay only keeps the latest connection of a node ID, so every relay gets one endpoint
+    let (own, others) = match args.per_relay {
+        true => relays.split_at(relays.len().min(1)),
+        false => (&args.relay_urls[..], &[][..]),
+    };
+    let mut builder = Endpoint::builder().relay_mode(relay_mode(own)?);
+    if !args.no_discovery {
+        builder = discovery(builder, &args.discovery);
+    }
+    if let Some(path) = &args.secret_key_file {
+        builder = builder.secret_key(secret_key(path)?);
+    }
+    if let Some(port) = args.port {
+        builder = builder.bind_addr_v4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port));
+    }
+    builder = keylog(builder, args.keylog)?;
+    let endpoint = args.transport.apply(builder)?.bind().await?;
+    let (v4, v6) = endpoint.bound_sockets();
+    match v6 {
+        Some(v6) => println!("Bound to {v4} and {v6}"),
+        None => println!("Bound to {v4}"),
+    }
+    for sizes in SocketBuffers::iroh_sizes(&endpoint) {
+        println!("{sizes}");
+    }
+    let options = ServeOptions {
+        quiet: false,
+        agent: args.agent,
+        blobs: args.blobs.then_some(BlobLimits {
+            max_size: args.max_blob_size,
+            per_client: args.max_blobs_per_client,
+        }),
+        gossip: args.gossip,
+        allow: Allowlist::load(&args.allow, args.allow_file.as_deref())?,
+        limits: Limits::new(args.max_connections, args.max_bytes_per_conn),
+    };
+    let mut relay_routers = Vec::new();
+    for url in others {
+        let builder = Endpoint::builder()
+            .relay_mode(relay_mode(std::slice::from_ref(url))?)
+            .secret_key(endpoint.secret_key().clone());
+        let builder = keylog(builder, args.keylog)?;
+        let relay_endpoint = args.transport.apply(builder)?.bind().await?;
+        let router = p2p::server::router(relay_endpoint, metrics.clone(), options.clone()).await?;
+        println!("Also reachable via {url}");
+        relay_routers.push(router);
+    }
+    let router = p2p::server::router(endpoint, metrics, options).await?;
+    Ok((router, relay_routers))
+}
@@ -0,0 +1,237 @@
+//! Terminal charts printed after a run, to spot trends and outliers at a glance.
+
+use std::{fmt::Write as _, time::Duration};
+
+use crate::report::Benchmark;
+use crate::units::format_size;
+
+/// Width of the longest bar, in characters.
+const BAR_WIDTH: usize = 40;
+
+/// Number of buckets of latency histograms.
+const BUCKETS: usize = 10;
+
+/// Eighths of a block, for bars with sub-character resolution.
+const PARTIAL_BLOCKS: [&str; 8] = ["", "▏", "▎", "▍", "▌", "▋", "▊", "▉"];
+
+/// Charts of the samples of a benchmark: the throughput of every iteration of bandwidth runs, and
+/// by size if there are several, and histograms of the latencies of all other runs. Empty if the
+/// benchmark has nothing to chart.
+pub fn charts(benchmark: &Benchmark) -> String {
+    let mut out = String::new();
+    match benchmark {
+        Benchmark::Bandwidth { results, .. } => {
+            for result in results {
+                let size = format_size(result.size);
+                for (direction, download) in [("Upload", false), ("Download", true)] {
+                    let rows: Vec<_> = result
+                        .iterations
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(i, it)| {
+                            let mbps =
+                                if download { it.aggregate.download } else { it.aggregate.upload };
+                            Some((format!("#{}", i + 1), mbps?))
+                        })
+                        .collect();
+                    if !rows.is_empty() {
+                        writeln!(out, "\n{direction} throughput per iteration, {size} (Mbit/s):")
+                            .unwrap();
+                        out.push_str(&bars(&rows));
+                    }
+                }
+            }
+            if results.len() > 1 {
+                for (direction, download) in [("Upload", false), ("Download", true)] {
+                    let rows: Vec<_> = results
+                        .iter()
+                        .filter_map(|result| {
+                            let stats = if download { result.download } else { result.upload };
+                            Some((format_size(result.size), stats?.average))
+                        })
+                        .collect();
+                    if !rows.is_empty() {
+                        writeln!(out, "\n{direction} throughput by size (Mbit/s):").unwrap();
+                        out.push_str(&bars(&rows));
+                    }
+                }
+            }
+        }
+        Benchmark::ChunkSweep { results, .. } => {
+            for (direction, download) in [("Upload", false), ("Download", true)] {
+                let rows: Vec<_> = results
+                    .iter()
+                    .filter_map(|result| {
+                        let stats = if download { result.download } else { result.upload };
+                        Some((format_size(result.chunk_size), stats?.average))
+                    })
+                    .collect();
+                if !rows.is_empty() {
+                    writeln!(out, "\n{direction} throughput by chunk size (Mbit/s):").unwrap();
+                    out.push_str(&bars(&rows));
+                }
+            }
+        }
+        Benchmark::StreamSweep { results, .. } => {
+            for (direction, download) in [("Upload", false), ("Download", true)] {
+                let rows: Vec<_> = results
+                    .iter()
+                    .filter_map(|result| {
+                        let stats = if download { result.download } else { result.upload };
+                        Some((format!("{} streams", result.streams), stats?.average))
+                    })
+                    .collect();
+                if !rows.is_empty() {
+                    writeln!(out, "\n{direction} throughput by stream count (Mbit/s):").unwrap();
+                    out.push_str(&bars(&rows));
+                }
+            }
+        }
+        Benchmark::Ping { rtts_ms, .. } => out.push_str(&histogram("Round-trip time", rtts_ms)),
+        Benchmark::Discover { discovery_ms, handshake_ms, .. } => {
+            out.push_str(&histogram("Discovery time", discovery_ms));
+            out.push_str(&histogram("Handshake time", handshake_ms));
+        }
+        Benchmark::Connect { cold_ms, warm_ms, .. } => {
+            out.push_str(&histogram("Cold connect time", cold_ms));
+            out.push_str(&histogram("Warm connect time", warm_ms));
+        }
+        Benchmark::Resume { cold_ms, warm_ms, .. } => {
+            out.push_str(&histogram("1-RTT first response", cold_ms));
+            out.push_str(&histogram("0-RTT first response", warm_ms));
+        }
+        Benchmark::Streams { first_byte_ms, .. } => {
+            out.push_str(&histogram("Time to first byte", first_byte_ms));
+        }
+        Benchmark::Gossip { latency_ms, .. } => {
+            out.push_str(&histogram("Delivery latency", latency_ms));
+        }
+        Benchmark::Bufferbloat { idle_ms, loaded_ms, .. } => {
+            out.push_str(&histogram("Idle round-trip time", idle_ms));
+            out.push_str(&histogram("Loaded round-trip time", loaded_ms));
+        }
+        Benchmark::Rpc { latency_ms, .. } => {
+            out.push_str(&histogram("Request latency", latency_ms));
+        }
+        Benchmark::Delay { forward_ms, backward_ms, .. } => {
+            out.push_str(&histogram("Client to server delay", forward_ms));
+            out.push_str(&histogram("Server to client delay", backward_ms));
+        }
+        Benchmark::Soak { intervals, interval_secs, .. } => {
+            for (direction, download) in [("Upload", false), ("Download", true)] {
+                let rows: Vec<_> = intervals
+                    .iter()
+                    .filter_map(|interval| {
+                        let bandwidth = &interval.bandwidth;
+                        let mbps = if download { bandwidth.download } else { bandwidth.upload };
+                        let at = Duration::from_secs(interval.elapsed_secs as u64);
+                        Some((humantime::format_duration(at).to_string(), mbps?))
+                    })
+                    .collect();
+                if !rows.is_empty() {
+                    let every = humantime::format_duration(Duration::from_secs_f64(*interval_secs));
+                    writeln!(out, "\n{direction} throughput every {every} (Mbit/s):").unwrap();
+                    out.push_str(&bars(&rows));
+                }
+            }
+        }
+        Benchmark::Migrate { throughput, rebind_at_secs, .. } => {
+            let rows: Vec<_> = throughput
+                .iter()
+                .map(|sample| (format!("{:.1} s", sample.elapsed_ms / 1000.0), sample.mbps))
+                .collect();
+            if !rows.is_empty() {
+                writeln!(out, "\nThroughput, rebinding after {rebind_at_secs:.1} s (Mbit/s):")
+                    .unwrap();
+                out.push_str(&bars(&rows));
+            }
+        }
+        Benchmark::Bursts { bursts, .. } => {
+            for (direction, download) in [("Upload", false), ("Download", true)] {
+                let rows: Vec<_> = bursts
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, burst)| {
+                        let bandwidth = &burst.bandwidth;
+                        let mbps = if download { bandwidth.download } else { bandwidth.upload };
+                        Some((format!("#{}", i + 1), mbps?))
+                    })
+                    .collect();
+                if !rows.is_empty() {
+                    writeln!(out, "\n{direction} throughput per burst (Mbit/s):").unwrap();
+                    out.push_str(&bars(&rows));
+                }
+            }
+        }
+        Benchmark::Blobs { results, .. } => {
+            for result in results {
+                let rows: Vec<_> = result
+                    .download_mbps
+                    .iter()
+                    .enumerate()
+                    .map(|(i, mbps)| (format!("#{}", i + 1), *mbps))
+                    .collect();
+                if !rows.is_empty() {
+                    let size = format_size(result.size);
+                    writeln!(out, "\nFetch throughput per iteration, {size} (Mbit/s):").unwrap();
+                    out.push_str(&bars(&rows));
+                }
+            }
+        }
+        Benchmark::Timed { .. }
+        | Benchmark::Datagram { .. }
+        | Benchmark::HolePunch { .. }
+        | Benchmark::Messages { .. } => {}
+    }
+    out
+}
+
+/// Draws one labelled horizontal bar per row, scaled to the largest value.
+pub fn bars(rows: &[(String, f64)]) -> String {
+    let max = rows.iter().map(|(_, value)| *value).fold(0.0, f64::max);
+    let label_width = rows.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+    let mut out = String::new();
+    for (label, value) in rows {
+        let bar = bar(if max > 0.0 { value / max } else { 0.0 });
+        writeln!(out, "  {label:>label_width$} {bar:<BAR_WIDTH$} {value:.2}").unwrap();
+    }
+    out
+}
+
+/// Draws a histogram of `values` in milliseconds, headed by `title`. Empty if there are fewer
+/// than two values.
+pub fn histogram(title: &str, values: &[f64]) -> String {
+    if values.len() < 2 {
+        return String::new();
+    }
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let width = (max - min) / BUCKETS as f64;
+    let mut counts = [0usize; BUCKETS];
+    for value in values {
+        let bucket = if width > 0.0 { ((value - min) / width) as usize } else { 0 };
+        counts[bucket.min(BUCKETS - 1)] += 1;
+    }
+    let rows: Vec<_> = counts
+        .iter()
+        .enumerate()
+        .map(|(i, count)| {
+            let from = min + width * i as f64;
+            (format!("{from:>9.3} - {:>9.3} ms", from + width), *count as f64)
+        })
+        .collect();
+
+    let mut out = format!("\n{title} ({} samples):\n", values.len());
+    let max_count = counts.iter().copied().max().unwrap_or(0) as f64;
+    for (label, count) in rows {
+        let bar = bar(count / max_count);
+        writeln!(out, "  {label} {bar:<BAR_WIDTH$} {count}").unwrap();
+    }
+    out
+}
+
+/// A bar of `fraction` of [`BAR_WIDTH`] characters.
+fn bar(fraction: f64) -> String {
+    let eighths = (fraction.clamp(0.0, 1.0) * (BAR_WIDTH * 8) as f64).round() as usize;
+    "█".repeat(eighths / 8) + PARTIAL_BLOCKS[eighths % 8]
+}
@@ -1,113 +1,3828 @@
-//! Very basic example to showcase how to use iroh's APIs.
+//! Benchmarks run by the client against a server of [`crate::server`].
 //!
-//! This example implements a simple protocol that echos any data sent to it in the first stream.
-//!
-//! ## Usage
-//!
-//!     cargo run --bin client -- --public-key <public-key>
-
-use anyhow::Result;
-use iroh::{Endpoint, NodeAddr, PublicKey, endpoint::Connection};
-use tokio::time::{Instant, sleep};
-use hex;
-use std::time::Duration;
-use clap::Parser;
-
-/// Each protocol is identified by its ALPN string.
-///
-/// The ALPN, or application-layer protocol negotiation, is exchanged in the connection handshake,
-/// and the connection is aborted unless both nodes pass the same bytestring.
-const ALPN: &[u8] = b"iroh-example/print/0";
-
-/// CLI arguments
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-struct Args {
-    /// Public key in hex format
-    #[arg(short, long)]
-    public_key: String,
-}
-
-#[tokio::main]
-async fn main() -> Result<()> {
-    let args = Args::parse();
-
-    // Decode the hex string into raw bytes
-    let pk_bytes = hex::decode(&args.public_key)?;
-    let pk_array: [u8; 32] = pk_bytes[..].try_into()
-        .map_err(|_| anyhow::anyhow!("Invalid public key length - expected 32 bytes"))?;
-    
-    // Create public key and node address
-    let public_key = PublicKey::from_bytes(&pk_array)?;
-    let node_addr = NodeAddr::new(public_key);
-    println!("Node Address: {:?}", node_addr);
-
-    connect_side(node_addr).await?;
+//! Every benchmark takes a connected or bindable [`Endpoint`], the server's [`NodeAddr`] and a
+//! [`BenchmarkConfig`], and returns the measured [`Benchmark`]. Progress is reported on stderr, or
+//! to [`BenchmarkConfig::progress`] if one is set, and human-readable results on stdout if
+//! [`BenchmarkConfig::print_results`] is set.
+
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Result};
+use clap::builder::RangedU64ValueParser;
+use iroh::{
+    Endpoint, NodeAddr, NodeId,
+    endpoint::{ConnectOptions, Connection, ConnectionType, OpenBi, RecvStream, SendStream},
+    watchable::Watcher,
+};
+use iroh_blobs::{
+    get::fsm::{ConnectedNext, EndBlobNext},
+    protocol::GetRequest,
+};
+use n0_future::{StreamExt, task::AbortOnDropHandle, try_join_all};
+use tokio::{
+    sync::{Semaphore, mpsc, watch},
+    time::{Instant, MissedTickBehavior, interval_at, sleep, sleep_until, timeout, timeout_at},
+};
+use tracing::{Instrument, debug, debug_span, trace};
+
+use crate::baseline::{quic_connect, unspecified};
+use crate::clock::{ClockSync, ProbeTimes};
+use crate::config::EndpointConfig;
+use crate::gossip::{GossipConfig, run_gossip};
+use crate::outliers::OutlierRule;
+use crate::pacing::Pacer;
+use crate::payload::{Pattern, Payload};
+use crate::progress::{Counters, IntervalSample, IntervalSink, Progress, StreamCounters};
+use crate::protocol::{
+    ACK, ALPN, ClockReply, DATAGRAM_ALPN, DatagramHeader, DatagramSummary, Direction,
+    MAX_CHUNK_SIZE, MAX_RPC_MESSAGE, MAX_STREAMS, Request, Response, ServerReport, Transfer,
+    UNBOUNDED, read_rpc_message, rpc_message, wall_clock_us,
+};
+use crate::report::{
+    Benchmark, BlobResult, BurstResult, ChunkResult, HolePunchTrial, Iteration, PathStability,
+    PathTransition, RttSample, Sample, ServerSide, SizeResult, SoakInterval, Stats,
+    StreamCountResult, ThroughputSample,
+    TransportStats, fairness, jitter, millis, path_label, timestamp, trend,
+};
+use crate::units::{SizeRange, format_size, parse_bandwidth, parse_size};
+
+/// Parameters shared by all benchmarks, doubling as the client's command line options.
+#[derive(clap::Args, Debug, Clone)]
+pub struct BenchmarkConfig {
+    #[command(flatten)]
+    pub endpoint: EndpointConfig,
+
+    /// Direction in which the payload is sent
+    #[arg(short, long, value_enum, default_value_t = Direction::Upload)]
+    pub direction: Direction,
+
+    /// Comma-separated payload sizes to benchmark (e.g. `64K,1M,16M`)
+    #[arg(short, long, value_delimiter = ',', value_parser = parse_size, default_value = "1M,2M,5M,10M")]
+    pub sizes: Vec<u64>,
+
+    /// Number of transfers per payload size
+    #[arg(short, long, default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..))]
+    pub iterations: u32,
+
+    /// Number of transfers per payload size to run before measuring, excluded from statistics
+    #[arg(short, long, default_value_t = 0)]
+    pub warmup: u32,
+
+    /// Number of parallel streams per connection the payload is striped across
+    #[arg(short = 'P', long, default_value_t = 1, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    pub streams: usize,
+
+    /// Number of concurrent connections, each transferring the full payload
+    #[arg(short, long, default_value_t = 1, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    pub connections: usize,
+
+    /// Open the connections once and run all transfers on them, instead of reconnecting for
+    /// every iteration
+    #[arg(long)]
+    pub reuse_connection: bool,
+
+    /// Size of the chunks payloads are written and read in (e.g. `16K`, `1M`)
+    #[arg(long, value_parser = parse_size, default_value = "64K")]
+    pub chunk_size: u64,
+
+    /// Content of the payloads sent by either side
+    #[arg(long, value_enum, default_value_t = Pattern::Zeros)]
+    pub pattern: Pattern,
+
+    /// Seed of the `random` pattern, the same seed always generates the same payload
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+
+    /// Check every payload end to end with BLAKE3 hashes, failing on any mismatch
+    #[arg(long, conflicts_with = "time")]
+    pub verify: bool,
+
+    /// Benchmark the sizes doubling across this range (e.g. `64K..1G`) instead of `--sizes`, to
+    /// find the size from which on the bandwidth levels off
+    #[arg(long, conflicts_with_all = ["sizes", "time", "soak", "traffic"])]
+    pub sweep: Option<SizeRange>,
+
+    /// Benchmark the chunk sizes doubling across this range (e.g. `4K..4M`) with the first of
+    /// `--sizes`, to find the write granularity streams are fastest with
+    #[arg(long, conflicts_with_all = ["chunk_size", "sweep", "time", "soak", "traffic"])]
+    pub chunk_sweep: Option<SizeRange>,
+
+    /// Benchmark the first of `--sizes` on one connection striped across 1, 2, 4 and so on up to
+    /// this many streams, to see how the bandwidth scales and how fairly the streams share it
+    #[arg(long, value_parser = RangedU64ValueParser::<usize>::new().range(1..=MAX_STREAMS as u64), conflicts_with_all = ["streams", "connections", "sweep", "chunk_sweep", "time", "soak", "traffic", "target_duration"])]
+    pub stream_sweep: Option<usize>,
+
+    /// Size every payload so that its transfer takes about this long (e.g. `5s`), starting from
+    /// the first of `--sizes` and recalibrating after every transfer
+    #[arg(long, value_parser = humantime::parse_duration, conflicts_with_all = ["time", "soak", "traffic", "sweep", "chunk_sweep"])]
+    pub target_duration: Option<Duration>,
+
+    /// Stream continuously for this long (e.g. `10s`) instead of sending fixed-size payloads
+    #[arg(short, long, value_parser = humantime::parse_duration)]
+    pub time: Option<Duration>,
+
+    /// Pace the sender of every connection to this bitrate (e.g. `50Mbps`), split evenly across
+    /// its streams, instead of sending at line rate
+    #[arg(long, value_parser = parse_bandwidth)]
+    pub bitrate: Option<f64>,
+
+    /// Send on/off traffic instead of fixed payloads: `burst:<size>x<count>@<interval>` sends
+    /// `count` bursts of `size` per connection, one every interval (e.g. `burst:256Kx20@500ms`)
+    #[arg(long, conflicts_with_all = ["time", "soak"])]
+    pub traffic: Option<Burst>,
+
+    /// Send the payload over reliable streams or as unreliable QUIC datagrams
+    #[arg(long, value_enum, default_value_t = Transport::Stream)]
+    pub transport: Transport,
+
+    /// Rate at which datagrams are sent, in Mbit/s
+    #[arg(long, default_value_t = 10.0)]
+    pub rate: f64,
+
+    /// Size of each datagram in bytes, defaults to the largest the path supports
+    #[arg(long, value_parser = RangedU64ValueParser::<usize>::new().range(DatagramHeader::LEN as u64..))]
+    pub datagram_size: Option<usize>,
+
+    /// Interval between throughput reports during transfers, `0s` disables them
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "1s")]
+    pub interval: Duration,
+
+    /// Run continuous transfers on one connection for this long (e.g. `6h`), reconnecting when it
+    /// is lost and summarizing every `--soak-interval`
+    #[arg(long, value_parser = humantime::parse_duration, conflicts_with_all = ["time", "verify"])]
+    pub soak: Option<Duration>,
+
+    /// Length of the intervals a soak test is summarized in
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "1m")]
+    pub soak_interval: Duration,
+
+    /// Flag iterations whose bandwidth lies far from the others, such as one that ran while the
+    /// connection fell back to the relay, and also report statistics without them
+    #[arg(long)]
+    pub trim_outliers: bool,
+
+    /// Rule deciding which iterations `--trim-outliers` flags
+    #[arg(long, value_enum, default_value_t = OutlierRule::Iqr)]
+    pub outlier_rule: OutlierRule,
+
+    /// Before measuring, ping every connection until it uses a direct path and its RTT estimate
+    /// is stable, waiting at most this long (e.g. `10s`)
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub settle: Option<Duration>,
+
+    /// Before measuring, estimate the offset and drift of the server's clock with a burst of
+    /// timestamp probes, to report when the server started and finished every transfer on the
+    /// client's clock
+    #[arg(long)]
+    pub clock_sync: bool,
+
+    /// Give up on connecting after this long (e.g. `10s`), counting the handshake and `--settle`
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub connect_timeout: Option<Duration>,
+
+    /// Give up on a transfer after this long (e.g. `1m`)
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub transfer_timeout: Option<Duration>,
+
+    /// Retry failed connections and transfers this many times, waiting twice as long before every
+    /// next attempt
+    #[arg(long, default_value_t = 0)]
+    pub retries: u32,
+
+    /// Refuse to benchmark unless the connection uses a direct path, exits with code 3 otherwise
+    #[arg(long, conflicts_with = "relay_only")]
+    pub direct_only: bool,
+
+    /// Print human-readable results to stdout as the benchmark runs
+    #[arg(skip)]
+    pub print_results: bool,
+
+    /// Dashboard to report progress to instead of stderr
+    #[arg(skip)]
+    pub progress: Option<Arc<Progress>>,
+
+    /// Receiver of the throughput of every `--interval`, in addition to stderr or the dashboard
+    #[arg(skip)]
+    pub interval_sink: Option<IntervalSink>,
+
+    /// Turns true once the benchmark is interrupted, e.g. with Ctrl-C, after which benchmarks
+    /// abandon the transfer in flight and return what they measured so far
+    #[arg(skip)]
+    pub interrupt: Option<watch::Receiver<bool>>,
+}
+
+impl Default for BenchmarkConfig {
+    fn default() -> Self {
+        cli_defaults()
+    }
+}
+
+impl BenchmarkConfig {
+    /// Checks the options the command line parser cannot check on its own.
+    pub fn validate(&self) -> Result<()> {
+        anyhow::ensure!(
+            (1..=MAX_CHUNK_SIZE as u64).contains(&self.chunk_size),
+            "The chunk size must be between 1 byte and {}",
+            format_size(MAX_CHUNK_SIZE as u64)
+        );
+        anyhow::ensure!(
+            self.bitrate.is_none_or(|mbps| mbps > 0.0),
+            "The bitrate must be greater than zero"
+        );
+        anyhow::ensure!(
+            self.bitrate.is_none() || self.transport == Transport::Stream,
+            "Datagrams are paced with --rate instead of --bitrate"
+        );
+        anyhow::ensure!(
+            self.traffic.is_none() || self.transport == Transport::Stream,
+            "Bursts are only sent on streams"
+        );
+        anyhow::ensure!(
+            self.target_duration.is_none_or(|target| !target.is_zero()),
+            "The target duration must not be zero"
+        );
+        anyhow::ensure!(
+            [self.connect_timeout, self.transfer_timeout].iter().flatten().all(|t| !t.is_zero()),
+            "The connect and transfer timeouts must not be zero"
+        );
+        Ok(())
+    }
+
+    /// Bitrate the sender of every connection is paced to in bit/s, or 0 for line rate.
+    pub fn paced_bitrate(&self) -> u64 {
+        self.bitrate.map_or(0, |mbps| (mbps * 1e6) as u64)
+    }
+
+    /// Whether the benchmark was interrupted, see [`BenchmarkConfig::interrupt`].
+    pub fn interrupted(&self) -> bool {
+        self.interrupt.as_ref().is_some_and(|interrupt| *interrupt.borrow())
+    }
+
+    /// Completes once the benchmark is interrupted, or never if it cannot be.
+    pub async fn until_interrupted(&self) {
+        if let Some(mut interrupt) = self.interrupt.clone()
+            && interrupt.wait_for(|interrupted| *interrupted).await.is_ok()
+        {
+            return;
+        }
+        std::future::pending().await
+    }
+
+    /// Reports a status message on stderr, or to the dashboard if there is one.
+    pub fn status(&self, line: String) {
+        match &self.progress {
+            Some(progress) => progress.log(line),
+            None => eprintln!("{line}"),
+        }
+    }
+}
+
+/// How the payload of bandwidth benchmarks is sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Transport {
+    /// Reliable, ordered QUIC streams
+    Stream,
+    /// Unreliable QUIC datagrams, sent at `--rate` for `--time` (default 10s)
+    Datagram,
+}
+
+/// On/off traffic of `--traffic burst:<size>x<count>@<interval>`: `count` bursts of `size` bytes
+/// per connection, starting every `interval`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Burst {
+    pub size: u64,
+    pub count: u32,
+    pub interval: Duration,
+}
+
+impl std::str::FromStr for Burst {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let usage = || format!("expected burst:<size>x<count>@<interval>, got `{s}`");
+        let spec = s.strip_prefix("burst:").ok_or_else(usage)?;
+        let (shape, interval) = spec.split_once('@').ok_or_else(usage)?;
+        let (size, count) = shape.rsplit_once(['x', 'X']).ok_or_else(usage)?;
+        let burst = Burst {
+            size: parse_size(size)?,
+            count: count.parse().map_err(|err| format!("invalid burst count `{count}`: {err}"))?,
+            interval: humantime::parse_duration(interval).map_err(|err| err.to_string())?,
+        };
+        if burst.size == 0 || burst.count == 0 || burst.interval.is_zero() {
+            return Err("the burst size, count and interval must not be zero".to_string());
+        }
+        Ok(burst)
+    }
+}
+
+impl std::fmt::Display for Burst {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (size, interval) = (format_size(self.size), humantime::format_duration(self.interval));
+        write!(f, "{} bursts of {size} every {interval}", self.count)
+    }
+}
+
+/// Largest factor `--target-duration` grows the payload by from one transfer to the next, as the
+/// bandwidth of short transfers underestimates that of longer ones.
+const MAX_CALIBRATION_STEP: f64 = 8.0;
+
+/// Share of the best average bandwidth of a `--sweep` that marks where the bandwidth levels off.
+const SWEEP_KNEE: f64 = 0.9;
+
+/// Number of consecutive RTT estimates that must agree before `--settle` considers a path
+/// stable, how far they may spread relative to the smallest, and the pings that produce them.
+const SETTLE_SAMPLES: usize = 5;
+const SETTLE_TOLERANCE: f64 = 0.1;
+const SETTLE_PROBE_INTERVAL: Duration = Duration::from_millis(50);
+const SETTLE_PING_SIZE: usize = 32;
+
+/// Pause before the first retry of `--retries`, doubling for every next one up to
+/// [`MAX_RETRY_BACKOFF`].
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Pause of soak tests before connecting again after connecting failed.
+const SOAK_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// How often soak tests sample the throughput, and over how long before and after a change of
+/// the path they compare it.
+const PATH_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+const PATH_IMPACT_WINDOW: Duration = Duration::from_secs(5);
+
+/// Duration of datagram benchmarks without `--time`.
+const DEFAULT_DATAGRAM_TIME: Duration = Duration::from_secs(10);
+
+/// Benchmarks other than the bandwidth test, selected by the client's subcommand.
+#[derive(clap::Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Measure round-trip latency with small ping-pong messages
+    Ping(PingConfig),
+    /// Measure how long discovery takes to resolve the server's addresses
+    Discover(DiscoverConfig),
+    /// Measure connection establishment latency from fresh and from reused endpoints
+    Connect(ConnectConfig),
+    /// Measure how often and how quickly fresh connections upgrade to a direct path
+    Holepunch(HolePunchConfig),
+    /// Measure the latency of opening streams and getting their first byte back
+    Streams(StreamsConfig),
+    /// Compare the time to the first response of resumed connections with and without 0-RTT
+    Resume(ResumeConfig),
+    /// Fetch blobs of every payload size from the server's blob store with iroh-blobs
+    Blobs,
+    /// Measure message delivery across a small gossip swarm bootstrapped by the server
+    Gossip(GossipConfig),
+    /// Measure how much the round-trip time inflates while the connection carries a bulk
+    /// transfer in `--direction`
+    Bufferbloat(BufferbloatConfig),
+    /// Measure requests per second and latency of small request/response exchanges
+    Rpc(RpcConfig),
+    /// Measure how many small framed messages per second a single stream carries
+    Messages(MessagesConfig),
+    /// Estimate the one-way delay in each direction from timestamped probes
+    Delay(DelayConfig),
+    /// Measure how the throughput dips and whether the connection survives when the client
+    /// moves to a new local socket in the middle of a transfer
+    Migrate(MigrateConfig),
+}
+
+/// Parameters of [`run_migrate`].
+#[derive(clap::Args, Debug, Clone)]
+pub struct MigrateConfig {
+    /// How long to stream in `--direction`
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "10s")]
+    pub duration: Duration,
+
+    /// Time into the transfer to rebind at, halfway through by default
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub at: Option<Duration>,
+
+    /// Length of the windows the throughput is sampled in
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "100ms")]
+    pub sample_interval: Duration,
+
+    /// Migrate a plain QUIC connection to the server's `--quic-baseline` endpoint at this
+    /// address instead of iroh's, moving it to a socket on a new port
+    #[arg(long, value_name = "IP:PORT")]
+    pub quic: Option<SocketAddr>,
+}
+
+impl Default for MigrateConfig {
+    fn default() -> Self {
+        cli_defaults()
+    }
+}
+
+/// Parameters of [`run_delay`].
+#[derive(clap::Args, Debug, Clone)]
+pub struct DelayConfig {
+    /// Number of probes to exchange
+    #[arg(short, long, default_value_t = 100, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    pub count: usize,
+
+    /// Pause between probes, a probe still waiting for its answer delays the next one
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "10ms")]
+    pub interval: Duration,
+
+    /// Trust that the clocks of both hosts are synchronized, e.g. with PTP, instead of
+    /// estimating their offset under the assumption that the fastest probe took as long in
+    /// both directions
+    #[arg(long)]
+    pub synced_clocks: bool,
+}
+
+impl Default for DelayConfig {
+    fn default() -> Self {
+        cli_defaults()
+    }
+}
+
+/// Parameters of [`run_messages`].
+#[derive(clap::Args, Debug, Clone)]
+pub struct MessagesConfig {
+    /// Number of messages to send
+    #[arg(short, long, default_value_t = 1_000_000, value_parser = clap::value_parser!(u64).range(1..))]
+    pub count: u64,
+
+    /// Size of each message body in bytes
+    #[arg(short, long, default_value_t = 128, value_parser = clap::value_parser!(u32).range(1..=MAX_RPC_MESSAGE as i64))]
+    pub size: u32,
+}
+
+impl Default for MessagesConfig {
+    fn default() -> Self {
+        cli_defaults()
+    }
+}
+
+/// Parameters of [`run_rpc`].
+#[derive(clap::Args, Debug, Clone)]
+pub struct RpcConfig {
+    /// Number of requests to send
+    #[arg(short, long, default_value_t = 10000, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    pub count: usize,
+
+    /// Size of each request body in bytes
+    #[arg(long, default_value_t = 64, value_parser = clap::value_parser!(u32).range(..=MAX_RPC_MESSAGE as i64))]
+    pub request_size: u32,
+
+    /// Size of each response body in bytes
+    #[arg(long, default_value_t = 256, value_parser = clap::value_parser!(u32).range(..=MAX_RPC_MESSAGE as i64))]
+    pub response_size: u32,
+
+    /// Number of requests in flight at once, 1 waits for every response before the next request
+    #[arg(long, default_value_t = 1, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    pub depth: usize,
+}
+
+impl Default for RpcConfig {
+    fn default() -> Self {
+        cli_defaults()
+    }
+}
+
+/// Parameters of [`run_bufferbloat`].
+#[derive(clap::Args, Debug, Clone)]
+pub struct BufferbloatConfig {
+    /// How long to probe the idle connection before starting the load
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "3s")]
+    pub idle: Duration,
+
+    /// How long to run the bulk transfer while probing
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "10s")]
+    pub duration: Duration,
+
+    /// Pause between probes, a probe still waiting for its reply delays the next one
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "50ms")]
+    pub probe_interval: Duration,
+
+    /// Size of each probe in bytes
+    #[arg(short, long, default_value_t = 32, value_parser = clap::value_parser!(u64).range(1..))]
+    pub size: u64,
+}
+
+impl Default for BufferbloatConfig {
+    fn default() -> Self {
+        cli_defaults()
+    }
+}
+
+/// Parameters of [`run_resume`].
+#[derive(clap::Args, Debug, Clone)]
+pub struct ResumeConfig {
+    /// Number of connections to measure for each variant
+    #[arg(short, long, default_value_t = 10)]
+    pub trials: usize,
+
+    /// Only measure connections sending the request after the handshake
+    #[arg(long, conflicts_with = "warm")]
+    pub cold: bool,
+
+    /// Only measure connections resuming an earlier session with 0-RTT
+    #[arg(long)]
+    pub warm: bool,
+}
+
+impl Default for ResumeConfig {
+    fn default() -> Self {
+        cli_defaults()
+    }
+}
+
+/// Parameters of [`run_streams`].
+#[derive(clap::Args, Debug, Clone)]
+pub struct StreamsConfig {
+    /// Number of streams to open, one after another
+    #[arg(short, long, default_value_t = 1000)]
+    pub count: usize,
+}
+
+impl Default for StreamsConfig {
+    fn default() -> Self {
+        cli_defaults()
+    }
+}
+
+/// Parameters of [`run_holepunch`].
+#[derive(clap::Args, Debug, Clone)]
+pub struct HolePunchConfig {
+    /// Number of connections to attempt, each from a fresh endpoint
+    #[arg(short, long, default_value_t = 10)]
+    pub trials: usize,
+
+    /// How long to wait for a direct path after the connection is established
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "10s")]
+    pub timeout: Duration,
+}
+
+impl Default for HolePunchConfig {
+    fn default() -> Self {
+        cli_defaults()
+    }
+}
+
+/// Parameters of [`run_connect`].
+#[derive(clap::Args, Debug, Clone)]
+pub struct ConnectConfig {
+    /// Number of connections to measure for each variant
+    #[arg(short, long, default_value_t = 10)]
+    pub trials: usize,
+}
+
+impl Default for ConnectConfig {
+    fn default() -> Self {
+        cli_defaults()
+    }
+}
+
+/// Parameters of [`run_discover`].
+#[derive(clap::Args, Debug, Clone)]
+pub struct DiscoverConfig {
+    /// Number of resolutions to measure, each on a fresh endpoint
+    #[arg(short, long, default_value_t = 10)]
+    pub trials: usize,
+}
+
+impl Default for DiscoverConfig {
+    fn default() -> Self {
+        cli_defaults()
+    }
+}
+
+/// Parameters of [`run_latency`].
+#[derive(clap::Args, Debug, Clone)]
+pub struct PingConfig {
+    /// Number of round trips to measure
+    #[arg(short, long, default_value_t = 100)]
+    pub count: usize,
+
+    /// Size of each ping message in bytes
+    #[arg(short, long, default_value_t = 32, value_parser = clap::value_parser!(u64).range(1..))]
+    pub size: u64,
+}
+
+impl Default for PingConfig {
+    fn default() -> Self {
+        cli_defaults()
+    }
+}
+
+/// Builds a configuration with the defaults of its command line options.
+fn cli_defaults<T: clap::Args + clap::FromArgMatches>() -> T {
+    let command = T::augment_args(clap::Command::new("defaults").no_binary_name(true));
+    let matches = command.get_matches_from(std::iter::empty::<String>());
+    T::from_arg_matches(&matches).expect("the defaults are valid")
+}
+
+/// How often the RTT estimate is sampled during transfers.
+const RTT_SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Number of timestamp probes `--clock-sync` exchanges, and the pause between them. The drift
+/// is only as accurate as the probes are long apart.
+const CLOCK_SYNC_PROBES: usize = 20;
+const CLOCK_SYNC_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Error returned when `--direct-only` is set but the connection does not use a direct path.
+#[derive(Debug)]
+pub struct NotDirect(pub String);
+
+impl std::fmt::Display for NotDirect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Connection is not direct (path: {})", self.0)
+    }
+}
+
+impl std::error::Error for NotDirect {}
 
+/// Error returned when the benchmark was interrupted, see [`BenchmarkConfig::interrupt`], by
+/// benchmarks that cannot report partial results, and by the client once it wrote those of the
+/// others.
+#[derive(Debug)]
+pub struct Interrupted;
+
+impl std::fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Interrupted before the benchmark completed")
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+/// Waits until the connection to `node_id` uses a direct path, for at most `grace`.
+///
+/// Returns whether a direct path was established in time.
+pub async fn wait_for_direct(endpoint: &Endpoint, node_id: NodeId, grace: Duration) -> Result<bool> {
+    let mut watcher = endpoint.conn_type(node_id)?;
+    let wait = async {
+        while !matches!(watcher.get()?, ConnectionType::Direct(_)) {
+            watcher.updated().await?;
+        }
+        anyhow::Ok(())
+    };
+    match timeout(grace, wait).await {
+        Ok(res) => res.map(|()| true),
+        Err(_) => Ok(false),
+    }
+}
+
+/// Fails with [`NotDirect`] if `--direct-only` is set and a benchmark did not run entirely
+/// over a direct path.
+fn ensure_direct(config: &BenchmarkConfig, connection_type: &str) -> Result<()> {
+    if config.direct_only && connection_type != "direct" {
+        return Err(NotDirect(connection_type.to_string()).into());
+    }
     Ok(())
 }
 
-async fn connect_side(addr: NodeAddr) -> Result<()> {
-    let endpoint = Endpoint::builder().discovery_n0().bind().await?;
+/// Runs the benchmark selected by `command`, or the bandwidth test if there is none.
+///
+/// Only the bandwidth tests keep what they measured when interrupted, the benchmarks of the
+/// subcommands fail with [`Interrupted`] right away.
+pub async fn run_command(
+    endpoint: &Endpoint,
+    addr: NodeAddr,
+    config: &BenchmarkConfig,
+    command: Option<&Command>,
+) -> Result<Benchmark> {
+    let Some(command) = command else {
+        return run(endpoint, addr, config).await;
+    };
+    tokio::select! {
+        benchmark = run_subcommand(endpoint, addr, config, command) => benchmark,
+        _ = config.until_interrupted() => Err(Interrupted.into()),
+    }
+}
+
+/// Runs the benchmark of a subcommand.
+async fn run_subcommand(
+    endpoint: &Endpoint,
+    addr: NodeAddr,
+    config: &BenchmarkConfig,
+    command: &Command,
+) -> Result<Benchmark> {
+    match command {
+        Command::Ping(ping) => run_latency(endpoint, addr, config, ping).await,
+        Command::Discover(discover) => run_discover(addr.node_id, config, discover).await,
+        Command::Connect(connect) => run_connect(endpoint, addr, config, connect).await,
+        Command::Holepunch(holepunch) => run_holepunch(addr, config, holepunch).await,
+        Command::Streams(streams) => run_streams(endpoint, addr, config, streams).await,
+        Command::Resume(resume) => run_resume(addr, config, resume).await,
+        Command::Blobs => run_blobs(endpoint, addr, config).await,
+        Command::Gossip(gossip) => run_gossip(endpoint, addr, config, gossip).await,
+        Command::Bufferbloat(bloat) => run_bufferbloat(endpoint, addr, config, bloat).await,
+        Command::Rpc(rpc) => run_rpc(endpoint, addr, config, rpc).await,
+        Command::Messages(messages) => run_messages(endpoint, addr, config, messages).await,
+        Command::Delay(delay) => run_delay(endpoint, addr, config, delay).await,
+        Command::Migrate(migrate) => run_migrate(endpoint, addr, config, migrate).await,
+    }
+}
+
+/// Runs the bandwidth benchmark selected by the transport and duration of `config`.
+pub async fn run(endpoint: &Endpoint, addr: NodeAddr, config: &BenchmarkConfig) -> Result<Benchmark> {
+    if let Some(mbps) = config.bitrate {
+        config.status(format!("Pacing the sender of every connection to {mbps} Mbit/s"));
+    }
+    if let Some(duration) = config.soak {
+        anyhow::ensure!(config.transport == Transport::Stream, "Soak tests only use streams");
+        return run_soak(endpoint, addr, config, duration).await;
+    }
+    if let Some(burst) = config.traffic {
+        anyhow::ensure!(config.transport == Transport::Stream, "Bursts are only sent on streams");
+        return run_bursts(endpoint, addr, config, burst).await;
+    }
+    if let Some(sweep) = config.sweep {
+        anyhow::ensure!(config.transport == Transport::Stream, "Sweeps only use streams");
+        return run_sweep(endpoint, addr, config, sweep).await;
+    }
+    if let Some(chunk_sizes) = config.chunk_sweep {
+        anyhow::ensure!(config.transport == Transport::Stream, "Sweeps only use streams");
+        return run_chunk_sweep(endpoint, addr, config, chunk_sizes).await;
+    }
+    if let Some(max_streams) = config.stream_sweep {
+        anyhow::ensure!(config.transport == Transport::Stream, "Sweeps only use streams");
+        return run_stream_sweep(endpoint, addr, config, max_streams).await;
+    }
+    match (config.transport, config.time) {
+        (Transport::Datagram, time) => {
+            let duration = time.unwrap_or(DEFAULT_DATAGRAM_TIME);
+            run_datagram(endpoint, addr, config, duration).await
+        }
+        (Transport::Stream, Some(duration)) => run_timed(endpoint, addr, config, duration).await,
+        (Transport::Stream, None) => run_bandwidth(endpoint, addr, config).await,
+    }
+}
+
+/// Measures the upload bandwidth for every payload size of `config`, regardless of its
+/// direction.
+pub async fn run_upload(
+    endpoint: &Endpoint,
+    addr: NodeAddr,
+    config: &BenchmarkConfig,
+) -> Result<Benchmark> {
+    let config = BenchmarkConfig { direction: Direction::Upload, ..config.clone() };
+    run_bandwidth(endpoint, addr, &config).await
+}
+
+/// Transfers every payload size of `config` `iterations` times and measures the bandwidth.
+pub async fn run_bandwidth(
+    endpoint: &Endpoint,
+    addr: NodeAddr,
+    config: &BenchmarkConfig,
+) -> Result<Benchmark> {
+    let direction = config.direction;
+    let warmup = config.warmup;
+    let iterations = config.iterations;
+    let mut results = Vec::new();
+    let (mut last_error, mut measured_any) = (None, false);
+    let mut reused = match config.reuse_connection {
+        true => Some(open_connections(endpoint, &addr, config.connections, config).await?),
+        false => None,
+    };
+    let clock = match config.clock_sync {
+        true => Some(sync_clock(endpoint, &addr, config).await?),
+        false => None,
+    };
 
     // Perform multiple measurements with different data sizes
-    let mb = 1024 * 1024;
-    let sizes = vec![1 * mb, 2 * mb, 5 * mb, 10 * mb]; // 1KB, 1MB, 10MB
-    
-    // Actual benchmarks
-    println!("\nStarting benchmarks:");
-    for size in sizes {
-        println!("\nTesting with {} MB:", size / (1024 * 1024));
+    if config.verify {
+        config.status("Verifying payloads with BLAKE3".to_string());
+    }
+    // Aiming for a target duration runs a single series of transfers, starting at the first size
+    let sizes = match config.target_duration {
+        Some(target) => {
+            let target = humantime::format_duration(target);
+            config.status(format!("Sizing every payload to take about {target}"));
+            &config.sizes[..1]
+        }
+        None => &config.sizes[..],
+    };
+    config.status(match reused {
+        Some(_) => format!("\nStarting {direction:?} benchmarks on reused connections:"),
+        None => format!("\nStarting {direction:?} benchmarks:"),
+    });
+    for &size in sizes {
+        config.status(format!("\nTesting with {}:", format_size(size)));
         
-        let iterations = 5;
-        let mut bandwidths = Vec::new();
+        let mut samples = Vec::new();
+        let mut chunk_latencies = Stats::histogram();
+        let mut next_size = size;
         
-        for i in 0..iterations {
-            println!("Iteration {}", i + 1);
-            let conn = endpoint.connect(addr.clone(), ALPN).await?;
-            let bw = benchmark_transfer(&conn, size).await?;
-            bandwidths.push(bw);
-            conn.close(0u32.into(), b"bye!");
-            if i < iterations - 1 {
+        for i in 0..warmup + iterations {
+            let size = next_size;
+            let calibrated = match config.target_duration {
+                Some(_) => format!(" ({})", format_size(size)),
+                None => String::new(),
+            };
+            if i < warmup {
+                config.status(format!("Warmup {}{calibrated}", i + 1));
+            } else {
+                config.status(format!("Iteration {}{calibrated}", i - warmup + 1));
+            }
+            let transfer = Transfer {
+                direction,
+                size,
+                chunk_size: config.chunk_size as u32,
+                streams: config.streams as u32,
+                verify: config.verify,
+                pattern: config.pattern,
+                seed: config.seed,
+                bitrate: config.paced_bitrate(),
+                report: true,
+            };
+            let started_at = SystemTime::now();
+            let mut backoff = Backoff::new(config.retries);
+            let measuring = async {
+                loop {
+                    let (reused, clock) = (&mut reused, clock.as_ref());
+                    let attempt =
+                        measure_iteration(endpoint, &addr, config, reused, clock, transfer, i);
+                    match attempt.await {
+                        Ok(measured) => break Ok(measured),
+                        Err(err) => backoff.retry(config, "Iteration", err).await?,
+                    }
+                }
+            };
+            // An interrupted transfer is abandoned, its connections close as they are dropped
+            let measured = tokio::select! {
+                measured = measuring => measured,
+                _ = config.until_interrupted() => {
+                    config.status("Interrupted, keeping the iterations measured so far".into());
+                    break;
+                }
+            };
+
+            // A failed iteration is recorded without bandwidth instead of ending the benchmark
+            let (iteration, counters) = match measured {
+                Ok(measured) => measured,
+                Err(err) => {
+                    config.status(format!("Iteration failed: {err:#}"));
+                    if i >= warmup {
+                        samples.push(Iteration::failed(timestamp(started_at), &err));
+                    }
+                    last_error = Some(err);
+                    continue;
+                }
+            };
+            ensure_direct(config, &iteration.connection_type)?;
+            if let Some(target) = config.target_duration {
+                let elapsed = iteration.upload_ms.into_iter().chain(iteration.download_ms);
+                let elapsed = elapsed.reduce(f64::max).unwrap_or_default();
+                let elapsed = Duration::from_secs_f64(elapsed / 1000.0);
+                next_size = calibrate(size, elapsed, target).max(config.chunk_size);
+            }
+
+            // Warmup transfers are executed but excluded from the statistics
+            if i >= warmup {
+                counters.merge_chunk_latencies(&mut chunk_latencies);
+                samples.push(iteration);
+                measured_any = true;
+            }
+            if i < warmup + iterations - 1 {
                 sleep(Duration::from_millis(100)).await;
             }
         }
         
-        // Calculate statistics
-        let avg_bw = bandwidths.iter().sum::<f64>() / iterations as f64;
-        let min_bw = bandwidths.iter().fold(f64::INFINITY, |a, &b| a.min(b));
-        let max_bw = bandwidths.iter().fold(f64::NEG_INFINITY, |a, &b| a.max(b));
-        
-        println!("Bandwidth statistics (Mbit/s):");
-        println!("  Average: {:.2}", avg_bw);
-        println!("  Min: {:.2}", min_bw);
-        println!("  Max: {:.2}", max_bw);
+        let upload: Vec<f64> = samples.iter().filter_map(|s| s.aggregate.upload).collect();
+        let download: Vec<f64> = samples.iter().filter_map(|s| s.aggregate.download).collect();
+        let (mut trimmed_upload, mut trimmed_download) = (None, None);
+        if config.trim_outliers {
+            flag_outliers(&mut samples, config.outlier_rule);
+            let kept = samples.iter().filter(|s| !s.outlier);
+            let upload: Vec<f64> = kept.clone().filter_map(|s| s.aggregate.upload).collect();
+            let download: Vec<f64> = kept.filter_map(|s| s.aggregate.download).collect();
+            trimmed_upload = Stats::from_samples(&upload);
+            trimmed_download = Stats::from_samples(&download);
+        }
+        let result = SizeResult {
+            size,
+            upload: Stats::from_samples(&upload),
+            download: Stats::from_samples(&download),
+            iterations: samples,
+            chunk_latency: Stats::from_histogram(&chunk_latencies),
+            trimmed_upload,
+            trimmed_download,
+        };
+        // Sizes an interruption left without a single iteration are not reported
+        if config.interrupted() && result.iterations.is_empty() {
+            break;
+        }
+        if config.print_results {
+            print_size_result(&result, config.connections, config.reuse_connection);
+        }
+        results.push(result);
+        if config.interrupted() {
+            break;
+        }
+    }
+    if let Some(conns) = &reused {
+        close_connections(conns);
+    }
+    // Only a benchmark that measured nothing at all fails as a whole
+    if let Some(err) = last_error.filter(|_| !measured_any) {
+        return Err(err);
     }
 
-    Ok(())
+    Ok(Benchmark::Bandwidth {
+        direction,
+        streams: config.streams,
+        connections: config.connections,
+        reuse_connection: config.reuse_connection,
+        verified: config.verify,
+        pattern: config.pattern,
+        seed: config.seed,
+        trim_outliers: config.trim_outliers.then_some(config.outlier_rule),
+        bitrate_mbps: config.bitrate,
+        target_duration_secs: config.target_duration.map(|target| target.as_secs_f64()),
+        knee_size: None,
+        clock,
+        results,
+    })
 }
 
-async fn benchmark_transfer(conn: &Connection, size: usize) -> Result<f64> {
-    let (mut send, mut recv) = conn.open_bi().await?;
-    
-    // Create data chunk of specified size
-    let data = vec![0u8; size];
-    
-    // Start timing before send
+/// Estimates the offset and drift of the server's clock on a connection of its own, for
+/// `--clock-sync`.
+async fn sync_clock(
+    endpoint: &Endpoint,
+    addr: &NodeAddr,
+    config: &BenchmarkConfig,
+) -> Result<ClockSync> {
+    let conn = connect(endpoint, addr, config).await?;
+    let probes = exchange_clock_probes(&conn, CLOCK_SYNC_PROBES, CLOCK_SYNC_INTERVAL).await?;
+    conn.close(0u32.into(), b"bye!");
+    let clock = ClockSync::estimate(&probes).expect("at least one probe");
+    config.status(format!(
+        "Server clock offset: {:+.3} ms (within {:.3} ms), drift: {:+.1} ppm",
+        clock.offset_us / 1000.0,
+        clock.rtt_us as f64 / 2000.0,
+        clock.drift_ppm
+    ));
+    Ok(clock)
+}
+
+/// Transfers the payload of `transfer` once on every connection at the same time, on the reused
+/// connections if there are any and on fresh ones otherwise, failing after `--transfer-timeout`.
+/// With the server's `clock`, its report is placed on the client's clock.
+async fn measure_iteration(
+    endpoint: &Endpoint,
+    addr: &NodeAddr,
+    config: &BenchmarkConfig,
+    reused: &mut Option<Vec<Connection>>,
+    clock: Option<&ClockSync>,
+    transfer: Transfer,
+    iteration: u32,
+) -> Result<(Iteration, Arc<Counters>)> {
+    let started_at = SystemTime::now();
+    let connect_one = || connect_once(endpoint, addr, ALPN, config);
+    let reconnect = || try_join_all((0..config.connections).map(|_| connect_one()));
+    // A failed attempt may have lost a reused connection, in which case all are replaced
+    if let Some(conns) = reused.as_mut()
+        && conns.iter().any(|conn| conn.close_reason().is_some())
+    {
+        close_connections(conns);
+        *conns = reconnect().await?;
+    }
+    let fresh;
+    let conns = match reused.as_deref() {
+        Some(conns) => conns,
+        None => {
+            fresh = reconnect()
+                .instrument(debug_span!("connect", node_id = %addr.node_id))
+                .await?;
+            &fresh
+        }
+    };
+    let before: Vec<_> =
+        conns.iter().map(|conn| TransportStats::from_stats(&conn.stats())).collect();
+
+    let paths = PathTracker::start(endpoint, addr.node_id)?;
+    let rtts = RttSampler::start(conns);
+    let counters = Arc::new(Counters::default());
+    let reporter = spawn_reporter(counters.clone(), config);
+    let transfer_started_us = wall_clock_us();
+    let transferring =
+        try_join_all(conns.iter().map(|conn| benchmark_transfer(conn, transfer, &counters)))
+            .instrument(debug_span!("transfer", size = transfer.size, iteration = iteration + 1));
+    let timings = within(config.transfer_timeout, "Transfer", transferring).await?;
+    drop(reporter);
+    let rtt_series = rtts.finish();
+    let connection_type = paths.finish()?;
+    debug!(?timings, %connection_type, "Transfer complete");
+
+    // The aggregate transfer is only complete once the slowest connection finished
+    let total = transfer.size as usize * conns.len();
+    let slowest = Timing::slowest(&timings);
+    let rtt = conns.iter().map(|conn| conn.rtt()).sum::<Duration>() / conns.len() as u32;
+    let transport: Vec<_> = conns
+        .iter()
+        .zip(&before)
+        .map(|(conn, before)| TransportStats::from_stats(&conn.stats()).since(before))
+        .collect();
+    let per_connection = if conns.len() > 1 {
+        timings.iter().map(|t| t.to_sample(transfer.size as usize)).collect()
+    } else {
+        Vec::new()
+    };
+    let reports: Vec<_> = timings.iter().filter_map(|t| t.server).collect();
+    let server = (reports.len() == timings.len())
+        .then(|| ServerSide::from_reports(&reports, clock, transfer_started_us));
+    if reused.is_none() {
+        close_connections(conns);
+    }
+    let iteration = Iteration {
+        started_at: timestamp(started_at),
+        bytes: total as u64,
+        upload_ms: slowest.upload.map(millis),
+        download_ms: slowest.download.map(millis),
+        aggregate: slowest.to_sample(total),
+        connection_type,
+        rtt_ms: millis(rtt),
+        per_connection,
+        transport,
+        rtt_series,
+        outlier: false,
+        error: None,
+        server,
+    };
+    Ok((iteration, counters))
+}
+
+/// Scales a payload of `size` bytes whose transfer took `elapsed`, so that the next transfer
+/// takes about `target`.
+fn calibrate(size: u64, elapsed: Duration, target: Duration) -> u64 {
+    let factor = (target.as_secs_f64() / elapsed.as_secs_f64()).min(MAX_CALIBRATION_STEP);
+    (size as f64 * factor) as u64
+}
+
+/// Runs the bandwidth benchmark over the sizes doubling across `sweep`, and finds the smallest
+/// size whose bandwidth comes close to the best, the point from which on connection setup and
+/// slow start no longer dominate the transfer.
+pub async fn run_sweep(
+    endpoint: &Endpoint,
+    addr: NodeAddr,
+    config: &BenchmarkConfig,
+    sweep: SizeRange,
+) -> Result<Benchmark> {
+    let sizes = sweep.doublings();
+    config.status(format!(
+        "Sweeping {} sizes from {} to {}",
+        sizes.len(),
+        format_size(sweep.start),
+        format_size(sweep.end)
+    ));
+    let config = BenchmarkConfig { sizes, ..config.clone() };
+    let mut benchmark = run_bandwidth(endpoint, addr, &config).await?;
+    if let Benchmark::Bandwidth { results, knee_size, .. } = &mut benchmark {
+        *knee_size = sweep_knee(results);
+        if let Some(size) = knee_size.filter(|_| config.print_results) {
+            println!(
+                "\nBandwidth levels off from {} on, reaching {:.0}% of the best size's",
+                format_size(size),
+                SWEEP_KNEE * 100.0
+            );
+        }
+    }
+    Ok(benchmark)
+}
+
+/// Smallest payload size whose average bandwidth reaches [`SWEEP_KNEE`] of the best size's,
+/// comparing the slower direction of every size.
+fn sweep_knee(results: &[SizeResult]) -> Option<u64> {
+    let slower = |result: &SizeResult| slower_average(result.upload, result.download);
+    let best = results.iter().filter_map(slower).reduce(f64::max)?;
+    let knee = results
+        .iter()
+        .find(|result| slower(result).is_some_and(|mbps| mbps >= SWEEP_KNEE * best));
+    knee.map(|result| result.size)
+}
+
+/// Average bandwidth of the slower of the directions that were measured.
+fn slower_average(upload: Option<Stats>, download: Option<Stats>) -> Option<f64> {
+    let averages = [upload, download].into_iter().flatten().map(|stats| stats.average);
+    averages.reduce(f64::min)
+}
+
+/// Transfers the first of `--sizes` `iterations` times with every chunk size doubling across
+/// `chunk_sizes`, and finds the chunk size with the best bandwidth.
+///
+/// The chunk size is how much the sender writes and the receiver reads at once, on both sides.
+pub async fn run_chunk_sweep(
+    endpoint: &Endpoint,
+    addr: NodeAddr,
+    config: &BenchmarkConfig,
+    chunk_sizes: SizeRange,
+) -> Result<Benchmark> {
+    anyhow::ensure!(
+        chunk_sizes.end <= MAX_CHUNK_SIZE as u64,
+        "The chunk size must be at most {}",
+        format_size(MAX_CHUNK_SIZE as u64)
+    );
+    let size = config.sizes[0];
+    let mut results = Vec::new();
+    for chunk_size in chunk_sizes.doublings() {
+        config.status(format!("\nTesting chunks of {}:", format_size(chunk_size)));
+        let config = BenchmarkConfig {
+            chunk_size,
+            sizes: vec![size],
+            print_results: false,
+            ..config.clone()
+        };
+        let Benchmark::Bandwidth { results: mut sizes, .. } =
+            run_bandwidth(endpoint, addr.clone(), &config).await?
+        else {
+            unreachable!("run_bandwidth returns bandwidth results");
+        };
+        let Some(result) = sizes.pop() else {
+            anyhow::ensure!(config.interrupted(), "No results");
+            break;
+        };
+        results.push(ChunkResult {
+            chunk_size,
+            iterations: result.iterations.iter().map(|it| it.aggregate).collect(),
+            upload: result.upload,
+            download: result.download,
+        });
+        if config.interrupted() {
+            break;
+        }
+    }
+    let best = results
+        .iter()
+        .filter_map(|result| {
+            let mbps = slower_average(result.upload, result.download)?;
+            Some((result.chunk_size, mbps))
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(chunk_size, _)| chunk_size);
+
+    if config.print_results {
+        println!("\nAverage bandwidth of {} payloads by chunk size:", format_size(size));
+        for result in &results {
+            let average = Sample {
+                upload: result.upload.map(|stats| stats.average),
+                download: result.download.map(|stats| stats.average),
+            };
+            println!("  {:>10}: {}", format_size(result.chunk_size), average.describe());
+        }
+        if let Some(chunk_size) = best {
+            println!("Best chunk size: {}", format_size(chunk_size));
+        }
+    }
+    Ok(Benchmark::ChunkSweep {
+        direction: config.direction,
+        size,
+        streams: config.streams,
+        connections: config.connections,
+        results,
+        best_chunk_size: best,
+    })
+}
+
+/// Transfers the first of `--sizes` `iterations` times on one connection for every number of
+/// streams doubling up to `max_streams`, measuring the bandwidth of every stream besides the
+/// total.
+pub async fn run_stream_sweep(
+    endpoint: &Endpoint,
+    addr: NodeAddr,
+    config: &BenchmarkConfig,
+    max_streams: usize,
+) -> Result<Benchmark> {
+    let (direction, size, warmup) = (config.direction, config.sizes[0], config.warmup);
+    let counts: Vec<usize> = std::iter::successors(Some(1), |n| Some(n * 2))
+        .take_while(|n| *n < max_streams)
+        .chain([max_streams])
+        .collect();
+    let conn = connect(endpoint, &addr, config).await?;
+    let paths = PathTracker::start(endpoint, addr.node_id)?;
+    config.status(format!(
+        "\nStarting {direction:?} benchmarks of {} on up to {max_streams} streams:",
+        format_size(size)
+    ));
+    let mut results = Vec::with_capacity(counts.len());
+    for streams in counts {
+        config.status(format!("\nTesting with {streams} streams:"));
+        let transfer = Transfer {
+            direction,
+            size,
+            chunk_size: config.chunk_size as u32,
+            streams: streams as u32,
+            verify: config.verify,
+            pattern: config.pattern,
+            seed: config.seed,
+            bitrate: config.paced_bitrate(),
+            report: false,
+        };
+        let parts: Vec<usize> = stripe(size as usize, streams).collect();
+        let mut iterations = Vec::new();
+        let (mut per_stream_upload, mut per_stream_download) = (Vec::new(), Vec::new());
+        let (mut upload_fairness, mut download_fairness) = (Vec::new(), Vec::new());
+        let mut errors = Vec::new();
+        for i in 0..warmup + config.iterations {
+            if i < warmup {
+                config.status(format!("Warmup {}", i + 1));
+            } else {
+                config.status(format!("Iteration {}", i - warmup + 1));
+            }
+            let counters = Arc::new(Counters::default());
+            let reporter = spawn_reporter(counters.clone(), config);
+            let transferring = benchmark_streams(&conn, transfer, &counters)
+                .instrument(debug_span!("transfer", size, streams, iteration = i + 1));
+            let transferring = within(config.transfer_timeout, "Transfer", transferring);
+            let timings = tokio::select! {
+                timings = transferring => timings,
+                _ = config.until_interrupted() => break,
+            };
+            drop(reporter);
+            // A failed iteration is recorded instead of ending the sweep
+            let timings = match timings {
+                Ok(timings) => timings,
+                Err(err) => {
+                    config.status(format!("Iteration failed: {err:#}"));
+                    if i >= warmup {
+                        errors.push(format!("{err:#}"));
+                    }
+                    continue;
+                }
+            };
+            if i >= warmup {
+                let samples: Vec<Sample> =
+                    timings.iter().zip(&parts).map(|(t, part)| t.to_sample(*part)).collect();
+                let upload: Vec<f64> = samples.iter().filter_map(|s| s.upload).collect();
+                let download: Vec<f64> = samples.iter().filter_map(|s| s.download).collect();
+                upload_fairness.extend(fairness(&upload));
+                download_fairness.extend(fairness(&download));
+                per_stream_upload.extend(upload);
+                per_stream_download.extend(download);
+                iterations.push(Timing::slowest(&timings).to_sample(size as usize));
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+        let mean = |values: &[f64]| {
+            (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+        };
+        if iterations.is_empty() && errors.is_empty() && config.interrupted() {
+            break;
+        }
+        let upload: Vec<f64> = iterations.iter().filter_map(|s| s.upload).collect();
+        let download: Vec<f64> = iterations.iter().filter_map(|s| s.download).collect();
+        results.push(StreamCountResult {
+            streams,
+            upload: Stats::from_samples(&upload),
+            download: Stats::from_samples(&download),
+            iterations,
+            per_stream_upload: Stats::from_samples(&per_stream_upload),
+            per_stream_download: Stats::from_samples(&per_stream_download),
+            upload_fairness: mean(&upload_fairness),
+            download_fairness: mean(&download_fairness),
+            errors,
+        });
+        if config.interrupted() {
+            break;
+        }
+    }
+    let connection_type = paths.finish()?;
+    ensure_direct(config, &connection_type)?;
+    conn.close(0u32.into(), b"bye!");
+
+    if config.print_results {
+        println!("Connection type: {connection_type}");
+        println!("\nAverage bandwidth of {} payloads by stream count:", format_size(size));
+        for result in &results {
+            let average = Sample {
+                upload: result.upload.map(|stats| stats.average),
+                download: result.download.map(|stats| stats.average),
+            };
+            println!("  {:>3} streams: {}", result.streams, average.describe());
+            let directions = [
+                ("upload", result.per_stream_upload, result.upload_fairness),
+                ("download", result.per_stream_download, result.download_fairness),
+            ];
+            for (direction, per_stream, fairness) in directions {
+                let (Some(stats), Some(fairness)) = (per_stream, fairness) else {
+                    continue;
+                };
+                let (average, min) = (stats.average, stats.min);
+                println!(
+                    "      {direction} per stream: {average:.2} Mbit/s (min {min:.2}), \
+                     fairness {fairness:.3}"
+                );
+            }
+        }
+    }
+    Ok(Benchmark::StreamSweep { direction, size, connection_type, results })
+}
+
+/// Sends on/off traffic on the same connections: at the start of every interval, each connection
+/// transfers a burst in `--direction` as fast as it can, then idles until the next one.
+///
+/// Besides how long every burst took, records the congestion window and RTT it started with, to
+/// show how the congestion controller treats a connection that went idle.
+pub async fn run_bursts(
+    endpoint: &Endpoint,
+    addr: NodeAddr,
+    config: &BenchmarkConfig,
+    burst: Burst,
+) -> Result<Benchmark> {
+    let direction = config.direction;
+    let conns = open_connections(endpoint, &addr, config.connections, config).await?;
+    config.status(format!("\nStarting {direction:?} traffic of {burst}:"));
+    let paths = PathTracker::start(endpoint, addr.node_id)?;
+    let transfer = Transfer {
+        direction,
+        size: burst.size,
+        chunk_size: config.chunk_size as u32,
+        streams: config.streams as u32,
+        verify: config.verify,
+        pattern: config.pattern,
+        seed: config.seed,
+        bitrate: config.paced_bitrate(),
+        report: false,
+    };
+    let total = burst.size as usize * conns.len();
     let t0 = Instant::now();
-    
-    // Send data
-    send.write_all(&data).await?;
-    send.finish()?;
-    
-    // Wait for small acknowledgment from server
-    let ack = recv.read_to_end(8).await?; // Read up to 8 bytes for ack
-    assert_eq!(&ack, b"received", "Invalid acknowledgment from server");
-    
-    let total_time = t0.elapsed();
-    
-    // Calculate bandwidth (only counting the sent data, not the tiny ack)
-    let bandwidth = (size as f64 / total_time.as_secs_f64()) * 8.0 / 1_000_000.0; // Convert to Mbit/s
-    
-    Ok(bandwidth)
-}
\ No newline at end of file
+    let mut ticks = interval_at(t0, burst.interval);
+    // A burst that outlasts the interval delays the next one instead of being followed at once
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut bursts = Vec::with_capacity(burst.count as usize);
+    for i in 0..burst.count {
+        let started = tokio::select! {
+            started = ticks.tick() => started,
+            _ = config.until_interrupted() => break,
+        };
+        let before: Vec<_> =
+            conns.iter().map(|conn| TransportStats::from_stats(&conn.stats())).collect();
+        let counters = Counters::default();
+        let transferring =
+            try_join_all(conns.iter().map(|conn| benchmark_transfer(conn, transfer, &counters)))
+                .instrument(debug_span!("burst", burst = i + 1));
+        let transferring = within(config.transfer_timeout, "Burst", transferring);
+        // An interrupted burst is abandoned, the completed ones are kept
+        let timings = tokio::select! {
+            timings = transferring => timings,
+            _ = config.until_interrupted() => {
+                config.status("Interrupted, keeping the bursts completed so far".into());
+                break;
+            }
+        };
+        // A failed burst is recorded without bandwidth instead of ending the benchmark
+        let (timings, error) = match timings {
+            Ok(timings) => (timings, None),
+            Err(err) => (Vec::new(), Some(format!("{err:#}"))),
+        };
+        let slowest = Timing::slowest(&timings);
+        let during: Vec<_> = conns
+            .iter()
+            .zip(&before)
+            .map(|(conn, before)| TransportStats::from_stats(&conn.stats()).since(before))
+            .collect();
+        let (before, during) = (TransportStats::total(&before), TransportStats::total(&during));
+        let result = BurstResult {
+            offset_ms: millis(started - t0),
+            upload_ms: slowest.upload.map(millis),
+            download_ms: slowest.download.map(millis),
+            bandwidth: slowest.to_sample(total),
+            cwnd: before.cwnd,
+            rtt_ms: before.rtt_ms,
+            congestion_events: during.congestion_events,
+            lost_packets: during.lost_packets,
+            error,
+        };
+        config.status(match &result.error {
+            Some(err) => format!("Burst {} failed: {err}", i + 1),
+            None => format!(
+                "Burst {}: {}, starting with cwnd {}",
+                i + 1,
+                result.bandwidth.describe(),
+                format_size(result.cwnd)
+            ),
+        });
+        bursts.push(result);
+    }
+    let connection_type = paths.finish()?;
+    ensure_direct(config, &connection_type)?;
+    close_connections(&conns);
+
+    let upload: Vec<f64> = bursts.iter().filter_map(|b| b.bandwidth.upload).collect();
+    let download: Vec<f64> = bursts.iter().filter_map(|b| b.bandwidth.download).collect();
+    let (upload, download) = (Stats::from_samples(&upload), Stats::from_samples(&download));
+    if config.print_results {
+        println!("Connection type: {connection_type}");
+        print_stats("Burst upload", upload);
+        print_stats("Burst download", download);
+    }
+    Ok(Benchmark::Bursts {
+        direction,
+        size: burst.size,
+        count: burst.count,
+        interval_ms: millis(burst.interval),
+        streams: config.streams,
+        connections: config.connections,
+        bitrate_mbps: config.bitrate,
+        connection_type,
+        bursts,
+        upload,
+        download,
+    })
+}
+
+/// Flags the iterations whose bandwidth in either direction is an outlier according to `rule`.
+fn flag_outliers(iterations: &mut [Iteration], rule: OutlierRule) {
+    let directions: [fn(&Sample) -> Option<f64>; 2] = [|s| s.upload, |s| s.download];
+    for bandwidth in directions {
+        let (indices, samples): (Vec<usize>, Vec<f64>) = iterations
+            .iter()
+            .enumerate()
+            .filter_map(|(i, it)| Some((i, bandwidth(&it.aggregate)?)))
+            .unzip();
+        for (i, outlier) in indices.into_iter().zip(rule.outliers(&samples)) {
+            iterations[i].outlier |= outlier;
+        }
+    }
+}
+
+/// Opens `n` connections to the server concurrently, see [`connect`].
+async fn open_connections(
+    endpoint: &Endpoint,
+    addr: &NodeAddr,
+    n: usize,
+    config: &BenchmarkConfig,
+) -> Result<Vec<Connection>> {
+    try_join_all((0..n).map(|_| connect(endpoint, addr, config)))
+        .instrument(debug_span!("connect", node_id = %addr.node_id, connections = n))
+        .await
+}
+
+/// Connects to the server, see [`connect_once`], retrying up to `--retries` times.
+async fn connect(
+    endpoint: &Endpoint,
+    addr: &NodeAddr,
+    config: &BenchmarkConfig,
+) -> Result<Connection> {
+    let mut backoff = Backoff::new(config.retries);
+    loop {
+        match connect_once(endpoint, addr, ALPN, config).await {
+            Ok(conn) => return Ok(conn),
+            Err(err) => backoff.retry(config, "Connecting", err).await?,
+        }
+    }
+}
+
+/// Connects to the server on `alpn`, and with `--settle` waits for the path to settle before
+/// returning, failing after `--connect-timeout`.
+///
+/// Only connections on [`ALPN`] settle, as the server answers the pings of [`settle`] only there.
+async fn connect_once(
+    endpoint: &Endpoint,
+    addr: &NodeAddr,
+    alpn: &[u8],
+    config: &BenchmarkConfig,
+) -> Result<Connection> {
+    let connecting = async {
+        let conn = endpoint.connect(addr.clone(), alpn).await?;
+        if let Some(limit) = config.settle.filter(|_| alpn == ALPN) {
+            let settling = settle(endpoint, &conn, addr.node_id, limit, config);
+            settling.instrument(debug_span!("settle")).await?;
+        }
+        Ok(conn)
+    };
+    within(config.connect_timeout, "Connecting", connecting).await
+}
+
+/// Pauses between the attempts of `--retries`, [`RETRY_BACKOFF`] before the first retry and
+/// twice as long before every next one.
+struct Backoff {
+    retries: u32,
+    retry: u32,
+    delay: Duration,
+}
+
+impl Backoff {
+    fn new(retries: u32) -> Self {
+        Self { retries, retry: 0, delay: RETRY_BACKOFF }
+    }
+
+    /// Waits before the next attempt after one failed with `err`, or returns `err` if there are
+    /// no retries left.
+    async fn retry(
+        &mut self,
+        config: &BenchmarkConfig,
+        what: &str,
+        err: anyhow::Error,
+    ) -> Result<()> {
+        if self.retry == self.retries {
+            return Err(err);
+        }
+        self.retry += 1;
+        let (retry, retries, delay) = (self.retry, self.retries, self.delay);
+        config.status(format!("{what} failed, retry {retry}/{retries} in {delay:.2?}: {err:#}"));
+        sleep(delay).await;
+        self.delay = (delay * 2).min(MAX_RETRY_BACKOFF);
+        Ok(())
+    }
+}
+
+/// Awaits `fut`, failing if it takes longer than `limit`, if there is one.
+async fn within<T>(
+    limit: Option<Duration>,
+    what: &str,
+    fut: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let Some(limit) = limit else {
+        return fut.await;
+    };
+    match timeout(limit, fut).await {
+        Ok(res) => res,
+        Err(_) => anyhow::bail!("{what} timed out after {}", humantime::format_duration(limit)),
+    }
+}
+
+/// Pings the server on `conn` until it uses a direct path, unless `--relay-only` is set, and the
+/// last [`SETTLE_SAMPLES`] RTT estimates lie within [`SETTLE_TOLERANCE`] of each other.
+///
+/// Gives up after `limit` and lets the benchmark measure the unsettled path.
+async fn settle(
+    endpoint: &Endpoint,
+    conn: &Connection,
+    node_id: NodeId,
+    limit: Duration,
+    config: &BenchmarkConfig,
+) -> Result<()> {
+    let t0 = Instant::now();
+    let (mut send, mut recv) = conn.open_bi().await?;
+    Request::Ping { size: SETTLE_PING_SIZE as u64 }.write(&mut send).await?;
+    Response::read(&mut recv).await?;
+    let watcher = endpoint.conn_type(node_id)?;
+    let mut ping = [0u8; SETTLE_PING_SIZE];
+    let mut rtts = std::collections::VecDeque::with_capacity(SETTLE_SAMPLES);
+    let probe = async {
+        loop {
+            send.write_all(&ping).await?;
+            recv.read_exact(&mut ping).await?;
+            if rtts.len() == SETTLE_SAMPLES {
+                rtts.pop_front();
+            }
+            rtts.push_back(conn.rtt());
+            let direct = config.endpoint.relay_only
+                || matches!(watcher.get()?, ConnectionType::Direct(_));
+            let (min, max) = (rtts.iter().min(), rtts.iter().max());
+            let stable = rtts.len() == SETTLE_SAMPLES
+                && min.zip(max).is_some_and(|(min, max)| {
+                    (*max - *min).as_secs_f64() <= SETTLE_TOLERANCE * min.as_secs_f64()
+                });
+            if direct && stable {
+                return anyhow::Ok(());
+            }
+            sleep(SETTLE_PROBE_INTERVAL).await;
+        }
+    };
+    match timeout(limit, probe).await {
+        Ok(res) => {
+            res?;
+            let rtt = millis(conn.rtt());
+            config.status(format!("Path settled after {:.2?}, RTT {rtt:.2} ms", t0.elapsed()));
+        }
+        Err(_) => {
+            let limit = humantime::format_duration(limit);
+            config.status(format!("Path did not settle within {limit}, measuring anyway"));
+        }
+    }
+    send.finish()?;
+    Ok(())
+}
+
+fn close_connections(conns: &[Connection]) {
+    let _span = debug_span!("close", connections = conns.len()).entered();
+    for conn in conns {
+        conn.close(0u32.into(), b"bye!");
+    }
+}
+
+/// Prints the statistics of one payload size in human-readable form.
+fn print_size_result(result: &SizeResult, connections: usize, reused: bool) {
+    let failed = result.iterations.iter().filter(|i| i.error.is_some()).count();
+    if failed == result.iterations.len() {
+        println!("All {failed} iterations failed");
+        return;
+    }
+    // Count how many iterations ran over each path, in order of first appearance
+    let mut paths: Vec<(&str, usize)> = Vec::new();
+    let measured = result.iterations.iter().filter(|i| i.error.is_none());
+    for iteration in measured.clone() {
+        match paths.iter_mut().find(|(path, _)| *path == iteration.connection_type) {
+            Some((_, count)) => *count += 1,
+            None => paths.push((&iteration.connection_type, 1)),
+        }
+    }
+    let paths: Vec<String> = paths.iter().map(|(path, n)| format!("{path} x{n}")).collect();
+    match reused {
+        true => println!("Connection type: {} (reused connection)", paths.join(", ")),
+        false => println!("Connection type: {}", paths.join(", ")),
+    }
+    let transport: Vec<_> = measured.clone().map(|i| TransportStats::total(&i.transport)).collect();
+    print_transport_stats(
+        "Transport statistics (per iteration)",
+        &TransportStats::average(&transport),
+    );
+    let server: Vec<_> = measured.filter_map(|i| i.server.as_ref()).collect();
+    if !server.is_empty() {
+        let transport: Vec<_> =
+            server.iter().map(|s| TransportStats::total(&s.transport)).collect();
+        print_transport_stats(
+            "Server transport statistics (per iteration)",
+            &TransportStats::average(&transport),
+        );
+    }
+    if let Some(chunk_latency) = &result.chunk_latency {
+        print_latency_stats("Chunk latency", chunk_latency);
+    }
+    if failed > 0 {
+        println!("Failed iterations: {failed} of {}", result.iterations.len());
+    }
+
+    if connections == 1 {
+        print_stats("Upload", result.upload);
+        print_stats("Download", result.download);
+    } else {
+        let per_connection = |f: fn(&Sample) -> Option<f64>| {
+            let samples: Vec<f64> = result
+                .iterations
+                .iter()
+                .flat_map(|i| i.per_connection.iter().filter_map(f))
+                .collect();
+            Stats::from_samples(&samples)
+        };
+        print_stats("Aggregate upload", result.upload);
+        print_stats("Per-connection upload", per_connection(|s| s.upload));
+        print_stats("Aggregate download", result.download);
+        print_stats("Per-connection download", per_connection(|s| s.download));
+    }
+    // The server received the upload and sent the download, so its view complements ours
+    let received: Vec<f64> = server.iter().filter_map(|s| s.bandwidth.upload).collect();
+    print_stats("Server-side upload", Stats::from_samples(&received));
+    let sent: Vec<f64> = server.iter().filter_map(|s| s.bandwidth.download).collect();
+    print_stats("Server-side download", Stats::from_samples(&sent));
+    let started: Vec<f64> = server.iter().filter_map(|s| s.started_ms).collect();
+    let finished: Vec<f64> = server.iter().filter_map(|s| s.finished_ms).collect();
+    if let (Some(started), Some(finished)) =
+        (Stats::from_samples(&started), Stats::from_samples(&finished))
+    {
+        println!(
+            "Server started serving after {:.3} ms and finished after {:.3} ms on average",
+            started.average, finished.average
+        );
+    }
+    print_two_sided(result);
+
+    if result.trimmed_upload.is_none() && result.trimmed_download.is_none() {
+        return;
+    }
+    let outliers: Vec<String> = result
+        .iterations
+        .iter()
+        .enumerate()
+        .filter(|(_, it)| it.outlier)
+        .map(|(i, it)| format!("#{} ({})", i + 1, it.connection_type))
+        .collect();
+    match outliers.is_empty() {
+        true => println!("Outliers: none"),
+        false => println!("Outliers: {}", outliers.join(", ")),
+    }
+    print_stats("Upload (outliers trimmed)", result.trimmed_upload);
+    print_stats("Download (outliers trimmed)", result.trimmed_download);
+}
+
+/// Prints every iteration of a size as the sender and the receiver saw it, with where their views
+/// contradict each other.
+fn print_two_sided(result: &SizeResult) {
+    let views: Vec<_> = (result.iterations.iter().enumerate())
+        .flat_map(|(i, iteration)| iteration.two_sided().into_iter().map(move |view| (i, view)))
+        .collect();
+    if views.is_empty() {
+        return;
+    }
+    println!("Sender and receiver per iteration (client / server):");
+    for (i, view) in views {
+        println!(
+            "  #{} {}: sent at {:.2} Mbit/s, received at {:.2} Mbit/s, lost packets {} / {}, \
+             RTT {:.3} / {:.3} ms",
+            i + 1,
+            view.direction,
+            view.sender_mbps,
+            view.receiver_mbps,
+            view.client_lost_packets,
+            view.server_lost_packets,
+            view.client_rtt_ms,
+            view.server_rtt_ms,
+        );
+        for discrepancy in &view.discrepancies {
+            println!("    Discrepancy: {discrepancy}");
+        }
+    }
+}
+
+/// Streams data for `duration` on every connection and measures the sustained bandwidth.
+pub async fn run_timed(
+    endpoint: &Endpoint,
+    addr: NodeAddr,
+    config: &BenchmarkConfig,
+    duration: Duration,
+) -> Result<Benchmark> {
+    let direction = config.direction;
+    let conns = open_connections(endpoint, &addr, config.connections, config).await?;
+
+    let duration_label = humantime::format_duration(duration);
+    config.status(format!("\nStarting {direction:?} benchmark for {duration_label}:"));
+    let paths = PathTracker::start(endpoint, addr.node_id)?;
+    let rtts = RttSampler::start(&conns);
+    let counters = Arc::new(Counters::default());
+    let reporter = spawn_reporter(counters.clone(), config);
+    let transfer = Transfer {
+        direction,
+        size: UNBOUNDED,
+        chunk_size: config.chunk_size as u32,
+        streams: config.streams as u32,
+        verify: false,
+        pattern: config.pattern,
+        seed: config.seed,
+        bitrate: config.paced_bitrate(),
+        report: false,
+    };
+    let per_connection = try_join_all(
+        conns.iter().map(|conn| benchmark_timed(conn, duration, transfer, &counters)),
+    )
+    .instrument(debug_span!("transfer", duration = %duration_label))
+    .await?;
+    drop(reporter);
+    let rtt_series = rtts.finish();
+    let connection_type = paths.finish()?;
+    debug!(?per_connection, %connection_type, "Transfer complete");
+    ensure_direct(config, &connection_type)?;
+    let transport: Vec<_> =
+        conns.iter().map(|conn| TransportStats::from_stats(&conn.stats())).collect();
+    let mut chunk_latencies = Stats::histogram();
+    counters.merge_chunk_latencies(&mut chunk_latencies);
+    let chunk_latency = Stats::from_histogram(&chunk_latencies);
+    close_connections(&conns);
+
+    // All connections share the time window, so their bandwidths add up
+    let upload: Vec<f64> = per_connection.iter().filter_map(|s| s.upload).collect();
+    let download: Vec<f64> = per_connection.iter().filter_map(|s| s.download).collect();
+    let total = Sample {
+        upload: (!upload.is_empty()).then(|| upload.iter().sum()),
+        download: (!download.is_empty()).then(|| download.iter().sum()),
+    };
+
+    if config.print_results {
+        println!("Connection type: {connection_type}");
+        println!("Sustained bandwidth (Mbit/s):");
+        if let Some(upload) = total.upload {
+            println!("  Upload: {:.2}", upload);
+        }
+        if let Some(download) = total.download {
+            println!("  Download: {:.2}", download);
+        }
+        if config.connections > 1 {
+            print_stats("Per-connection upload", Stats::from_samples(&upload));
+            print_stats("Per-connection download", Stats::from_samples(&download));
+        }
+        print_transport_stats("Transport statistics", &TransportStats::total(&transport));
+        if let Some(chunk_latency) = &chunk_latency {
+            print_latency_stats("Chunk latency", chunk_latency);
+        }
+    }
+
+    Ok(Benchmark::Timed {
+        direction,
+        streams: config.streams,
+        connections: config.connections,
+        duration_secs: duration.as_secs_f64(),
+        pattern: config.pattern,
+        seed: config.seed,
+        bitrate_mbps: config.bitrate,
+        connection_type,
+        total,
+        per_connection,
+        transport,
+        rtt_series,
+        chunk_latency,
+    })
+}
+
+/// Streams data on one connection for `duration`, summarizing every `--soak-interval`, and
+/// tracks how the bandwidth and RTT drift, how often the connection is lost and how often its
+/// path changes.
+pub async fn run_soak(
+    endpoint: &Endpoint,
+    addr: NodeAddr,
+    config: &BenchmarkConfig,
+    duration: Duration,
+) -> Result<Benchmark> {
+    anyhow::ensure!(!config.soak_interval.is_zero(), "The soak interval must not be zero");
+    let direction = config.direction;
+    let transfer = Transfer {
+        direction,
+        size: UNBOUNDED,
+        chunk_size: config.chunk_size as u32,
+        streams: config.streams as u32,
+        verify: false,
+        pattern: config.pattern,
+        seed: config.seed,
+        bitrate: config.paced_bitrate(),
+        report: false,
+    };
+    config.status(format!(
+        "\nStarting {direction:?} soak test for {}, summarizing every {}:",
+        humantime::format_duration(duration),
+        humantime::format_duration(config.soak_interval)
+    ));
+
+    let start = Instant::now();
+    let deadline = start + duration;
+    let throughput = Arc::new(Mutex::new(Vec::new()));
+    let mut path_log: Option<PathLog> = None;
+    let mut conn: Option<Connection> = None;
+    let mut connected_before = false;
+    let mut intervals = Vec::new();
+    let (mut reconnects, mut path_changes) = (0, 0);
+    let mut last_path: Option<String> = None;
+    while Instant::now() < deadline && !config.interrupted() {
+        let started_at = SystemTime::now();
+        let elapsed = start.elapsed();
+        let mut interval = SoakInterval {
+            started_at: timestamp(started_at),
+            elapsed_secs: elapsed.as_secs_f64(),
+            bandwidth: Sample::default(),
+            rtt_ms: 0.0,
+            connection_type: "none".to_string(),
+            reconnected: false,
+            error: None,
+        };
+        if conn.as_ref().is_none_or(|conn| conn.close_reason().is_some()) {
+            match connect_once(endpoint, &addr, ALPN, config).await {
+                Ok(new) => {
+                    interval.reconnected = connected_before;
+                    reconnects += connected_before as usize;
+                    connected_before = true;
+                    conn = Some(new);
+                }
+                Err(err) => {
+                    config.status(format!("Connecting failed: {err:#}"));
+                    interval.error = Some(format!("{err:#}"));
+                    intervals.push(interval);
+                    conn = None;
+                    // Retry after a pause instead of hammering an unreachable server
+                    sleep_until(deadline.min(Instant::now() + SOAK_RETRY_DELAY)).await;
+                    continue;
+                }
+            }
+        }
+        let active = conn.as_ref().expect("connected above");
+        if path_log.is_none() {
+            path_log = Some(PathLog::start(endpoint, addr.node_id, start)?);
+        }
+
+        let window = config.soak_interval.min(deadline - Instant::now());
+        let paths = PathTracker::start(endpoint, addr.node_id)?;
+        let counters = Arc::new(Counters::default());
+        let reporter = spawn_reporter(counters.clone(), config);
+        let sampler = spawn_throughput_sampler(counters.clone(), start, throughput.clone());
+        let res = tokio::select! {
+            res = benchmark_timed(active, window, transfer, &counters) => res,
+            _ = config.until_interrupted() => break,
+        };
+        drop((reporter, sampler));
+        interval.connection_type = paths.finish()?;
+        for transition in path_log.as_mut().map(PathLog::new_transitions).unwrap_or_default() {
+            config.status(format!(
+                "Path changed from {} to {} at {}",
+                transition.from, transition.to, transition.at
+            ));
+        }
+        interval.rtt_ms = millis(active.rtt());
+        match res {
+            Ok(sample) => interval.bandwidth = sample,
+            Err(err) => {
+                config.status(format!("Transfer failed, reconnecting: {err:#}"));
+                interval.error = Some(format!("{err:#}"));
+                active.close(0u32.into(), b"bye!");
+                conn = None;
+            }
+        }
+
+        // Count changes within the interval and from the path the previous one ended on
+        path_changes += interval.connection_type.matches("->").count();
+        let first = interval.connection_type.split("->").next().map(str::to_string);
+        if last_path.is_some() && first != last_path {
+            path_changes += 1;
+        }
+        last_path = interval.connection_type.rsplit("->").next().map(str::to_string);
+
+        if config.print_results {
+            let mbps = |v: Option<f64>| v.map_or("-".to_string(), |v| format!("{v:.2}"));
+            println!(
+                "[{}] up {} Mbit/s, down {} Mbit/s, RTT {:.2} ms, {}{}",
+                humantime::format_duration(Duration::from_secs(elapsed.as_secs())),
+                mbps(interval.bandwidth.upload),
+                mbps(interval.bandwidth.download),
+                interval.rtt_ms,
+                interval.connection_type,
+                interval.error.as_deref().map(|err| format!(" ({err})")).unwrap_or_default(),
+            );
+        }
+        intervals.push(interval);
+    }
+    if let Some(conn) = conn {
+        conn.close(0u32.into(), b"bye!");
+    }
+
+    let hours = |it: &SoakInterval| it.elapsed_secs / 3600.0;
+    let points = |f: fn(&Sample) -> Option<f64>| -> Vec<(f64, f64)> {
+        intervals.iter().filter_map(|it| Some((hours(it), f(&it.bandwidth)?))).collect()
+    };
+    let (upload, download) = (points(|s| s.upload), points(|s| s.download));
+    let values = |points: &[(f64, f64)]| -> Vec<f64> { points.iter().map(|(_, y)| *y).collect() };
+    let rtts: Vec<_> = intervals
+        .iter()
+        .filter(|it| it.error.is_none())
+        .map(|it| (hours(it), it.rtt_ms))
+        .collect();
+    let upload_drift_mbps_per_hour = trend(&upload);
+    let download_drift_mbps_per_hour = trend(&download);
+    let rtt_drift_ms_per_hour = trend(&rtts);
+    let upload = Stats::from_samples(&values(&upload));
+    let download = Stats::from_samples(&values(&download));
+    let throughput = std::mem::take(&mut *throughput.lock().expect("poisoned"));
+    let path_stability = path_log.map(|log| log.finish(&throughput)).unwrap_or_default();
+
+    if config.print_results {
+        println!("Intervals: {}", intervals.len());
+        println!("Reconnects: {reconnects}");
+        println!("Path changes: {path_changes}");
+        print_stats("Upload", upload);
+        print_stats("Download", download);
+        println!("Drift (per hour):");
+        if let Some(drift) = upload_drift_mbps_per_hour {
+            println!("  Upload: {drift:+.2} Mbit/s");
+        }
+        if let Some(drift) = download_drift_mbps_per_hour {
+            println!("  Download: {drift:+.2} Mbit/s");
+        }
+        if let Some(drift) = rtt_drift_ms_per_hour {
+            println!("  RTT: {drift:+.3} ms");
+        }
+        print_path_stability(&path_stability);
+    }
+
+    Ok(Benchmark::Soak {
+        direction,
+        streams: config.streams,
+        duration_secs: duration.as_secs_f64(),
+        interval_secs: config.soak_interval.as_secs_f64(),
+        bitrate_mbps: config.bitrate,
+        intervals,
+        reconnects,
+        path_changes,
+        upload,
+        download,
+        upload_drift_mbps_per_hour,
+        download_drift_mbps_per_hour,
+        rtt_drift_ms_per_hour,
+        path_stability,
+    })
+}
+
+/// Prints how long a soak test stayed on every path, and every change with the throughput
+/// before and after it.
+fn print_path_stability(stability: &PathStability) {
+    println!("Path stability:");
+    for (path, percent) in &stability.time_on_path_percent {
+        println!("  {path}: {percent:.1}% of the time");
+    }
+    let mbps = |v: Option<f64>| v.map_or("-".to_string(), |v| format!("{v:.2}"));
+    for transition in &stability.transitions {
+        println!(
+            "  {} (after {}): {} -> {}, {} -> {} Mbit/s",
+            transition.at,
+            humantime::format_duration(Duration::from_secs(transition.elapsed_secs as u64)),
+            transition.from,
+            transition.to,
+            mbps(transition.before_mbps),
+            mbps(transition.after_mbps),
+        );
+    }
+}
+
+/// Sends datagrams at a fixed rate for `duration` and compares how many the server received.
+pub async fn run_datagram(
+    endpoint: &Endpoint,
+    addr: NodeAddr,
+    config: &BenchmarkConfig,
+    duration: Duration,
+) -> Result<Benchmark> {
+    anyhow::ensure!(config.rate > 0.0, "The datagram rate must be positive");
+    let mut backoff = Backoff::new(config.retries);
+    let conn = loop {
+        match connect_once(endpoint, &addr, DATAGRAM_ALPN, config).await {
+            Ok(conn) => break conn,
+            Err(err) => backoff.retry(config, "Connecting", err).await?,
+        }
+    };
+    let max_size = conn.max_datagram_size().context("The server does not accept datagrams")?;
+    let size = config.datagram_size.unwrap_or(max_size);
+    anyhow::ensure!(size <= max_size, "Datagrams on this path are limited to {max_size} bytes");
+
+    config.status(format!(
+        "\nStarting datagram benchmark for {} ({size} bytes at {} Mbit/s):",
+        humantime::format_duration(duration),
+        config.rate
+    ));
+    let paths = PathTracker::start(endpoint, addr.node_id)?;
+    let counters = Arc::new(Counters::default());
+    let reporter = spawn_reporter(counters.clone(), config);
+    let stream = counters.stream(None);
+
+    // Datagrams are scheduled at fixed intervals; when the timer lags behind, the overdue ones
+    // are sent in a burst to keep the average rate
+    let mut payload = vec![0u8; size];
+    let gap = Duration::from_secs_f64(size as f64 * 8.0 / (config.rate * 1e6));
+    let t0 = Instant::now();
+    let deadline = t0 + duration;
+    let mut next = t0;
+    let mut sent = 0u64;
+    while next < deadline {
+        if next > Instant::now() {
+            sleep_until(next).await;
+        }
+        let header = DatagramHeader { seq: sent, sent_at_us: t0.elapsed().as_micros() as u64 };
+        header.encode_into(&mut payload);
+        conn.send_datagram(bytes::Bytes::copy_from_slice(&payload))?;
+        stream.add_sent(size);
+        sent += 1;
+        next += gap;
+    }
+    let elapsed = t0.elapsed();
+    drop(reporter);
+
+    let (mut send, mut recv) = conn.open_bi().await?;
+    send.write_all(&sent.to_be_bytes()).await?;
+    send.finish()?;
+    let summary = DatagramSummary::read(&mut recv).await?;
+    let connection_type = paths.finish()?;
+    ensure_direct(config, &connection_type)?;
+    conn.close(0u32.into(), b"bye!");
+
+    let loss_percent = 100.0 * (1.0 - summary.received as f64 / sent.max(1) as f64);
+    let reorder_percent = 100.0 * summary.reordered as f64 / summary.received.max(1) as f64;
+    let jitter_ms = summary.jitter_us as f64 / 1000.0;
+    let sent_mbps = bandwidth(sent as usize * size, elapsed);
+    let received_mbps = bandwidth(summary.bytes as usize, elapsed);
+    if config.print_results {
+        println!("Connection type: {connection_type}");
+        println!(
+            "Datagrams sent: {sent}, received: {} ({loss_percent:.2}% loss)",
+            summary.received
+        );
+        println!(
+            "Reordered: {} ({reorder_percent:.2}%), loss runs: {} (longest {}), jitter: {jitter_ms:.3} ms",
+            summary.reordered, summary.loss_runs, summary.max_loss_run
+        );
+        println!("Datagram bandwidth (Mbit/s):");
+        println!("  Sent: {sent_mbps:.2}");
+        println!("  Received: {received_mbps:.2}");
+    }
+    Ok(Benchmark::Datagram {
+        size: size as u64,
+        target_mbps: config.rate,
+        duration_secs: elapsed.as_secs_f64(),
+        connection_type,
+        sent,
+        received: summary.received,
+        loss_percent,
+        reordered: summary.reordered,
+        reorder_percent,
+        loss_runs: summary.loss_runs,
+        max_loss_run: summary.max_loss_run,
+        jitter_ms,
+        sent_mbps,
+        received_mbps,
+    })
+}
+
+/// Measures the round-trip latency of ping messages on a single stream.
+pub async fn run_latency(
+    endpoint: &Endpoint,
+    addr: NodeAddr,
+    config: &BenchmarkConfig,
+    ping: &PingConfig,
+) -> Result<Benchmark> {
+    let conn = connect(endpoint, &addr, config).await?;
+    let (mut send, mut recv) = conn.open_bi().await?;
+    Request::Ping { size: ping.size }.write(&mut send).await?;
+    Response::read(&mut recv).await?;
+
+    config.status(format!("\nStarting ping benchmark ({} x {} bytes):", ping.count, ping.size));
+    let msg = vec![0u8; ping.size as usize];
+    let mut reply = vec![0u8; ping.size as usize];
+    let mut rtts = Vec::with_capacity(ping.count);
+    let paths = PathTracker::start(endpoint, addr.node_id)?;
+    for _ in 0..ping.count {
+        let t0 = Instant::now();
+        send.write_all(&msg).await?;
+        recv.read_exact(&mut reply).await?;
+        rtts.push(t0.elapsed());
+    }
+    send.finish()?;
+    let connection_type = paths.finish()?;
+    ensure_direct(config, &connection_type)?;
+    conn.close(0u32.into(), b"bye!");
+
+    let summary = Stats::from_rtts(&rtts);
+    let jitter_ms = jitter(&rtts);
+    if let Some(summary) = summary.filter(|_| config.print_results) {
+        println!("Connection type: {connection_type}");
+        print_latency_stats("Round-trip time", &summary);
+        if let Some(jitter) = jitter_ms {
+            println!("  Jitter: {jitter:.3}");
+        }
+    }
+    Ok(Benchmark::Ping {
+        size: ping.size,
+        connection_type,
+        rtts_ms: rtts.into_iter().map(millis).collect(),
+        summary,
+        jitter_ms,
+    })
+}
+
+/// Estimates the one-way delay in each direction from timestamp probes, which unlike the
+/// round-trip time shows paths that are slower one way, e.g. through different relays.
+///
+/// Without `--synced-clocks` the offset and drift between the clocks come from the fastest
+/// probes, so their delays count as symmetric and only the queueing the other probes saw on top
+/// tells the directions apart.
+pub async fn run_delay(
+    endpoint: &Endpoint,
+    addr: NodeAddr,
+    config: &BenchmarkConfig,
+    delay: &DelayConfig,
+) -> Result<Benchmark> {
+    anyhow::ensure!(!delay.interval.is_zero(), "The probe interval must not be zero");
+    let conn = connect(endpoint, &addr, config).await?;
+    config.status(format!("\nStarting one-way delay benchmark ({} probes):", delay.count));
+    let paths = PathTracker::start(endpoint, addr.node_id)?;
+    let probes = exchange_clock_probes(&conn, delay.count, delay.interval).await?;
+    let connection_type = paths.finish()?;
+    ensure_direct(config, &connection_type)?;
+    conn.close(0u32.into(), b"bye!");
+
+    let clock = (!delay.synced_clocks).then(|| ClockSync::estimate(&probes)).flatten();
+    let offset_at = |probe: &ProbeTimes| clock.map_or(0.0, |c| c.offset_at(probe.client_sent));
+    let ms = |us: f64| us / 1000.0;
+    let rtts_ms: Vec<f64> = probes.iter().map(|p| ms(p.rtt_us() as f64)).collect();
+    let forward_ms: Vec<f64> = probes.iter().map(|p| ms(p.forward_us(offset_at(p)))).collect();
+    let backward_ms: Vec<f64> = probes.iter().map(|p| ms(p.backward_us(offset_at(p)))).collect();
+    let rtt = Stats::from_samples(&rtts_ms);
+    let (forward, backward) = (Stats::from_samples(&forward_ms), Stats::from_samples(&backward_ms));
+    let asymmetry_ms = forward.zip(backward).map(|(f, b)| f.median - b.median);
+    if config.print_results {
+        println!("Connection type: {connection_type}");
+        match clock {
+            Some(clock) => println!(
+                "Clock offset: {:+.3} ms (server clock ahead), drift: {:+.1} ppm",
+                ms(clock.offset_us),
+                clock.drift_ppm
+            ),
+            None => println!("Clock offset: none, clocks assumed synchronized"),
+        }
+        for (label, summary) in [
+            ("Round-trip time", &rtt),
+            ("Client to server delay", &forward),
+            ("Server to client delay", &backward),
+        ] {
+            if let Some(summary) = summary {
+                print_latency_stats(label, summary);
+            }
+        }
+        if let Some(asymmetry) = asymmetry_ms {
+            let slower = if asymmetry > 0.0 { "client to server" } else { "server to client" };
+            println!("Median asymmetry: {:.3} ms, {slower} slower", asymmetry.abs());
+        }
+    }
+    Ok(Benchmark::Delay {
+        connection_type,
+        synced_clocks: delay.synced_clocks,
+        clock_offset_ms: clock.map_or(0.0, |clock| ms(clock.offset_us)),
+        clock_drift_ppm: clock.map_or(0.0, |clock| clock.drift_ppm),
+        rtts_ms,
+        forward_ms,
+        backward_ms,
+        rtt,
+        forward,
+        backward,
+        asymmetry_ms,
+    })
+}
+
+/// Exchanges `count` timestamp probes with the server on a new stream, one every `interval`.
+async fn exchange_clock_probes(
+    conn: &Connection,
+    count: usize,
+    interval: Duration,
+) -> Result<Vec<ProbeTimes>> {
+    let (mut send, mut recv) = conn.open_bi().await?;
+    Request::Clock.write(&mut send).await?;
+    Response::read(&mut recv).await?;
+    let mut ticks = interval_at(Instant::now(), interval);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut buf = [0u8; ClockReply::LEN];
+    let mut probes = Vec::with_capacity(count);
+    for _ in 0..count {
+        ticks.tick().await;
+        // Time the answer on the monotonic clock, so that the wall clock stepping in between
+        // does not distort the round trip
+        let (client_sent, t0) = (wall_clock_us(), Instant::now());
+        send.write_all(&client_sent.to_be_bytes()).await?;
+        recv.read_exact(&mut buf).await?;
+        let client_received = client_sent + t0.elapsed().as_micros() as u64;
+        let reply = ClockReply::decode(&buf)?;
+        anyhow::ensure!(reply.client_sent_us == client_sent, "Clock probe answered out of order");
+        probes.push(ProbeTimes {
+            client_sent,
+            server_received: reply.server_received_us,
+            server_sent: reply.server_sent_us,
+            client_received,
+        });
+    }
+    send.finish()?;
+    Ok(probes)
+}
+
+/// Share of the throughput before the rebind of [`run_migrate`] at which it counts as recovered.
+const RECOVERED_SHARE: f64 = 0.9;
+
+/// Streams in `--direction` for `--duration` and moves the client to a new local socket after
+/// `--at`, measuring how long the throughput takes to recover and whether the connection survives
+/// the move.
+///
+/// iroh cannot be told to rebind on demand: [`Endpoint::network_change`] has its network monitor
+/// look at the interfaces again, and only if they changed are the sockets rebound and the public
+/// addresses probed anew. Switching interfaces by hand around `--at`, e.g. from Wi-Fi to Ethernet,
+/// exercises a real migration, and the run fails if the client's direct addresses stayed the
+/// same, as nothing migrated then. `--quic` instead rebinds the socket of a plain QUIC
+/// connection, which the server sees as the client moving to a new address.
+pub async fn run_migrate(
+    endpoint: &Endpoint,
+    addr: NodeAddr,
+    config: &BenchmarkConfig,
+    migrate: &MigrateConfig,
+) -> Result<Benchmark> {
+    let at = migrate.at.unwrap_or(migrate.duration / 2);
+    anyhow::ensure!(!migrate.sample_interval.is_zero(), "The sample interval must not be zero");
+    anyhow::ensure!(at < migrate.duration, "The rebind must happen before the transfer ends");
+    let direction = config.direction;
+    let transfer = Transfer {
+        direction,
+        size: UNBOUNDED,
+        chunk_size: config.chunk_size as u32,
+        streams: config.streams as u32,
+        verify: false,
+        pattern: config.pattern,
+        seed: config.seed,
+        bitrate: config.paced_bitrate(),
+        report: false,
+    };
+    let (duration_label, at_label) =
+        (humantime::format_duration(migrate.duration), humantime::format_duration(at));
+    let counters = Arc::new(Counters::default());
+    let reporter = spawn_reporter(counters.clone(), config);
+
+    let (load, throughput, survived, connection_type, local_addrs_before, local_addrs_after) =
+        match migrate.quic {
+            None => {
+                let conn = connect(endpoint, &addr, config).await?;
+                config.status(format!(
+                    "\nStarting {direction:?} transfer for {duration_label}, notifying iroh of a \
+                     network change after {at_label}:"
+                ));
+                let addrs = || {
+                    let addrs = endpoint.direct_addresses().get().ok().flatten();
+                    addrs.into_iter().flatten().map(|addr| addr.addr).collect::<Vec<_>>()
+                };
+                let paths = PathTracker::start(endpoint, addr.node_id)?;
+                let before = addrs();
+                let rebind = async {
+                    endpoint.network_change().await;
+                    Ok(())
+                };
+                let (load, throughput) =
+                    stream_across_rebind(&conn, transfer, migrate, at, rebind, &counters).await?;
+                let survived = load.is_ok() && conn.close_reason().is_none();
+                let connection_type = paths.finish()?;
+                conn.close(0u32.into(), b"bye!");
+                let after = addrs();
+                anyhow::ensure!(
+                    after != before,
+                    "The client's addresses did not change, so nothing migrated: switch interfaces \
+                     around --at, or migrate a plain QUIC connection with --quic"
+                );
+                (load, throughput, survived, connection_type, before, after)
+            }
+            Some(quic_addr) => {
+                let buffers = &config.endpoint.buffers;
+                let (quic, conn) = quic_connect(quic_addr, buffers).await?;
+                config.status(format!(
+                    "\nStarting {direction:?} transfer over plain QUIC for {duration_label}, \
+                     rebinding to a new port after {at_label}:"
+                ));
+                let before = vec![quic.local_addr()?];
+                let rebind = async {
+                    quic.rebind(buffers.bind(unspecified(quic_addr))?.0)?;
+                    Ok(())
+                };
+                let (load, throughput) =
+                    stream_across_rebind(&conn, transfer, migrate, at, rebind, &counters).await?;
+                let survived = load.is_ok() && conn.close_reason().is_none();
+                conn.close(0u32.into(), b"bye!");
+                let after = vec![quic.local_addr()?];
+                quic.wait_idle().await;
+                (load, throughput, survived, "direct".to_string(), before, after)
+            }
+        };
+    drop(reporter);
+    ensure_direct(config, &connection_type)?;
+    let error = load.as_ref().err().map(|err| format!("{err:#}"));
+    if let Some(err) = &error {
+        config.status(format!("Transfer failed: {err}"));
+    }
+
+    // The dip starts with the window the rebind fell into and lasts until the throughput is
+    // back to most of the median before
+    let at_ms = millis(at);
+    let (before, after): (Vec<ThroughputSample>, Vec<ThroughputSample>) =
+        throughput.iter().partition(|sample| sample.elapsed_ms <= at_ms);
+    let median = |samples: &[ThroughputSample]| {
+        let mbps: Vec<f64> = samples.iter().map(|sample| sample.mbps).collect();
+        Stats::from_samples(&mbps).map(|stats| stats.median)
+    };
+    let before_mbps = median(&before);
+    let threshold = before_mbps.unwrap_or_default() * RECOVERED_SHARE;
+    let dipped = after.iter().take_while(|sample| sample.mbps < threshold).count();
+    let (dip, recovered) = after.split_at(dipped);
+    let dip_ms = (!recovered.is_empty())
+        .then(|| dip.last().map_or(0.0, |sample| sample.elapsed_ms - at_ms));
+    let dip_min_mbps = dip.iter().map(|sample| sample.mbps).min_by(f64::total_cmp);
+    let after_mbps = median(recovered);
+
+    if config.print_results {
+        let addrs = |addrs: &[SocketAddr]| {
+            addrs.iter().map(SocketAddr::to_string).collect::<Vec<_>>().join(", ")
+        };
+        println!("Connection type: {connection_type}");
+        println!(
+            "Local addresses: {} -> {}",
+            addrs(&local_addrs_before),
+            addrs(&local_addrs_after)
+        );
+        if let Some(mbps) = before_mbps {
+            println!("Median throughput before the rebind: {mbps:.2} Mbit/s");
+        }
+        match (dip_ms, dip_min_mbps) {
+            (None, _) => println!("Throughput did not recover before the transfer ended"),
+            (Some(_), None) => println!("Throughput did not dip"),
+            (Some(ms), Some(min)) => println!("Dip: {ms:.0} ms, down to {min:.2} Mbit/s"),
+        }
+        if let Some(mbps) = after_mbps {
+            println!("Median throughput after recovering: {mbps:.2} Mbit/s");
+        }
+        match &error {
+            None if survived => println!("Connection survived: yes"),
+            None => println!("Connection survived: no, closed after the transfer"),
+            Some(err) => println!("Connection survived: no ({err})"),
+        }
+    }
+    Ok(Benchmark::Migrate {
+        direction,
+        quic: migrate.quic.is_some(),
+        duration_secs: migrate.duration.as_secs_f64(),
+        rebind_at_secs: at.as_secs_f64(),
+        connection_type,
+        local_addrs_before,
+        local_addrs_after,
+        throughput,
+        before_mbps,
+        after_mbps,
+        dip_ms,
+        dip_min_mbps,
+        survived,
+        error,
+        load: load.unwrap_or_default(),
+    })
+}
+
+/// Streams `transfer` on `conn` for `--duration`, sampling the throughput of both directions
+/// every `--sample-interval`, and runs `rebind` once `at` has passed.
+///
+/// Only fails if the rebind does, the outcome of the transfer is returned with the samples.
+async fn stream_across_rebind(
+    conn: &impl OpenStreams,
+    transfer: Transfer,
+    migrate: &MigrateConfig,
+    at: Duration,
+    rebind: impl Future<Output = Result<()>>,
+    counters: &Counters,
+) -> Result<(Result<Sample>, Vec<ThroughputSample>)> {
+    let start = Instant::now();
+    let deadline = start + migrate.duration;
+    let sample = async {
+        let mut ticks = interval_at(start + migrate.sample_interval, migrate.sample_interval);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let (mut samples, mut last_moved, mut last_tick) = (Vec::new(), 0, start);
+        loop {
+            let now = ticks.tick().await;
+            if now > deadline {
+                break samples;
+            }
+            let moved = counters.sent() + counters.received();
+            let mbps = bandwidth((moved - last_moved) as usize, now - last_tick);
+            samples.push(ThroughputSample { elapsed_ms: millis(now - start), mbps });
+            (last_moved, last_tick) = (moved, now);
+        }
+    };
+    let rebind = async {
+        sleep_until(start + at).await;
+        rebind.await
+    };
+    let load = benchmark_timed(conn, migrate.duration, transfer, counters);
+    let (load, samples, rebound) = tokio::join!(load, sample, rebind);
+    rebound?;
+    Ok((load, samples))
+}
+
+/// Probes the round-trip time of a connection while it is idle, then while it carries a bulk
+/// transfer in `--direction` on other streams, and reports how much the load inflates it.
+///
+/// Probes are echoed on a stream of their own, so they queue behind the bulk data in the
+/// congestion window and the buffers along the path, but not behind its stream.
+pub async fn run_bufferbloat(
+    endpoint: &Endpoint,
+    addr: NodeAddr,
+    config: &BenchmarkConfig,
+    bloat: &BufferbloatConfig,
+) -> Result<Benchmark> {
+    anyhow::ensure!(!bloat.probe_interval.is_zero(), "The probe interval must not be zero");
+    let direction = config.direction;
+    let conn = connect(endpoint, &addr, config).await?;
+    let (mut send, mut recv) = conn.open_bi().await?;
+    Request::Ping { size: bloat.size }.write(&mut send).await?;
+    Response::read(&mut recv).await?;
+    let (size, interval) = (bloat.size as usize, bloat.probe_interval);
+    let mut probes = Probes { send, recv, size, interval };
+    let paths = PathTracker::start(endpoint, addr.node_id)?;
+
+    let idle_label = humantime::format_duration(bloat.idle);
+    config.status(format!("\nProbing the idle connection for {idle_label}:"));
+    let idle = probes.until(Instant::now() + bloat.idle).await?;
+
+    let duration_label = humantime::format_duration(bloat.duration);
+    config.status(format!("\nProbing during a {direction:?} transfer of {duration_label}:"));
+    let counters = Arc::new(Counters::default());
+    let reporter = spawn_reporter(counters.clone(), config);
+    let transfer = Transfer {
+        direction,
+        size: UNBOUNDED,
+        chunk_size: config.chunk_size as u32,
+        streams: config.streams as u32,
+        verify: false,
+        pattern: config.pattern,
+        seed: config.seed,
+        bitrate: config.paced_bitrate(),
+        report: false,
+    };
+    let load = benchmark_timed(&conn, bloat.duration, transfer, &counters);
+    let (load, loaded) = tokio::try_join!(load, probes.until(Instant::now() + bloat.duration))?;
+    drop(reporter);
+    probes.send.finish()?;
+    let connection_type = paths.finish()?;
+    ensure_direct(config, &connection_type)?;
+    conn.close(0u32.into(), b"bye!");
+
+    let (idle_stats, loaded_stats) = (Stats::from_rtts(&idle), Stats::from_rtts(&loaded));
+    let inflation_ms =
+        idle_stats.zip(loaded_stats).map(|(idle, loaded)| loaded.median - idle.median);
+    if config.print_results {
+        println!("Connection type: {connection_type}");
+        println!("Load: {}", load.describe());
+        if let Some(summary) = &idle_stats {
+            print_latency_stats("Idle round-trip time", summary);
+        }
+        if let Some(summary) = &loaded_stats {
+            print_latency_stats("Loaded round-trip time", summary);
+        }
+        if let Some(inflation) = inflation_ms {
+            println!("Median RTT inflation under load: {inflation:+.3} ms");
+        }
+    }
+    Ok(Benchmark::Bufferbloat {
+        direction,
+        size: bloat.size,
+        connection_type,
+        load,
+        idle_ms: idle.into_iter().map(millis).collect(),
+        loaded_ms: loaded.into_iter().map(millis).collect(),
+        idle: idle_stats,
+        loaded: loaded_stats,
+        inflation_ms,
+    })
+}
+
+/// Stream the latency probes of [`run_bufferbloat`] are echoed on.
+struct Probes {
+    send: SendStream,
+    recv: RecvStream,
+    size: usize,
+    interval: Duration,
+}
+
+impl Probes {
+    /// Sends a probe every interval until `deadline`, returning the round-trip times.
+    async fn until(&mut self, deadline: Instant) -> Result<Vec<Duration>> {
+        let msg = vec![0u8; self.size];
+        let mut reply = vec![0u8; self.size];
+        let mut rtts = Vec::new();
+        let mut ticks = interval_at(Instant::now(), self.interval);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        while ticks.tick().await < deadline {
+            let t0 = Instant::now();
+            self.send.write_all(&msg).await?;
+            self.recv.read_exact(&mut reply).await?;
+            rtts.push(t0.elapsed());
+        }
+        Ok(rtts)
+    }
+}
+
+/// Sends `--count` framed requests on a single stream and times each until its response
+/// arrives, keeping up to `--depth` requests in flight.
+///
+/// The server answers in order, so the reader matches every response to the oldest request
+/// still waiting and frees its slot for the writer.
+pub async fn run_rpc(
+    endpoint: &Endpoint,
+    addr: NodeAddr,
+    config: &BenchmarkConfig,
+    rpc: &RpcConfig,
+) -> Result<Benchmark> {
+    let conn = connect(endpoint, &addr, config).await?;
+    let (mut send, mut recv) = conn.open_bi().await?;
+    Request::Rpc { response_size: rpc.response_size }.write(&mut send).await?;
+    Response::read(&mut recv).await?;
+
+    config.status(format!(
+        "\nStarting RPC benchmark ({} requests of {} bytes, responses of {} bytes, depth {}):",
+        rpc.count, rpc.request_size, rpc.response_size, rpc.depth
+    ));
+    let request = rpc_message(rpc.request_size);
+    let in_flight = Semaphore::new(rpc.depth);
+    let (sent_tx, mut sent_rx) = mpsc::unbounded_channel();
+    let paths = PathTracker::start(endpoint, addr.node_id)?;
+    let t0 = Instant::now();
+    let writer = async {
+        for _ in 0..rpc.count {
+            in_flight.acquire().await?.forget();
+            sent_tx.send(Instant::now())?;
+            send.write_all(&request).await?;
+        }
+        send.finish()?;
+        anyhow::Ok(())
+    };
+    let reader = async {
+        let mut latencies = Vec::with_capacity(rpc.count);
+        let mut buf = Vec::new();
+        while latencies.len() < rpc.count {
+            let len = read_rpc_message(&mut recv, &mut buf)
+                .await?
+                .context("The server finished the stream before answering every request")?;
+            let expected = rpc.response_size;
+            anyhow::ensure!(len == expected, "Response of {len} bytes, expected {expected}");
+            let sent = sent_rx.recv().await.context("Response to a request never sent")?;
+            latencies.push(sent.elapsed());
+            in_flight.add_permits(1);
+        }
+        anyhow::Ok(latencies)
+    };
+    let ((), latencies) = tokio::try_join!(writer, reader)?;
+    let elapsed = t0.elapsed();
+    let connection_type = paths.finish()?;
+    ensure_direct(config, &connection_type)?;
+    conn.close(0u32.into(), b"bye!");
+
+    let requests_per_sec = rpc.count as f64 / elapsed.as_secs_f64();
+    let latency = Stats::from_rtts(&latencies);
+    if config.print_results {
+        println!("Connection type: {connection_type}");
+        println!("Requests: {} in {elapsed:.2?}", rpc.count);
+        println!("Throughput: {requests_per_sec:.0} requests/s");
+        if let Some(summary) = &latency {
+            print_latency_stats("Request latency", summary);
+        }
+    }
+    Ok(Benchmark::Rpc {
+        request_size: rpc.request_size,
+        response_size: rpc.response_size,
+        depth: rpc.depth,
+        connection_type,
+        requests: rpc.count,
+        requests_per_sec,
+        latency_ms: latencies.into_iter().map(millis).collect(),
+        latency,
+    })
+}
+
+/// Writes `--count` small framed messages to a single stream, one write per message, until the
+/// server confirms it read them all.
+///
+/// The overhead per message is what the connection sent in UDP datagrams beyond the message
+/// bodies: the length prefixes and every message's share of the packet headers and frames.
+pub async fn run_messages(
+    endpoint: &Endpoint,
+    addr: NodeAddr,
+    config: &BenchmarkConfig,
+    messages: &MessagesConfig,
+) -> Result<Benchmark> {
+    let conn = connect(endpoint, &addr, config).await?;
+    let (mut send, mut recv) = conn.open_bi().await?;
+    Request::Messages.write(&mut send).await?;
+    Response::read(&mut recv).await?;
+
+    let (count, size) = (messages.count, messages.size);
+    config.status(format!("\nStarting message benchmark ({count} x {size} bytes):"));
+    let message = rpc_message(size);
+    let paths = PathTracker::start(endpoint, addr.node_id)?;
+    let udp_before = conn.stats().udp_tx.bytes;
+    let t0 = Instant::now();
+    for _ in 0..count {
+        send.write_all(&message).await?;
+    }
+    send.finish()?;
+    let mut received = [0u8; 8];
+    recv.read_exact(&mut received).await?;
+    let elapsed = t0.elapsed();
+    let udp_bytes = conn.stats().udp_tx.bytes - udp_before;
+    let received = u64::from_be_bytes(received);
+    anyhow::ensure!(received == count, "The server received {received} of {count} messages");
+    let connection_type = paths.finish()?;
+    ensure_direct(config, &connection_type)?;
+    conn.close(0u32.into(), b"bye!");
+
+    let messages_per_sec = count as f64 / elapsed.as_secs_f64();
+    let goodput_mbps = bandwidth((count * size as u64) as usize, elapsed);
+    let us_per_message = elapsed.as_secs_f64() * 1e6 / count as f64;
+    let overhead_bytes_per_message = udp_bytes as f64 / count as f64 - size as f64;
+    if config.print_results {
+        println!("Connection type: {connection_type}");
+        println!("Messages: {count} of {size} bytes in {elapsed:.2?}");
+        println!("Throughput: {messages_per_sec:.0} messages/s");
+        println!("Goodput: {goodput_mbps:.2} Mbit/s of message bodies");
+        println!("Time per message: {us_per_message:.3} us");
+        println!("Wire overhead per message: {overhead_bytes_per_message:.1} bytes");
+    }
+    Ok(Benchmark::Messages {
+        size,
+        connection_type,
+        messages: count,
+        duration_secs: elapsed.as_secs_f64(),
+        messages_per_sec,
+        goodput_mbps,
+        us_per_message,
+        overhead_bytes_per_message,
+    })
+}
+
+/// Resolves the server's addresses via discovery on a fresh endpoint per trial, timing the
+/// resolution separately from the QUIC handshake that follows it.
+pub async fn run_discover(
+    node_id: NodeId,
+    config: &BenchmarkConfig,
+    discover: &DiscoverConfig,
+) -> Result<Benchmark> {
+    anyhow::ensure!(!config.endpoint.no_discovery, "The discover benchmark requires discovery");
+
+    config.status(format!("\nStarting discovery benchmark ({} trials):", discover.trials));
+    let mut resolutions = Vec::with_capacity(discover.trials);
+    let mut handshakes = Vec::with_capacity(discover.trials);
+    let mut provenance = Vec::new();
+    for i in 0..discover.trials {
+        config.status(format!("Trial {}", i + 1));
+        // A fresh endpoint has no cached addressing information for the server
+        let endpoint = config.endpoint.builder()?.bind().await?;
+        let discovery = endpoint.discovery().context("No discovery configured")?;
+
+        let t0 = Instant::now();
+        let mut items = discovery
+            .resolve(endpoint.clone(), node_id)
+            .context("Discovery cannot resolve node ids")?;
+        let item = items.next().await.context("Discovery produced no results")??;
+        resolutions.push(t0.elapsed());
+        if !provenance.contains(&item.provenance()) {
+            provenance.push(item.provenance());
+        }
+
+        let t1 = Instant::now();
+        let conn = endpoint.connect(item.into_node_addr(), ALPN).await?;
+        handshakes.push(t1.elapsed());
+        conn.close(0u32.into(), b"bye!");
+        endpoint.close().await;
+    }
+
+    let discovery = Stats::from_rtts(&resolutions);
+    let handshake = Stats::from_rtts(&handshakes);
+    if config.print_results {
+        println!("Resolved via: {}", provenance.join(", "));
+        if let Some(summary) = &discovery {
+            print_latency_stats("Discovery time", summary);
+        }
+        if let Some(summary) = &handshake {
+            print_latency_stats("Handshake time", summary);
+        }
+    }
+    Ok(Benchmark::Discover {
+        provenance: provenance.into_iter().map(String::from).collect(),
+        discovery_ms: resolutions.into_iter().map(millis).collect(),
+        handshake_ms: handshakes.into_iter().map(millis).collect(),
+        discovery,
+        handshake,
+    })
+}
+
+/// Dials the server repeatedly and measures how long `connect` takes until the handshake is
+/// complete, once binding a fresh endpoint for every connection and once reusing `endpoint`.
+pub async fn run_connect(
+    endpoint: &Endpoint,
+    addr: NodeAddr,
+    config: &BenchmarkConfig,
+    connect: &ConnectConfig,
+) -> Result<Benchmark> {
+    config.status(format!("\nStarting cold connect benchmark ({} trials):", connect.trials));
+    let mut cold = Vec::with_capacity(connect.trials);
+    for i in 0..connect.trials {
+        config.status(format!("Trial {}", i + 1));
+        let endpoint = config.endpoint.builder()?.bind().await?;
+        let t0 = Instant::now();
+        let conn = endpoint.connect(addr.clone(), ALPN).await?;
+        cold.push(t0.elapsed());
+        conn.close(0u32.into(), b"bye!");
+        endpoint.close().await;
+    }
+
+    config.status(format!("\nStarting warm connect benchmark ({} trials):", connect.trials));
+    // The first connection populates the endpoint's path state and is not measured
+    let conn = endpoint.connect(addr.clone(), ALPN).await?;
+    conn.close(0u32.into(), b"bye!");
+    let mut warm = Vec::with_capacity(connect.trials);
+    for i in 0..connect.trials {
+        config.status(format!("Trial {}", i + 1));
+        let t0 = Instant::now();
+        let conn = endpoint.connect(addr.clone(), ALPN).await?;
+        warm.push(t0.elapsed());
+        conn.close(0u32.into(), b"bye!");
+    }
+
+    let cold_summary = Stats::from_rtts(&cold);
+    let warm_summary = Stats::from_rtts(&warm);
+    if config.print_results {
+        if let Some(summary) = &cold_summary {
+            print_latency_stats("Cold connect time", summary);
+        }
+        if let Some(summary) = &warm_summary {
+            print_latency_stats("Warm connect time", summary);
+        }
+    }
+    Ok(Benchmark::Connect {
+        cold_ms: cold.into_iter().map(millis).collect(),
+        warm_ms: warm.into_iter().map(millis).collect(),
+        cold: cold_summary,
+        warm: warm_summary,
+    })
+}
+
+/// Connects to the server from a fresh endpoint per trial and waits for the connection to
+/// upgrade to a direct path, recording whether and when it did.
+///
+/// Only trials that started on the relay or a mixed path count towards the success rate, those
+/// that were direct from the start had no hole to punch and are counted separately.
+pub async fn run_holepunch(
+    addr: NodeAddr,
+    config: &BenchmarkConfig,
+    holepunch: &HolePunchConfig,
+) -> Result<Benchmark> {
+    anyhow::ensure!(!config.endpoint.relay_only, "Hole punching cannot succeed with --relay-only");
+
+    config.status(format!("\nStarting hole punching benchmark ({} trials):", holepunch.trials));
+    let mut trials = Vec::with_capacity(holepunch.trials);
+    let mut times_to_direct = Vec::new();
+    for i in 0..holepunch.trials {
+        let endpoint = config.endpoint.builder()?.bind().await?;
+        let t0 = Instant::now();
+        let conn = endpoint.connect(addr.clone(), ALPN).await?;
+        let initial_path = path_label(&endpoint.conn_type(addr.node_id)?.get()?);
+        let direct = wait_for_direct(&endpoint, addr.node_id, holepunch.timeout).await?;
+        let time_to_direct = direct.then(|| t0.elapsed());
+        config.status(match time_to_direct {
+            Some(elapsed) => {
+                format!("Trial {}: direct after {elapsed:.2?}, started {initial_path}", i + 1)
+            }
+            None => format!("Trial {}: no direct path, started {initial_path}", i + 1),
+        });
+        if initial_path != "direct" {
+            times_to_direct.extend(time_to_direct);
+        }
+        trials.push(HolePunchTrial {
+            initial_path: initial_path.to_string(),
+            direct,
+            time_to_direct_ms: time_to_direct.map(millis),
+        });
+        conn.close(0u32.into(), b"bye!");
+        endpoint.close().await;
+    }
+
+    let successes = times_to_direct.len();
+    let already_direct = trials.iter().filter(|trial| trial.initial_path == "direct").count();
+    let attempts = holepunch.trials - already_direct;
+    let success_rate = (attempts > 0).then(|| successes as f64 / attempts as f64);
+    let time_to_direct = Stats::from_rtts(&times_to_direct);
+    if config.print_results {
+        match success_rate {
+            Some(rate) => println!("Hole punched: {successes}/{attempts} ({:.1}%)", rate * 100.0),
+            None => println!("Hole punched: no trial started on the relay"),
+        }
+        println!("Direct from the start: {already_direct}/{}", holepunch.trials);
+        if let Some(summary) = &time_to_direct {
+            print_latency_stats("Time to direct", summary);
+        }
+    }
+    Ok(Benchmark::HolePunch {
+        timeout_secs: holepunch.timeout.as_secs_f64(),
+        successes,
+        already_direct,
+        success_rate,
+        trials,
+        time_to_direct,
+    })
+}
+
+/// Opens streams one after another on a single connection, exchanging a single byte on each,
+/// and measures how long `open_bi` takes and how long until the first byte comes back.
+pub async fn run_streams(
+    endpoint: &Endpoint,
+    addr: NodeAddr,
+    config: &BenchmarkConfig,
+    streams: &StreamsConfig,
+) -> Result<Benchmark> {
+    let conn = connect(endpoint, &addr, config).await?;
+
+    config.status(format!("\nStarting stream benchmark ({} streams):", streams.count));
+    let paths = PathTracker::start(endpoint, addr.node_id)?;
+    let mut opens = Vec::with_capacity(streams.count);
+    let mut first_bytes = Vec::with_capacity(streams.count);
+    let mut byte = [0u8; 1];
+    for _ in 0..streams.count {
+        let t0 = Instant::now();
+        let (mut send, mut recv) = conn.open_bi().await?;
+        opens.push(t0.elapsed());
+        Request::Ping { size: 1 }.write(&mut send).await?;
+        send.write_all(&byte).await?;
+        Response::read(&mut recv).await?;
+        recv.read_exact(&mut byte).await?;
+        first_bytes.push(t0.elapsed());
+        send.finish()?;
+        recv.read_to_end(0).await?;
+    }
+    let connection_type = paths.finish()?;
+    ensure_direct(config, &connection_type)?;
+    conn.close(0u32.into(), b"bye!");
+
+    let open = Stats::from_rtts(&opens);
+    let first_byte = Stats::from_rtts(&first_bytes);
+    if config.print_results {
+        println!("Connection type: {connection_type}");
+        if let Some(summary) = &open {
+            print_latency_stats("Stream open time", summary);
+        }
+        if let Some(summary) = &first_byte {
+            print_latency_stats("Time to first byte", summary);
+        }
+    }
+    Ok(Benchmark::Streams {
+        connection_type,
+        open_ms: opens.into_iter().map(millis).collect(),
+        first_byte_ms: first_bytes.into_iter().map(millis).collect(),
+        open,
+        first_byte,
+    })
+}
+
+/// Measures the time from dialing until the first response arrives, once sending the request
+/// after the handshake completes and once sending it as 0-RTT data, resuming the session.
+///
+/// Every trial binds a fresh endpoint. iroh keeps the session tickets of an endpoint to itself,
+/// so an unmeasured connection obtains the ticket, which also finds the path to the server. Both
+/// variants thus resume the session on a known path, and only differ in when the request is sent.
+pub async fn run_resume(
+    addr: NodeAddr,
+    config: &BenchmarkConfig,
+    resume: &ResumeConfig,
+) -> Result<Benchmark> {
+    let mut cold = Vec::new();
+    if !resume.warm {
+        config.status(format!("\nStarting 1-RTT connect benchmark ({} trials):", resume.trials));
+        for i in 0..resume.trials {
+            config.status(format!("Trial {}", i + 1));
+            let endpoint = resumable_endpoint(&addr, config).await?;
+            let t0 = Instant::now();
+            let conn = endpoint.connect(addr.clone(), ALPN).await?;
+            first_response(&conn).await?;
+            cold.push(t0.elapsed());
+            conn.close(0u32.into(), b"bye!");
+            endpoint.close().await;
+        }
+    }
+
+    let mut warm = Vec::new();
+    let mut accepted = 0;
+    if !resume.cold {
+        config.status(format!("\nStarting 0-RTT connect benchmark ({} trials):", resume.trials));
+        for i in 0..resume.trials {
+            let endpoint = resumable_endpoint(&addr, config).await?;
+            let t0 = Instant::now();
+            let connecting =
+                endpoint.connect_with_opts(addr.clone(), ALPN, ConnectOptions::new()).await?;
+            let (conn, zero_rtt) = match connecting.into_0rtt() {
+                Ok((conn, zero_rtt_accepted)) => {
+                    // Streams opened in 0-RTT fail if the server rejects the early data, in
+                    // which case the request is repeated after the handshake
+                    let res = first_response(&conn).await;
+                    let zero_rtt = zero_rtt_accepted.await;
+                    if zero_rtt {
+                        res?;
+                    } else {
+                        first_response(&conn).await?;
+                    }
+                    (conn, zero_rtt)
+                }
+                Err(connecting) => {
+                    let conn = connecting.await?;
+                    first_response(&conn).await?;
+                    (conn, false)
+                }
+            };
+            warm.push(t0.elapsed());
+            let outcome = if zero_rtt { "accepted" } else { "rejected" };
+            config.status(format!("Trial {}: 0-RTT {outcome}", i + 1));
+            accepted += zero_rtt as usize;
+            conn.close(0u32.into(), b"bye!");
+            endpoint.close().await;
+        }
+    }
+
+    let cold_summary = Stats::from_rtts(&cold);
+    let warm_summary = Stats::from_rtts(&warm);
+    if config.print_results {
+        if let Some(summary) = &cold_summary {
+            print_latency_stats("1-RTT time to first response", summary);
+        }
+        if let Some(summary) = &warm_summary {
+            println!("0-RTT accepted: {accepted}/{}", warm.len());
+            print_latency_stats("Resumed time to first response", summary);
+        }
+    }
+    Ok(Benchmark::Resume {
+        cold_ms: cold.into_iter().map(millis).collect(),
+        warm_ms: warm.into_iter().map(millis).collect(),
+        zero_rtt_accepted: accepted,
+        cold: cold_summary,
+        warm: warm_summary,
+    })
+}
+
+/// Has the server import a blob of every payload size of `config` into its blob store and
+/// fetches it `iterations` times with the iroh-blobs protocol, measuring how long each fetch
+/// takes until the blob is received and verified.
+pub async fn run_blobs(
+    endpoint: &Endpoint,
+    addr: NodeAddr,
+    config: &BenchmarkConfig,
+) -> Result<Benchmark> {
+    let conn = connect(endpoint, &addr, config).await?;
+    let blobs = endpoint
+        .connect(addr.clone(), iroh_blobs::ALPN)
+        .await
+        .context("Connecting to the blob store failed, is the server started with --blobs?")?;
+    let paths = PathTracker::start(endpoint, addr.node_id)?;
+    let mut results = Vec::with_capacity(config.sizes.len());
+    for &size in &config.sizes {
+        let (mut send, mut recv) = conn.open_bi().await?;
+        let (pattern, seed) = (config.pattern, config.seed);
+        Request::Blob { size, pattern, seed }.write(&mut send).await?;
+        Response::read(&mut recv).await?;
+        let mut hash = [0u8; 32];
+        recv.read_exact(&mut hash).await?;
+        let hash = iroh_blobs::Hash::from_bytes(hash);
+
+        config.status(format!(
+            "\nStarting blob benchmark ({} iterations, {} warmup, {}):",
+            config.iterations,
+            config.warmup,
+            format_size(size)
+        ));
+        let mut fetches = Vec::with_capacity(config.iterations as usize);
+        for i in 0..config.warmup + config.iterations {
+            let elapsed = fetch_blob(&blobs, hash).await?;
+            if i < config.warmup {
+                config.status(format!("Warmup {}: {elapsed:.2?}", i + 1));
+                continue;
+            }
+            let mbps = bandwidth(size as usize, elapsed);
+            config.status(format!("Iteration {}: {mbps:.2} Mbit/s", i - config.warmup + 1));
+            fetches.push(elapsed);
+        }
+        // Finishing the request stream lets the server release the blob
+        send.finish()?;
+        recv.read_to_end(0).await?;
+
+        let download_mbps: Vec<f64> =
+            fetches.iter().map(|elapsed| bandwidth(size as usize, *elapsed)).collect();
+        let download = Stats::from_samples(&download_mbps);
+        if config.print_results {
+            println!("\nBlob {} ({}):", format_size(size), hash.to_hex());
+            print_stats("Fetch", download);
+        }
+        results.push(BlobResult {
+            size,
+            hash: hash.to_hex().to_string(),
+            fetch_ms: fetches.into_iter().map(millis).collect(),
+            download_mbps,
+            download,
+        });
+    }
+    let connection_type = paths.finish()?;
+    ensure_direct(config, &connection_type)?;
+    blobs.close(0u32.into(), b"bye!");
+    conn.close(0u32.into(), b"bye!");
+    if config.print_results {
+        println!("Connection type: {connection_type}");
+    }
+    Ok(Benchmark::Blobs { pattern: config.pattern, seed: config.seed, connection_type, results })
+}
+
+/// Fetches the blob `hash` with the iroh-blobs protocol on `conn`, verifying it as it arrives,
+/// and returns how long it took.
+async fn fetch_blob(conn: &Connection, hash: iroh_blobs::Hash) -> Result<Duration> {
+    let t0 = Instant::now();
+    let request = iroh_blobs::get::fsm::start(conn.clone(), GetRequest::single(hash));
+    let ConnectedNext::StartRoot(root) = request.next().await?.next().await? else {
+        anyhow::bail!("The server did not send the blob");
+    };
+    let EndBlobNext::Closing(closing) = root.next().drain().await?.next() else {
+        anyhow::bail!("The server sent more than the blob");
+    };
+    closing.next().await?;
+    Ok(t0.elapsed())
+}
+
+/// Binds a fresh endpoint and connects it to the server once, so that it holds a session ticket.
+async fn resumable_endpoint(addr: &NodeAddr, config: &BenchmarkConfig) -> Result<Endpoint> {
+    let endpoint = config.endpoint.builder()?.bind().await?;
+    let conn = endpoint.connect(addr.clone(), ALPN).await?;
+    first_response(&conn).await?;
+    conn.close(0u32.into(), b"bye!");
+    Ok(endpoint)
+}
+
+/// Sends a one-byte ping on a new stream and waits for the echo.
+async fn first_response(conn: &Connection) -> Result<()> {
+    let (mut send, mut recv) = conn.open_bi().await?;
+    Request::Ping { size: 1 }.write(&mut send).await?;
+    send.write_all(&[0]).await?;
+    Response::read(&mut recv).await?;
+    recv.read_exact(&mut [0]).await?;
+    send.finish()?;
+    Ok(())
+}
+
+/// Prints min/avg/max and percentiles of measured latencies.
+pub(crate) fn print_latency_stats(label: &str, summary: &Stats) {
+    println!("{label} statistics (ms):");
+    println!("  Min: {:.3}", summary.min);
+    println!("  Average: {:.3}", summary.average);
+    println!("  Max: {:.3}", summary.max);
+    println!("  Std dev: {:.3} (CV {:.1}%)", summary.stddev, summary.cv * 100.0);
+    println!("  p5: {:.3}", summary.p5);
+    println!("  p50: {:.3}", summary.median);
+    println!("  p95: {:.3}", summary.p95);
+    println!("  p99: {:.3}", summary.p99);
+    println!("  p99.9: {:.3}", summary.p999);
+}
+
+/// Prints congestion and loss counters, which put the measured bandwidth into context.
+fn print_transport_stats(label: &str, stats: &TransportStats) {
+    println!("{label}:");
+    println!("  RTT: {:.3} ms", stats.rtt_ms);
+    println!("  Congestion window: {}", format_size(stats.cwnd));
+    println!("  Congestion events: {}", stats.congestion_events);
+    println!(
+        "  Lost packets: {} of {} ({})",
+        stats.lost_packets,
+        stats.sent_packets,
+        format_size(stats.lost_bytes)
+    );
+    if stats.mtu > 0 {
+        println!("  Path MTU: {} bytes", stats.mtu);
+    }
+}
+
+/// Prints summary statistics for a set of bandwidth samples, if there are any.
+fn print_stats(label: &str, summary: Option<Stats>) {
+    let Some(summary) = summary else {
+        return;
+    };
+
+    println!("{label} bandwidth statistics (Mbit/s):");
+    println!("  Average: {:.2}", summary.average);
+    println!("  Median: {:.2}", summary.median);
+    println!("  Min: {:.2}", summary.min);
+    println!("  Max: {:.2}", summary.max);
+    println!("  Std dev: {:.2} (CV {:.1}%)", summary.stddev, summary.cv * 100.0);
+    println!("  p5: {:.2}", summary.p5);
+    println!("  p95: {:.2}", summary.p95);
+}
+
+/// Connection benchmark streams are opened on: an iroh connection, or a plain QUIC connection of
+/// the QUIC baseline.
+pub(crate) trait OpenStreams {
+    fn open_bi(&self) -> OpenBi<'_>;
+}
+
+impl OpenStreams for Connection {
+    fn open_bi(&self) -> OpenBi<'_> {
+        Connection::open_bi(self)
+    }
+}
+
+impl OpenStreams for quinn::Connection {
+    fn open_bi(&self) -> OpenBi<'_> {
+        quinn::Connection::open_bi(self)
+    }
+}
+
+/// Transfers the payload striped across parallel streams of one connection and measures how
+/// long each direction took.
+pub(crate) async fn benchmark_transfer(
+    conn: &impl OpenStreams,
+    transfer: Transfer,
+    counters: &Counters,
+) -> Result<Timing> {
+    let timings = benchmark_streams(conn, transfer, counters).await?;
+    // The transfer is only complete once the slowest stream finished
+    Ok(Timing::slowest(&timings))
+}
+
+/// Like [`benchmark_transfer`], but returns how long each direction took on every stream, in
+/// the order of the parts of [`stripe`].
+async fn benchmark_streams(
+    conn: &impl OpenStreams,
+    transfer: Transfer,
+    counters: &Counters,
+) -> Result<Vec<Timing>> {
+    // Start timing before the first request goes out
+    let t0 = Instant::now();
+    let parts = stripe(transfer.size as usize, transfer.streams as usize);
+    let bitrate = transfer.bitrate / transfer.streams as u64;
+    try_join_all(parts.map(|part| {
+        transfer_stream(conn, Transfer { size: part as u64, bitrate, ..transfer }, t0, counters)
+    }))
+    .await
+}
+
+/// Splits `size` bytes into `streams` parts that differ by at most one byte.
+pub(crate) fn stripe(size: usize, streams: usize) -> impl Iterator<Item = usize> {
+    (0..streams).map(move |i| size / streams + usize::from(i < size % streams))
+}
+
+/// Time it took from the start of a transfer until each direction completed.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Timing {
+    upload: Option<Duration>,
+    download: Option<Duration>,
+    /// The server's own measurements, if requested with [`Transfer::report`] and supported.
+    server: Option<ServerReport>,
+}
+
+impl Timing {
+    /// The timing of parallel transfers, which completed once the slowest of them did.
+    pub(crate) fn slowest(timings: &[Timing]) -> Self {
+        Timing {
+            upload: timings.iter().filter_map(|t| t.upload).max(),
+            download: timings.iter().filter_map(|t| t.download).max(),
+            server: timings.iter().filter_map(|t| t.server).reduce(merge_reports),
+        }
+    }
+
+    /// Converts the timings of a transfer of `size` bytes per direction into bandwidths.
+    pub(crate) fn to_sample(self, size: usize) -> Sample {
+        Sample {
+            upload: self.upload.map(|elapsed| bandwidth(size, elapsed)),
+            download: self.download.map(|elapsed| bandwidth(size, elapsed)),
+        }
+    }
+}
+
+/// Runs a single fixed-size transfer on a new stream, timed relative to `t0`.
+async fn transfer_stream(
+    conn: &impl OpenStreams,
+    transfer: Transfer,
+    t0: Instant,
+    counters: &Counters,
+) -> Result<Timing> {
+    let counters = &counters.stream(Some(transfer.size));
+    let (mut send, mut recv) = conn.open_bi().await?;
+    trace!(stream = %send.id(), ?transfer, "Requesting transfer");
+    Request::Transfer(transfer).write(&mut send).await?;
+    let (size, chunk_size) = (transfer.size as usize, transfer.chunk_size as usize);
+    let mut payload = Payload::new(transfer.pattern, transfer.seed);
+    let mut pacer = Pacer::new(transfer.bitrate, chunk_size);
+    let mut sent_hash = transfer.verify.then(blake3::Hasher::new);
+    let mut received_hash = transfer.verify.then(blake3::Hasher::new);
+
+    let mut timing = Timing::default();
+    match transfer.direction {
+        Direction::Upload => {
+            // A rejected request makes the server stop reading, explained by its response
+            let hash = sent_hash.as_mut();
+            let res =
+                send_payload(&mut send, size, chunk_size, &mut payload, &mut pacer, counters, hash)
+                    .await;
+            if let Err(err) = res {
+                Response::read(&mut recv).await?;
+                return Err(err);
+            }
+            send.finish()?;
+            Response::read(&mut recv).await?;
+
+            // Wait for small acknowledgment from server
+            let trailer = recv.read_to_end(transfer.trailer_len()).await?;
+            timing.upload = Some(t0.elapsed());
+            let (trailer, server) = split_report(transfer, &trailer)?;
+            timing.server = server;
+            let (ack, hash) = trailer.split_at(ACK.len().min(trailer.len()));
+            anyhow::ensure!(ack == ACK, "The server sent an invalid acknowledgment");
+            if let Some(sent) = sent_hash {
+                check_hash("uploaded", sent.finalize(), hash)?;
+            }
+        }
+        Direction::Download => {
+            send.finish()?;
+            Response::read(&mut recv).await?;
+            receive_payload(&mut recv, size, chunk_size, counters, received_hash.as_mut()).await?;
+            timing.download = Some(t0.elapsed());
+            let trailer = recv.read_to_end(transfer.trailer_len()).await?;
+            let (trailer, server) = split_report(transfer, &trailer)?;
+            timing.server = server;
+            if let Some(received) = received_hash {
+                check_hash("downloaded", received.finalize(), trailer)?;
+            }
+        }
+        Direction::Both | Direction::Echo => {
+            let upload = async {
+                let hash = sent_hash.as_mut();
+                let pacer = &mut pacer;
+                send_payload(&mut send, size, chunk_size, &mut payload, pacer, counters, hash)
+                    .await?;
+                send.finish()?;
+
+                // The server is busy sending its own payload, so rely on the transport
+                // acknowledging all of our stream data instead of an application-level ack
+                send.stopped().await?;
+                anyhow::Ok(t0.elapsed())
+            };
+            let download = async {
+                Response::read(&mut recv).await?;
+                let hash = received_hash.as_mut();
+                receive_payload(&mut recv, size, chunk_size, counters, hash).await?;
+                let elapsed = t0.elapsed();
+                let trailer = recv.read_to_end(transfer.trailer_len()).await?;
+                anyhow::Ok((elapsed, trailer))
+            };
+            // Report the download error first, it carries the reason of a rejection
+            let (upload_time, download) = tokio::join!(upload, download);
+            let (download_time, trailer) = download?;
+            timing.download = Some(download_time);
+            timing.upload = Some(upload_time?);
+            let (trailer, server) = split_report(transfer, &trailer)?;
+            timing.server = server;
+
+            if let (Some(sent), Some(received)) = (sent_hash, received_hash) {
+                match transfer.direction {
+                    Direction::Echo => {
+                        check_hash("echoed", sent.finalize(), received.finalize().as_bytes())?;
+                    }
+                    _ => {
+                        let split = blake3::OUT_LEN.min(trailer.len());
+                        let (server_sent, server_received) = trailer.split_at(split);
+                        check_hash("downloaded", received.finalize(), server_sent)?;
+                        check_hash("uploaded", sent.finalize(), server_received)?;
+                    }
+                }
+            }
+        }
+    }
+
+    trace!(?timing, "Stream complete");
+    Ok(timing)
+}
+
+/// Splits the [`ServerReport`] off the end of a transfer's trailer, if the server appended one.
+fn split_report(transfer: Transfer, trailer: &[u8]) -> Result<(&[u8], Option<ServerReport>)> {
+    if !transfer.report || trailer.len() < transfer.trailer_len() {
+        return Ok((trailer, None));
+    }
+    let (rest, report) = trailer.split_at(trailer.len() - ServerReport::LEN);
+    Ok((rest, Some(ServerReport::decode(report)?)))
+}
+
+/// Combines the reports of parallel streams: their bytes add up, their durations are those of
+/// the slowest stream, which also started first and finished last. The streams of one connection
+/// share its statistics, so these are taken from the stream that saw the most of the transfer.
+fn merge_reports(a: ServerReport, b: ServerReport) -> ServerReport {
+    let longest = if a.sent_packets >= b.sent_packets { a } else { b };
+    ServerReport {
+        received: a.received + b.received,
+        sent: a.sent + b.sent,
+        receive_us: a.receive_us.max(b.receive_us),
+        send_us: a.send_us.max(b.send_us),
+        started_us: a.started_us.min(b.started_us),
+        finished_us: a.finished_us.max(b.finished_us),
+        ..longest
+    }
+}
+
+/// Fails unless the hash of our side of the data matches the one of the other side.
+fn check_hash(what: &str, ours: blake3::Hash, theirs: &[u8]) -> Result<()> {
+    // Servers that do not know `verify` ignore it and send no hashes
+    anyhow::ensure!(
+        theirs.len() == blake3::OUT_LEN,
+        "The server sent no hash of the {what} data, it may not support --verify"
+    );
+    anyhow::ensure!(
+        ours.as_bytes()[..] == *theirs,
+        "Payload verification failed: {what} data hashes to {ours} here but to {} on the other end",
+        hex::encode(theirs),
+    );
+    Ok(())
+}
+
+/// Streams data on parallel streams for a fixed wall-clock duration and measures the aggregate
+/// sustained bandwidth.
+pub(crate) async fn benchmark_timed(
+    conn: &impl OpenStreams,
+    duration: Duration,
+    transfer: Transfer,
+    counters: &Counters,
+) -> Result<Sample> {
+    let t0 = Instant::now();
+    let deadline = t0 + duration;
+    let transfer = Transfer { bitrate: transfer.bitrate / transfer.streams as u64, ..transfer };
+    let samples = try_join_all(
+        (0..transfer.streams).map(|_| timed_stream(conn, t0, deadline, transfer, counters)),
+    )
+    .await?;
+
+    // Parallel streams share the time window, so their bandwidths add up
+    let sum = |bandwidths: Vec<f64>| (!bandwidths.is_empty()).then(|| bandwidths.iter().sum());
+    Ok(Sample {
+        upload: sum(samples.iter().filter_map(|s| s.upload).collect()),
+        download: sum(samples.iter().filter_map(|s| s.download).collect()),
+    })
+}
+
+/// Streams data on a single new stream from `t0` until `deadline`.
+async fn timed_stream(
+    conn: &impl OpenStreams,
+    t0: Instant,
+    deadline: Instant,
+    transfer: Transfer,
+    counters: &Counters,
+) -> Result<Sample> {
+    let counters = &counters.stream(None);
+    let (mut send, mut recv) = conn.open_bi().await?;
+    Request::Transfer(transfer).write(&mut send).await?;
+    let chunk_size = transfer.chunk_size as usize;
+    let mut payload = Payload::new(transfer.pattern, transfer.seed);
+    let mut pacer = Pacer::new(transfer.bitrate, chunk_size);
+
+    let mut sample = Sample::default();
+    match transfer.direction {
+        Direction::Upload => {
+            let sent = send_until(&mut send, deadline, chunk_size, &mut payload, &mut pacer, counters);
+            let sent = match sent.await {
+                Ok(sent) => sent,
+                Err(err) => {
+                    Response::read(&mut recv).await?;
+                    return Err(err);
+                }
+            };
+            send.finish()?;
+            Response::read(&mut recv).await?;
+            let ack = recv.read_to_end(ACK.len()).await?;
+            anyhow::ensure!(ack == ACK, "The server sent an invalid acknowledgment");
+            sample.upload = Some(bandwidth(sent, t0.elapsed()));
+        }
+        Direction::Download => {
+            send.finish()?;
+            Response::read(&mut recv).await?;
+            let received = receive_until(&mut recv, deadline, chunk_size, counters).await?;
+            sample.download = Some(bandwidth(received, t0.elapsed()));
+        }
+        Direction::Both | Direction::Echo => {
+            let upload = async {
+                let pacer = &mut pacer;
+                let sent =
+                    send_until(&mut send, deadline, chunk_size, &mut payload, pacer, counters).await?;
+                send.finish()?;
+                send.stopped().await?;
+                anyhow::Ok(bandwidth(sent, t0.elapsed()))
+            };
+            let download = async {
+                Response::read(&mut recv).await?;
+                let received = receive_until(&mut recv, deadline, chunk_size, counters).await?;
+                anyhow::Ok(bandwidth(received, t0.elapsed()))
+            };
+            let (up, down) = tokio::join!(upload, download);
+            sample.download = Some(down?);
+            sample.upload = Some(up?);
+        }
+    }
+    Ok(sample)
+}
+
+/// Writes chunks until the deadline passes, returning the number of bytes sent.
+async fn send_until(
+    send: &mut SendStream,
+    deadline: Instant,
+    chunk_size: usize,
+    payload: &mut Payload,
+    pacer: &mut Pacer,
+    counters: &StreamCounters,
+) -> Result<usize> {
+    let mut chunk = vec![0u8; chunk_size];
+    let mut sent = 0;
+    while Instant::now() < deadline {
+        payload.fill(&mut chunk);
+        pacer.wait(chunk.len()).await;
+        let start = Instant::now();
+        send.write_all(&chunk).await?;
+        counters.record_chunk(start.elapsed());
+        sent += chunk.len();
+        counters.add_sent(chunk.len());
+    }
+    Ok(sent)
+}
+
+/// Writes `size` bytes of `payload` in chunks of `chunk_size`, reusing a single buffer so that
+/// the payload is never held in memory as a whole.
+async fn send_payload(
+    send: &mut SendStream,
+    size: usize,
+    chunk_size: usize,
+    payload: &mut Payload,
+    pacer: &mut Pacer,
+    counters: &StreamCounters,
+    mut hasher: Option<&mut blake3::Hasher>,
+) -> Result<()> {
+    let mut chunk = vec![0u8; chunk_size.min(size)];
+    let mut remaining = size;
+    while remaining > 0 {
+        let n = remaining.min(chunk_size);
+        payload.fill(&mut chunk[..n]);
+        pacer.wait(n).await;
+        let start = Instant::now();
+        send.write_all(&chunk[..n]).await?;
+        counters.record_chunk(start.elapsed());
+        counters.add_sent(n);
+        if let Some(hasher) = hasher.as_mut() {
+            hasher.update(&chunk[..n]);
+        }
+        remaining -= n;
+    }
+    Ok(())
+}
+
+/// Counts received bytes until the deadline passes, then asks the server to stop sending.
+///
+/// Data still in flight when the deadline passes is not counted.
+async fn receive_until(
+    recv: &mut RecvStream,
+    deadline: Instant,
+    chunk_size: usize,
+    counters: &StreamCounters,
+) -> Result<usize> {
+    let mut buf = vec![0u8; chunk_size];
+    let mut received = 0;
+    let mut start = Instant::now();
+    // Count until the deadline, or until the server ends the stream early
+    while let Ok(read) = timeout_at(deadline, recv.read(&mut buf)).await {
+        match read? {
+            Some(n) => {
+                counters.record_chunk(start.elapsed());
+                start = Instant::now();
+                received += n;
+                counters.add_received(n);
+            }
+            None => return Ok(received),
+        }
+    }
+    recv.stop(0u32.into())?;
+    Ok(received)
+}
+
+/// Drains `size` bytes of payload sent by the server into a reusable buffer, only counting
+/// and optionally hashing them.
+async fn receive_payload(
+    recv: &mut RecvStream,
+    size: usize,
+    chunk_size: usize,
+    counters: &StreamCounters,
+    mut hasher: Option<&mut blake3::Hasher>,
+) -> Result<()> {
+    let mut buf = vec![0u8; chunk_size.min(size)];
+    let mut received = 0;
+    while received < size {
+        let limit = (size - received).min(chunk_size);
+        let start = Instant::now();
+        let Some(n) = recv.read(&mut buf[..limit]).await? else {
+            anyhow::bail!("Server sent {received} bytes, expected {size}");
+        };
+        counters.record_chunk(start.elapsed());
+        received += n;
+        counters.add_received(n);
+        if let Some(hasher) = hasher.as_mut() {
+            hasher.update(&buf[..n]);
+        }
+    }
+    Ok(())
+}
+
+/// Records which paths the connection to a node used while a benchmark ran.
+struct PathTracker {
+    watcher: Watcher<ConnectionType>,
+    paths: Arc<Mutex<Vec<&'static str>>>,
+    _task: AbortOnDropHandle<()>,
+}
+
+impl PathTracker {
+    /// Starts watching the connection type, the node must already be connected.
+    fn start(endpoint: &Endpoint, node_id: NodeId) -> Result<Self> {
+        let watcher = endpoint.conn_type(node_id)?;
+        let paths = Arc::new(Mutex::new(vec![path_label(&watcher.get()?)]));
+        let mut updates = watcher.clone().stream_updates_only();
+        let task = tokio::spawn({
+            let paths = paths.clone();
+            async move {
+                while let Some(conn_type) = updates.next().await {
+                    record_path(&paths, &conn_type);
+                }
+            }
+        });
+        Ok(Self { watcher, paths, _task: AbortOnDropHandle::new(task) })
+    }
+
+    /// Stops watching and describes the observed paths, e.g. `direct`, or `relay->direct` if
+    /// the path changed during the benchmark.
+    fn finish(self) -> Result<String> {
+        record_path(&self.paths, &self.watcher.get()?);
+        let paths = self.paths.lock().expect("poisoned");
+        Ok(paths.join("->"))
+    }
+}
+
+/// Appends the path of `conn_type` unless it is the same as the last recorded one.
+fn record_path(paths: &Mutex<Vec<&'static str>>, conn_type: &ConnectionType) {
+    let label = path_label(conn_type);
+    let mut paths = paths.lock().expect("poisoned");
+    if paths.last() != Some(&label) {
+        paths.push(label);
+    }
+}
+
+/// Records every change of the path to a node over a soak test, with when it happened.
+struct PathLog {
+    start: Instant,
+    /// Time into the soak test the log started, and the path at that time.
+    since: Duration,
+    initial: &'static str,
+    transitions: Arc<Mutex<Vec<PathTransition>>>,
+    /// Number of transitions [`PathLog::new_transitions`] already returned.
+    reported: usize,
+    _task: AbortOnDropHandle<()>,
+}
+
+impl PathLog {
+    /// Starts watching the connection type, the node must already be connected. Times are
+    /// relative to `start`, the start of the soak test.
+    fn start(endpoint: &Endpoint, node_id: NodeId, start: Instant) -> Result<Self> {
+        let watcher = endpoint.conn_type(node_id)?;
+        let initial = path_label(&watcher.get()?);
+        let transitions = Arc::new(Mutex::new(Vec::new()));
+        let mut updates = watcher.stream_updates_only();
+        let task = tokio::spawn({
+            let transitions = transitions.clone();
+            async move {
+                let mut last = initial;
+                while let Some(conn_type) = updates.next().await {
+                    let label = path_label(&conn_type);
+                    if label == last {
+                        continue;
+                    }
+                    transitions.lock().expect("poisoned").push(PathTransition {
+                        at: timestamp(SystemTime::now()),
+                        elapsed_secs: start.elapsed().as_secs_f64(),
+                        from: last.to_string(),
+                        to: label.to_string(),
+                        before_mbps: None,
+                        after_mbps: None,
+                    });
+                    last = label;
+                }
+            }
+        });
+        Ok(Self {
+            start,
+            since: start.elapsed(),
+            initial,
+            transitions,
+            reported: 0,
+            _task: AbortOnDropHandle::new(task),
+        })
+    }
+
+    /// The transitions since the previous call.
+    fn new_transitions(&mut self) -> Vec<PathTransition> {
+        let transitions = self.transitions.lock().expect("poisoned");
+        let new = transitions[self.reported..].to_vec();
+        self.reported = transitions.len();
+        new
+    }
+
+    /// Stops watching and adds up the time on every path, and the median throughput of the
+    /// `throughput` samples within [`PATH_IMPACT_WINDOW`] before and after every transition.
+    fn finish(self, throughput: &[ThroughputSample]) -> PathStability {
+        let end = self.start.elapsed().as_secs_f64();
+        let mut transitions = std::mem::take(&mut *self.transitions.lock().expect("poisoned"));
+        let mut time_on_path: BTreeMap<String, f64> = BTreeMap::new();
+        let (mut path, mut from) = (self.initial.to_string(), self.since.as_secs_f64());
+        for transition in &mut transitions {
+            *time_on_path.entry(path).or_default() += transition.elapsed_secs - from;
+            (path, from) = (transition.to.clone(), transition.elapsed_secs);
+
+            let at_ms = transition.elapsed_secs * 1000.0;
+            let window_ms = millis(PATH_IMPACT_WINDOW);
+            let median = |range: std::ops::Range<f64>| {
+                let mbps: Vec<f64> = throughput
+                    .iter()
+                    .filter(|sample| range.contains(&sample.elapsed_ms))
+                    .map(|sample| sample.mbps)
+                    .collect();
+                Stats::from_samples(&mbps).map(|stats| stats.median)
+            };
+            transition.before_mbps = median(at_ms - window_ms..at_ms);
+            // A sample covers the second before it, so the first one after the change still
+            // partly precedes it
+            let first_after = at_ms + millis(PATH_SAMPLE_INTERVAL);
+            transition.after_mbps = median(first_after..first_after + window_ms);
+        }
+        *time_on_path.entry(path).or_default() += end - from;
+        let total = end - self.since.as_secs_f64();
+        let time_on_path_percent = time_on_path
+            .into_iter()
+            .map(|(path, secs)| (path, if total > 0.0 { secs / total * 100.0 } else { 0.0 }))
+            .collect();
+        PathStability { transitions, time_on_path_percent }
+    }
+}
+
+/// Appends the throughput of both directions of the transfer counted by `counters` to `samples`
+/// every [`PATH_SAMPLE_INTERVAL`], until the returned handle is dropped. Samples are timed from
+/// `start`.
+fn spawn_throughput_sampler(
+    counters: Arc<Counters>,
+    start: Instant,
+    samples: Arc<Mutex<Vec<ThroughputSample>>>,
+) -> AbortOnDropHandle<()> {
+    let task = tokio::spawn(async move {
+        let mut last_tick = Instant::now();
+        let mut ticks = interval_at(last_tick + PATH_SAMPLE_INTERVAL, PATH_SAMPLE_INTERVAL);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut last_moved = 0;
+        loop {
+            let now = ticks.tick().await;
+            let moved = counters.sent() + counters.received();
+            let mbps = bandwidth((moved - last_moved) as usize, now - last_tick);
+            let sample = ThroughputSample { elapsed_ms: millis(now - start), mbps };
+            samples.lock().expect("poisoned").push(sample);
+            (last_moved, last_tick) = (moved, now);
+        }
+    });
+    AbortOnDropHandle::new(task)
+}
+
+/// Samples the average RTT estimate of a set of connections every [`RTT_SAMPLE_INTERVAL`].
+struct RttSampler {
+    samples: Arc<Mutex<Vec<RttSample>>>,
+    _task: AbortOnDropHandle<()>,
+}
+
+impl RttSampler {
+    fn start(conns: &[Connection]) -> Self {
+        let samples = Arc::new(Mutex::new(Vec::new()));
+        let task = tokio::spawn({
+            let samples = samples.clone();
+            let conns = conns.to_vec();
+            async move {
+                let start = Instant::now();
+                let mut ticker = interval_at(start, RTT_SAMPLE_INTERVAL);
+                loop {
+                    let now = ticker.tick().await;
+                    let rtt = conns.iter().map(|conn| conn.rtt()).sum::<Duration>()
+                        / conns.len().max(1) as u32;
+                    let sample = RttSample { elapsed_ms: millis(now - start), rtt_ms: millis(rtt) };
+                    samples.lock().expect("poisoned").push(sample);
+                }
+            }
+        });
+        Self { samples, _task: AbortOnDropHandle::new(task) }
+    }
+
+    /// Stops sampling and returns the samples taken so far.
+    fn finish(self) -> Vec<RttSample> {
+        std::mem::take(&mut *self.samples.lock().expect("poisoned"))
+    }
+}
+
+/// Prints the throughput of every `--interval` based on the shared counters, until the returned
+/// handle is dropped. A zero interval disables reporting.
+///
+/// With a dashboard, the counters are handed to it instead.
+fn spawn_reporter(
+    counters: Arc<Counters>,
+    config: &BenchmarkConfig,
+) -> Option<AbortOnDropHandle<()>> {
+    if let Some(progress) = &config.progress {
+        progress.track(counters.clone());
+    }
+    // Progress goes to the dashboard if there is one, but the sink still wants every interval
+    let print = config.progress.is_none();
+    let sink = config.interval_sink.clone();
+    let interval = config.interval;
+    if interval.is_zero() || (!print && sink.is_none()) {
+        return None;
+    }
+    let task = tokio::spawn(async move {
+        let start = Instant::now();
+        let mut ticker = interval_at(start + interval, interval);
+        let (mut last_sent, mut last_received) = (0, 0);
+        loop {
+            let now = ticker.tick().await;
+            let sent = counters.sent();
+            let received = counters.received();
+
+            let from = (now - interval - start).as_secs_f64();
+            let to = (now - start).as_secs_f64();
+            let upload = (sent > 0).then(|| bandwidth((sent - last_sent) as usize, interval));
+            let download =
+                (received > 0).then(|| bandwidth((received - last_received) as usize, interval));
+            if print {
+                let mut line = format!("[{from:6.2}-{to:6.2} s]");
+                if let Some(mbps) = upload {
+                    line += &format!("  up {mbps:10.2} Mbit/s");
+                }
+                if let Some(mbps) = download {
+                    line += &format!("  down {mbps:10.2} Mbit/s");
+                }
+                eprintln!("{line}");
+            }
+            if let Some(sink) = &sink {
+                // The receiver only goes away once the benchmark is over
+                let _ = sink.send(IntervalSample { time: SystemTime::now(), upload, download });
+            }
+            (last_sent, last_received) = (sent, received);
+        }
+    });
+    Some(AbortOnDropHandle::new(task))
+}
+
+/// Calculates bandwidth in Mbit/s (only counting the payload, not the request or ack).
+pub(crate) fn bandwidth(size: usize, elapsed: Duration) -> f64 {
+    (size as f64 / elapsed.as_secs_f64()) * 8.0 / 1_000_000.0
+}
@@ -0,0 +1,99 @@
+//! Offset between the clocks of the client and the server, estimated from timestamp probes the
+//! way NTP does.
+//!
+//! A probe leaves the client at `t1` and reaches the server at `t2`, the server answers at `t3`
+//! and the answer arrives at `t4`, each time taken on the clock of its host. If the probe took
+//! as long in both directions, the server's clock is ahead by `((t2 - t1) + (t3 - t4)) / 2`.
+//! Queueing delays one direction more than the other, so the offset is taken from the probes
+//! with the shortest round trips, those that queued the least. As the clocks tick at slightly
+//! different rates, the offset drifts, which a burst of probes shows the longer it lasts.
+
+use serde::{Deserialize, Serialize};
+
+use crate::report::trend;
+
+/// Timestamps of one probe in microseconds since the UNIX epoch, see the [module](self) docs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProbeTimes {
+    pub client_sent: u64,
+    pub server_received: u64,
+    pub server_sent: u64,
+    pub client_received: u64,
+}
+
+impl ProbeTimes {
+    /// Time the probe spent on the network, without the time the server took to answer.
+    pub fn rtt_us(&self) -> i64 {
+        let total = self.client_received as i64 - self.client_sent as i64;
+        total - (self.server_sent as i64 - self.server_received as i64)
+    }
+
+    /// Offset of the server's clock from the client's, if the probe took as long in both
+    /// directions.
+    pub fn offset_us(&self) -> f64 {
+        let forward = self.server_received as i64 - self.client_sent as i64;
+        let backward = self.server_sent as i64 - self.client_received as i64;
+        (forward + backward) as f64 / 2.0
+    }
+
+    /// Delay of the probe from the client to the server, given the offset of the server's clock.
+    pub fn forward_us(&self, offset_us: f64) -> f64 {
+        (self.server_received as i64 - self.client_sent as i64) as f64 - offset_us
+    }
+
+    /// Delay of the answer from the server to the client, given the offset of the server's clock.
+    pub fn backward_us(&self, offset_us: f64) -> f64 {
+        (self.client_received as i64 - self.server_sent as i64) as f64 + offset_us
+    }
+}
+
+/// Offset and drift of the server's clock relative to the client's.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ClockSync {
+    /// Client time the offset refers to, in microseconds since the UNIX epoch.
+    pub reference_us: u64,
+    /// Offset of the server's clock at `reference_us`, positive if it is ahead.
+    pub offset_us: f64,
+    /// How fast the offset grows, in microseconds per second.
+    pub drift_ppm: f64,
+    /// Round trip of the fastest probe, half of which bounds the error of the offset.
+    pub rtt_us: i64,
+}
+
+impl ClockSync {
+    /// Fits a line through the offsets of the faster half of the probes over the time they were
+    /// sent, or returns `None` if there are no probes. Probes sent at the same time give no
+    /// drift.
+    pub fn estimate(probes: &[ProbeTimes]) -> Option<Self> {
+        let reference_us = probes.iter().map(|probe| probe.client_sent).min()?;
+        let mut fastest = probes.to_vec();
+        fastest.sort_by_key(ProbeTimes::rtt_us);
+        let rtt_us = fastest[0].rtt_us();
+        fastest.truncate(fastest.len().div_ceil(2));
+
+        // Seconds since the reference against offsets in microseconds, so the slope is in ppm
+        let points: Vec<(f64, f64)> = fastest
+            .iter()
+            .map(|probe| ((probe.client_sent - reference_us) as f64 / 1e6, probe.offset_us()))
+            .collect();
+        let n = points.len() as f64;
+        let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+        let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+        let drift_ppm = trend(&points).unwrap_or_default();
+        Some(Self { reference_us, offset_us: mean_y - drift_ppm * mean_x, drift_ppm, rtt_us })
+    }
+
+    /// Offset of the server's clock at client time `client_us`.
+    pub fn offset_at(&self, client_us: u64) -> f64 {
+        let elapsed_secs = (client_us as i64 - self.reference_us as i64) as f64 / 1e6;
+        self.offset_us + self.drift_ppm * elapsed_secs
+    }
+
+    /// Converts a time on the server's clock to the client's, both in microseconds since the
+    /// UNIX epoch.
+    pub fn to_client_us(&self, server_us: u64) -> f64 {
+        // The offset changes by millionths, so taking it at the server's time instead of the
+        // client's makes no difference
+        server_us as f64 - self.offset_at(server_us)
+    }
+}
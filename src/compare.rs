@@ -0,0 +1,603 @@
+//! Comparison of the headline metrics of two saved runs, as done by `client compare`, and of a
+//! run against fixed [`Thresholds`].
+//!
+//! Reports are matched by their scenario test name, or by their mode if they have none, and by
+//! their position among the reports with the same name.
+//!
+//! Metrics backed by several samples, such as the bandwidths of all iterations, are also tested
+//! for significance: a bootstrap confidence interval of the change of their average, and a
+//! permutation test of whether both runs could have measured the same distribution.
+
+use std::io::IsTerminal;
+
+use crate::payload::splitmix64;
+use crate::report::{Benchmark, BenchmarkReport, Stats, percentile};
+use crate::units::format_size;
+
+/// Number of resamples of the bootstrap and the permutation test.
+const RESAMPLES: usize = 10_000;
+
+/// Probability of a change this large by chance below which it counts as significant.
+const SIGNIFICANCE_LEVEL: f64 = 0.05;
+
+/// A headline number of a benchmark.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metric {
+    pub name: String,
+    pub unit: &'static str,
+    pub value: f64,
+    /// Whether larger values are better, as for bandwidths, rather than worse, as for latencies.
+    pub higher_is_better: bool,
+    pub kind: MetricKind,
+    /// Samples whose average the value is, such as the bandwidths of all iterations. Empty if the
+    /// value is a single measurement or a percentile.
+    pub samples: Vec<f64>,
+}
+
+/// What a metric measures, which decides the [`Thresholds`] it is checked against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricKind {
+    /// A bandwidth in Mbit/s.
+    Bandwidth,
+    /// The median of a latency distribution in milliseconds.
+    MedianLatency,
+    Other,
+}
+
+/// Limits a run has to stay within, e.g. to fail CI pipelines on performance regressions.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Thresholds {
+    /// Lowest acceptable bandwidth in Mbit/s.
+    pub min_bandwidth: Option<f64>,
+    /// Highest acceptable median latency in milliseconds.
+    pub max_latency_ms: Option<f64>,
+}
+
+impl Thresholds {
+    /// Describes every metric of `reports` that violates a threshold.
+    pub fn violations(&self, reports: &[BenchmarkReport]) -> Vec<String> {
+        let mut violations = Vec::new();
+        for (test, report) in label_tests(reports) {
+            for metric in report.benchmark.metrics() {
+                let (limit, violated) = match metric.kind {
+                    MetricKind::Bandwidth => match self.min_bandwidth {
+                        Some(min) => (min, metric.value < min),
+                        None => continue,
+                    },
+                    MetricKind::MedianLatency => match self.max_latency_ms {
+                        Some(max) => (max, metric.value > max),
+                        None => continue,
+                    },
+                    MetricKind::Other => continue,
+                };
+                if violated {
+                    let bound = if metric.higher_is_better { "minimum" } else { "maximum" };
+                    violations.push(format!(
+                        "{test}: {} is {:.3} {unit}, the {bound} is {limit:.3} {unit}",
+                        metric.name,
+                        metric.value,
+                        unit = metric.unit,
+                    ));
+                }
+            }
+        }
+        violations
+    }
+}
+
+/// Change of one metric between the baseline and the current run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricDelta {
+    pub name: String,
+    pub unit: &'static str,
+    pub baseline: f64,
+    pub current: f64,
+    /// Relative change in percent, `None` if the baseline is zero.
+    pub change_percent: Option<f64>,
+    pub verdict: Verdict,
+    /// Whether the change is statistically significant, if both runs have at least two samples.
+    pub significance: Option<Significance>,
+}
+
+/// Statistical significance of the change of an average between two runs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Significance {
+    /// 95% bootstrap confidence interval of the change of the average.
+    pub ci_low: f64,
+    pub ci_high: f64,
+    /// Two-sided p-value of a permutation test: the probability of a change at least this
+    /// large if both runs measured the same distribution.
+    pub p_value: f64,
+}
+
+impl Significance {
+    /// Tests whether the averages of `baseline` and `current` differ, or returns `None` if
+    /// either has fewer than two samples.
+    pub fn test(baseline: &[f64], current: &[f64]) -> Option<Self> {
+        if baseline.len() < 2 || current.len() < 2 {
+            return None;
+        }
+        let mean = |samples: &[f64]| samples.iter().sum::<f64>() / samples.len() as f64;
+        let observed = mean(current) - mean(baseline);
+        let mut rng = Rng::default();
+
+        // Changes of the averages of samples drawn with replacement from either run
+        let mut changes: Vec<f64> = (0..RESAMPLES)
+            .map(|_| {
+                let resample = |samples: &[f64], rng: &mut Rng| {
+                    (0..samples.len()).map(|_| samples[rng.below(samples.len())]).sum::<f64>()
+                        / samples.len() as f64
+                };
+                resample(current, &mut rng) - resample(baseline, &mut rng)
+            })
+            .collect();
+        changes.sort_by(f64::total_cmp);
+
+        // Changes of the averages after shuffling the samples of both runs together
+        let mut pooled: Vec<f64> = baseline.iter().chain(current).copied().collect();
+        let extreme = (0..RESAMPLES)
+            .filter(|_| {
+                rng.shuffle(&mut pooled);
+                let (base, cur) = pooled.split_at(baseline.len());
+                (mean(cur) - mean(base)).abs() >= observed.abs()
+            })
+            .count();
+
+        Some(Self {
+            ci_low: percentile(&changes, 2.5),
+            ci_high: percentile(&changes, 97.5),
+            p_value: (extreme + 1) as f64 / (RESAMPLES + 1) as f64,
+        })
+    }
+
+    pub fn is_significant(&self) -> bool {
+        self.p_value < SIGNIFICANCE_LEVEL
+    }
+}
+
+/// Deterministic random numbers, so that comparing the same files always gives the same result.
+#[derive(Debug, Default)]
+struct Rng(u64);
+
+impl Rng {
+    /// A random number below `n`.
+    fn below(&mut self, n: usize) -> usize {
+        self.0 += 1;
+        (splitmix64(self.0) % n as u64) as usize
+    }
+
+    fn shuffle(&mut self, values: &mut [f64]) {
+        for i in (1..values.len()).rev() {
+            values.swap(i, self.below(i + 1));
+        }
+    }
+}
+
+/// Whether a change is significant, relative to the comparison threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Unchanged,
+    Improved,
+    Regressed,
+}
+
+/// Comparison of the reports of one test.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestComparison {
+    /// Name of the test, with its position if the name occurs several times.
+    pub test: String,
+    /// Changes of the metrics present in both runs, empty if the test only ran in one of them.
+    pub deltas: Vec<MetricDelta>,
+    /// Whether the test is missing from the baseline or the current run.
+    pub missing: Option<&'static str>,
+}
+
+impl Benchmark {
+    /// Name of the benchmark, as in the `mode` field of the results.
+    pub fn mode(&self) -> &'static str {
+        match self {
+            Benchmark::Bandwidth { .. } => "bandwidth",
+            Benchmark::ChunkSweep { .. } => "chunk_sweep",
+            Benchmark::StreamSweep { .. } => "stream_sweep",
+            Benchmark::Timed { .. } => "timed",
+            Benchmark::Datagram { .. } => "datagram",
+            Benchmark::Ping { .. } => "ping",
+            Benchmark::Discover { .. } => "discover",
+            Benchmark::Connect { .. } => "connect",
+            Benchmark::Resume { .. } => "resume",
+            Benchmark::Streams { .. } => "streams",
+            Benchmark::HolePunch { .. } => "hole_punch",
+            Benchmark::Soak { .. } => "soak",
+            Benchmark::Gossip { .. } => "gossip",
+            Benchmark::Bufferbloat { .. } => "bufferbloat",
+            Benchmark::Rpc { .. } => "rpc",
+            Benchmark::Bursts { .. } => "bursts",
+            Benchmark::Messages { .. } => "messages",
+            Benchmark::Delay { .. } => "delay",
+            Benchmark::Migrate { .. } => "migrate",
+            Benchmark::Blobs { .. } => "blobs",
+        }
+    }
+
+    /// The headline metrics of the benchmark: average bandwidths, latency percentiles, loss and
+    /// success rates.
+    pub fn metrics(&self) -> Vec<Metric> {
+        let mut metrics = Vec::new();
+        match self {
+            Benchmark::Bandwidth { results, .. } => {
+                for result in results {
+                    let size = format_size(result.size);
+                    let samples = |download: bool| -> Vec<f64> {
+                        let iterations = result.iterations.iter();
+                        match download {
+                            false => iterations.filter_map(|it| it.aggregate.upload).collect(),
+                            true => iterations.filter_map(|it| it.aggregate.download).collect(),
+                        }
+                    };
+                    if let Some(upload) = result.upload {
+                        let name = format!("upload {size}");
+                        let metric = Metric::bandwidth(name, upload.average);
+                        metrics.push(Metric { samples: samples(false), ..metric });
+                    }
+                    if let Some(download) = result.download {
+                        let name = format!("download {size}");
+                        let metric = Metric::bandwidth(name, download.average);
+                        metrics.push(Metric { samples: samples(true), ..metric });
+                    }
+                }
+            }
+            Benchmark::ChunkSweep { results, .. } => {
+                for result in results {
+                    let chunk_size = format_size(result.chunk_size);
+                    let directions =
+                        [("upload", result.upload, false), ("download", result.download, true)];
+                    for (direction, stats, download) in directions {
+                        let Some(stats) = stats else {
+                            continue;
+                        };
+                        let iterations = result.iterations.iter();
+                        let samples = match download {
+                            false => iterations.filter_map(|sample| sample.upload).collect(),
+                            true => iterations.filter_map(|sample| sample.download).collect(),
+                        };
+                        let name = format!("{direction} chunk {chunk_size}");
+                        let metric = Metric::bandwidth(name, stats.average);
+                        metrics.push(Metric { samples, ..metric });
+                    }
+                }
+            }
+            Benchmark::StreamSweep { results, .. } => {
+                for result in results {
+                    let streams = result.streams;
+                    let directions = [
+                        ("upload", result.upload, result.upload_fairness, false),
+                        ("download", result.download, result.download_fairness, true),
+                    ];
+                    for (direction, stats, fairness, download) in directions {
+                        let Some(stats) = stats else {
+                            continue;
+                        };
+                        let iterations = result.iterations.iter();
+                        let samples = match download {
+                            false => iterations.filter_map(|sample| sample.upload).collect(),
+                            true => iterations.filter_map(|sample| sample.download).collect(),
+                        };
+                        let name = format!("{direction} {streams} streams");
+                        let metric = Metric::bandwidth(name, stats.average);
+                        metrics.push(Metric { samples, ..metric });
+                        let name = format!("{direction} fairness {streams} streams");
+                        metrics.extend(fairness.map(|index| Metric::other(&name, "", index, true)));
+                    }
+                }
+            }
+            Benchmark::Timed { total, .. } => {
+                for (name, mbps) in [("upload", total.upload), ("download", total.download)] {
+                    metrics.extend(mbps.map(|mbps| Metric::bandwidth(name.into(), mbps)));
+                }
+            }
+            Benchmark::Datagram { received_mbps, loss_percent, jitter_ms, .. } => {
+                metrics.push(Metric::bandwidth("received".into(), *received_mbps));
+                metrics.push(Metric::other("loss", "%", *loss_percent, false));
+                metrics.push(Metric::other("jitter", "ms", *jitter_ms, false));
+            }
+            Benchmark::Ping { summary, jitter_ms, .. } => {
+                metrics.extend(Metric::latency("round trip", summary));
+                let jitter = jitter_ms.map(|jitter| Metric::other("jitter", "ms", jitter, false));
+                metrics.extend(jitter);
+            }
+            Benchmark::Discover { discovery, handshake, .. } => {
+                metrics.extend(Metric::latency("discovery", discovery));
+                metrics.extend(Metric::latency("handshake", handshake));
+            }
+            Benchmark::Connect { cold, warm, .. } | Benchmark::Resume { cold, warm, .. } => {
+                metrics.extend(Metric::latency("cold", cold));
+                metrics.extend(Metric::latency("warm", warm));
+            }
+            Benchmark::Streams { open, first_byte, .. } => {
+                metrics.extend(Metric::latency("open", open));
+                metrics.extend(Metric::latency("first byte", first_byte));
+            }
+            Benchmark::HolePunch { success_rate, time_to_direct, .. } => {
+                metrics.extend(Metric::latency("time to direct", time_to_direct));
+                if let Some(rate) = success_rate {
+                    metrics.push(Metric::other("success rate", "%", rate * 100.0, true));
+                }
+            }
+            Benchmark::Soak { intervals, upload, download, reconnects, path_changes, .. } => {
+                let directions = [("upload", upload, false), ("download", download, true)];
+                for (name, stats, download) in directions {
+                    let Some(stats) = stats else {
+                        continue;
+                    };
+                    let bandwidths = intervals.iter().map(|it| &it.bandwidth);
+                    let samples = match download {
+                        false => bandwidths.filter_map(|b| b.upload).collect(),
+                        true => bandwidths.filter_map(|b| b.download).collect(),
+                    };
+                    let metric = Metric::bandwidth(name.to_string(), stats.average);
+                    metrics.push(Metric { samples, ..metric });
+                }
+                metrics.push(Metric::other("reconnects", "count", *reconnects as f64, false));
+                metrics.push(Metric::other("path changes", "count", *path_changes as f64, false));
+            }
+            Benchmark::Bursts { bursts, upload, download, .. } => {
+                let directions = [("upload", upload, false), ("download", download, true)];
+                for (direction, stats, download) in directions {
+                    let Some(stats) = stats else {
+                        continue;
+                    };
+                    let bandwidths = bursts.iter().map(|burst| &burst.bandwidth);
+                    let samples = match download {
+                        false => bandwidths.filter_map(|b| b.upload).collect(),
+                        true => bandwidths.filter_map(|b| b.download).collect(),
+                    };
+                    let metric = Metric::bandwidth(format!("burst {direction}"), stats.average);
+                    metrics.push(Metric { samples, ..metric });
+                }
+            }
+            Benchmark::Gossip { latency, delivery_percent, throughput_mbps, .. } => {
+                metrics.extend(Metric::latency("delivery", latency));
+                metrics.push(Metric::other("delivered", "%", *delivery_percent, true));
+                metrics.push(Metric::bandwidth("throughput".into(), *throughput_mbps));
+            }
+            Benchmark::Bufferbloat { load, idle, loaded, inflation_ms, .. } => {
+                metrics.extend(Metric::latency("idle", idle));
+                metrics.extend(Metric::latency("loaded", loaded));
+                let inflation = inflation_ms.map(|ms| Metric::other("inflation", "ms", ms, false));
+                metrics.extend(inflation);
+                let directions = [("load upload", load.upload), ("load download", load.download)];
+                for (name, mbps) in directions {
+                    metrics.extend(mbps.map(|mbps| Metric::bandwidth(name.into(), mbps)));
+                }
+            }
+            Benchmark::Rpc { latency, requests_per_sec, .. } => {
+                metrics.extend(Metric::latency("request", latency));
+                metrics.push(Metric::other("requests", "req/s", *requests_per_sec, true));
+            }
+            Benchmark::Delay { rtt, forward, backward, asymmetry_ms, .. } => {
+                metrics.extend(Metric::latency("round trip", rtt));
+                metrics.extend(Metric::latency("forward", forward));
+                metrics.extend(Metric::latency("backward", backward));
+                let asymmetry = asymmetry_ms.map(|ms| ms.abs());
+                metrics.extend(asymmetry.map(|ms| Metric::other("asymmetry", "ms", ms, false)));
+            }
+            Benchmark::Migrate { before_mbps, after_mbps, dip_ms, load, .. } => {
+                let throughputs = [("before rebind", before_mbps), ("after rebind", after_mbps)];
+                for (name, mbps) in throughputs {
+                    metrics.extend(mbps.map(|mbps| Metric::bandwidth(name.into(), mbps)));
+                }
+                metrics.extend(dip_ms.map(|ms| Metric::other("dip", "ms", ms, false)));
+                let directions = [("upload", load.upload), ("download", load.download)];
+                for (name, mbps) in directions {
+                    metrics.extend(mbps.map(|mbps| Metric::bandwidth(name.into(), mbps)));
+                }
+            }
+            Benchmark::Messages {
+                messages_per_sec, goodput_mbps, overhead_bytes_per_message, ..
+            } => {
+                metrics.push(Metric::other("messages", "msg/s", *messages_per_sec, true));
+                metrics.push(Metric::bandwidth("goodput".into(), *goodput_mbps));
+                let overhead = *overhead_bytes_per_message;
+                metrics.push(Metric::other("overhead per message", "bytes", overhead, false));
+            }
+            Benchmark::Blobs { results, .. } => {
+                for result in results {
+                    let Some(download) = result.download else {
+                        continue;
+                    };
+                    let name = format!("fetch {}", format_size(result.size));
+                    let metric = Metric::bandwidth(name, download.average);
+                    metrics.push(Metric { samples: result.download_mbps.clone(), ..metric });
+                }
+            }
+        }
+        metrics
+    }
+}
+
+impl Metric {
+    fn bandwidth(name: String, mbps: f64) -> Self {
+        Self {
+            name,
+            unit: "Mbit/s",
+            value: mbps,
+            higher_is_better: true,
+            kind: MetricKind::Bandwidth,
+            samples: Vec::new(),
+        }
+    }
+
+    /// The median and 95th percentile of a latency distribution.
+    fn latency(label: &str, summary: &Option<Stats>) -> Vec<Self> {
+        let Some(summary) = summary else {
+            return Vec::new();
+        };
+        let latency = |percentile, value, kind| Self {
+            name: format!("{label} {percentile}"),
+            unit: "ms",
+            value,
+            higher_is_better: false,
+            kind,
+            samples: Vec::new(),
+        };
+        vec![
+            latency("p50", summary.median, MetricKind::MedianLatency),
+            latency("p95", summary.p95, MetricKind::Other),
+        ]
+    }
+
+    fn other(name: &str, unit: &'static str, value: f64, higher_is_better: bool) -> Self {
+        Self {
+            name: name.to_string(),
+            unit,
+            value,
+            higher_is_better,
+            kind: MetricKind::Other,
+            samples: Vec::new(),
+        }
+    }
+}
+
+/// Compares the metrics of every test in `current` with the same test in `baseline`.
+///
+/// Changes for the worse by more than `threshold_percent` count as regressions, changes for the
+/// better by as much as improvements.
+pub fn compare(
+    baseline: &[BenchmarkReport],
+    current: &[BenchmarkReport],
+    threshold_percent: f64,
+) -> Vec<TestComparison> {
+    let baseline = label_tests(baseline);
+    let current = label_tests(current);
+    let mut comparisons = Vec::new();
+    for (test, report) in &current {
+        let Some((_, base)) = baseline.iter().find(|(name, _)| name == test) else {
+            comparisons.push(TestComparison {
+                test: test.clone(),
+                deltas: Vec::new(),
+                missing: Some("baseline"),
+            });
+            continue;
+        };
+        let base_metrics = base.benchmark.metrics();
+        let deltas = report
+            .benchmark
+            .metrics()
+            .into_iter()
+            .filter_map(|metric| {
+                let base = base_metrics.iter().find(|base| base.name == metric.name)?;
+                Some(delta(base, metric, threshold_percent))
+            })
+            .collect();
+        comparisons.push(TestComparison { test: test.clone(), deltas, missing: None });
+    }
+    for (test, _) in &baseline {
+        if !current.iter().any(|(name, _)| name == test) {
+            comparisons.push(TestComparison {
+                test: test.clone(),
+                deltas: Vec::new(),
+                missing: Some("current run"),
+            });
+        }
+    }
+    comparisons
+}
+
+/// Labels every report with its test name, numbering names that occur several times.
+pub fn label_tests(reports: &[BenchmarkReport]) -> Vec<(String, &BenchmarkReport)> {
+    let name = |report: &BenchmarkReport| {
+        report.name.clone().unwrap_or_else(|| report.benchmark.mode().to_string())
+    };
+    reports
+        .iter()
+        .enumerate()
+        .map(|(i, report)| {
+            let test = name(report);
+            let total = reports.iter().filter(|r| name(r) == test).count();
+            if total == 1 {
+                return (test, report);
+            }
+            let nth = reports[..i].iter().filter(|r| name(r) == test).count() + 1;
+            (format!("{test} #{nth}"), report)
+        })
+        .collect()
+}
+
+fn delta(base: &Metric, metric: Metric, threshold_percent: f64) -> MetricDelta {
+    let baseline = base.value;
+    let change_percent =
+        (baseline != 0.0).then(|| (metric.value - baseline) / baseline.abs() * 100.0);
+    let verdict = match change_percent {
+        Some(change) if change.abs() > threshold_percent => {
+            if (change > 0.0) == metric.higher_is_better {
+                Verdict::Improved
+            } else {
+                Verdict::Regressed
+            }
+        }
+        _ => Verdict::Unchanged,
+    };
+    MetricDelta {
+        name: metric.name,
+        unit: metric.unit,
+        baseline,
+        current: metric.value,
+        change_percent,
+        verdict,
+        significance: Significance::test(&base.samples, &metric.samples),
+    }
+}
+
+/// Prints the comparisons as one table per test, highlighting regressions in red and
+/// improvements in green if stdout is a terminal.
+///
+/// Metrics with several samples per run also show the 95% confidence interval of the change and
+/// the p-value of the permutation test.
+pub fn print_comparison(comparisons: &[TestComparison]) {
+    let color = std::io::stdout().is_terminal();
+    for comparison in comparisons {
+        println!("\n{}", comparison.test);
+        if let Some(missing) = comparison.missing {
+            println!("  Not present in the {missing}");
+            continue;
+        }
+        println!(
+            "  {:<28} {:>12} {:>12} {:>12} {:>9} {:>21} {:>7}",
+            "Metric", "Baseline", "Current", "Delta", "Change", "95% CI of delta", "p"
+        );
+        for delta in &comparison.deltas {
+            let change = match delta.change_percent {
+                Some(change) => format!("{change:+.1}%"),
+                None => "-".to_string(),
+            };
+            let (label, code) = match delta.verdict {
+                Verdict::Unchanged => ("", ""),
+                Verdict::Improved => ("improved", "\x1b[32m"),
+                Verdict::Regressed => ("REGRESSION", "\x1b[31m"),
+            };
+            let (ci, p, label) = match delta.significance {
+                Some(sig) => {
+                    let label = match (label.is_empty(), sig.is_significant()) {
+                        (true, _) => String::new(),
+                        (false, true) => format!("{label}, significant at 95%"),
+                        (false, false) => format!("{label}, not significant"),
+                    };
+                    let ci = format!("[{:+.3}, {:+.3}]", sig.ci_low, sig.ci_high);
+                    (ci, format!("{:.3}", sig.p_value), label)
+                }
+                None => ("-".to_string(), "-".to_string(), label.to_string()),
+            };
+            let line = format!(
+                "  {:<28} {:>12.3} {:>12.3} {:>+12.3} {change:>9} {ci:>21} {p:>7}  {label}",
+                format!("{} ({})", delta.name, delta.unit),
+                delta.baseline,
+                delta.current,
+                delta.current - delta.baseline,
+            );
+            match (color, code.is_empty()) {
+                (true, false) => println!("{code}{}\x1b[0m", line.trim_end()),
+                _ => println!("{}", line.trim_end()),
+            }
+        }
+    }
+}
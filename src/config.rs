@@ -0,0 +1,392 @@
+//! Endpoint configuration shared by the client and server.
+
+use std::{
+    fmt,
+    fs::OpenOptions,
+    io::{ErrorKind, Write},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use iroh::{Endpoint, RelayMap, RelayMode, RelayUrl, SecretKey, endpoint::Builder};
+use quinn::{
+    IdleTimeout, MtuDiscoveryConfig, TransportConfig, VarInt,
+    congestion::{BbrConfig, CubicConfig, NewRenoConfig},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::units::{format_size, parse_size};
+
+/// Interval of iroh's keep-alives, which a custom transport config has to set again.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Mechanism used to find the addresses of other nodes and publish our own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Discovery {
+    /// n0's DNS and pkarr relay based discovery
+    N0,
+    /// mDNS-like discovery of nodes on the local network
+    Local,
+}
+
+/// Endpoint options of the benchmark client.
+#[derive(clap::Args, Debug, Clone)]
+pub struct EndpointConfig {
+    /// Discovery mechanism used to find the server's addresses, can be repeated
+    #[arg(long, value_enum, default_value = "n0")]
+    pub discovery: Vec<Discovery>,
+
+    /// Disable discovery and only dial the addresses from `--addr` or the ticket
+    #[arg(long, conflicts_with = "discovery")]
+    pub no_discovery: bool,
+
+    /// Relay server to use instead of n0's default relays, can be repeated
+    #[arg(long = "relay-url")]
+    pub relay_urls: Vec<RelayUrl>,
+
+    /// Only communicate via the relay and never attempt to hole punch a direct path, needs a build
+    /// with `--features relay-only`
+    #[arg(long)]
+    pub relay_only: bool,
+
+    /// File with the secret key of the client's node, generated if missing, to keep its node ID
+    /// across runs
+    #[arg(long)]
+    pub secret_key_file: Option<PathBuf>,
+
+    /// Write the TLS session keys to the file named by `SSLKEYLOGFILE`, to decrypt captured
+    /// traffic in Wireshark
+    #[arg(long)]
+    pub keylog: bool,
+
+    #[command(flatten)]
+    pub transport: TransportTuning,
+
+    #[command(flatten)]
+    pub buffers: SocketBuffers,
+}
+
+/// Congestion controller of the connections, which decides how fast a sender may send.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Congestion {
+    /// CUBIC, quinn's default
+    #[default]
+    Cubic,
+    /// BBR, which models the bottleneck bandwidth and round-trip time instead of reacting to
+    /// loss (experimental in quinn)
+    Bbr,
+    /// NewReno, the classic loss-based controller
+    Newreno,
+}
+
+impl Congestion {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Cubic => "cubic",
+            Self::Bbr => "bbr",
+            Self::Newreno => "newreno",
+        }
+    }
+}
+
+/// QUIC transport parameters of the endpoint, left at iroh's defaults unless given.
+///
+/// Flow control windows limit how much data can be in flight, so on links with a high
+/// bandwidth-delay product they cap the throughput unless raised on the receiving side.
+#[derive(clap::Args, Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransportTuning {
+    /// Flow control window of every stream (e.g. `8M`), used as its initial and maximum window
+    #[arg(long, value_parser = parse_size)]
+    pub stream_receive_window: Option<u64>,
+
+    /// Flow control window of the whole connection, across all streams (e.g. `32M`)
+    #[arg(long, value_parser = parse_size)]
+    pub receive_window: Option<u64>,
+
+    /// Number of bidirectional streams the peer may have open at once
+    #[arg(long)]
+    pub max_concurrent_streams: Option<u32>,
+
+    /// Close connections after this long without any packet from the peer
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub idle_timeout: Option<Duration>,
+
+    /// Congestion controller of the data this endpoint sends
+    #[arg(long, value_enum)]
+    pub congestion: Option<Congestion>,
+
+    /// UDP payload size used before MTU discovery, at least 1200 bytes
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1200..))]
+    pub initial_mtu: Option<u16>,
+
+    /// Largest UDP payload size MTU discovery probes for, e.g. `8952` on jumbo frame links,
+    /// `0` disables MTU discovery
+    #[arg(long)]
+    pub max_mtu: Option<u16>,
+}
+
+impl TransportTuning {
+    /// The transport config with these parameters on top of iroh's defaults, or `None` to keep
+    /// iroh's config if no parameter is given.
+    pub fn transport_config(&self) -> Result<Option<TransportConfig>> {
+        if *self == Self::default() {
+            return Ok(None);
+        }
+        let mut config = TransportConfig::default();
+        config.keep_alive_interval(Some(KEEP_ALIVE_INTERVAL));
+        if let Some(window) = self.stream_receive_window {
+            config.stream_receive_window(VarInt::from_u64(window)?);
+        }
+        if let Some(window) = self.receive_window {
+            config.receive_window(VarInt::from_u64(window)?);
+        }
+        if let Some(streams) = self.max_concurrent_streams {
+            config.max_concurrent_bidi_streams(streams.into());
+        }
+        if let Some(timeout) = self.idle_timeout {
+            config.max_idle_timeout(Some(IdleTimeout::try_from(timeout)?));
+        }
+        if let Some(mtu) = self.initial_mtu {
+            config.initial_mtu(mtu);
+        }
+        match self.max_mtu {
+            Some(0) => {
+                config.mtu_discovery_config(None);
+            }
+            Some(mtu) => {
+                let mut discovery = MtuDiscoveryConfig::default();
+                discovery.upper_bound(mtu);
+                config.mtu_discovery_config(Some(discovery));
+            }
+            None => {}
+        }
+        match self.congestion {
+            None | Some(Congestion::Cubic) => {
+                config.congestion_controller_factory(Arc::new(CubicConfig::default()))
+            }
+            Some(Congestion::Bbr) => {
+                config.congestion_controller_factory(Arc::new(BbrConfig::default()))
+            }
+            Some(Congestion::Newreno) => {
+                config.congestion_controller_factory(Arc::new(NewRenoConfig::default()))
+            }
+        };
+        Ok(Some(config))
+    }
+
+    /// Applies the parameters to an endpoint builder.
+    pub fn apply(&self, builder: Builder) -> Result<Builder> {
+        Ok(match self.transport_config()? {
+            Some(config) => builder.transport_config(config),
+            None => builder,
+        })
+    }
+}
+
+impl Default for EndpointConfig {
+    fn default() -> Self {
+        Self {
+            discovery: vec![Discovery::N0],
+            no_discovery: false,
+            relay_urls: Vec::new(),
+            relay_only: false,
+            secret_key_file: None,
+            keylog: false,
+            transport: TransportTuning::default(),
+            buffers: SocketBuffers::default(),
+        }
+    }
+}
+
+/// Keeps the endpoint from using direct paths.
+#[cfg(feature = "relay-only")]
+fn relay_only(builder: Builder) -> Result<Builder> {
+    Ok(builder.path_selection(iroh::endpoint::PathSelection::RelayOnly))
+}
+
+#[cfg(not(feature = "relay-only"))]
+fn relay_only(_builder: Builder) -> Result<Builder> {
+    anyhow::bail!("Relay-only endpoints need a build with `--features relay-only`")
+}
+
+impl EndpointConfig {
+    /// Configures an endpoint according to the relay, discovery and path options.
+    pub fn builder(&self) -> Result<Builder> {
+        let mut builder = Endpoint::builder().relay_mode(relay_mode(&self.relay_urls)?);
+        if !self.no_discovery {
+            builder = discovery(builder, &self.discovery);
+        }
+        if self.relay_only {
+            builder = relay_only(builder)?;
+        }
+        self.transport.apply(keylog(builder, self.keylog)?)
+    }
+
+    /// Like [`Self::builder`], with the key of `--secret-key-file` if given.
+    ///
+    /// Only the client's main endpoint uses it: the endpoints benchmarks bind for fresh
+    /// connections or gossip peers must have node IDs of their own.
+    pub fn keyed_builder(&self) -> Result<Builder> {
+        let builder = self.builder()?;
+        match &self.secret_key_file {
+            Some(path) => Ok(builder.secret_key(secret_key(path)?)),
+            None => Ok(builder),
+        }
+    }
+}
+
+/// Buffer size iroh (through netwatch) requests for its UDP sockets.
+const IROH_SOCKET_BUFFER: usize = 7 << 20;
+
+/// Buffer sizes of the UDP sockets of the plain QUIC baselines, left at the OS defaults unless
+/// given.
+///
+/// iroh neither exposes its sockets nor takes buffer sizes, it requests 7 MiB for them. The OS caps
+/// all sizes, on Linux at `net.core.wmem_max` and `net.core.rmem_max`, which are often far lower
+/// and then limit the throughput on fast links.
+#[derive(clap::Args, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SocketBuffers {
+    /// Send buffer size of the UDP sockets of the QUIC baseline (e.g. `16M`), iroh sizes its own
+    #[arg(long, value_parser = parse_size)]
+    pub send_buffer: Option<u64>,
+
+    /// Receive buffer size of the UDP sockets of the QUIC baseline (e.g. `16M`), iroh sizes its own
+    #[arg(long, value_parser = parse_size)]
+    pub recv_buffer: Option<u64>,
+}
+
+/// Buffer sizes of one UDP socket. Those of sockets bound here are as the OS reports them, which
+/// on Linux is twice the size that was set, as it counts its bookkeeping.
+#[derive(Debug, Clone, Copy)]
+pub struct SocketBufferSizes {
+    pub addr: SocketAddr,
+    pub send: usize,
+    pub recv: usize,
+}
+
+impl fmt::Display for SocketBufferSizes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (send, recv) = (format_size(self.send as u64), format_size(self.recv as u64));
+        write!(f, "UDP socket {}: send buffer {send}, receive buffer {recv}", self.addr)
+    }
+}
+
+impl SocketBuffers {
+    /// Binds a UDP socket to `addr` with the buffer sizes and returns it with the sizes in effect.
+    pub fn bind(&self, addr: SocketAddr) -> Result<(std::net::UdpSocket, SocketBufferSizes)> {
+        use socket2::{Domain, Protocol, Socket, Type};
+
+        let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+        if let Some(size) = self.send_buffer {
+            socket.set_send_buffer_size(size as usize)?;
+        }
+        if let Some(size) = self.recv_buffer {
+            socket.set_recv_buffer_size(size as usize)?;
+        }
+        socket.bind(&addr.into())?;
+        let (send, recv) = (socket.send_buffer_size()?, socket.recv_buffer_size()?);
+        let socket = std::net::UdpSocket::from(socket);
+        let addr = socket.local_addr()?;
+        Ok((socket, SocketBufferSizes { addr, send, recv }))
+    }
+
+    /// Binds a plain QUIC endpoint to `addr` with the buffer sizes, a server if there is a
+    /// `server` config, and returns it with the sizes in effect.
+    pub fn quic_endpoint(
+        &self,
+        addr: SocketAddr,
+        server: Option<quinn::ServerConfig>,
+    ) -> Result<(quinn::Endpoint, SocketBufferSizes)> {
+        let (socket, sizes) = self.bind(addr)?;
+        let runtime = Arc::new(quinn::TokioRuntime);
+        let endpoint = quinn::Endpoint::new(Default::default(), server, socket, runtime)?;
+        Ok((endpoint, sizes))
+    }
+
+    /// Buffer sizes of the UDP sockets `endpoint` is bound to: iroh's request of 7 MiB, capped by
+    /// the OS limits where they are known (on Linux).
+    ///
+    /// Best effort: iroh does not expose its sockets, so the sizes are not read back from them.
+    pub fn iroh_sizes(endpoint: &Endpoint) -> Vec<SocketBufferSizes> {
+        let limit = |name: &str| {
+            std::fs::read_to_string(format!("/proc/sys/net/core/{name}"))
+                .ok()
+                .and_then(|limit| limit.trim().parse().ok())
+                .map_or(IROH_SOCKET_BUFFER, |limit: usize| limit.min(IROH_SOCKET_BUFFER))
+        };
+        let (send, recv) = (limit("wmem_max"), limit("rmem_max"));
+        let (v4, v6) = endpoint.bound_sockets();
+        std::iter::once(v4)
+            .chain(v6)
+            .map(|addr| SocketBufferSizes { addr, send, recv })
+            .collect()
+    }
+}
+
+/// Reads the hex-encoded secret key at `path`, or generates a key and writes it there if the file
+/// does not exist yet.
+pub fn secret_key(path: &Path) -> Result<SecretKey> {
+    match std::fs::read_to_string(path) {
+        Ok(key) => key
+            .trim()
+            .parse()
+            .with_context(|| format!("Invalid secret key in {}", path.display())),
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            let key = SecretKey::generate(rand::rngs::OsRng);
+            let mut options = OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            let mut file = options.open(path).with_context(|| {
+                format!("Failed to create the secret key file {}", path.display())
+            })?;
+            writeln!(file, "{key}")?;
+            Ok(key)
+        }
+        Err(err) => Err(err)
+            .with_context(|| format!("Failed to read the secret key file {}", path.display())),
+    }
+}
+
+/// Enables writing TLS session keys to `SSLKEYLOGFILE` if `enabled`, which must then be set.
+///
+/// rustls silently ignores a keylog without the variable, so this fails instead of leaving the
+/// user with a capture that cannot be decrypted.
+pub fn keylog(builder: Builder, enabled: bool) -> Result<Builder> {
+    if enabled {
+        anyhow::ensure!(
+            std::env::var_os("SSLKEYLOGFILE").is_some(),
+            "`--keylog` needs SSLKEYLOGFILE set to the file to write the keys to"
+        );
+    }
+    Ok(builder.keylog(enabled))
+}
+
+/// Builds the relay mode for the given relay URLs, using n0's default relays if there are none.
+pub fn relay_mode(urls: &[RelayUrl]) -> Result<RelayMode> {
+    if urls.is_empty() {
+        return Ok(RelayMode::Default);
+    }
+    let nodes: Vec<_> = urls
+        .iter()
+        .flat_map(|url| RelayMap::from_url(url.clone()).nodes().cloned().collect::<Vec<_>>())
+        .collect();
+    Ok(RelayMode::Custom(RelayMap::from_nodes(nodes)?))
+}
+
+/// Enables the given discovery mechanisms on the endpoint builder.
+pub fn discovery(mut builder: Builder, discovery: &[Discovery]) -> Builder {
+    for discovery in discovery {
+        builder = match discovery {
+            Discovery::N0 => builder.discovery_n0(),
+            Discovery::Local => builder.discovery_local_network(),
+        };
+    }
+    builder
+}
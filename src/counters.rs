@@ -0,0 +1,81 @@
+//! Counters iroh records internally with `iroh-metrics`, such as the bytes sent per path.
+//!
+//! The counters are global to the process, so the client takes a [`CounterSnapshot`] before every
+//! benchmark and reports the difference to the counters after it as [`IrohCounters`].
+
+use iroh::metrics::MagicsockMetrics;
+use iroh_metrics::core::{Core, Metric};
+use serde::{Deserialize, Serialize};
+
+/// Starts recording iroh's counters, which it otherwise skips. Does nothing if recording was
+/// already started.
+pub fn init() {
+    // Fails only if the metrics are already initialized, in which case they are recorded
+    let _ = Core::try_init(|registry, metrics| {
+        metrics.insert(MagicsockMetrics::new(registry));
+    });
+}
+
+/// Values of iroh's counters at one point in time.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CounterSnapshot(IrohCounters);
+
+impl CounterSnapshot {
+    /// Reads the current counters, all zero if [`init`] was not called.
+    pub fn now() -> Self {
+        let Some(m) = MagicsockMetrics::try_get() else {
+            return Self::default();
+        };
+        Self(IrohCounters {
+            sent_direct_bytes: m.send_ipv4.get() + m.send_ipv6.get(),
+            sent_relay_bytes: m.send_relay.get(),
+            received_direct_bytes: m.recv_data_ipv4.get() + m.recv_data_ipv6.get(),
+            received_relay_bytes: m.recv_data_relay.get(),
+            relay_percent: 0.0,
+            holepunch_attempts: m.sent_disco_call_me_maybe.get(),
+            connections: m.connection_handshake_success.get(),
+            became_direct: m.connection_became_direct.get(),
+        })
+    }
+
+    /// The increase of every counter since `earlier`.
+    pub fn since(&self, earlier: &Self) -> IrohCounters {
+        let (now, then) = (&self.0, &earlier.0);
+        let mut counters = IrohCounters {
+            sent_direct_bytes: now.sent_direct_bytes - then.sent_direct_bytes,
+            sent_relay_bytes: now.sent_relay_bytes - then.sent_relay_bytes,
+            received_direct_bytes: now.received_direct_bytes - then.received_direct_bytes,
+            received_relay_bytes: now.received_relay_bytes - then.received_relay_bytes,
+            relay_percent: 0.0,
+            holepunch_attempts: now.holepunch_attempts - then.holepunch_attempts,
+            connections: now.connections - then.connections,
+            became_direct: now.became_direct - then.became_direct,
+        };
+        let relayed = counters.sent_relay_bytes + counters.received_relay_bytes;
+        let total = relayed + counters.sent_direct_bytes + counters.received_direct_bytes;
+        if total > 0 {
+            counters.relay_percent = relayed as f64 / total as f64 * 100.0;
+        }
+        counters
+    }
+}
+
+/// What iroh counted on the client's endpoints during a benchmark.
+///
+/// Sent bytes include iroh's own discovery and hole punching messages, received bytes only
+/// QUIC packets.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct IrohCounters {
+    /// Bytes sent over direct UDP paths, IPv4 and IPv6.
+    pub sent_direct_bytes: u64,
+    pub sent_relay_bytes: u64,
+    pub received_direct_bytes: u64,
+    pub received_relay_bytes: u64,
+    /// Share of all bytes that went through the relay.
+    pub relay_percent: f64,
+    /// Call-me-maybe messages sent to start hole punching.
+    pub holepunch_attempts: u64,
+    /// Connections that completed their handshake, and how many of them became direct.
+    pub connections: u64,
+    pub became_direct: u64,
+}
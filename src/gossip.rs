@@ -0,0 +1,185 @@
+//! Gossip benchmark: message delivery across a small iroh-gossip swarm bootstrapped by the
+//! server.
+//!
+//! The client binds `--peers` endpoints in its own process, which join a fresh topic with the
+//! server as their bootstrap node. The first peer broadcasts the messages, all others record when
+//! each of them arrives. As every peer runs on the same clock, the delivery latencies need no
+//! clock synchronization.
+
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use clap::builder::RangedU64ValueParser;
+use iroh::{Endpoint, NodeAddr, protocol::Router};
+use iroh_gossip::{
+    net::{Event, Gossip, GossipEvent, GossipReceiver},
+    proto::TopicId,
+};
+use n0_future::{StreamExt, try_join_all};
+use tokio::time::{Instant, sleep_until, timeout};
+
+use crate::client::{BenchmarkConfig, bandwidth, print_latency_stats};
+use crate::protocol::{ALPN, MAX_GOSSIP_MESSAGE, MAX_GOSSIP_PAYLOAD, Request, Response};
+use crate::report::{Benchmark, Stats, millis};
+
+/// How long peers wait to join the swarm via the server.
+const JOIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long receiving peers wait for further messages before counting the rest as lost.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Length of the header every message starts with: the sequence number and the time it was
+/// sent in nanoseconds since the start of the benchmark, both as big-endian `u64`s.
+const HEADER_LEN: u64 = 16;
+
+/// Parameters of [`run_gossip`].
+#[derive(clap::Args, Debug, Clone)]
+pub struct GossipConfig {
+    /// Number of peers joining the swarm from the client, one of which broadcasts
+    #[arg(short, long, default_value_t = 4, value_parser = RangedU64ValueParser::<usize>::new().range(2..))]
+    pub peers: usize,
+
+    /// Number of messages to broadcast
+    #[arg(short, long, default_value_t = 1000)]
+    pub count: u64,
+
+    /// Size of each message in bytes
+    #[arg(short, long, default_value_t = 1024, value_parser = clap::value_parser!(u64).range(HEADER_LEN..=MAX_GOSSIP_PAYLOAD))]
+    pub size: u64,
+
+    /// Pause between messages, `0s` broadcasts as fast as the swarm takes them
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "1ms")]
+    pub interval: Duration,
+}
+
+/// Deliveries recorded by one receiving peer.
+#[derive(Debug, Default)]
+struct Deliveries {
+    /// Time from broadcasting until each message arrived.
+    latencies: Vec<Duration>,
+    /// Time from the start of broadcasting until the last message arrived.
+    last: Duration,
+}
+
+/// Lets the server join a fresh topic, bootstraps a swarm of `--peers` local peers from it and
+/// broadcasts `--count` messages from one of them, measuring when they reach the others.
+pub async fn run_gossip(
+    endpoint: &Endpoint,
+    addr: NodeAddr,
+    config: &BenchmarkConfig,
+    gossip: &GossipConfig,
+) -> Result<Benchmark> {
+    // A topic of its own keeps concurrent runs against the same server apart
+    let seed = format!("{} {:?}", endpoint.node_id(), SystemTime::now());
+    let topic = TopicId::from_bytes(*blake3::hash(seed.as_bytes()).as_bytes());
+    let conn = endpoint.connect(addr.clone(), ALPN).await?;
+    let (mut send, mut recv) = conn.open_bi().await?;
+    Request::Gossip { topic: *topic.as_bytes() }.write(&mut send).await?;
+    Response::read(&mut recv).await?;
+
+    config.status(format!("\nJoining gossip swarm with {} peers:", gossip.peers));
+    let mut routers = Vec::with_capacity(gossip.peers);
+    let mut senders = Vec::with_capacity(gossip.peers);
+    let mut receivers = Vec::with_capacity(gossip.peers);
+    for _ in 0..gossip.peers {
+        let endpoint = config.endpoint.builder()?.bind().await?;
+        endpoint.add_node_addr(addr.clone())?;
+        let swarm =
+            Gossip::builder().max_message_size(MAX_GOSSIP_MESSAGE).spawn(endpoint.clone()).await?;
+        let router =
+            Router::builder(endpoint).accept(iroh_gossip::ALPN, swarm.clone()).spawn().await?;
+        let join = swarm.subscribe_and_join(topic, vec![addr.node_id]);
+        let subscription =
+            timeout(JOIN_TIMEOUT, join).await.context("Timed out joining the swarm")??;
+        let (sender, receiver) = subscription.split();
+        routers.push(router);
+        senders.push(sender);
+        receivers.push(receiver);
+    }
+
+    config.status(format!(
+        "\nStarting gossip benchmark ({} x {} bytes):",
+        gossip.count, gossip.size
+    ));
+    let t0 = Instant::now();
+    let deliveries = receivers.iter_mut().skip(1).map(|receiver| receive(receiver, gossip, t0));
+    let broadcast = async {
+        let mut next = t0;
+        for seq in 0..gossip.count {
+            let mut message = vec![0u8; gossip.size as usize];
+            message[..8].copy_from_slice(&seq.to_be_bytes());
+            let sent_at = t0.elapsed().as_nanos() as u64;
+            message[8..16].copy_from_slice(&sent_at.to_be_bytes());
+            senders[0].broadcast(message.into()).await?;
+            if !gossip.interval.is_zero() {
+                next += gossip.interval;
+                sleep_until(next).await;
+            }
+        }
+        anyhow::Ok(())
+    };
+    let (deliveries, ()) = tokio::try_join!(try_join_all(deliveries), broadcast)?;
+
+    for router in routers {
+        router.shutdown().await?;
+    }
+    send.finish()?;
+    recv.read_to_end(0).await?;
+    conn.close(0u32.into(), b"bye!");
+
+    let expected = gossip.count * deliveries.len() as u64;
+    let latencies: Vec<Duration> =
+        deliveries.iter().flat_map(|d| d.latencies.iter().copied()).collect();
+    let delivered = latencies.len() as u64;
+    let delivery_percent = delivered as f64 / expected.max(1) as f64 * 100.0;
+    let throughput_mbps = deliveries
+        .iter()
+        .map(|d| bandwidth(d.latencies.len() * gossip.size as usize, d.last))
+        .sum::<f64>()
+        / deliveries.len().max(1) as f64;
+    let latency = Stats::from_rtts(&latencies);
+    if config.print_results {
+        println!("Delivered: {delivered}/{expected} ({delivery_percent:.1}%)");
+        println!("Throughput per peer: {throughput_mbps:.2} Mbit/s");
+        if let Some(summary) = &latency {
+            print_latency_stats("Delivery latency", summary);
+        }
+    }
+    Ok(Benchmark::Gossip {
+        peers: gossip.peers,
+        size: gossip.size,
+        sent: gossip.count,
+        delivered,
+        delivery_percent,
+        latency_ms: latencies.into_iter().map(millis).collect(),
+        latency,
+        throughput_mbps,
+    })
+}
+
+/// Records the messages arriving at one peer until all were received or none arrived for
+/// [`IDLE_TIMEOUT`].
+async fn receive(
+    receiver: &mut GossipReceiver,
+    gossip: &GossipConfig,
+    t0: Instant,
+) -> Result<Deliveries> {
+    let mut deliveries = Deliveries::default();
+    while (deliveries.latencies.len() as u64) < gossip.count {
+        let Ok(event) = timeout(IDLE_TIMEOUT, receiver.next()).await else {
+            break;
+        };
+        match event.context("The gossip subscription closed")?? {
+            Event::Gossip(GossipEvent::Received(message)) => {
+                let elapsed = t0.elapsed();
+                let sent_at = message.content.get(8..16).context("Gossip message is truncated")?;
+                let sent_at = u64::from_be_bytes(sent_at.try_into().expect("8 bytes"));
+                deliveries.latencies.push(elapsed.saturating_sub(Duration::from_nanos(sent_at)));
+                deliveries.last = elapsed;
+            }
+            Event::Lagged => anyhow::bail!("A peer fell behind receiving gossip messages"),
+            Event::Gossip(_) => {}
+        }
+    }
+    Ok(deliveries)
+}
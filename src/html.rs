@@ -0,0 +1,361 @@
+//! Standalone HTML pages of saved results, as written by `client report`.
+//!
+//! The page plots the bandwidth of every payload size and the latency percentiles of every
+//! benchmark as inline SVG, so it can be shared as a single file without any scripts.
+
+use std::fmt::Write as _;
+
+use crate::compare::label_tests;
+use crate::report::{Benchmark, BenchmarkReport, Stats};
+use crate::units::format_size;
+
+/// Colors of the series of a chart, repeated if there are more series.
+const COLORS: [&str; 8] =
+    ["#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b", "#e377c2", "#17becf"];
+
+const WIDTH: f64 = 900.0;
+const HEIGHT: f64 = 360.0;
+/// Space around the plot area for the axes and their labels.
+const MARGIN: (f64, f64, f64, f64) = (20.0, 280.0, 50.0, 70.0);
+
+/// Renders the reports of every run as an HTML page, each run labelled by its name, e.g. the file
+/// it was loaded from.
+pub fn render(runs: &[(String, Vec<BenchmarkReport>)]) -> String {
+    let mut html = String::new();
+    html.push_str(concat!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n",
+        "<title>iroh benchmark report</title>\n<style>\n",
+        "body { font-family: sans-serif; margin: 2em auto; max-width: 960px; color: #222; }\n",
+        "table { border-collapse: collapse; margin: 1em 0; }\n",
+        "th, td { border: 1px solid #ccc; padding: 4px 8px; text-align: left; }\n",
+        "td.num { text-align: right; font-variant-numeric: tabular-nums; }\n",
+        "svg { display: block; margin: 1em 0; }\n",
+        "</style>\n</head>\n<body>\n<h1>iroh benchmark report</h1>\n",
+    ));
+
+    html.push_str("<h2>Runs</h2>\n<table>\n");
+    html.push_str("<tr><th>Name</th><th>Run ID</th><th>Label</th><th>iroh</th><th>Platform</th>");
+    html.push_str("<th>Host</th><th>Started</th><th>Reports</th></tr>\n");
+    for (name, reports) in runs {
+        let Some(first) = reports.first() else {
+            continue;
+        };
+        let run = &first.run;
+        writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{} {}</td><td>{}</td><td>{}</td>\
+             <td class=\"num\">{}</td></tr>",
+            escape(name),
+            escape(&run.run_id),
+            escape(run.label.as_deref().unwrap_or_default()),
+            escape(&run.iroh_version),
+            escape(&run.os),
+            escape(&run.arch),
+            escape(&run.hostname),
+            escape(&first.started_at),
+            reports.len(),
+        )
+        .unwrap();
+    }
+    html.push_str("</table>\n");
+
+    for (title, download) in [("Upload bandwidth", false), ("Download bandwidth", true)] {
+        let series = bandwidth_series(runs, download);
+        if !series.is_empty() {
+            writeln!(html, "<h2>{title}</h2>").unwrap();
+            let chart = Chart { x_label: "Payload size", y_label: "Mbit/s", series, log_x: true };
+            html.push_str(&chart.to_svg(|x| format_size(x as u64)));
+        }
+    }
+
+    let series = latency_series(runs);
+    if !series.is_empty() {
+        html.push_str("<h2>Latency percentiles</h2>\n");
+        let chart = Chart { x_label: "Percentile", y_label: "ms", series, log_x: false };
+        html.push_str(&chart.to_svg(|x| PERCENTILES[x as usize].to_string()));
+    }
+
+    html.push_str("<h2>Headline metrics</h2>\n<table>\n");
+    html.push_str("<tr><th>Run</th><th>Test</th><th>Metric</th><th>Value</th></tr>\n");
+    for (name, reports) in runs {
+        for (test, report) in label_tests(reports) {
+            for metric in report.benchmark.metrics() {
+                writeln!(
+                    html,
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td class=\"num\">{:.3} {}</td></tr>",
+                    escape(name),
+                    escape(&test),
+                    escape(&metric.name),
+                    metric.value,
+                    metric.unit,
+                )
+                .unwrap();
+            }
+        }
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+/// Labels of the points of the latency chart.
+const PERCENTILES: [&str; 5] = ["min", "p50", "p95", "p99", "max"];
+
+/// A line of a chart.
+struct Series {
+    name: String,
+    points: Vec<Point>,
+}
+
+/// A point of a [`Series`], with an optional range drawn around it.
+struct Point {
+    x: f64,
+    y: f64,
+    /// Lowest and highest value, such as the slowest and fastest iteration.
+    range: Option<(f64, f64)>,
+}
+
+/// The average bandwidth of every payload size of the bandwidth benchmarks, with the range
+/// between the slowest and fastest iteration.
+fn bandwidth_series(runs: &[(String, Vec<BenchmarkReport>)], download: bool) -> Vec<Series> {
+    let mut series = Vec::new();
+    for (name, reports) in runs {
+        for (test, report) in label_tests(reports) {
+            let Benchmark::Bandwidth { results, .. } = &report.benchmark else {
+                continue;
+            };
+            let points: Vec<_> = results
+                .iter()
+                .filter_map(|result| {
+                    let summary = if download { result.download } else { result.upload }?;
+                    let range = Some((summary.min, summary.max));
+                    Some(Point { x: result.size as f64, y: summary.average, range })
+                })
+                .collect();
+            if !points.is_empty() {
+                series.push(Series { name: format!("{name}: {test}"), points });
+            }
+        }
+    }
+    series
+}
+
+/// The latency distributions of all benchmarks that measure latencies.
+fn latency_series(runs: &[(String, Vec<BenchmarkReport>)]) -> Vec<Series> {
+    let mut series = Vec::new();
+    for (name, reports) in runs {
+        for (test, report) in label_tests(reports) {
+            for (label, summary) in latencies(&report.benchmark) {
+                let values = [summary.min, summary.median, summary.p95, summary.p99, summary.max];
+                series.push(Series {
+                    name: format!("{name}: {test} {label}"),
+                    points: (0..values.len())
+                        .map(|i| Point { x: i as f64, y: values[i], range: None })
+                        .collect(),
+                });
+            }
+        }
+    }
+    series
+}
+
+/// Every latency distribution of a benchmark, with what it measures.
+fn latencies(benchmark: &Benchmark) -> Vec<(&'static str, Stats)> {
+    let summaries = match benchmark {
+        Benchmark::Ping { summary, .. } => vec![("round trip", summary)],
+        Benchmark::Discover { discovery, handshake, .. } => {
+            vec![("discovery", discovery), ("handshake", handshake)]
+        }
+        Benchmark::Connect { cold, warm, .. } | Benchmark::Resume { cold, warm, .. } => {
+            vec![("cold", cold), ("warm", warm)]
+        }
+        Benchmark::Streams { open, first_byte, .. } => {
+            vec![("open", open), ("first byte", first_byte)]
+        }
+        Benchmark::HolePunch { time_to_direct, .. } => vec![("time to direct", time_to_direct)],
+        Benchmark::Gossip { latency, .. } => vec![("delivery", latency)],
+        Benchmark::Bufferbloat { idle, loaded, .. } => vec![("idle", idle), ("loaded", loaded)],
+        Benchmark::Rpc { latency, .. } => vec![("request", latency)],
+        Benchmark::Delay { forward, backward, .. } => {
+            vec![("forward", forward), ("backward", backward)]
+        }
+        Benchmark::Bandwidth { .. }
+        | Benchmark::ChunkSweep { .. }
+        | Benchmark::StreamSweep { .. }
+        | Benchmark::Timed { .. }
+        | Benchmark::Datagram { .. }
+        | Benchmark::Soak { .. }
+        | Benchmark::Messages { .. }
+        | Benchmark::Migrate { .. }
+        | Benchmark::Bursts { .. }
+        | Benchmark::Blobs { .. } => {
+            Vec::new()
+        }
+    };
+    summaries.into_iter().filter_map(|(label, summary)| Some((label, (*summary)?))).collect()
+}
+
+/// A line chart with a legend to the right of the plot.
+struct Chart {
+    x_label: &'static str,
+    y_label: &'static str,
+    series: Vec<Series>,
+    /// Whether the x axis is logarithmic, as for payload sizes.
+    log_x: bool,
+}
+
+impl Chart {
+    /// Draws the chart as SVG, labelling the ticks of the x axis, which are at the x values of the
+    /// points, with `x_tick`.
+    fn to_svg(&self, x_tick: impl Fn(f64) -> String) -> String {
+        let (top, right, bottom, left) = MARGIN;
+        let (plot_w, plot_h) = (WIDTH - left - right, HEIGHT - top - bottom);
+        let scale_x = |x: f64| if self.log_x { x.log2() } else { x };
+
+        let points = self.series.iter().flat_map(|s| &s.points);
+        let mut xs: Vec<f64> = points.clone().map(|point| point.x).collect();
+        xs.sort_by(f64::total_cmp);
+        xs.dedup();
+        let y_max = points
+            .map(|point| point.range.map_or(point.y, |(_, high)| high.max(point.y)))
+            .fold(0.0, f64::max)
+            .max(f64::MIN_POSITIVE)
+            * 1.1;
+        let (x_min, x_max) = (scale_x(xs[0]), scale_x(xs[xs.len() - 1]));
+        let x_span = if x_max > x_min { x_max - x_min } else { 1.0 };
+        let px = |x: f64| {
+            if x_max > x_min {
+                left + (scale_x(x) - x_min) / x_span * plot_w
+            } else {
+                left + plot_w / 2.0
+            }
+        };
+        let py = |y: f64| top + plot_h - y / y_max * plot_h;
+
+        let mut svg = String::new();
+        writeln!(
+            svg,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{WIDTH}\" height=\"{HEIGHT}\" \
+             font-size=\"12\">"
+        )
+        .unwrap();
+
+        // Axes, grid lines and ticks
+        for i in 0..=4 {
+            let y = y_max * i as f64 / 4.0;
+            writeln!(
+                svg,
+                "<line x1=\"{left}\" x2=\"{}\" y1=\"{py:.1}\" y2=\"{py:.1}\" stroke=\"#eee\"/>\
+                 <text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>",
+                left + plot_w,
+                left - 6.0,
+                py(y) + 4.0,
+                format_value(y),
+                py = py(y),
+            )
+            .unwrap();
+        }
+        for x in &xs {
+            writeln!(
+                svg,
+                "<text x=\"{:.1}\" y=\"{}\" text-anchor=\"middle\">{}</text>",
+                px(*x),
+                top + plot_h + 16.0,
+                escape(&x_tick(*x)),
+            )
+            .unwrap();
+        }
+        writeln!(
+            svg,
+            "<line x1=\"{left}\" x2=\"{left}\" y1=\"{top}\" y2=\"{bottom_y}\" stroke=\"#444\"/>\
+             <line x1=\"{left}\" x2=\"{right_x}\" y1=\"{bottom_y}\" y2=\"{bottom_y}\" \
+             stroke=\"#444\"/>",
+            bottom_y = top + plot_h,
+            right_x = left + plot_w,
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            "<text x=\"{:.1}\" y=\"{}\" text-anchor=\"middle\">{}</text>\
+             <text transform=\"translate(16 {:.1}) rotate(-90)\" text-anchor=\"middle\">{}</text>",
+            left + plot_w / 2.0,
+            HEIGHT - 10.0,
+            self.x_label,
+            top + plot_h / 2.0,
+            self.y_label,
+        )
+        .unwrap();
+
+        // Series with their ranges and a legend entry each
+        for (i, series) in self.series.iter().enumerate() {
+            let color = COLORS[i % COLORS.len()];
+            let line: Vec<String> = series
+                .points
+                .iter()
+                .map(|point| format!("{:.1},{:.1}", px(point.x), py(point.y)))
+                .collect();
+            writeln!(
+                svg,
+                "<polyline points=\"{}\" fill=\"none\" stroke=\"{color}\" stroke-width=\"2\"/>",
+                line.join(" ")
+            )
+            .unwrap();
+            for Point { x, y, range } in &series.points {
+                if let Some((low, high)) = range {
+                    writeln!(
+                        svg,
+                        "<line x1=\"{x:.1}\" x2=\"{x:.1}\" y1=\"{:.1}\" y2=\"{:.1}\" \
+                         stroke=\"{color}\" stroke-opacity=\"0.5\"/>",
+                        py(*low),
+                        py(*high),
+                        x = px(*x),
+                    )
+                    .unwrap();
+                }
+                writeln!(
+                    svg,
+                    "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"3\" fill=\"{color}\">\
+                     <title>{}: {} {}</title></circle>",
+                    px(*x),
+                    py(*y),
+                    escape(&series.name),
+                    format_value(*y),
+                    self.y_label,
+                )
+                .unwrap();
+            }
+            let legend_y = top + 10.0 + i as f64 * 16.0;
+            writeln!(
+                svg,
+                "<rect x=\"{}\" y=\"{:.1}\" width=\"10\" height=\"10\" fill=\"{color}\"/>\
+                 <text x=\"{}\" y=\"{:.1}\">{}</text>",
+                WIDTH - right + 10.0,
+                legend_y - 9.0,
+                WIDTH - right + 24.0,
+                legend_y,
+                escape(&series.name),
+            )
+            .unwrap();
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+/// Formats an axis or point value with a precision suited to its magnitude.
+fn format_value(value: f64) -> String {
+    if value >= 100.0 || value == 0.0 {
+        format!("{value:.0}")
+    } else if value >= 1.0 {
+        format!("{value:.1}")
+    } else {
+        format!("{value:.3}")
+    }
+}
+
+/// Escapes text for use in HTML and SVG content and attributes.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
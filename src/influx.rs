@@ -0,0 +1,171 @@
+//! Export of measurements in InfluxDB line protocol, as done by `client --influx`.
+//!
+//! The throughput of every interval is exported as it is measured, as the
+//! `iroh_benchmark_interval` measurement, and the headline metrics of every report once it
+//! completes, as `iroh_benchmark_metric`. Both are tagged with the run ID, the server's node ID,
+//! the mode and the test name, so that fleets of benchmark nodes can be graphed side by side.
+
+use std::{
+    io::Write as _,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use tokio::sync::mpsc;
+
+use crate::progress::IntervalSample;
+use crate::report::BenchmarkReport;
+
+/// Where the lines go.
+#[derive(Debug, Clone)]
+enum Target {
+    /// The write endpoint of an InfluxDB server, e.g.
+    /// `http://localhost:8086/api/v2/write?org=iroh&bucket=bench`.
+    Url(String),
+    /// A file the lines are appended to.
+    File(PathBuf),
+}
+
+/// Writes measurements to an InfluxDB server or a line protocol file.
+#[derive(Debug, Clone)]
+pub struct InfluxExporter {
+    target: Target,
+    /// API token sent as `Authorization: Token <token>`.
+    token: Option<String>,
+    http: reqwest::Client,
+}
+
+/// Tags identifying the benchmark measurements belong to.
+#[derive(Debug, Clone)]
+pub struct Tags {
+    pub run_id: String,
+    pub server: String,
+    pub mode: String,
+    pub test: String,
+    /// Name of the client machine and `--label` of the run.
+    pub host: String,
+    pub label: String,
+}
+
+impl InfluxExporter {
+    /// Exports to the InfluxDB write endpoint `target` if it is an HTTP(S) URL, and appends to the
+    /// file at `target` otherwise.
+    pub fn new(target: &str, token: Option<String>) -> Self {
+        let target = match target.starts_with("http://") || target.starts_with("https://") {
+            true => Target::Url(target.to_string()),
+            false => Target::File(target.into()),
+        };
+        Self { target, token, http: reqwest::Client::new() }
+    }
+
+    /// Writes the lines, each a point in line protocol.
+    pub async fn write(&self, lines: &[String]) -> Result<()> {
+        if lines.is_empty() {
+            return Ok(());
+        }
+        let body = lines.join("\n") + "\n";
+        match &self.target {
+            Target::Url(url) => {
+                let mut request = self.http.post(url).body(body);
+                if let Some(token) = &self.token {
+                    request = request.header("Authorization", format!("Token {token}"));
+                }
+                let response = request.send().await.context("Failed to reach InfluxDB")?;
+                let status = response.status();
+                if !status.is_success() {
+                    let text = response.text().await.unwrap_or_default();
+                    anyhow::bail!("InfluxDB rejected the points ({status}): {}", text.trim());
+                }
+            }
+            Target::File(path) => {
+                let mut file = std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("Failed to open {}", path.display()))?;
+                file.write_all(body.as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Exports every interval received on `samples` as it arrives, until all senders are gone.
+    ///
+    /// Failures are reported on stderr rather than ending the export, the benchmark goes on
+    /// regardless.
+    pub async fn forward(self, tags: Tags, mut samples: mpsc::UnboundedReceiver<IntervalSample>) {
+        while let Some(sample) = samples.recv().await {
+            let fields = [("upload_mbps", sample.upload), ("download_mbps", sample.download)];
+            let fields: Vec<_> =
+                fields.into_iter().filter_map(|(key, value)| Some((key, value?))).collect();
+            if fields.is_empty() {
+                continue;
+            }
+            let line = line("iroh_benchmark_interval", &tags.pairs(), &fields, sample.time);
+            if let Err(err) = self.write(&[line]).await {
+                tracing::warn!("Exporting to InfluxDB failed: {err:#}");
+            }
+        }
+    }
+
+    /// Exports the headline metrics of a completed report.
+    pub async fn write_report(&self, tags: &Tags, report: &BenchmarkReport) -> Result<()> {
+        let time = humantime::parse_rfc3339(&report.finished_at).unwrap_or(SystemTime::now());
+        let lines: Vec<_> = report
+            .benchmark
+            .metrics()
+            .iter()
+            .map(|metric| {
+                let mut tags = tags.pairs();
+                tags.push(("metric", &metric.name));
+                tags.push(("unit", metric.unit));
+                line("iroh_benchmark_metric", &tags, &[("value", metric.value)], time)
+            })
+            .collect();
+        self.write(&lines).await
+    }
+}
+
+impl Tags {
+    fn pairs(&self) -> Vec<(&str, &str)> {
+        vec![
+            ("run_id", &self.run_id),
+            ("server", &self.server),
+            ("mode", &self.mode),
+            ("test", &self.test),
+            ("host", &self.host),
+            ("label", &self.label),
+        ]
+    }
+}
+
+/// Formats one point in line protocol, with a nanosecond timestamp. Tags with empty values are
+/// left out, as line protocol does not allow them.
+pub fn line(
+    measurement: &str,
+    tags: &[(&str, &str)],
+    fields: &[(&str, f64)],
+    time: SystemTime,
+) -> String {
+    let mut line = escape(measurement);
+    for (key, value) in tags.iter().filter(|(_, value)| !value.is_empty()) {
+        line += &format!(",{}={}", escape(key), escape(value));
+    }
+    let fields: Vec<_> =
+        fields.iter().map(|(key, value)| format!("{}={value}", escape(key))).collect();
+    let nanos = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    format!("{line} {} {nanos}", fields.join(","))
+}
+
+/// Escapes the characters line protocol gives a meaning in measurements, tags and field keys.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, ',' | '=' | ' ' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
@@ -0,0 +1,30 @@
+//! Shared pieces of the iroh benchmark client and server.
+
+pub mod aggregate;
+pub mod baseline;
+pub mod chart;
+pub mod client;
+pub mod clock;
+pub mod compare;
+pub mod config;
+pub mod counters;
+pub mod gossip;
+pub mod html;
+pub mod influx;
+pub mod logging;
+pub mod mesh;
+pub mod metrics;
+pub mod outliers;
+pub mod pacing;
+pub mod payload;
+pub mod progress;
+pub mod protocol;
+pub mod relays;
+pub mod report;
+pub mod scenario;
+pub mod selftest;
+pub mod server;
+pub mod store;
+pub mod tui;
+pub mod units;
+pub mod webhook;
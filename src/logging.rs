@@ -0,0 +1,60 @@
+//! Diagnostic logging of the binaries via `tracing`.
+//!
+//! Results and progress are printed as before, logging is for finding out why a run failed:
+//! `-v` adds debug events and the timing of the connect, transfer and close phases, `-vv` traces
+//! every stream and adds iroh's own events. `RUST_LOG` overrides the verbosity flags.
+
+use std::{fs::OpenOptions, path::PathBuf, sync::Mutex};
+
+use anyhow::{Context, Result};
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::{EnvFilter, fmt::format::FmtSpan};
+
+/// Logging options of the binaries.
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct LogConfig {
+    /// Log more details, `-v` for debug events and phase timings, `-vv` to trace everything
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// Append the log to this file instead of writing it to stderr
+    #[arg(long, global = true)]
+    pub log_file: Option<PathBuf>,
+}
+
+impl LogConfig {
+    /// Installs the global subscriber, logging events of this crate and the binary `bin` from
+    /// `level` on, and those of dependencies only from `-vv` on.
+    pub fn init(&self, bin: &str, level: LevelFilter) -> Result<()> {
+        let ours = match self.verbose {
+            0 => level,
+            1 => level.max(LevelFilter::DEBUG),
+            _ => LevelFilter::TRACE,
+        };
+        // iroh wraps its tasks in error-level spans, whose timings would clutter the log
+        let deps = match self.verbose {
+            0 | 1 => LevelFilter::OFF,
+            2 => LevelFilter::INFO,
+            _ => LevelFilter::DEBUG,
+        };
+        let filter = match std::env::var("RUST_LOG") {
+            Ok(directives) => EnvFilter::try_new(directives)?,
+            Err(_) => EnvFilter::try_new(format!("{deps},p2p={ours},{bin}={ours}"))?,
+        };
+        let builder = tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_span_events(FmtSpan::CLOSE);
+        match &self.log_file {
+            Some(path) => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("Failed to open the log file {}", path.display()))?;
+                builder.with_ansi(false).with_writer(Mutex::new(file)).init();
+            }
+            None => builder.with_writer(std::io::stderr).init(),
+        }
+        Ok(())
+    }
+}
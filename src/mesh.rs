@@ -0,0 +1,463 @@
+//! Mesh benchmarks among many nodes, orchestrated by a coordinator.
+//!
+//! Servers started with `--coordinator <ticket>` register with the coordinator, the
+//! `client mesh` command, on [`MESH_ALPN`]. Once enough nodes registered, the coordinator has
+//! every node measure the throughput and round-trip time to every other node, one pair at a time
+//! so that the measurements don't compete for bandwidth, and collects them into a matrix.
+//!
+//! Every message is JSON on a stream of its own: a node registers by sending its address on a
+//! unidirectional stream, and the coordinator sends every [`Instruction`] on a bidirectional
+//! stream, which the node answers with a [`PairResult`].
+//!
+//! Servers started with `--agent` run the same instructions for any client connecting on
+//! [`AGENT_ALPN`]. `client remote` uses this to measure the path between two remote machines
+//! from a third one, e.g. a laptop, without a shell on either of them.
+
+use std::{fmt::Write as _, time::Duration, time::SystemTime};
+
+use anyhow::{Context, Result};
+use clap::builder::RangedU64ValueParser;
+use iroh::{
+    Endpoint, NodeAddr, NodeId,
+    endpoint::{Connecting, Connection, ConnectionError},
+    protocol::{ProtocolHandler, Router},
+};
+use iroh_base::ticket::NodeTicket;
+use n0_future::boxed::BoxFuture;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::mpsc,
+    time::{Instant, timeout, timeout_at},
+};
+use tracing::info;
+
+use crate::client::{BenchmarkConfig, PingConfig};
+use crate::config::EndpointConfig;
+use crate::protocol::{AGENT_ALPN, MESH_ALPN};
+use crate::report::{Benchmark, RunMetadata, csv_field};
+use crate::server::Allowlist;
+
+/// Largest JSON message read from a mesh stream.
+const MAX_MESSAGE_LEN: usize = 64 * 1024;
+
+/// Time a node may take beyond the measurement itself before its pair counts as failed.
+const INSTRUCTION_GRACE: Duration = Duration::from_secs(60);
+
+/// Options of `client mesh`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct MeshConfig {
+    /// Number of nodes to wait for before measuring
+    #[arg(short, long, value_parser = RangedU64ValueParser::<usize>::new().range(2..))]
+    pub nodes: usize,
+
+    /// How long to wait for all nodes to register
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "5m")]
+    pub wait: Duration,
+
+    /// Duration of the upload between every pair of nodes
+    #[arg(short, long, value_parser = humantime::parse_duration, default_value = "5s")]
+    pub duration: Duration,
+
+    /// Number of pings between every pair of nodes
+    #[arg(long, default_value_t = 20)]
+    pub pings: usize,
+}
+
+/// Options of `client remote`, which instructs the agent given by `--ticket`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct RemoteConfig {
+    /// Ticket of the node the agent measures the path to
+    #[arg(long)]
+    pub to: NodeTicket,
+
+    /// Duration of the upload from the agent
+    #[arg(short, long, value_parser = humantime::parse_duration, default_value = "5s")]
+    pub duration: Duration,
+
+    /// Number of pings from the agent
+    #[arg(long, default_value_t = 20)]
+    pub pings: usize,
+}
+
+/// Measurement the coordinator asks a node to run against another node.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Instruction {
+    pub target: NodeAddr,
+    /// Duration of the timed upload to the target.
+    pub duration_secs: f64,
+    /// Number of pings measuring the round-trip time.
+    pub pings: usize,
+}
+
+/// Measurements from one node to another.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PairResult {
+    pub from: String,
+    pub to: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upload_mbps: Option<f64>,
+    /// Median round-trip time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rtt_ms: Option<f64>,
+    /// Paths used during the upload, see [`crate::report::path_label`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connection_type: Option<String>,
+    /// Why the measurement failed, if it did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Results of a mesh run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MeshReport {
+    pub run: RunMetadata,
+    /// Node IDs of the registered nodes, in the order of the matrix.
+    pub nodes: Vec<String>,
+    /// Measurements of every ordered pair of nodes.
+    pub pairs: Vec<PairResult>,
+}
+
+impl PairResult {
+    fn failed(from: NodeId, to: NodeId, err: anyhow::Error) -> Self {
+        Self {
+            from: from.to_string(),
+            to: to.to_string(),
+            upload_mbps: None,
+            rtt_ms: None,
+            connection_type: None,
+            error: Some(format!("{err:#}")),
+        }
+    }
+}
+
+/// Reads one metric of a pair, for the matrices of [`MeshReport`].
+type PairMetric = fn(&PairResult) -> Option<f64>;
+
+/// Accepts the registrations of nodes on [`MESH_ALPN`].
+#[derive(Debug, Clone)]
+struct Registrations(mpsc::Sender<(Connection, NodeAddr)>);
+
+impl ProtocolHandler for Registrations {
+    fn accept(&self, connecting: Connecting) -> BoxFuture<Result<()>> {
+        let registrations = self.0.clone();
+        Box::pin(async move {
+            let connection = connecting.await?;
+            let mut recv = connection.accept_uni().await?;
+            let addr: NodeAddr =
+                serde_json::from_slice(&recv.read_to_end(MAX_MESSAGE_LEN).await?)?;
+            anyhow::ensure!(
+                addr.node_id == connection.remote_node_id()?,
+                "Nodes can only register their own address"
+            );
+            // The coordinator stops listening once enough nodes registered
+            registrations.send((connection, addr)).await.ok();
+            Ok(())
+        })
+    }
+}
+
+/// Runs the instructions of clients on [`AGENT_ALPN`], for servers started with `--agent`.
+#[derive(Debug, Clone)]
+pub struct Agent {
+    /// Endpoint the measurements are run from.
+    pub endpoint: Endpoint,
+    /// Clients allowed to instruct the agent.
+    pub allow: Allowlist,
+}
+
+impl ProtocolHandler for Agent {
+    fn accept(&self, connecting: Connecting) -> BoxFuture<Result<()>> {
+        let (endpoint, allow) = (self.endpoint.clone(), self.allow.clone());
+        Box::pin(async move {
+            let connection = connecting.await?;
+            let Some(node_id) = allow.admit(&connection, false)? else {
+                return Ok(());
+            };
+            info!("Instructed by {node_id}");
+            follow(&endpoint, &connection).await
+        })
+    }
+}
+
+/// Waits for `--nodes` nodes to register with a coordinator bound with `endpoint`, then measures
+/// every ordered pair of them.
+pub async fn run_mesh(
+    endpoint: &EndpointConfig,
+    mesh: &MeshConfig,
+    print_results: bool,
+) -> Result<MeshReport> {
+    let endpoint = endpoint.keyed_builder()?.bind().await?;
+    let (registrations, mut registered) = mpsc::channel(mesh.nodes);
+    let router = Router::builder(endpoint.clone())
+        .accept(MESH_ALPN, Registrations(registrations))
+        .spawn()
+        .await?;
+    let run = RunMetadata::new(endpoint.node_id(), SystemTime::now());
+    eprintln!("Run ID: {}", run.run_id);
+    eprintln!("Coordinator ticket: {}", NodeTicket::new(endpoint.node_addr().await?));
+    eprintln!("Waiting for {} nodes started with `server --coordinator <ticket>`", mesh.nodes);
+
+    let deadline = Instant::now() + mesh.wait;
+    let mut nodes: Vec<(Connection, NodeAddr)> = Vec::with_capacity(mesh.nodes);
+    while nodes.len() < mesh.nodes {
+        let Ok(Some((connection, addr))) = timeout_at(deadline, registered.recv()).await else {
+            anyhow::bail!("Only {} of {} nodes registered in time", nodes.len(), mesh.nodes);
+        };
+        // A node registering again replaces its earlier registration
+        nodes.retain(|(_, node)| node.node_id != addr.node_id);
+        eprintln!("Node {} registered ({}/{})", addr.node_id, nodes.len() + 1, mesh.nodes);
+        nodes.push((connection, addr));
+    }
+
+    let mut pairs = Vec::with_capacity(nodes.len() * (nodes.len() - 1));
+    for (connection, from) in &nodes {
+        for (_, to) in nodes.iter().filter(|(_, to)| to.node_id != from.node_id) {
+            eprintln!("Measuring {} -> {}", from.node_id.fmt_short(), to.node_id.fmt_short());
+            let instruction = Instruction {
+                target: to.clone(),
+                duration_secs: mesh.duration.as_secs_f64(),
+                pings: mesh.pings,
+            };
+            let res = instruct(connection, &instruction);
+            let res = timeout(mesh.duration + INSTRUCTION_GRACE, res)
+                .await
+                .context("The node did not report back in time")
+                .and_then(|res| res);
+            let result = match res {
+                Ok(result) => result,
+                Err(err) => PairResult::failed(from.node_id, to.node_id, err),
+            };
+            if let Some(error) = &result.error {
+                eprintln!("Failed: {error}");
+            }
+            pairs.push(result);
+        }
+    }
+    for (connection, _) in &nodes {
+        connection.close(0u32.into(), b"done");
+    }
+    router.shutdown().await?;
+
+    let report = MeshReport {
+        run,
+        nodes: nodes.iter().map(|(_, node)| node.node_id.to_string()).collect(),
+        pairs,
+    };
+    if print_results {
+        print!("{}", report.to_text());
+    }
+    Ok(report)
+}
+
+/// Has the agent at `agent` measure the path to `--to`, connecting to it from an endpoint bound
+/// with `endpoint`.
+///
+/// The result is reported like a mesh of the two nodes with a single pair.
+pub async fn run_remote(
+    endpoint: &EndpointConfig,
+    agent: NodeAddr,
+    remote: &RemoteConfig,
+    print_results: bool,
+) -> Result<MeshReport> {
+    let endpoint = endpoint.keyed_builder()?.bind().await?;
+    let run = RunMetadata::new(endpoint.node_id(), SystemTime::now());
+    eprintln!("Run ID: {}", run.run_id);
+    let target = remote.to.node_addr().clone();
+    let (from, to) = (agent.node_id, target.node_id);
+    eprintln!("Instructing agent {from} to measure the path to {to}");
+    let instruction = Instruction {
+        target,
+        duration_secs: remote.duration.as_secs_f64(),
+        pings: remote.pings,
+    };
+    let res = async {
+        let connection = endpoint
+            .connect(agent, AGENT_ALPN)
+            .await
+            .context("Failed to connect to the agent, was it started with `--agent`?")?;
+        let result = instruct(&connection, &instruction).await;
+        connection.close(0u32.into(), b"done");
+        result
+    };
+    let res = timeout(remote.duration + INSTRUCTION_GRACE, res)
+        .await
+        .context("The agent did not report back in time")
+        .and_then(|res| res);
+    endpoint.close().await;
+    let result = res?;
+    if print_results {
+        println!("From {from}\nTo   {to}");
+        if let Some(error) = &result.error {
+            println!("Failed: {error}");
+        }
+        if let Some(upload) = result.upload_mbps {
+            println!("Upload: {upload:.2} Mbit/s");
+        }
+        if let Some(rtt) = result.rtt_ms {
+            println!("Median round-trip time: {rtt:.2} ms");
+        }
+        if let Some(connection_type) = &result.connection_type {
+            println!("Connection type: {connection_type}");
+        }
+    }
+    Ok(MeshReport { run, nodes: vec![from.to_string(), to.to_string()], pairs: vec![result] })
+}
+
+/// Sends an instruction to a registered node or an agent and waits for its result.
+async fn instruct(connection: &Connection, instruction: &Instruction) -> Result<PairResult> {
+    let (mut send, mut recv) = connection.open_bi().await?;
+    send.write_all(&serde_json::to_vec(instruction)?).await?;
+    send.finish()?;
+    Ok(serde_json::from_slice(&recv.read_to_end(MAX_MESSAGE_LEN).await?)?)
+}
+
+/// Registers the node of `endpoint` with the coordinator and runs its instructions until the
+/// coordinator is done.
+pub async fn join(endpoint: Endpoint, coordinator: NodeAddr) -> Result<()> {
+    let addr = endpoint.node_addr().await?;
+    let connection = endpoint.connect(coordinator, MESH_ALPN).await?;
+    let mut send = connection.open_uni().await?;
+    send.write_all(&serde_json::to_vec(&addr)?).await?;
+    send.finish()?;
+    info!("Registered with coordinator {}", connection.remote_node_id()?);
+    follow(&endpoint, &connection).await
+}
+
+/// Runs the instructions arriving on `connection` one after another, until it is closed.
+async fn follow(endpoint: &Endpoint, connection: &Connection) -> Result<()> {
+    loop {
+        let (mut send, mut recv) = match connection.accept_bi().await {
+            Ok(stream) => stream,
+            Err(ConnectionError::ApplicationClosed(_)) => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        let instruction: Instruction =
+            serde_json::from_slice(&recv.read_to_end(MAX_MESSAGE_LEN).await?)?;
+        let target = instruction.target.node_id;
+        info!("Measuring the path to {target}");
+        let result = match measure(endpoint, &instruction).await {
+            Ok(result) => result,
+            Err(err) => PairResult::failed(endpoint.node_id(), target, err),
+        };
+        send.write_all(&serde_json::to_vec(&result)?).await?;
+        send.finish()?;
+    }
+}
+
+/// Runs a timed upload and pings to the target of the instruction.
+async fn measure(endpoint: &Endpoint, instruction: &Instruction) -> Result<PairResult> {
+    let config = BenchmarkConfig { interval: Duration::ZERO, ..Default::default() };
+    let target = instruction.target.clone();
+    let duration = Duration::from_secs_f64(instruction.duration_secs);
+    let timed = crate::client::run_timed(endpoint, target.clone(), &config, duration).await?;
+    let Benchmark::Timed { total, connection_type, .. } = timed else {
+        unreachable!("timed transfers produce timed results");
+    };
+    let rtt_ms = match instruction.pings {
+        0 => None,
+        count => {
+            let ping = PingConfig { count, ..Default::default() };
+            match crate::client::run_latency(endpoint, target, &config, &ping).await? {
+                Benchmark::Ping { summary, .. } => summary.map(|summary| summary.median),
+                _ => unreachable!("pings produce ping results"),
+            }
+        }
+    };
+    Ok(PairResult {
+        from: endpoint.node_id().to_string(),
+        to: instruction.target.node_id.to_string(),
+        upload_mbps: total.upload,
+        rtt_ms,
+        connection_type: Some(connection_type),
+        error: None,
+    })
+}
+
+impl MeshReport {
+    /// Metrics shown as matrices, with their title.
+    const METRICS: [(&str, PairMetric); 2] = [
+        ("Upload throughput (Mbit/s)", |pair| pair.upload_mbps),
+        ("Median round-trip time (ms)", |pair| pair.rtt_ms),
+    ];
+
+    /// The result of the pair from `from` to `to`, if it was measured.
+    fn pair(&self, from: &str, to: &str) -> Option<&PairResult> {
+        self.pairs.iter().find(|pair| pair.from == from && pair.to == to)
+    }
+
+    /// Renders one matrix per metric, with a row per sending node and a column per receiving
+    /// node, followed by the failed pairs.
+    pub fn to_text(&self) -> String {
+        let short = |node: &str| node[..node.len().min(10)].to_string();
+        let mut out = String::new();
+        for (title, value) in Self::METRICS {
+            writeln!(out, "\n{title}, from row to column:").unwrap();
+            write!(out, "{:>10}", "").unwrap();
+            for node in &self.nodes {
+                write!(out, " {:>12}", short(node)).unwrap();
+            }
+            out.push('\n');
+            for from in &self.nodes {
+                write!(out, "{:>10}", short(from)).unwrap();
+                for to in &self.nodes {
+                    let cell = self.cell(from, to, value);
+                    write!(out, " {cell:>12}").unwrap();
+                }
+                out.push('\n');
+            }
+        }
+        for pair in &self.pairs {
+            if let Some(error) = &pair.error {
+                writeln!(out, "{} -> {} failed: {error}", short(&pair.from), short(&pair.to))
+                    .unwrap();
+            }
+        }
+        out
+    }
+
+    /// Renders the matrices as Markdown tables, for pasting into GitHub issues.
+    pub fn to_markdown(&self) -> String {
+        let short = |node: &str| format!("`{}`", &node[..node.len().min(10)]);
+        let mut out = format!("### iroh mesh benchmark: run `{}`\n", self.run.run_id);
+        for (title, value) in Self::METRICS {
+            writeln!(out, "\n{title}, from row to column:\n").unwrap();
+            let header: Vec<_> = self.nodes.iter().map(|node| short(node)).collect();
+            writeln!(out, "| | {} |", header.join(" | ")).unwrap();
+            writeln!(out, "|---|{}", "---:|".repeat(self.nodes.len())).unwrap();
+            for from in &self.nodes {
+                let cells: Vec<_> =
+                    self.nodes.iter().map(|to| self.cell(from, to, value)).collect();
+                writeln!(out, "| {} | {} |", short(from), cells.join(" | ")).unwrap();
+            }
+        }
+        out
+    }
+
+    /// Renders every pair as a CSV row, with a header row.
+    pub fn to_csv(&self) -> String {
+        let opt = |v: Option<f64>| v.map(|v| format!("{v:.3}")).unwrap_or_default();
+        let mut out = String::from("from,to,upload_mbps,rtt_ms,connection_type,error\n");
+        for pair in &self.pairs {
+            writeln!(
+                out,
+                "{},{},{},{},{},{}",
+                pair.from,
+                pair.to,
+                opt(pair.upload_mbps),
+                opt(pair.rtt_ms),
+                pair.connection_type.as_deref().unwrap_or_default(),
+                csv_field(pair.error.as_deref().unwrap_or_default()),
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    /// Formats one cell of a matrix: `-` on the diagonal and for failed pairs.
+    fn cell(&self, from: &str, to: &str, value: PairMetric) -> String {
+        match self.pair(from, to).and_then(value) {
+            Some(value) => format!("{value:.2}"),
+            None => "-".to_string(),
+        }
+    }
+}
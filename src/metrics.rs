@@ -0,0 +1,246 @@
+//! Prometheus metrics of the benchmark server, so long-running servers can be scraped by existing
+//! monitoring.
+//!
+//! The protocol handlers count the payload bytes of every client in [`ServerMetrics`], and
+//! [`serve`] exposes them together with the connection counts and the connection types of the
+//! connected clients on `/metrics`, in the Prometheus text format.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write as _,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use anyhow::Result;
+use iroh::{Endpoint, NodeId};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::report::path_label;
+
+/// Prefix of the names of all metrics.
+const PREFIX: &str = "iroh_benchmark";
+
+/// Largest request head the metrics endpoint reads before giving up on a request.
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+
+/// Time a metrics request has to send its head before it is dropped.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Number of clients beyond which the idle ones no longer get their own series.
+const MAX_CLIENTS: usize = 1024;
+
+/// Counters of all clients the server has seen since it started.
+#[derive(Debug, Default)]
+pub struct ServerMetrics {
+    connections_total: AtomicU64,
+    clients: Mutex<HashMap<NodeId, Arc<ClientMetrics>>>,
+    /// Bytes of the idle clients evicted from `clients`, which still count towards the totals.
+    evicted: ClientMetrics,
+}
+
+/// Counters of a single client, across all its connections.
+#[derive(Debug, Default)]
+pub struct ClientMetrics {
+    /// Payload bytes received from the client.
+    pub bytes_received: AtomicU64,
+    /// Payload bytes sent to the client.
+    pub bytes_sent: AtomicU64,
+    active_connections: AtomicU64,
+    /// Blobs the server currently holds for the client's blob benchmarks.
+    pub blobs: AtomicU64,
+}
+
+/// Marks a connection of a client as active until it is dropped.
+#[derive(Debug)]
+pub struct ConnectionGuard {
+    client: Arc<ClientMetrics>,
+}
+
+impl ServerMetrics {
+    /// Records a new connection of `node_id`, which counts as active until the returned guard
+    /// is dropped.
+    ///
+    /// Once more than `MAX_CLIENTS` clients are tracked, those nothing refers to anymore are
+    /// evicted and their bytes only count towards the totals.
+    pub fn connected(&self, node_id: NodeId) -> ConnectionGuard {
+        self.connections_total.fetch_add(1, Ordering::Relaxed);
+        let mut clients = self.clients.lock().expect("poisoned");
+        let client = clients.entry(node_id).or_default().clone();
+        client.active_connections.fetch_add(1, Ordering::Relaxed);
+        if clients.len() > MAX_CLIENTS {
+            let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed) as usize;
+            clients.retain(|_, client| {
+                let idle = Arc::strong_count(client) == 1;
+                if idle {
+                    self.evicted.add_received(load(&client.bytes_received));
+                    self.evicted.add_sent(load(&client.bytes_sent));
+                }
+                !idle
+            });
+        }
+        ConnectionGuard { client }
+    }
+
+    /// Encodes the metrics in the Prometheus text format, looking up the connection types of
+    /// the connected clients on `endpoint`.
+    pub fn encode(&self, endpoint: &Endpoint) -> String {
+        let clients: Vec<_> = self
+            .clients
+            .lock()
+            .expect("poisoned")
+            .iter()
+            .map(|(node_id, client)| (*node_id, client.clone()))
+            .collect();
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+
+        let mut received = load(&self.evicted.bytes_received);
+        let mut sent = load(&self.evicted.bytes_sent);
+        let mut active = 0;
+        let mut conn_types =
+            BTreeMap::from([("direct", 0), ("relay", 0), ("mixed", 0), ("none", 0)]);
+        for (node_id, client) in &clients {
+            received += load(&client.bytes_received);
+            sent += load(&client.bytes_sent);
+            let connections = load(&client.active_connections);
+            active += connections;
+            if connections > 0 {
+                let conn_type = endpoint.conn_type(*node_id).ok().and_then(|w| w.get().ok());
+                let label = conn_type.as_ref().map_or("none", path_label);
+                *conn_types.entry(label).or_default() += connections;
+            }
+        }
+
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, u64)]| {
+            writeln!(out, "# HELP {PREFIX}_{name} {help}").unwrap();
+            writeln!(out, "# TYPE {PREFIX}_{name} {kind}").unwrap();
+            for (labels, value) in samples {
+                writeln!(out, "{PREFIX}_{name}{labels} {value}").unwrap();
+            }
+        };
+        let total = |value| [(String::new(), value)];
+        let per_client = |counter: fn(&ClientMetrics) -> &AtomicU64| {
+            clients
+                .iter()
+                .map(|(node_id, client)| {
+                    (format!("{{client=\"{node_id}\"}}"), load(counter(client)))
+                })
+                .collect::<Vec<_>>()
+        };
+        metric("bytes_received_total", "counter", "Payload bytes received.", &total(received));
+        metric("bytes_sent_total", "counter", "Payload bytes sent.", &total(sent));
+        let connections = load(&self.connections_total);
+        metric("connections_total", "counter", "Connections accepted.", &total(connections));
+        metric("active_connections", "gauge", "Connections currently open.", &total(active));
+        metric(
+            "connections_by_type",
+            "gauge",
+            "Open connections by the type of their path.",
+            &conn_types
+                .into_iter()
+                .map(|(label, n)| (format!("{{type=\"{label}\"}}"), n))
+                .collect::<Vec<_>>(),
+        );
+        metric(
+            "client_bytes_received_total",
+            "counter",
+            "Payload bytes received per client.",
+            &per_client(|client| &client.bytes_received),
+        );
+        metric(
+            "client_bytes_sent_total",
+            "counter",
+            "Payload bytes sent per client.",
+            &per_client(|client| &client.bytes_sent),
+        );
+        out
+    }
+}
+
+impl ConnectionGuard {
+    /// Counters of the client of the connection.
+    pub fn client(&self) -> &Arc<ClientMetrics> {
+        &self.client
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.client.active_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl ClientMetrics {
+    /// Adds `bytes` to the bytes received from the client.
+    pub fn add_received(&self, bytes: usize) {
+        self.bytes_received.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Adds `bytes` to the bytes sent to the client.
+    pub fn add_sent(&self, bytes: usize) {
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+/// Serves the metrics on `/metrics` to every connection accepted by `listener`, until accepting
+/// fails.
+pub async fn serve(
+    listener: TcpListener,
+    metrics: Arc<ServerMetrics>,
+    endpoint: Endpoint,
+) -> Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let metrics = metrics.clone();
+        let endpoint = endpoint.clone();
+        tokio::spawn(async move {
+            if let Err(err) = respond(stream, &metrics, &endpoint).await {
+                tracing::warn!("Failed to serve metrics: {err:#}");
+            }
+        });
+    }
+}
+
+/// Answers a single HTTP request, with the metrics if it is for `/metrics`.
+async fn respond(
+    mut stream: TcpStream,
+    metrics: &ServerMetrics,
+    endpoint: &Endpoint,
+) -> Result<()> {
+    let mut head = Vec::new();
+    let read_head = async {
+        let mut buf = [0u8; 1024];
+        while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+            let n = stream.read(&mut buf).await?;
+            anyhow::ensure!(n > 0, "Connection closed before the end of the request");
+            head.extend_from_slice(&buf[..n]);
+            anyhow::ensure!(head.len() <= MAX_REQUEST_HEAD, "Request head too large");
+        }
+        Ok(())
+    };
+    tokio::time::timeout(REQUEST_TIMEOUT, read_head)
+        .await
+        .map_err(|_| anyhow::anyhow!("Timed out reading the request head"))??;
+    let request_line = String::from_utf8_lossy(&head);
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", metrics.encode(endpoint)),
+        (Some("GET"), _) => ("404 Not Found", "Not found, try /metrics\n".to_string()),
+        _ => ("405 Method Not Allowed", String::new()),
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
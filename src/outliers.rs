@@ -0,0 +1,45 @@
+//! Detection of outlying samples, such as an iteration that ran while the connection fell back
+//! to the relay.
+
+use crate::report::percentile;
+
+/// Fewest samples outliers are looked for in, fewer do not tell what is typical.
+const MIN_SAMPLES: usize = 4;
+
+/// Rule deciding which samples are outliers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutlierRule {
+    /// More than 1.5 interquartile ranges below the first or above the third quartile
+    Iqr,
+    /// More than 3 scaled median absolute deviations away from the median
+    Mad,
+}
+
+impl OutlierRule {
+    /// Flags the outliers among `samples`, in the same order. Nothing is flagged if there are
+    /// fewer than four samples.
+    pub fn outliers(self, samples: &[f64]) -> Vec<bool> {
+        if samples.len() < MIN_SAMPLES {
+            return vec![false; samples.len()];
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let (low, high) = match self {
+            OutlierRule::Iqr => {
+                let (q1, q3) = (percentile(&sorted, 25.0), percentile(&sorted, 75.0));
+                let iqr = q3 - q1;
+                (q1 - 1.5 * iqr, q3 + 1.5 * iqr)
+            }
+            OutlierRule::Mad => {
+                let median = percentile(&sorted, 50.0);
+                let mut deviations: Vec<f64> = sorted.iter().map(|x| (x - median).abs()).collect();
+                deviations.sort_by(f64::total_cmp);
+                // Scaled to estimate the standard deviation of normally distributed samples
+                let mad = 1.4826 * percentile(&deviations, 50.0);
+                (median - 3.0 * mad, median + 3.0 * mad)
+            }
+        };
+        samples.iter().map(|x| *x < low || *x > high).collect()
+    }
+}
@@ -0,0 +1,48 @@
+//! Pacing of payload senders to a target bitrate, for `--bitrate`.
+//!
+//! A token bucket fills at the target rate and every chunk takes its size in tokens. The bucket
+//! may go into debt by one chunk, which the sender then sleeps off, so the average rate matches
+//! the target even though tokio's timers only sleep in whole milliseconds.
+
+use std::time::Duration;
+
+use tokio::time::{Instant, sleep};
+
+/// Longest burst the bucket holds tokens for, which absorbs the coarse timer granularity at high
+/// rates.
+const MAX_BURST: Duration = Duration::from_millis(5);
+
+/// Token bucket limiting one sender to a bitrate.
+#[derive(Debug)]
+pub struct Pacer {
+    /// Rate in bytes per second, or 0 to send at line rate.
+    rate: f64,
+    /// Tokens the bucket holds at most, in bytes.
+    capacity: f64,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl Pacer {
+    /// A pacer sending `bitrate` bits per second in chunks of up to `chunk_size` bytes, or at
+    /// line rate if `bitrate` is 0.
+    pub fn new(bitrate: u64, chunk_size: usize) -> Self {
+        let rate = bitrate as f64 / 8.0;
+        let capacity = (rate * MAX_BURST.as_secs_f64()).max(chunk_size as f64);
+        Self { rate, capacity, tokens: capacity, refilled: Instant::now() }
+    }
+
+    /// Waits until `n` more bytes may be sent.
+    pub async fn wait(&mut self, n: usize) {
+        if self.rate == 0.0 {
+            return;
+        }
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity) - n as f64;
+        self.refilled = now;
+        if self.tokens < 0.0 {
+            sleep(Duration::from_secs_f64(-self.tokens / self.rate)).await;
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! Generation of benchmark payloads.
+//!
+//! Payloads are generated chunk by chunk as they are sent, so that transfers never hold the
+//! whole payload in memory. Every byte only depends on the pattern, the seed and its offset.
+
+/// Content of the payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Pattern {
+    /// All zero bytes, trivially compressible
+    #[default]
+    Zeros,
+    /// Pseudo-random bytes derived from the seed, incompressible
+    Random,
+    /// Bytes counting up from zero, wrapping around at 256
+    Seq,
+}
+
+impl Pattern {
+    pub fn to_byte(self) -> u8 {
+        match self {
+            Pattern::Zeros => 0,
+            Pattern::Random => 1,
+            Pattern::Seq => 2,
+        }
+    }
+
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Pattern::Zeros),
+            1 => Some(Pattern::Random),
+            2 => Some(Pattern::Seq),
+            _ => None,
+        }
+    }
+}
+
+/// Generates consecutive chunks of a payload.
+#[derive(Debug, Clone)]
+pub struct Payload {
+    pattern: Pattern,
+    seed: u64,
+    offset: u64,
+}
+
+impl Payload {
+    pub fn new(pattern: Pattern, seed: u64) -> Self {
+        Self { pattern, seed, offset: 0 }
+    }
+
+    /// Fills `buf` with the next `buf.len()` bytes of the payload.
+    pub fn fill(&mut self, buf: &mut [u8]) {
+        match self.pattern {
+            Pattern::Zeros => buf.fill(0),
+            Pattern::Seq => {
+                for (i, byte) in buf.iter_mut().enumerate() {
+                    *byte = (self.offset + i as u64) as u8;
+                }
+            }
+            Pattern::Random => {
+                // Every aligned 8-byte word is a hash of its index, so chunks can start anywhere
+                let word = |offset: u64| splitmix64(self.seed ^ (offset / 8)).to_le_bytes();
+                let head = ((8 - self.offset % 8) % 8).min(buf.len() as u64) as usize;
+                let (head_buf, rest) = buf.split_at_mut(head);
+                for (i, byte) in head_buf.iter_mut().enumerate() {
+                    let offset = self.offset + i as u64;
+                    *byte = word(offset)[(offset % 8) as usize];
+                }
+                let mut offset = self.offset + head as u64;
+                let mut words = rest.chunks_exact_mut(8);
+                for chunk in &mut words {
+                    chunk.copy_from_slice(&word(offset));
+                    offset += 8;
+                }
+                let tail = words.into_remainder();
+                let bytes = word(offset);
+                tail.copy_from_slice(&bytes[..tail.len()]);
+            }
+        }
+        self.offset += buf.len() as u64;
+    }
+}
+
+/// SplitMix64 finalizer, a fast hash with good statistical quality.
+pub(crate) fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
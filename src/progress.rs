@@ -0,0 +1,146 @@
+//! Live state of a running benchmark, for dashboards such as the client's `--tui`.
+//!
+//! The transfers count their bytes per stream in [`Counters`]. Without a [`Progress`] the client
+//! prints the throughput of every interval and its status messages on stderr; with one, both are
+//! handed to the dashboard instead. The throughput of every interval can additionally be sent to
+//! an [`IntervalSink`], e.g. to export it to InfluxDB.
+
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime},
+};
+
+use hdrhistogram::Histogram;
+use tokio::sync::mpsc;
+
+use crate::report::{Stats, record_latency};
+
+/// Number of status messages a [`Progress`] keeps.
+const MAX_LOG_LINES: usize = 1000;
+
+/// Payload bytes moved so far by the streams of a transfer.
+#[derive(Debug, Default)]
+pub struct Counters {
+    streams: Mutex<Vec<Arc<StreamCounters>>>,
+}
+
+/// Payload bytes moved so far by a single stream.
+#[derive(Debug)]
+pub struct StreamCounters {
+    /// Bytes to move in each direction, `None` for streams that run until a deadline.
+    pub size: Option<u64>,
+    sent: AtomicU64,
+    received: AtomicU64,
+    /// Time every chunk took to be written or read.
+    chunk_latencies: Mutex<Histogram<u64>>,
+}
+
+impl Counters {
+    /// Adds the counters of a new stream.
+    pub fn stream(&self, size: Option<u64>) -> Arc<StreamCounters> {
+        let stream = Arc::new(StreamCounters {
+            size,
+            sent: AtomicU64::new(0),
+            received: AtomicU64::new(0),
+            chunk_latencies: Mutex::new(Stats::histogram()),
+        });
+        self.streams.lock().expect("poisoned").push(stream.clone());
+        stream
+    }
+
+    /// The counters of every stream, in the order the streams started.
+    pub fn streams(&self) -> Vec<Arc<StreamCounters>> {
+        self.streams.lock().expect("poisoned").clone()
+    }
+
+    /// Bytes sent by all streams.
+    pub fn sent(&self) -> u64 {
+        self.streams().iter().map(|stream| stream.sent()).sum()
+    }
+
+    /// Bytes received by all streams.
+    pub fn received(&self) -> u64 {
+        self.streams().iter().map(|stream| stream.received()).sum()
+    }
+
+    /// Chunk latencies of all streams, merged into `histogram`.
+    pub fn merge_chunk_latencies(&self, histogram: &mut Histogram<u64>) {
+        for stream in self.streams() {
+            let chunks = stream.chunk_latencies.lock().expect("poisoned");
+            // Both histograms have the same bounds, so adding cannot fail
+            histogram.add(&*chunks).expect("same bounds");
+        }
+    }
+}
+
+impl StreamCounters {
+    pub fn add_sent(&self, n: usize) {
+        self.sent.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    pub fn add_received(&self, n: usize) {
+        self.received.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    /// Records how long writing or reading a chunk took.
+    pub fn record_chunk(&self, latency: Duration) {
+        record_latency(&mut self.chunk_latencies.lock().expect("poisoned"), latency);
+    }
+
+    pub fn sent(&self) -> u64 {
+        self.sent.load(Ordering::Relaxed)
+    }
+
+    pub fn received(&self) -> u64 {
+        self.received.load(Ordering::Relaxed)
+    }
+}
+
+/// Receiver of the throughput of every interval of a transfer.
+pub type IntervalSink = mpsc::UnboundedSender<IntervalSample>;
+
+/// Throughput of a transfer during one interval, in Mbit/s.
+#[derive(Debug, Clone, Copy)]
+pub struct IntervalSample {
+    /// End of the interval.
+    pub time: SystemTime,
+    pub upload: Option<f64>,
+    pub download: Option<f64>,
+}
+
+/// What a dashboard shows of the running benchmark.
+#[derive(Debug, Default)]
+pub struct Progress {
+    log: Mutex<Vec<String>>,
+    counters: Mutex<Option<Arc<Counters>>>,
+}
+
+impl Progress {
+    /// Records a status message, such as the start of an iteration.
+    pub fn log(&self, line: String) {
+        let mut log = self.log.lock().expect("poisoned");
+        if log.len() == MAX_LOG_LINES {
+            log.remove(0);
+        }
+        log.push(line);
+    }
+
+    /// The last `n` status messages.
+    pub fn last_lines(&self, n: usize) -> Vec<String> {
+        let log = self.log.lock().expect("poisoned");
+        log[log.len().saturating_sub(n)..].to_vec()
+    }
+
+    /// Makes `counters` the ones of the currently running transfer.
+    pub fn track(&self, counters: Arc<Counters>) {
+        *self.counters.lock().expect("poisoned") = Some(counters);
+    }
+
+    /// The counters of the currently running transfer, if any transfer started yet.
+    pub fn counters(&self) -> Option<Arc<Counters>> {
+        self.counters.lock().expect("poisoned").clone()
+    }
+}
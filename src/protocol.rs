@@ -0,0 +1,628 @@
+//! Wire protocol spoken between the benchmark client and server.
+//!
+//! Every benchmark runs on its own bidirectional stream. The client starts the stream with a
+//! versioned [`Request`] telling the server what kind of exchange follows: a bulk transfer in
+//! one or both directions, an echo of the client's payload, a ping-pong of small messages, the
+//! import of a blob the client then fetches with the iroh-blobs protocol, joining a gossip
+//! topic as the bootstrap node of the client's swarm, answering small framed requests,
+//! counting a stream of small framed messages, or timestamping probes to compare clocks.
+//! The server validates the request and answers with a [`Response`] before any payload.
+//!
+//! Datagram benchmarks use a separate ALPN, [`DATAGRAM_ALPN`]. The client sends unreliable
+//! datagrams, each starting with a [`DatagramHeader`], then the number it sent as a big-endian
+//! `u64` on a bidirectional stream, and the server answers on that stream with a
+//! [`DatagramSummary`] of what arrived.
+//!
+//! The TCP baseline starts every TCP connection with the same [`Request`] and [`Response`].
+
+use std::time::SystemTime;
+
+use anyhow::{Context, Result, bail};
+use iroh::endpoint::{ReadExactError, RecvStream, SendStream};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::payload::Pattern;
+
+/// Each protocol is identified by its ALPN string.
+///
+/// The ALPN, or application-layer protocol negotiation, is exchanged in the connection handshake,
+/// and the connection is aborted unless both nodes pass the same bytestring.
+pub const ALPN: &[u8] = b"iroh-example/print/2";
+
+/// ALPN of the datagram benchmark.
+pub const DATAGRAM_ALPN: &[u8] = b"iroh-example/datagram/1";
+
+/// ALPN nodes register with a mesh coordinator on, see [`crate::mesh`].
+pub const MESH_ALPN: &[u8] = b"iroh-example/mesh/0";
+
+/// ALPN clients instruct servers started with `--agent` on, see [`crate::mesh::Agent`].
+pub const AGENT_ALPN: &[u8] = b"iroh-example/agent/0";
+
+/// ALPN clients upload their results to the aggregation service on, see [`crate::aggregate`].
+pub const RESULTS_ALPN: &[u8] = b"iroh-example/results/0";
+
+/// Small acknowledgment the server sends once it received an upload.
+pub const ACK: &[u8] = b"received";
+
+/// Transfer size that makes the sender stream until the client ends the transfer, either by
+/// finishing its send side (uploads) or by stopping its receive side (downloads).
+pub const UNBOUNDED: u64 = u64::MAX;
+
+/// Direction in which the benchmark payload flows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// The client sends the payload and the server acknowledges it.
+    Upload,
+    /// The server sends the payload and the client measures receive throughput.
+    Download,
+    /// Both sides send a payload of the same size at the same time.
+    Both,
+    /// The client sends the payload and the server mirrors every chunk back, so the download
+    /// measures round-trip goodput.
+    Echo,
+}
+
+/// Version of the [`Request`] and [`Response`] framing.
+///
+/// Fields added to a message within a version are appended to its body, and readers ignore
+/// trailing bytes they don't know, so only incompatible changes need a new version.
+pub const VERSION: u8 = 1;
+
+/// Largest chunk size a client may request.
+pub const MAX_CHUNK_SIZE: u32 = 16 * 1024 * 1024;
+
+/// Largest number of parallel streams per connection a client may request, matching QUIC's
+/// default limit of concurrent bidirectional streams.
+pub const MAX_STREAMS: u32 = 100;
+
+/// Largest blob a client may request, as blobs are held in memory.
+pub const MAX_BLOB_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// Largest payload of a gossip benchmark message.
+pub const MAX_GOSSIP_PAYLOAD: u64 = 32 * 1024;
+
+/// Largest encoded message iroh-gossip accepts in the gossip benchmark, leaving room for the
+/// framing around the payload. All members of the swarm must agree on it.
+pub const MAX_GOSSIP_MESSAGE: usize = MAX_GOSSIP_PAYLOAD as usize + 1024;
+
+/// Largest request or response body of an RPC benchmark.
+pub const MAX_RPC_MESSAGE: u32 = 1024 * 1024;
+
+/// Largest encoded message body, to bound what is read before the message is validated.
+const MAX_BODY_LEN: usize = 1024;
+
+/// Parameters of a bulk transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transfer {
+    pub direction: Direction,
+    /// Payload size of this stream in bytes, or [`UNBOUNDED`].
+    pub size: u64,
+    /// Size of the chunks the payload is written and read in.
+    pub chunk_size: u32,
+    /// Number of parallel streams on the connection the payload is striped across.
+    pub streams: u32,
+    /// Whether the server reports BLAKE3 hashes of the payload, see [`Transfer::trailer_len`].
+    pub verify: bool,
+    /// Content of the payload sent by either side.
+    pub pattern: Pattern,
+    /// Seed of [`Pattern::Random`].
+    pub seed: u64,
+    /// Rate the sender paces the payload of this stream to in bit/s, or 0 for line rate.
+    pub bitrate: u64,
+    /// Whether the server appends a [`ServerReport`] of its own measurements to the trailer.
+    pub report: bool,
+}
+
+impl Transfer {
+    /// Length of the trailer the server writes after its payload.
+    ///
+    /// Without verification the server only acknowledges uploads with [`ACK`]. With it, it also
+    /// sends the BLAKE3 hash of everything it sent, then of everything it received. Echoed data
+    /// is compared by the client alone. A requested [`ServerReport`] comes last, servers that
+    /// predate it leave it out.
+    pub fn trailer_len(&self) -> usize {
+        let hashes = match (self.verify, self.direction) {
+            (false, _) | (true, Direction::Echo) => 0,
+            (true, Direction::Upload | Direction::Download) => 1,
+            (true, Direction::Both) => 2,
+        };
+        let ack = if self.direction == Direction::Upload { ACK.len() } else { 0 };
+        let report = if self.report { ServerReport::LEN } else { 0 };
+        ack + hashes * blake3::OUT_LEN + report
+    }
+}
+
+/// Control message sent by the client at the start of every stream.
+///
+/// Encoded as a frame of the protocol [`VERSION`], the body length as a big-endian `u16` and
+/// the body: a tag byte followed by the fields, integers in big-endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Request {
+    /// Bulk transfer in the given direction.
+    Transfer(Transfer),
+    /// Ping-pong of messages of `size` bytes each, which the server echoes back one by one
+    /// until the client finishes its side of the stream.
+    Ping { size: u64 },
+    /// Import of a payload of `size` bytes into the server's blob store. The server answers
+    /// with the BLAKE3 hash of the blob and keeps it until the client finishes the stream, so the
+    /// client can fetch it with the iroh-blobs protocol in the meantime.
+    Blob { size: u64, pattern: Pattern, seed: u64 },
+    /// Subscription to the iroh-gossip topic with the given ID, so that the client's peers can
+    /// bootstrap their swarm from the server. The server stays subscribed until the client
+    /// finishes the stream.
+    Gossip { topic: [u8; 32] },
+    /// Request/response exchange: the server answers every RPC message of the client, see
+    /// [`rpc_message`], with one of `response_size` bytes until the client finishes the stream.
+    Rpc { response_size: u32 },
+    /// Stream of small messages framed like [`rpc_message`], which the server reads one by one
+    /// until the client finishes the stream, then answers with their number as a big-endian
+    /// `u64`.
+    Messages,
+    /// Exchange of timestamp probes: the client sends the time it sent every probe, see
+    /// [`wall_clock_us`], as a big-endian `u64`, and the server answers each with a
+    /// [`ClockReply`] until the client finishes the stream.
+    Clock,
+}
+
+impl Request {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        match *self {
+            Request::Transfer(transfer) => {
+                let direction = match transfer.direction {
+                    Direction::Upload => 0,
+                    Direction::Download => 1,
+                    Direction::Both => 2,
+                    Direction::Echo => 3,
+                };
+                body.extend([0, direction]);
+                body.extend(transfer.size.to_be_bytes());
+                body.extend(transfer.chunk_size.to_be_bytes());
+                body.extend(transfer.streams.to_be_bytes());
+                body.push(transfer.verify.into());
+                body.push(transfer.pattern.to_byte());
+                body.extend(transfer.seed.to_be_bytes());
+                body.extend(transfer.bitrate.to_be_bytes());
+                body.push(transfer.report.into());
+            }
+            Request::Ping { size } => {
+                body.push(1);
+                body.extend(size.to_be_bytes());
+            }
+            Request::Blob { size, pattern, seed } => {
+                body.push(2);
+                body.extend(size.to_be_bytes());
+                body.push(pattern.to_byte());
+                body.extend(seed.to_be_bytes());
+            }
+            Request::Gossip { topic } => {
+                body.push(3);
+                body.extend(topic);
+            }
+            Request::Rpc { response_size } => {
+                body.push(4);
+                body.extend(response_size.to_be_bytes());
+            }
+            Request::Messages => body.push(5),
+            Request::Clock => body.push(6),
+        }
+        frame(VERSION, &body)
+    }
+
+    /// Decodes a message body of the given version.
+    pub fn decode(version: u8, body: &[u8]) -> Result<Self> {
+        if version != VERSION {
+            bail!("Unsupported protocol version {version}, expected {VERSION}");
+        }
+        let mut body = Reader(body);
+        let request = match body.u8()? {
+            0 => {
+                let direction = match body.u8()? {
+                    0 => Direction::Upload,
+                    1 => Direction::Download,
+                    2 => Direction::Both,
+                    3 => Direction::Echo,
+                    other => bail!("Unknown direction {other}"),
+                };
+                Request::Transfer(Transfer {
+                    direction,
+                    size: body.u64()?,
+                    chunk_size: body.u32()?,
+                    streams: body.u32()?,
+                    verify: !body.is_empty() && body.u8()? != 0,
+                    pattern: if body.is_empty() {
+                        Pattern::Zeros
+                    } else {
+                        let byte = body.u8()?;
+                        Pattern::from_byte(byte).context(format!("Unknown pattern {byte}"))?
+                    },
+                    seed: if body.is_empty() { 0 } else { body.u64()? },
+                    bitrate: if body.is_empty() { 0 } else { body.u64()? },
+                    report: !body.is_empty() && body.u8()? != 0,
+                })
+            }
+            1 => Request::Ping { size: body.u64()? },
+            2 => Request::Blob {
+                size: body.u64()?,
+                pattern: {
+                    let byte = body.u8()?;
+                    Pattern::from_byte(byte).context(format!("Unknown pattern {byte}"))?
+                },
+                seed: body.u64()?,
+            },
+            3 => Request::Gossip { topic: body.take()? },
+            4 => Request::Rpc { response_size: body.u32()? },
+            5 => Request::Messages,
+            6 => Request::Clock,
+            other => bail!("Unknown request tag {other}"),
+        };
+        Ok(request)
+    }
+
+    /// Checks that the request is within the limits the server is willing to serve.
+    pub fn validate(&self) -> Result<()> {
+        match *self {
+            Request::Transfer(Transfer { chunk_size, streams, .. }) => {
+                if !(1..=MAX_CHUNK_SIZE).contains(&chunk_size) {
+                    bail!("Chunk size must be between 1 and {MAX_CHUNK_SIZE} bytes");
+                }
+                if !(1..=MAX_STREAMS).contains(&streams) {
+                    bail!("Number of streams must be between 1 and {MAX_STREAMS}");
+                }
+            }
+            Request::Ping { size } => {
+                if !(1..=MAX_CHUNK_SIZE as u64).contains(&size) {
+                    bail!("Ping size must be between 1 and {MAX_CHUNK_SIZE} bytes");
+                }
+            }
+            Request::Blob { size, .. } => {
+                if !(1..=MAX_BLOB_SIZE).contains(&size) {
+                    bail!("Blob size must be between 1 and {MAX_BLOB_SIZE} bytes");
+                }
+            }
+            Request::Gossip { .. } | Request::Messages | Request::Clock => {}
+            Request::Rpc { response_size } => {
+                if response_size > MAX_RPC_MESSAGE {
+                    bail!("RPC response size must be at most {MAX_RPC_MESSAGE} bytes");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn write(&self, send: &mut (impl AsyncWrite + Unpin)) -> Result<()> {
+        send.write_all(&self.encode()).await?;
+        Ok(())
+    }
+
+    pub async fn read(recv: &mut (impl AsyncRead + Unpin)) -> Result<Self> {
+        let (version, body) = read_frame(recv).await?;
+        Self::decode(version, &body)
+    }
+}
+
+/// The server's answer to a [`Request`], sent before any payload.
+///
+/// Framed like the request, the body is a status byte followed by the UTF-8 reason of a
+/// rejection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Response {
+    Accepted,
+    /// The server refused to serve the request, e.g. because it exceeds its limits.
+    Rejected(String),
+}
+
+impl Response {
+    pub fn encode(&self) -> Vec<u8> {
+        let body = match self {
+            Response::Accepted => vec![0],
+            Response::Rejected(reason) => [&[1], reason.as_bytes()].concat(),
+        };
+        frame(VERSION, &body)
+    }
+
+    /// Decodes a message body. Responses keep their layout across versions, so that a client
+    /// can read the rejection of a version the server does not support.
+    pub fn decode(body: &[u8]) -> Result<Self> {
+        let mut body = Reader(body);
+        let response = match body.u8()? {
+            0 => Response::Accepted,
+            1 => Response::Rejected(String::from_utf8_lossy(body.0).into_owned()),
+            other => bail!("Unknown response status {other}"),
+        };
+        Ok(response)
+    }
+
+    pub async fn write(&self, send: &mut (impl AsyncWrite + Unpin)) -> Result<()> {
+        send.write_all(&self.encode()).await?;
+        Ok(())
+    }
+
+    /// Reads the response, turning a rejection into an error.
+    pub async fn read(recv: &mut (impl AsyncRead + Unpin)) -> Result<()> {
+        let (_, body) = read_frame(recv).await?;
+        match Self::decode(&body)? {
+            Response::Accepted => Ok(()),
+            Response::Rejected(reason) => bail!("Server rejected the request: {reason}"),
+        }
+    }
+}
+
+/// Prefixes a message body with the version and its length.
+fn frame(version: u8, body: &[u8]) -> Vec<u8> {
+    let len = u16::try_from(body.len()).expect("message bodies are small");
+    [&[version][..], &len.to_be_bytes(), body].concat()
+}
+
+/// Reads a framed message, returning its version and body.
+async fn read_frame(recv: &mut (impl AsyncRead + Unpin)) -> Result<(u8, Vec<u8>)> {
+    let mut header = [0u8; 3];
+    recv.read_exact(&mut header).await?;
+    let len = u16::from_be_bytes([header[1], header[2]]) as usize;
+    if len > MAX_BODY_LEN {
+        bail!("Message of {len} bytes exceeds the limit of {MAX_BODY_LEN}");
+    }
+    let mut body = vec![0u8; len];
+    recv.read_exact(&mut body).await?;
+    Ok((header[0], body))
+}
+
+/// Encodes an RPC message with a body of `size` zeros: the body length as a big-endian `u32`,
+/// then the body.
+pub fn rpc_message(size: u32) -> Vec<u8> {
+    let mut message = vec![0u8; 4 + size as usize];
+    message[..4].copy_from_slice(&size.to_be_bytes());
+    message
+}
+
+/// Reads an RPC message into `buf`, returning its body length, or `None` if the peer finished
+/// the stream before another message.
+pub async fn read_rpc_message(recv: &mut RecvStream, buf: &mut Vec<u8>) -> Result<Option<u32>> {
+    let mut header = [0u8; 4];
+    match recv.read_exact(&mut header).await {
+        Ok(()) => {}
+        Err(ReadExactError::FinishedEarly(0)) => return Ok(None),
+        Err(err) => return Err(err.into()),
+    }
+    let len = u32::from_be_bytes(header);
+    if len > MAX_RPC_MESSAGE {
+        bail!("RPC message of {len} bytes exceeds the limit of {MAX_RPC_MESSAGE}");
+    }
+    buf.resize(len as usize, 0);
+    recv.read_exact(buf).await?;
+    Ok(Some(len))
+}
+
+/// Cursor over a message body.
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        if self.0.len() < N {
+            bail!("Message body is truncated");
+        }
+        let (head, rest) = self.0.split_at(N);
+        self.0 = rest;
+        Ok(head.try_into()?)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.take()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_be_bytes(self.take()?))
+    }
+}
+
+/// Prefix of every benchmark datagram, the rest is padding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DatagramHeader {
+    /// Sequence number, counting up from zero.
+    pub seq: u64,
+    /// Time the datagram was sent in microseconds since the start of the benchmark, on the
+    /// client's clock.
+    pub sent_at_us: u64,
+}
+
+impl DatagramHeader {
+    /// Encoded length: both fields as big-endian `u64`s.
+    pub const LEN: usize = 16;
+
+    /// Writes the header to the start of `buf`, which must be at least [`Self::LEN`] long.
+    pub fn encode_into(&self, buf: &mut [u8]) {
+        buf[..8].copy_from_slice(&self.seq.to_be_bytes());
+        buf[8..Self::LEN].copy_from_slice(&self.sent_at_us.to_be_bytes());
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Self> {
+        if buf.len() < Self::LEN {
+            bail!("Datagram of {} bytes is too short for its header", buf.len());
+        }
+        Ok(Self {
+            seq: u64::from_be_bytes(buf[..8].try_into()?),
+            sent_at_us: u64::from_be_bytes(buf[8..Self::LEN].try_into()?),
+        })
+    }
+}
+
+/// The server's own measurements of one transfer stream, which it appends to the trailer if the
+/// client sets [`Transfer::report`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ServerReport {
+    /// Payload bytes the server received and sent on the stream.
+    pub received: u64,
+    pub sent: u64,
+    /// Time the server spent receiving and sending the payload, in microseconds.
+    pub receive_us: u64,
+    pub send_us: u64,
+    /// RTT estimate and congestion window of the server's side of the connection once the
+    /// transfer completed, in microseconds and bytes.
+    pub rtt_us: u64,
+    pub cwnd: u64,
+    /// Counters of the server's side of the connection, accumulated during the transfer.
+    pub congestion_events: u64,
+    pub sent_packets: u64,
+    pub lost_packets: u64,
+    pub lost_bytes: u64,
+    pub mtu: u64,
+    /// Time the server started and finished serving the transfer, see [`wall_clock_us`].
+    pub started_us: u64,
+    pub finished_us: u64,
+}
+
+impl ServerReport {
+    /// Encoded length: all fields as big-endian `u64`s.
+    pub const LEN: usize = 104;
+
+    pub fn encode(&self) -> [u8; Self::LEN] {
+        let fields = [
+            self.received,
+            self.sent,
+            self.receive_us,
+            self.send_us,
+            self.rtt_us,
+            self.cwnd,
+            self.congestion_events,
+            self.sent_packets,
+            self.lost_packets,
+            self.lost_bytes,
+            self.mtu,
+            self.started_us,
+            self.finished_us,
+        ];
+        let mut buf = [0u8; Self::LEN];
+        for (chunk, field) in buf.chunks_exact_mut(8).zip(fields) {
+            chunk.copy_from_slice(&field.to_be_bytes());
+        }
+        buf
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Self> {
+        let mut buf = Reader(buf);
+        Ok(Self {
+            received: buf.u64()?,
+            sent: buf.u64()?,
+            receive_us: buf.u64()?,
+            send_us: buf.u64()?,
+            rtt_us: buf.u64()?,
+            cwnd: buf.u64()?,
+            congestion_events: buf.u64()?,
+            sent_packets: buf.u64()?,
+            lost_packets: buf.u64()?,
+            lost_bytes: buf.u64()?,
+            mtu: buf.u64()?,
+            started_us: buf.u64()?,
+            finished_us: buf.u64()?,
+        })
+    }
+}
+
+/// Current time on this host's clock in microseconds since the UNIX epoch, as exchanged in
+/// [`Request::Clock`].
+pub fn wall_clock_us() -> u64 {
+    let since_epoch = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH);
+    since_epoch.unwrap_or_default().as_micros() as u64
+}
+
+/// The server's answer to a timestamp probe of [`Request::Clock`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockReply {
+    /// Time the client sent the probe, on its clock.
+    pub client_sent_us: u64,
+    /// Time the probe arrived and the reply left, on the server's clock.
+    pub server_received_us: u64,
+    pub server_sent_us: u64,
+}
+
+impl ClockReply {
+    /// Encoded length: all fields as big-endian `u64`s.
+    pub const LEN: usize = 24;
+
+    pub fn encode(&self) -> [u8; Self::LEN] {
+        let mut buf = [0u8; Self::LEN];
+        buf[..8].copy_from_slice(&self.client_sent_us.to_be_bytes());
+        buf[8..16].copy_from_slice(&self.server_received_us.to_be_bytes());
+        buf[16..].copy_from_slice(&self.server_sent_us.to_be_bytes());
+        buf
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Self> {
+        let mut buf = Reader(buf);
+        Ok(Self {
+            client_sent_us: buf.u64()?,
+            server_received_us: buf.u64()?,
+            server_sent_us: buf.u64()?,
+        })
+    }
+}
+
+/// Datagrams the server received during a datagram benchmark.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DatagramSummary {
+    pub received: u64,
+    pub bytes: u64,
+    /// Datagrams that arrived after one with a higher sequence number.
+    pub reordered: u64,
+    /// Number of runs of consecutive lost datagrams.
+    pub loss_runs: u64,
+    /// Length of the longest run of consecutive lost datagrams.
+    pub max_loss_run: u64,
+    /// RFC 3550 interarrival jitter of the one-way transit times, in microseconds.
+    pub jitter_us: u64,
+}
+
+impl DatagramSummary {
+    /// Encoded length: all fields as big-endian `u64`s.
+    pub const LEN: usize = 48;
+
+    pub fn encode(&self) -> [u8; Self::LEN] {
+        let fields = [
+            self.received,
+            self.bytes,
+            self.reordered,
+            self.loss_runs,
+            self.max_loss_run,
+            self.jitter_us,
+        ];
+        let mut buf = [0u8; Self::LEN];
+        for (chunk, field) in buf.chunks_exact_mut(8).zip(fields) {
+            chunk.copy_from_slice(&field.to_be_bytes());
+        }
+        buf
+    }
+
+    pub fn decode(buf: &[u8; Self::LEN]) -> Result<Self> {
+        let field = |i: usize| -> Result<u64> {
+            Ok(u64::from_be_bytes(buf[i * 8..(i + 1) * 8].try_into()?))
+        };
+        Ok(Self {
+            received: field(0)?,
+            bytes: field(1)?,
+            reordered: field(2)?,
+            loss_runs: field(3)?,
+            max_loss_run: field(4)?,
+            jitter_us: field(5)?,
+        })
+    }
+
+    pub async fn write(&self, send: &mut SendStream) -> Result<()> {
+        send.write_all(&self.encode()).await?;
+        Ok(())
+    }
+
+    pub async fn read(recv: &mut RecvStream) -> Result<Self> {
+        let mut buf = [0u8; Self::LEN];
+        recv.read_exact(&mut buf).await?;
+        Self::decode(&buf)
+    }
+}
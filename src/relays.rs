@@ -0,0 +1,205 @@
+//! Latency to every relay of the relay map, to pick or verify the relay benchmarks go through.
+//!
+//! Every relay is probed the way iroh's net report probes it over HTTPS, with `GET` requests of
+//! its `/ping` path. The first request also resolves the relay's name and opens the connection,
+//! so it is reported apart from the others, which reuse the connection and take about one round
+//! trip each. The client's own endpoint meanwhile picks its home relay, the one iroh measured as
+//! the closest, which is reported next to the probes.
+
+use std::{fmt::Write as _, time::SystemTime};
+
+use anyhow::Result;
+use clap::builder::RangedU64ValueParser;
+use iroh::RelayUrl;
+use serde::{Deserialize, Serialize};
+use tokio::time::{Duration, Instant, timeout};
+
+use crate::config::{EndpointConfig, relay_mode};
+use crate::report::{RunMetadata, Stats, csv_field, millis};
+
+/// Path every relay answers probes on.
+const PROBE_PATH: &str = "/ping";
+
+/// Options of `client relays`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct RelaysConfig {
+    /// Number of requests to send to every relay, including the one opening the connection
+    #[arg(short, long, default_value_t = 10, value_parser = RangedU64ValueParser::<usize>::new().range(2..))]
+    pub count: usize,
+
+    /// How long to wait for every request, and for the endpoint to pick its home relay
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "5s")]
+    pub timeout: Duration,
+}
+
+/// Results of `client relays`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayReport {
+    pub run: RunMetadata,
+    /// Relay the client's endpoint picked as its home relay, if it picked one in time.
+    pub home_relay: Option<String>,
+    /// Every relay of the relay map, in the order of the map.
+    pub relays: Vec<RelayLatency>,
+}
+
+/// Latency of the requests to one relay.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayLatency {
+    pub url: String,
+    /// Address the relay's name resolved to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub addr: Option<String>,
+    /// Time of the first request, including name resolution and the TCP and TLS handshakes.
+    pub connect_ms: Option<f64>,
+    /// Time of every further request on the established connection.
+    pub rtts_ms: Vec<f64>,
+    pub rtt: Option<Stats>,
+    /// Why probing the relay failed, if it did.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Probes every relay of the relay map of `endpoint`, n0's default relays without
+/// `--relay-url`, with `--count` requests each.
+pub async fn run_relays(
+    endpoint: &EndpointConfig,
+    relays: &RelaysConfig,
+    print_results: bool,
+) -> Result<RelayReport> {
+    let urls: Vec<RelayUrl> =
+        relay_mode(&endpoint.relay_urls)?.relay_map().urls().cloned().collect();
+    // Bound first, so that the endpoint picks its home relay while the relays are probed
+    let endpoint = endpoint.builder()?.bind().await?;
+    let run = RunMetadata::new(endpoint.node_id(), SystemTime::now());
+    eprintln!("Run ID: {}", run.run_id);
+    let mut latencies = Vec::new();
+    for url in urls {
+        eprintln!("Probing {url}");
+        latencies.push(probe_relay(&url, relays).await);
+    }
+    let mut home_relay = endpoint.home_relay();
+    let home_relay = timeout(relays.timeout, home_relay.initialized()).await.ok().transpose()?;
+    endpoint.close().await;
+
+    let report = RelayReport {
+        run,
+        home_relay: home_relay.map(|url| url.to_string()),
+        relays: latencies,
+    };
+    if print_results {
+        print!("{}", report.to_text());
+    }
+    Ok(report)
+}
+
+/// Sends `--count` requests to `url` on one connection, recording what failed instead of
+/// failing.
+async fn probe_relay(url: &RelayUrl, relays: &RelaysConfig) -> RelayLatency {
+    let mut latency = RelayLatency {
+        url: url.to_string(),
+        addr: None,
+        connect_ms: None,
+        rtts_ms: Vec::new(),
+        rtt: None,
+        error: None,
+    };
+    let res = async {
+        let http = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .timeout(relays.timeout)
+            .build()?;
+        let probe = url.join(PROBE_PATH)?;
+        for i in 0..relays.count {
+            let t0 = Instant::now();
+            let response = http.get(probe.clone()).send().await?.error_for_status()?;
+            latency.addr = response.remote_addr().map(|addr| addr.ip().to_string());
+            // Reading the body to its end frees the connection for the next request
+            response.bytes().await?;
+            match i {
+                0 => latency.connect_ms = Some(millis(t0.elapsed())),
+                _ => latency.rtts_ms.push(millis(t0.elapsed())),
+            }
+        }
+        anyhow::Ok(())
+    };
+    if let Err(err) = res.await {
+        eprintln!("Probing {url} failed: {err:#}");
+        latency.error = Some(format!("{err:#}"));
+    }
+    latency.rtt = Stats::from_samples(&latency.rtts_ms);
+    latency
+}
+
+impl RelayReport {
+    /// Renders one line per relay, the fastest first, marking the home relay.
+    pub fn to_text(&self) -> String {
+        let mut relays: Vec<&RelayLatency> = self.relays.iter().collect();
+        relays.sort_by(|a, b| median(a).total_cmp(&median(b)));
+        let width = relays.iter().map(|relay| relay.url.len()).max().unwrap_or(0);
+        let mut out = String::from("\nRelay latency (ms), connect and median/p95 round trip:\n");
+        for relay in relays {
+            let home = if self.is_home(relay) { " (home relay)" } else { "" };
+            let line = match (&relay.error, relay.connect_ms, &relay.rtt) {
+                (Some(error), ..) => format!("failed: {error}"),
+                (None, Some(connect), Some(rtt)) => {
+                    format!("{connect:8.2} {:8.2} {:8.2}", rtt.median, rtt.p95)
+                }
+                _ => "-".to_string(),
+            };
+            writeln!(out, "  {:<width$} {line}{home}", relay.url).unwrap();
+        }
+        if self.home_relay.is_none() {
+            writeln!(out, "No home relay picked").unwrap();
+        }
+        out
+    }
+
+    /// Renders the relays as a Markdown table, for pasting into GitHub issues.
+    pub fn to_markdown(&self) -> String {
+        let mut out = format!("### iroh relay latency: run `{}`\n\n", self.run.run_id);
+        out.push_str("| Relay | Address | Connect (ms) | Median (ms) | p95 (ms) | Home |\n");
+        out.push_str("|---|---|---:|---:|---:|---|\n");
+        let opt = |v: Option<f64>| v.map_or("-".to_string(), |v| format!("{v:.2}"));
+        for relay in &self.relays {
+            writeln!(
+                out,
+                "| {} | {} | {} | {} | {} | {} |",
+                relay.url,
+                relay.addr.as_deref().unwrap_or("-"),
+                opt(relay.connect_ms),
+                opt(relay.rtt.map(|rtt| rtt.median)),
+                opt(relay.rtt.map(|rtt| rtt.p95)),
+                if self.is_home(relay) { "yes" } else { "" },
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    /// Renders every request as a CSV row, with a header row.
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("relay,addr,request,connect,latency_ms,home,error\n");
+        for relay in &self.relays {
+            let connect = relay.connect_ms.iter().map(|ms| (true, ms));
+            let requests = connect.chain(relay.rtts_ms.iter().map(|ms| (false, ms)));
+            let (addr, home) = (relay.addr.as_deref().unwrap_or_default(), self.is_home(relay));
+            let error = csv_field(relay.error.as_deref().unwrap_or_default());
+            for (i, (connect, ms)) in requests.enumerate() {
+                writeln!(out, "{},{addr},{},{connect},{ms:.3},{home},", relay.url, i + 1).unwrap();
+            }
+            if !error.is_empty() {
+                writeln!(out, "{},{addr},,,,{home},{error}", relay.url).unwrap();
+            }
+        }
+        out
+    }
+
+    fn is_home(&self, relay: &RelayLatency) -> bool {
+        self.home_relay.as_deref() == Some(relay.url.as_str())
+    }
+}
+
+/// Median round trip of a relay for sorting, failed relays last.
+fn median(relay: &RelayLatency) -> f64 {
+    relay.rtt.map_or(f64::INFINITY, |rtt| rtt.median)
+}
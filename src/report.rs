@@ -0,0 +1,1587 @@
+//! Structured benchmark results, as emitted by `client --output json|csv`.
+//!
+//! Bandwidths are in Mbit/s, latencies in milliseconds and timestamps in RFC 3339 format.
+
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    net::SocketAddr,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Result};
+use hdrhistogram::Histogram;
+use iroh::{
+    NodeId,
+    endpoint::{ConnectionStats, ConnectionType},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    baseline::BaselineResult, clock::ClockSync, config::Congestion, counters::IrohCounters,
+    outliers::OutlierRule, payload::Pattern,
+    protocol::{Direction, ServerReport},
+    units::format_size,
+};
+
+/// Results of a complete client run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchmarkReport {
+    /// Name of the scenario test the report belongs to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(flatten)]
+    pub run: RunMetadata,
+    pub client_node_id: String,
+    pub server_node_id: String,
+    pub started_at: String,
+    pub finished_at: String,
+    /// URLs of the relay servers the client was configured with.
+    pub relays: Vec<String>,
+    /// Whether direct paths were disabled and all traffic went through the relay.
+    pub relay_only: bool,
+    /// Congestion controller of the client, which governs uploads.
+    #[serde(default)]
+    pub congestion: Congestion,
+    /// iroh's own counts of the traffic per path during the benchmark.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iroh_counters: Option<IrohCounters>,
+    /// The same transfers over `--baseline` transports, next to iroh's bandwidth.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub baselines: Vec<BaselineResult>,
+    /// Whether the benchmark was interrupted, so that its results only cover the iterations that
+    /// completed before.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub interrupted: bool,
+    #[serde(flatten)]
+    pub benchmark: Benchmark,
+}
+
+/// Identifies a client run and the environment it ran in, shared by all reports of the run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunMetadata {
+    /// Start time and client node, e.g. `20250314T091502Z-1a2b3c4d`, also used as file name.
+    pub run_id: String,
+    /// Version of this benchmark.
+    pub version: String,
+    /// Version of iroh the benchmark was built against.
+    pub iroh_version: String,
+    pub os: String,
+    pub arch: String,
+    /// Name of the client machine, empty if it could not be read.
+    #[serde(default)]
+    pub hostname: String,
+    /// Free-form description of the run given with `--label`, e.g. the network or the change
+    /// under test.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Throughput over loopback measured before the run with `--machine-ceiling`, the most this
+    /// machine sustains without a network in between.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub machine_ceiling: Option<Sample>,
+}
+
+impl RunMetadata {
+    pub fn new(client_node_id: NodeId, started_at: SystemTime) -> Self {
+        let time = humantime::format_rfc3339_seconds(started_at).to_string();
+        let time = time.replace(['-', ':'], "");
+        let hostname = hostname::get().map(|name| name.to_string_lossy().into_owned());
+        Self {
+            run_id: format!("{time}-{}", &client_node_id.to_string()[..8]),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            iroh_version: env!("IROH_VERSION").to_string(),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            hostname: hostname.unwrap_or_default(),
+            label: None,
+            machine_ceiling: None,
+        }
+    }
+}
+
+/// Parameters and results of the benchmark that was run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum Benchmark {
+    /// Fixed-size transfers, repeated for every payload size.
+    Bandwidth {
+        direction: Direction,
+        streams: usize,
+        connections: usize,
+        /// Whether all transfers ran on the same connections, so that the results exclude the
+        /// cost of connecting and of the congestion controller's slow start.
+        reuse_connection: bool,
+        /// Whether every payload was checked end to end with BLAKE3 hashes.
+        verified: bool,
+        /// Content of the payloads, and the seed they were generated from.
+        pattern: Pattern,
+        seed: u64,
+        /// Rule that flagged outlying iterations, if they were looked for.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        trim_outliers: Option<OutlierRule>,
+        /// Bitrate the sender of every connection was paced to, if not line rate.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bitrate_mbps: Option<f64>,
+        /// Duration every payload was sized to take, in which case there is a single result
+        /// starting at its size and every iteration records the bytes it transferred.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        target_duration_secs: Option<f64>,
+        /// Smallest size of a `--sweep` whose bandwidth reached 90% of the best size's.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        knee_size: Option<u64>,
+        /// Offset and drift of the server's clock, estimated with `--clock-sync`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        clock: Option<ClockSync>,
+        results: Vec<SizeResult>,
+    },
+    /// Transfers of the same payload size written and read in chunks of different sizes.
+    ChunkSweep {
+        direction: Direction,
+        /// Payload size of every transfer in bytes, per connection.
+        size: u64,
+        streams: usize,
+        connections: usize,
+        results: Vec<ChunkResult>,
+        /// Chunk size with the best average bandwidth in the slower direction.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        best_chunk_size: Option<u64>,
+    },
+    /// Transfers of the same payload size striped across different numbers of parallel streams
+    /// on one connection.
+    StreamSweep {
+        direction: Direction,
+        /// Payload size of every transfer in bytes, across all its streams.
+        size: u64,
+        /// Paths used during the run, see [`path_label`].
+        connection_type: String,
+        results: Vec<StreamCountResult>,
+    },
+    /// Continuous streaming for a fixed duration.
+    Timed {
+        direction: Direction,
+        streams: usize,
+        connections: usize,
+        duration_secs: f64,
+        /// Content of the payloads, and the seed they were generated from.
+        pattern: Pattern,
+        seed: u64,
+        /// Bitrate the sender of every connection was paced to, if not line rate.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bitrate_mbps: Option<f64>,
+        /// Paths used during the run, see [`path_label`].
+        connection_type: String,
+        /// Sum of the bandwidths of all connections.
+        total: Sample,
+        /// Bandwidth of every individual connection.
+        per_connection: Vec<Sample>,
+        /// Transport statistics of every connection at the end of the run.
+        transport: Vec<TransportStats>,
+        /// RTT estimate sampled throughout the run.
+        rtt_series: Vec<RttSample>,
+        /// Time every chunk took to be written or read, across all streams.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        chunk_latency: Option<Stats>,
+    },
+    /// Unreliable datagrams sent at a fixed rate.
+    Datagram {
+        /// Size of every datagram in bytes.
+        size: u64,
+        target_mbps: f64,
+        duration_secs: f64,
+        /// Paths used during the run, see [`path_label`].
+        connection_type: String,
+        sent: u64,
+        received: u64,
+        loss_percent: f64,
+        /// Datagrams that arrived after one with a higher sequence number.
+        reordered: u64,
+        reorder_percent: f64,
+        /// Number of runs of consecutive lost datagrams, and the length of the longest.
+        loss_runs: u64,
+        max_loss_run: u64,
+        /// RFC 3550 interarrival jitter of the one-way transit times.
+        jitter_ms: f64,
+        sent_mbps: f64,
+        received_mbps: f64,
+    },
+    /// Ping-pong of small messages.
+    Ping {
+        size: u64,
+        /// Paths used during the run, see [`path_label`].
+        connection_type: String,
+        rtts_ms: Vec<f64>,
+        summary: Option<Stats>,
+        /// Interarrival jitter of the round-trip times, see [`jitter`].
+        jitter_ms: Option<f64>,
+    },
+    /// Address resolution via discovery, followed by a handshake with the resolved addresses.
+    Discover {
+        /// Discovery services that produced the first result.
+        provenance: Vec<String>,
+        discovery_ms: Vec<f64>,
+        handshake_ms: Vec<f64>,
+        discovery: Option<Stats>,
+        handshake: Option<Stats>,
+    },
+    /// Repeated connection establishment, until the handshake completes.
+    Connect {
+        /// Connections from a freshly bound endpoint each.
+        cold_ms: Vec<f64>,
+        /// Connections from one endpoint that already connected to the server before.
+        warm_ms: Vec<f64>,
+        cold: Option<Stats>,
+        warm: Option<Stats>,
+    },
+    /// Time from dialing until the first response, with and without session resumption.
+    Resume {
+        /// Connections resuming an earlier session, sending the request after the handshake.
+        cold_ms: Vec<f64>,
+        /// Connections resuming an earlier session, sending the request as 0-RTT data.
+        warm_ms: Vec<f64>,
+        /// Number of warm connections whose 0-RTT data the server accepted.
+        zero_rtt_accepted: usize,
+        cold: Option<Stats>,
+        warm: Option<Stats>,
+    },
+    /// Streams opened one after another on a single connection, exchanging one byte each.
+    Streams {
+        /// Paths used during the run, see [`path_label`].
+        connection_type: String,
+        /// Time `open_bi` took, which only waits when the stream limit is reached.
+        open_ms: Vec<f64>,
+        /// Time from opening the stream until the server's first byte arrived.
+        first_byte_ms: Vec<f64>,
+        open: Option<Stats>,
+        first_byte: Option<Stats>,
+    },
+    /// Fresh connections waiting to upgrade to a direct path.
+    HolePunch {
+        /// How long each connection waited for a direct path.
+        timeout_secs: f64,
+        /// Trials that started on the relay or a mixed path and became direct.
+        successes: usize,
+        /// Trials that were direct from the start, which count neither way.
+        #[serde(default)]
+        already_direct: usize,
+        /// Share of the trials starting on the relay or a mixed path that became direct, if any
+        /// did start there.
+        success_rate: Option<f64>,
+        trials: Vec<HolePunchTrial>,
+        /// Time from dialing until the path became direct, over the successful trials. The
+        /// median is `p50`.
+        time_to_direct: Option<Stats>,
+    },
+    /// Continuous transfers over hours on one connection, summarized every interval.
+    Soak {
+        direction: Direction,
+        streams: usize,
+        duration_secs: f64,
+        interval_secs: f64,
+        /// Bitrate the sender of every connection was paced to, if not line rate.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bitrate_mbps: Option<f64>,
+        intervals: Vec<SoakInterval>,
+        /// Times the connection was lost and had to be established again.
+        reconnects: usize,
+        /// Changes of the path over the whole run, e.g. from direct to relay and back.
+        path_changes: usize,
+        /// Bandwidths of the intervals that completed.
+        upload: Option<Stats>,
+        download: Option<Stats>,
+        /// Trends of the bandwidths and the round-trip time over the run, fitted by least
+        /// squares, in Mbit/s and ms per hour.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        upload_drift_mbps_per_hour: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        download_drift_mbps_per_hour: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        rtt_drift_ms_per_hour: Option<f64>,
+        /// Every change of the path over the run, and how long it stayed on each.
+        #[serde(default)]
+        path_stability: PathStability,
+    },
+    /// On/off traffic: bursts of a fixed size transferred at a fixed interval on the same
+    /// connections, idling in between.
+    Bursts {
+        direction: Direction,
+        /// Size of every burst in bytes, per connection.
+        size: u64,
+        count: u32,
+        /// Time from the start of one burst to the start of the next.
+        interval_ms: f64,
+        streams: usize,
+        connections: usize,
+        /// Bitrate the sender of every connection was paced to, if not line rate.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bitrate_mbps: Option<f64>,
+        /// Paths used during the run, see [`path_label`].
+        connection_type: String,
+        bursts: Vec<BurstResult>,
+        upload: Option<Stats>,
+        download: Option<Stats>,
+    },
+    /// Round-trip times of small probes on an idle connection and while it carries a bulk
+    /// transfer.
+    Bufferbloat {
+        /// Direction of the bulk transfer.
+        direction: Direction,
+        /// Size of every probe in bytes.
+        size: u64,
+        /// Paths used during the run, see [`path_label`].
+        connection_type: String,
+        /// Bandwidth of the bulk transfer.
+        load: Sample,
+        idle_ms: Vec<f64>,
+        loaded_ms: Vec<f64>,
+        idle: Option<Stats>,
+        loaded: Option<Stats>,
+        /// Increase of the median round-trip time under load.
+        inflation_ms: Option<f64>,
+    },
+    /// Small request/response exchanges on a single stream.
+    Rpc {
+        /// Body sizes of every request and response in bytes.
+        request_size: u32,
+        response_size: u32,
+        /// Requests kept in flight at once.
+        depth: usize,
+        /// Paths used during the run, see [`path_label`].
+        connection_type: String,
+        requests: usize,
+        requests_per_sec: f64,
+        /// Time from sending every request until its response arrived.
+        latency_ms: Vec<f64>,
+        latency: Option<Stats>,
+    },
+    /// Small framed messages sent back to back on a single stream.
+    Messages {
+        /// Size of every message body in bytes.
+        size: u32,
+        /// Paths used during the run, see [`path_label`].
+        connection_type: String,
+        messages: u64,
+        duration_secs: f64,
+        messages_per_sec: f64,
+        /// Bandwidth of the message bodies alone.
+        goodput_mbps: f64,
+        /// Time the client spent per message, until the server confirmed the last one.
+        us_per_message: f64,
+        /// UDP bytes sent per message beyond its body.
+        overhead_bytes_per_message: f64,
+    },
+    /// One-way delays estimated from timestamp probes on a single stream.
+    Delay {
+        /// Paths used during the run, see [`path_label`].
+        connection_type: String,
+        /// Whether the clocks were trusted to be synchronized instead of estimating their offset.
+        synced_clocks: bool,
+        /// Offset of the server's clock from the client's at the first probe, positive if it is
+        /// ahead, and how many microseconds per second it drifts.
+        clock_offset_ms: f64,
+        #[serde(default)]
+        clock_drift_ppm: f64,
+        /// Round-trip time of every probe, without the time the server took to answer.
+        rtts_ms: Vec<f64>,
+        /// Delay of every probe from the client to the server, and of its answer back.
+        forward_ms: Vec<f64>,
+        backward_ms: Vec<f64>,
+        rtt: Option<Stats>,
+        forward: Option<Stats>,
+        backward: Option<Stats>,
+        /// Median forward minus median backward delay, positive if the way to the server is
+        /// slower.
+        asymmetry_ms: Option<f64>,
+    },
+    /// A timed transfer during which the client moved to a new local socket.
+    Migrate {
+        direction: Direction,
+        /// Whether a plain QUIC connection was migrated instead of iroh's.
+        quic: bool,
+        duration_secs: f64,
+        /// Time into the transfer the client rebound its socket.
+        rebind_at_secs: f64,
+        /// Paths used during the run, see [`path_label`].
+        connection_type: String,
+        /// Direct addresses of the client before and after the rebind, or with `quic` the
+        /// addresses its socket was bound to.
+        local_addrs_before: Vec<SocketAddr>,
+        local_addrs_after: Vec<SocketAddr>,
+        /// Throughput of both directions together, sampled every `--sample-interval`.
+        throughput: Vec<ThroughputSample>,
+        /// Median throughput before the rebind, and after the throughput recovered.
+        before_mbps: Option<f64>,
+        after_mbps: Option<f64>,
+        /// Time from the rebind until the throughput was back to 90% of the median before, 0 if
+        /// it never fell below, `None` if it did not recover until the end of the transfer.
+        dip_ms: Option<f64>,
+        /// Lowest throughput during the dip.
+        dip_min_mbps: Option<f64>,
+        /// Whether the transfer completed on the connection it started on.
+        survived: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+        /// Bandwidth of the whole transfer, if it completed.
+        load: Sample,
+    },
+    /// Messages broadcast to a small gossip swarm bootstrapped by the server.
+    Gossip {
+        /// Peers the client ran, including the one broadcasting.
+        peers: usize,
+        /// Size of every message in bytes.
+        size: u64,
+        sent: u64,
+        /// Messages received across all receiving peers, and their share of the expected.
+        delivered: u64,
+        delivery_percent: f64,
+        /// Time from broadcasting until the message arrived, for every delivery.
+        latency_ms: Vec<f64>,
+        latency: Option<Stats>,
+        /// Payload bandwidth every receiving peer got, averaged over the peers.
+        throughput_mbps: f64,
+    },
+    /// Payloads imported into the server's blob store and fetched with the iroh-blobs protocol.
+    Blobs {
+        /// Content of the blobs, and the seed they were generated from.
+        pattern: Pattern,
+        seed: u64,
+        /// Paths used during the run, see [`path_label`].
+        connection_type: String,
+        results: Vec<BlobResult>,
+    },
+}
+
+impl Benchmark {
+    /// Paths used during the run, for the benchmarks that run on a single connection.
+    pub fn connection_type(&self) -> Option<&str> {
+        match self {
+            Benchmark::Timed { connection_type, .. }
+            | Benchmark::Datagram { connection_type, .. }
+            | Benchmark::Ping { connection_type, .. }
+            | Benchmark::Streams { connection_type, .. }
+            | Benchmark::Bufferbloat { connection_type, .. }
+            | Benchmark::Rpc { connection_type, .. }
+            | Benchmark::Bursts { connection_type, .. }
+            | Benchmark::StreamSweep { connection_type, .. }
+            | Benchmark::Messages { connection_type, .. }
+            | Benchmark::Delay { connection_type, .. }
+            | Benchmark::Migrate { connection_type, .. }
+            | Benchmark::Blobs { connection_type, .. } => Some(connection_type),
+            _ => None,
+        }
+    }
+}
+
+/// All fetches of the blob of one size.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlobResult {
+    /// Blob size in bytes.
+    pub size: u64,
+    /// BLAKE3 hash of the blob, as hex.
+    pub hash: String,
+    /// Time every measured fetch took, from requesting the blob until it was verified.
+    pub fetch_ms: Vec<f64>,
+    /// Bandwidth of every measured fetch.
+    pub download_mbps: Vec<f64>,
+    pub download: Option<Stats>,
+}
+
+/// Outcome of a single hole punching attempt.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HolePunchTrial {
+    /// Path in use once the handshake completed, see [`path_label`].
+    pub initial_path: String,
+    pub direct: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_to_direct_ms: Option<f64>,
+}
+
+/// One interval of a soak test.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SoakInterval {
+    pub started_at: String,
+    /// Time since the soak test started.
+    pub elapsed_secs: f64,
+    /// Bandwidth during the interval, empty if the transfer failed.
+    #[serde(flatten)]
+    pub bandwidth: Sample,
+    pub rtt_ms: f64,
+    /// Paths used during the interval, see [`path_label`].
+    pub connection_type: String,
+    /// Whether the interval started on a new connection after the previous one was lost.
+    pub reconnected: bool,
+    /// Why the transfer or the connection failed, if it did.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Every change of the path of a soak test as iroh reported it, and the share of the run it spent
+/// on each path.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PathStability {
+    pub transitions: Vec<PathTransition>,
+    /// Percentage of the time since the first connection spent on every path, by label, see
+    /// [`path_label`].
+    pub time_on_path_percent: BTreeMap<String, f64>,
+}
+
+/// A change of the path to the server, e.g. from the relay to a direct one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PathTransition {
+    pub at: String,
+    /// Time since the soak test started.
+    pub elapsed_secs: f64,
+    pub from: String,
+    pub to: String,
+    /// Median throughput of both directions together over the seconds before and after the
+    /// change, `None` if nothing was sampled then, e.g. while reconnecting.
+    pub before_mbps: Option<f64>,
+    pub after_mbps: Option<f64>,
+}
+
+/// Transfers of a chunk size sweep with one chunk size.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkResult {
+    pub chunk_size: u64,
+    /// Bandwidth of every iteration, excluding warmup transfers.
+    pub iterations: Vec<Sample>,
+    pub upload: Option<Stats>,
+    pub download: Option<Stats>,
+}
+
+/// Transfers of a stream count sweep with one number of streams.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamCountResult {
+    pub streams: usize,
+    /// Bandwidth of every iteration across all streams, excluding warmup transfers.
+    pub iterations: Vec<Sample>,
+    pub upload: Option<Stats>,
+    pub download: Option<Stats>,
+    /// Bandwidths of the individual streams over all iterations.
+    pub per_stream_upload: Option<Stats>,
+    pub per_stream_download: Option<Stats>,
+    /// Jain's fairness index of the streams, see [`fairness`], averaged over the iterations.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upload_fairness: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download_fairness: Option<f64>,
+    /// Why the failed iterations failed, which are left out of `iterations`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+/// One burst of on/off traffic.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BurstResult {
+    /// Start of the burst since the start of the first.
+    pub offset_ms: f64,
+    /// Time until the slowest connection completed the burst in each direction.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upload_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download_ms: Option<f64>,
+    #[serde(flatten)]
+    pub bandwidth: Sample,
+    /// Congestion window of all connections and their RTT estimate when the burst started.
+    pub cwnd: u64,
+    pub rtt_ms: f64,
+    /// Congestion events and packets lost during the burst.
+    pub congestion_events: u64,
+    pub lost_packets: u64,
+    /// Why the burst failed, if it did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// All measured iterations for one payload size.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SizeResult {
+    /// Payload size in bytes, per connection.
+    pub size: u64,
+    /// Measured iterations, excluding warmup transfers.
+    pub iterations: Vec<Iteration>,
+    pub upload: Option<Stats>,
+    pub download: Option<Stats>,
+    /// Time every chunk took to be written or read, across all measured iterations.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk_latency: Option<Stats>,
+    /// Statistics without the outlying iterations, with `--trim-outliers`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trimmed_upload: Option<Stats>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trimmed_download: Option<Stats>,
+}
+
+/// A single measured iteration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Iteration {
+    pub started_at: String,
+    /// Payload bytes transferred in each direction, across all connections.
+    pub bytes: u64,
+    /// Time until the slowest connection completed its upload.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upload_ms: Option<f64>,
+    /// Time until the slowest connection completed its download.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download_ms: Option<f64>,
+    /// Aggregate bandwidth across all connections.
+    #[serde(flatten)]
+    pub aggregate: Sample,
+    /// Paths used during the iteration, see [`path_label`]. Transitions are joined with `->`,
+    /// e.g. `relay->direct`.
+    pub connection_type: String,
+    /// Round-trip time estimate at the end of the iteration, averaged across connections.
+    pub rtt_ms: f64,
+    /// Bandwidth of every individual connection, omitted when only one was used.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub per_connection: Vec<Sample>,
+    /// Transport statistics of every connection at the end of the iteration.
+    pub transport: Vec<TransportStats>,
+    /// RTT estimate sampled while the payload was transferred, to reveal queueing delay and path
+    /// changes under load.
+    pub rtt_series: Vec<RttSample>,
+    /// Whether the bandwidth in either direction is an outlier among the iterations, only ever
+    /// set with `--trim-outliers`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub outlier: bool,
+    /// Why the iteration failed after all `--retries`, if it did.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The server's own measurements, omitted by servers that predate them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<ServerSide>,
+}
+
+impl Iteration {
+    /// An iteration that failed with `err`, without any measurements.
+    pub fn failed(started_at: String, err: &anyhow::Error) -> Self {
+        Self {
+            started_at,
+            bytes: 0,
+            upload_ms: None,
+            download_ms: None,
+            aggregate: Sample::default(),
+            connection_type: "none".to_string(),
+            rtt_ms: 0.0,
+            per_connection: Vec::new(),
+            transport: Vec::new(),
+            rtt_series: Vec::new(),
+            outlier: false,
+            error: Some(format!("{err:#}")),
+            server: None,
+        }
+    }
+
+    /// Merges the client's and the server's view of every direction the iteration transferred,
+    /// or returns nothing without the server's measurements.
+    pub fn two_sided(&self) -> Vec<TwoSided> {
+        let Some(server) = &self.server else {
+            return Vec::new();
+        };
+        let (client_stats, server_stats) =
+            (TransportStats::total(&self.transport), TransportStats::total(&server.transport));
+        // The server serves within the client's transfer, up to the error of the clock offset of
+        // half a round trip
+        let end_ms = self.upload_ms.into_iter().chain(self.download_ms).reduce(f64::max);
+        let clock_mismatch = match (server.started_ms, server.finished_ms, end_ms) {
+            (Some(started), Some(finished), Some(end))
+                if started < -self.rtt_ms || finished > end + self.rtt_ms =>
+            {
+                Some("server timestamps fall outside the transfer, the clock offset is off")
+            }
+            _ => None,
+        };
+        // The client sends the upload and the server the download
+        let (ours, theirs) = (&self.aggregate, &server.bandwidth);
+        let directions = [
+            ("upload", ours.upload, self.bytes, theirs.upload, server.received_bytes),
+            ("download", theirs.download, server.sent_bytes, ours.download, self.bytes),
+        ];
+        let mut views = Vec::new();
+        for (direction, sender_mbps, sent, receiver_mbps, received) in directions {
+            let (Some(sender_mbps), Some(receiver_mbps)) = (sender_mbps, receiver_mbps) else {
+                continue;
+            };
+            let mut discrepancies = Vec::new();
+            if sent != received {
+                discrepancies.push(format!("{received} of {sent} bytes arrived"));
+            }
+            // The client times downloads from before its request until the last byte, which
+            // encloses the server's sending, so only there a faster receiver is impossible
+            if direction == "download" && receiver_mbps > sender_mbps * (1.0 + RECEIVER_TOLERANCE)
+            {
+                discrepancies.push("receiver faster than sender, likely a timing bug".to_string());
+            }
+            discrepancies.extend(clock_mismatch.map(str::to_string));
+            let (client_rtt, server_rtt) = (client_stats.rtt_ms, server_stats.rtt_ms);
+            let (slower, faster) = (client_rtt.max(server_rtt), client_rtt.min(server_rtt));
+            if faster > 0.0 && slower > faster * RTT_DISAGREEMENT {
+                discrepancies.push(format!(
+                    "RTT estimates disagree: {client_rtt:.3} ms on the client, {server_rtt:.3} ms \
+                     on the server"
+                ));
+            }
+            views.push(TwoSided {
+                direction,
+                sender_mbps,
+                receiver_mbps,
+                client_lost_packets: client_stats.lost_packets,
+                server_lost_packets: server_stats.lost_packets,
+                client_rtt_ms: client_rtt,
+                server_rtt_ms: server_rtt,
+                discrepancies,
+            });
+        }
+        views
+    }
+}
+
+/// Share by which the receiver's goodput may exceed the sender's, e.g. through timer granularity,
+/// before [`Iteration::two_sided`] flags it.
+const RECEIVER_TOLERANCE: f64 = 0.1;
+
+/// Factor by which the RTT estimates of the two ends may differ before
+/// [`Iteration::two_sided`] flags them.
+const RTT_DISAGREEMENT: f64 = 2.0;
+
+/// One direction of an iteration as the sender and the receiver saw it.
+#[derive(Debug, Clone)]
+pub struct TwoSided {
+    /// `upload` or `download`.
+    pub direction: &'static str,
+    /// Goodput the sender and the receiver measured.
+    pub sender_mbps: f64,
+    pub receiver_mbps: f64,
+    /// Lost packets and RTT estimates of the client's and the server's side, across all
+    /// connections.
+    pub client_lost_packets: u64,
+    pub server_lost_packets: u64,
+    pub client_rtt_ms: f64,
+    pub server_rtt_ms: f64,
+    /// Where the two views contradict each other.
+    pub discrepancies: Vec<String>,
+}
+
+/// An iteration as the server saw it, to tell the sender's and the receiver's view of the same
+/// transfer apart and spot bottlenecks in only one direction.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerSide {
+    /// Payload bytes the server received and sent, across all connections.
+    pub received_bytes: u64,
+    pub sent_bytes: u64,
+    /// Bandwidth at which the server received the upload and sent the download, until the
+    /// slowest connection completed.
+    #[serde(flatten)]
+    pub bandwidth: Sample,
+    /// Transport statistics of the server's side of every connection.
+    pub transport: Vec<TransportStats>,
+    /// When the server started and finished serving the transfer, in milliseconds since the
+    /// client started it. Only known with `--clock-sync`, which places them on the client's
+    /// clock.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_ms: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_ms: Option<f64>,
+}
+
+impl ServerSide {
+    /// Combines the reports of the server for every connection of an iteration the client
+    /// started at `started_us`, see [`crate::protocol::wall_clock_us`].
+    pub fn from_reports(
+        reports: &[ServerReport],
+        clock: Option<&ClockSync>,
+        started_us: u64,
+    ) -> Self {
+        let received_bytes = reports.iter().map(|r| r.received).sum();
+        let sent_bytes = reports.iter().map(|r| r.sent).sum();
+        let slowest = |micros: fn(&ServerReport) -> u64| {
+            let elapsed = reports.iter().map(micros).max().unwrap_or(0);
+            (elapsed > 0).then(|| Duration::from_micros(elapsed))
+        };
+        let since_start = |server_us: u64| {
+            clock.map(|clock| (clock.to_client_us(server_us) - started_us as f64) / 1000.0)
+        };
+        let rate = |bytes: u64, elapsed: Option<Duration>| {
+            elapsed.map(|elapsed| bytes as f64 * 8.0 / elapsed.as_secs_f64() / 1_000_000.0)
+        };
+        Self {
+            received_bytes,
+            sent_bytes,
+            bandwidth: Sample {
+                upload: rate(received_bytes, slowest(|r| r.receive_us)),
+                download: rate(sent_bytes, slowest(|r| r.send_us)),
+            },
+            transport: reports.iter().map(TransportStats::from_report).collect(),
+            started_ms: reports.iter().map(|r| r.started_us).min().and_then(since_start),
+            finished_ms: reports.iter().map(|r| r.finished_us).max().and_then(since_start),
+        }
+    }
+}
+
+/// RTT estimate at one point of a transfer, averaged across connections.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RttSample {
+    /// Time since the transfer started.
+    pub elapsed_ms: f64,
+    pub rtt_ms: f64,
+}
+
+/// Throughput over one sampling window of a transfer.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ThroughputSample {
+    /// Time from the start of the transfer to the end of the window.
+    pub elapsed_ms: f64,
+    pub mbps: f64,
+}
+
+/// Congestion and loss counters of one QUIC connection, read from [`ConnectionStats`].
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct TransportStats {
+    /// Current round-trip time estimate.
+    pub rtt_ms: f64,
+    /// Current congestion window in bytes.
+    pub cwnd: u64,
+    pub congestion_events: u64,
+    pub sent_packets: u64,
+    pub lost_packets: u64,
+    /// Bytes in lost packets, which had to be retransmitted.
+    pub lost_bytes: u64,
+    /// Largest UDP payload the path carries, as found by MTU discovery.
+    #[serde(default)]
+    pub mtu: u16,
+}
+
+impl TransportStats {
+    pub fn from_stats(stats: &ConnectionStats) -> Self {
+        Self {
+            rtt_ms: millis(stats.path.rtt),
+            cwnd: stats.path.cwnd,
+            congestion_events: stats.path.congestion_events,
+            sent_packets: stats.path.sent_packets,
+            lost_packets: stats.path.lost_packets,
+            lost_bytes: stats.path.lost_bytes,
+            mtu: stats.path.current_mtu,
+        }
+    }
+
+    /// The statistics the server reported of its side of a connection.
+    pub fn from_report(report: &ServerReport) -> Self {
+        Self {
+            rtt_ms: report.rtt_us as f64 / 1000.0,
+            cwnd: report.cwnd,
+            congestion_events: report.congestion_events,
+            sent_packets: report.sent_packets,
+            lost_packets: report.lost_packets,
+            lost_bytes: report.lost_bytes,
+            mtu: report.mtu as u16,
+        }
+    }
+
+    /// Counters accumulated since the `earlier` snapshot of the same connection, with the current
+    /// RTT and congestion window.
+    pub fn since(self, earlier: &TransportStats) -> Self {
+        Self {
+            congestion_events: self.congestion_events - earlier.congestion_events,
+            sent_packets: self.sent_packets - earlier.sent_packets,
+            lost_packets: self.lost_packets - earlier.lost_packets,
+            lost_bytes: self.lost_bytes - earlier.lost_bytes,
+            ..self
+        }
+    }
+
+    /// Adds up the counters and congestion windows of several connections, averaging the RTT and
+    /// keeping the smallest MTU.
+    pub fn total(stats: &[TransportStats]) -> Self {
+        let mut total = stats.iter().fold(Self::default(), |acc, s| Self {
+            rtt_ms: acc.rtt_ms + s.rtt_ms,
+            cwnd: acc.cwnd + s.cwnd,
+            congestion_events: acc.congestion_events + s.congestion_events,
+            sent_packets: acc.sent_packets + s.sent_packets,
+            lost_packets: acc.lost_packets + s.lost_packets,
+            lost_bytes: acc.lost_bytes + s.lost_bytes,
+            mtu: match (acc.mtu, s.mtu) {
+                (0, mtu) | (mtu, 0) => mtu,
+                (a, b) => a.min(b),
+            },
+        });
+        total.rtt_ms /= stats.len().max(1) as f64;
+        total
+    }
+
+    /// Averages every field over several measurements, e.g. the iterations of one size.
+    pub fn average(stats: &[TransportStats]) -> Self {
+        let n = stats.len().max(1) as u64;
+        let total = Self::total(stats);
+        Self {
+            rtt_ms: total.rtt_ms,
+            cwnd: total.cwnd / n,
+            congestion_events: total.congestion_events / n,
+            sent_packets: total.sent_packets / n,
+            lost_packets: total.lost_packets / n,
+            lost_bytes: total.lost_bytes / n,
+            mtu: total.mtu,
+        }
+    }
+}
+
+/// Bandwidths measured in each direction, in Mbit/s.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct Sample {
+    #[serde(rename = "upload_mbps", skip_serializing_if = "Option::is_none")]
+    pub upload: Option<f64>,
+    #[serde(rename = "download_mbps", skip_serializing_if = "Option::is_none")]
+    pub download: Option<f64>,
+}
+
+impl Sample {
+    /// The bandwidths of both directions, e.g. `upload 940.12 Mbit/s, download 912.40 Mbit/s`.
+    pub fn describe(&self) -> String {
+        let directions = [("upload", self.upload), ("download", self.download)];
+        let parts: Vec<_> = directions
+            .into_iter()
+            .filter_map(|(direction, mbps)| Some(format!("{direction} {:.2} Mbit/s", mbps?)))
+            .collect();
+        parts.join(", ")
+    }
+}
+
+/// Summary statistics over a set of samples, shared by all benchmark modes: bandwidths in
+/// Mbit/s or latencies in milliseconds.
+///
+/// Latencies are recorded in an HDR histogram with nanosecond resolution, so their percentiles
+/// are exact to three significant digits however many samples there are.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Stats {
+    pub min: f64,
+    pub average: f64,
+    pub max: f64,
+    #[serde(default, alias = "p50")]
+    pub median: f64,
+    /// Population standard deviation.
+    #[serde(default)]
+    pub stddev: f64,
+    /// Coefficient of variation, the standard deviation relative to the average.
+    #[serde(default)]
+    pub cv: f64,
+    #[serde(default)]
+    pub p5: f64,
+    #[serde(default)]
+    pub p95: f64,
+    #[serde(default)]
+    pub p99: f64,
+    #[serde(default)]
+    pub p999: f64,
+}
+
+impl Stats {
+    /// Summarizes the given samples, or returns `None` if there are none.
+    pub fn from_samples(samples: &[f64]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let n = sorted.len() as f64;
+        let average = sorted.iter().sum::<f64>() / n;
+        let variance = sorted.iter().map(|x| (x - average).powi(2)).sum::<f64>() / n;
+        Some(Self::new(
+            sorted[0],
+            average,
+            sorted[sorted.len() - 1],
+            variance.sqrt(),
+            |p| percentile(&sorted, p),
+        ))
+    }
+
+    /// Summarizes the given latencies, or returns `None` if there are none.
+    pub fn from_rtts(rtts: &[Duration]) -> Option<Self> {
+        let mut histogram = Self::histogram();
+        for rtt in rtts {
+            record_latency(&mut histogram, *rtt);
+        }
+        Self::from_histogram(&histogram)
+    }
+
+    /// An empty histogram to record latencies into, see [`record_latency`].
+    pub fn histogram() -> Histogram<u64> {
+        Histogram::new_with_bounds(1, MAX_LATENCY.as_nanos() as u64, 3).expect("valid bounds")
+    }
+
+    /// Summarizes the latencies recorded in `histogram` in milliseconds, or returns `None` if it
+    /// is empty.
+    pub fn from_histogram(histogram: &Histogram<u64>) -> Option<Self> {
+        if histogram.is_empty() {
+            return None;
+        }
+        let ms = |ns: u64| ns as f64 / 1e6;
+        Some(Self::new(
+            ms(histogram.min()),
+            histogram.mean() / 1e6,
+            ms(histogram.max()),
+            histogram.stdev() / 1e6,
+            |p| ms(histogram.value_at_quantile(p / 100.0)),
+        ))
+    }
+
+    fn new(min: f64, average: f64, max: f64, stddev: f64, percentile: impl Fn(f64) -> f64) -> Self {
+        Self {
+            min,
+            average,
+            max,
+            median: percentile(50.0),
+            stddev,
+            cv: if average != 0.0 { stddev / average } else { 0.0 },
+            p5: percentile(5.0),
+            p95: percentile(95.0),
+            p99: percentile(99.0),
+            p999: percentile(99.9),
+        }
+    }
+}
+
+/// Longest latency [`Stats`] tell apart, longer ones are recorded as this.
+const MAX_LATENCY: Duration = Duration::from_secs(3600);
+
+/// Records a latency in nanoseconds in a histogram created by [`Stats::histogram`].
+pub fn record_latency(histogram: &mut Histogram<u64>, latency: Duration) {
+    histogram.saturating_record(latency.as_nanos() as u64);
+}
+
+impl BenchmarkReport {
+    /// Loads the reports saved in a JSON file, either a single report or the list written by
+    /// scenario runs.
+    pub fn load(path: &Path) -> Result<Vec<Self>> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Saved {
+            One(Box<BenchmarkReport>),
+            Many(Vec<BenchmarkReport>),
+        }
+
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read results {}", path.display()))?;
+        let saved = serde_json::from_str(&contents)
+            .with_context(|| format!("Invalid results {}", path.display()))?;
+        Ok(match saved {
+            Saved::One(report) => vec![*report],
+            Saved::Many(reports) => reports,
+        })
+    }
+
+    /// Renders the samples as CSV with a header row.
+    ///
+    /// Bandwidth runs produce one row per (size, iteration), chunk size sweeps one row per (chunk
+    /// size, iteration), stream count sweeps one row per (streams, iteration), timed runs one row
+    /// per connection, datagram and message runs a single row, ping runs one row per round trip,
+    /// stream runs one row per stream, soak runs one row per interval, burst runs one row per
+    /// burst, gossip runs one row per delivery, bufferbloat and delay runs one row per probe, RPC
+    /// runs one row per request, blob runs one row per (size, fetch), migration runs one row per
+    /// throughput sample, and discover, connect, resume and hole punching runs one row per trial.
+    pub fn to_csv(&self) -> String {
+        let opt = |v: Option<f64>| v.map(|v| format!("{v:.3}")).unwrap_or_default();
+        let mut out = String::new();
+        match &self.benchmark {
+            Benchmark::Bandwidth { results, .. } => {
+                out.push_str("size,iteration,started_at,bytes,upload_mbps,upload_ms,download_mbps,download_ms,connection_type,rtt_ms,cwnd,congestion_events,lost_packets,lost_bytes,mtu,server_upload_mbps,server_download_mbps,server_lost_packets,server_started_ms,server_finished_ms,error\n");
+                for result in results {
+                    for (i, it) in result.iterations.iter().enumerate() {
+                        let transport = TransportStats::total(&it.transport);
+                        let server = it.server.as_ref();
+                        let server_lost =
+                            server.map(|s| TransportStats::total(&s.transport).lost_packets);
+                        out.push_str(&format!(
+                            "{},{},{},{},{},{},{},{},{},{:.3},{},{},{},{},{},{},{},{},{},{},{}\n",
+                            result.size,
+                            i + 1,
+                            it.started_at,
+                            it.bytes,
+                            opt(it.aggregate.upload),
+                            opt(it.upload_ms),
+                            opt(it.aggregate.download),
+                            opt(it.download_ms),
+                            it.connection_type,
+                            it.rtt_ms,
+                            transport.cwnd,
+                            transport.congestion_events,
+                            transport.lost_packets,
+                            transport.lost_bytes,
+                            transport.mtu,
+                            opt(server.and_then(|s| s.bandwidth.upload)),
+                            opt(server.and_then(|s| s.bandwidth.download)),
+                            server_lost.map(|lost| lost.to_string()).unwrap_or_default(),
+                            opt(server.and_then(|s| s.started_ms)),
+                            opt(server.and_then(|s| s.finished_ms)),
+                            csv_field(it.error.as_deref().unwrap_or("")),
+                        ));
+                    }
+                }
+            }
+            Benchmark::ChunkSweep { size, results, .. } => {
+                out.push_str("chunk_size,iteration,size,upload_mbps,download_mbps\n");
+                for result in results {
+                    for (i, sample) in result.iterations.iter().enumerate() {
+                        out.push_str(&format!(
+                            "{},{},{size},{},{}\n",
+                            result.chunk_size,
+                            i + 1,
+                            opt(sample.upload),
+                            opt(sample.download),
+                        ));
+                    }
+                }
+            }
+            Benchmark::StreamSweep { size, results, .. } => {
+                out.push_str("streams,iteration,size,upload_mbps,download_mbps\n");
+                for result in results {
+                    for (i, sample) in result.iterations.iter().enumerate() {
+                        out.push_str(&format!(
+                            "{},{},{size},{},{}\n",
+                            result.streams,
+                            i + 1,
+                            opt(sample.upload),
+                            opt(sample.download),
+                        ));
+                    }
+                }
+            }
+            Benchmark::Timed { duration_secs, per_connection, transport, .. } => {
+                out.push_str("connection,duration_secs,upload_mbps,download_mbps,rtt_ms,cwnd,congestion_events,lost_packets,lost_bytes,mtu\n");
+                for (i, (sample, stats)) in per_connection.iter().zip(transport).enumerate() {
+                    out.push_str(&format!(
+                        "{},{duration_secs},{},{},{:.3},{},{},{},{},{}\n",
+                        i + 1,
+                        opt(sample.upload),
+                        opt(sample.download),
+                        stats.rtt_ms,
+                        stats.cwnd,
+                        stats.congestion_events,
+                        stats.lost_packets,
+                        stats.lost_bytes,
+                        stats.mtu,
+                    ));
+                }
+            }
+            Benchmark::Datagram {
+                size,
+                target_mbps,
+                duration_secs,
+                connection_type,
+                sent,
+                received,
+                loss_percent,
+                reordered,
+                reorder_percent,
+                loss_runs,
+                max_loss_run,
+                jitter_ms,
+                sent_mbps,
+                received_mbps,
+            } => {
+                out.push_str("size,target_mbps,duration_secs,connection_type,sent,received,loss_percent,reordered,reorder_percent,loss_runs,max_loss_run,jitter_ms,sent_mbps,received_mbps\n");
+                out.push_str(&format!(
+                    "{size},{target_mbps},{duration_secs:.3},{connection_type},{sent},{received},{loss_percent:.3},{reordered},{reorder_percent:.3},{loss_runs},{max_loss_run},{jitter_ms:.3},{sent_mbps:.3},{received_mbps:.3}\n"
+                ));
+            }
+            Benchmark::Discover { discovery_ms, handshake_ms, .. } => {
+                out.push_str("trial,discovery_ms,handshake_ms\n");
+                for (i, (discovery, handshake)) in discovery_ms.iter().zip(handshake_ms).enumerate() {
+                    out.push_str(&format!("{},{discovery:.3},{handshake:.3}\n", i + 1));
+                }
+            }
+            Benchmark::Connect { cold_ms, warm_ms, .. } => {
+                push_variants(&mut out, "connect_ms", cold_ms, warm_ms);
+            }
+            Benchmark::Resume { cold_ms, warm_ms, .. } => {
+                push_variants(&mut out, "first_response_ms", cold_ms, warm_ms);
+            }
+            Benchmark::Streams { open_ms, first_byte_ms, .. } => {
+                out.push_str("stream,open_ms,first_byte_ms\n");
+                for (i, (open, first_byte)) in open_ms.iter().zip(first_byte_ms).enumerate() {
+                    out.push_str(&format!("{},{open:.3},{first_byte:.3}\n", i + 1));
+                }
+            }
+            Benchmark::HolePunch { trials, .. } => {
+                out.push_str("trial,initial_path,direct,time_to_direct_ms\n");
+                for (i, trial) in trials.iter().enumerate() {
+                    out.push_str(&format!(
+                        "{},{},{},{}\n",
+                        i + 1,
+                        trial.initial_path,
+                        trial.direct,
+                        opt(trial.time_to_direct_ms),
+                    ));
+                }
+            }
+            Benchmark::Ping { size, rtts_ms, .. } => {
+                out.push_str("iteration,size,rtt_ms\n");
+                for (i, rtt) in rtts_ms.iter().enumerate() {
+                    out.push_str(&format!("{},{size},{rtt:.3}\n", i + 1));
+                }
+            }
+            Benchmark::Soak { intervals, .. } => {
+                out.push_str("interval,started_at,elapsed_secs,upload_mbps,download_mbps,rtt_ms,connection_type,reconnected,error\n");
+                for (i, interval) in intervals.iter().enumerate() {
+                    out.push_str(&format!(
+                        "{},{},{:.3},{},{},{:.3},{},{},{}\n",
+                        i + 1,
+                        interval.started_at,
+                        interval.elapsed_secs,
+                        opt(interval.bandwidth.upload),
+                        opt(interval.bandwidth.download),
+                        interval.rtt_ms,
+                        interval.connection_type,
+                        interval.reconnected,
+                        csv_field(interval.error.as_deref().unwrap_or("")),
+                    ));
+                }
+            }
+            Benchmark::Bursts { size, bursts, .. } => {
+                out.push_str("burst,size,offset_ms,upload_mbps,upload_ms,download_mbps,download_ms,cwnd,rtt_ms,congestion_events,lost_packets,error\n");
+                for (i, burst) in bursts.iter().enumerate() {
+                    out.push_str(&format!(
+                        "{},{size},{:.3},{},{},{},{},{},{:.3},{},{},{}\n",
+                        i + 1,
+                        burst.offset_ms,
+                        opt(burst.bandwidth.upload),
+                        opt(burst.upload_ms),
+                        opt(burst.bandwidth.download),
+                        opt(burst.download_ms),
+                        burst.cwnd,
+                        burst.rtt_ms,
+                        burst.congestion_events,
+                        burst.lost_packets,
+                        csv_field(burst.error.as_deref().unwrap_or("")),
+                    ));
+                }
+            }
+            Benchmark::Bufferbloat { size, idle_ms, loaded_ms, .. } => {
+                out.push_str("phase,probe,size,rtt_ms\n");
+                for (phase, rtts) in [("idle", idle_ms), ("loaded", loaded_ms)] {
+                    for (i, rtt) in rtts.iter().enumerate() {
+                        out.push_str(&format!("{phase},{},{size},{rtt:.3}\n", i + 1));
+                    }
+                }
+            }
+            Benchmark::Rpc { request_size, response_size, depth, latency_ms, .. } => {
+                out.push_str("request,request_size,response_size,depth,latency_ms\n");
+                for (i, latency) in latency_ms.iter().enumerate() {
+                    out.push_str(&format!(
+                        "{},{request_size},{response_size},{depth},{latency:.3}\n",
+                        i + 1
+                    ));
+                }
+            }
+            Benchmark::Messages {
+                size,
+                connection_type,
+                messages,
+                duration_secs,
+                messages_per_sec,
+                goodput_mbps,
+                us_per_message,
+                overhead_bytes_per_message,
+            } => {
+                out.push_str("size,connection_type,messages,duration_secs,messages_per_sec,goodput_mbps,us_per_message,overhead_bytes_per_message\n");
+                out.push_str(&format!(
+                    "{size},{connection_type},{messages},{duration_secs:.3},{messages_per_sec:.0},{goodput_mbps:.3},{us_per_message:.3},{overhead_bytes_per_message:.1}\n"
+                ));
+            }
+            Benchmark::Delay { clock_offset_ms, rtts_ms, forward_ms, backward_ms, .. } => {
+                out.push_str("probe,rtt_ms,forward_ms,backward_ms,clock_offset_ms\n");
+                let probes = rtts_ms.iter().zip(forward_ms).zip(backward_ms);
+                for (i, ((rtt, forward), backward)) in probes.enumerate() {
+                    out.push_str(&format!(
+                        "{},{rtt:.3},{forward:.3},{backward:.3},{clock_offset_ms:.3}\n",
+                        i + 1
+                    ));
+                }
+            }
+            Benchmark::Migrate { rebind_at_secs, throughput, .. } => {
+                out.push_str("sample,elapsed_ms,mbps,after_rebind\n");
+                for (i, sample) in throughput.iter().enumerate() {
+                    out.push_str(&format!(
+                        "{},{:.1},{:.3},{}\n",
+                        i + 1,
+                        sample.elapsed_ms,
+                        sample.mbps,
+                        sample.elapsed_ms > rebind_at_secs * 1000.0,
+                    ));
+                }
+            }
+            Benchmark::Gossip { size, latency_ms, .. } => {
+                out.push_str("delivery,size,latency_ms\n");
+                for (i, latency) in latency_ms.iter().enumerate() {
+                    out.push_str(&format!("{},{size},{latency:.3}\n", i + 1));
+                }
+            }
+            Benchmark::Blobs { results, .. } => {
+                out.push_str("size,iteration,hash,fetch_ms,download_mbps
+");
+                for result in results {
+                    let fetches = result.fetch_ms.iter().zip(&result.download_mbps);
+                    for (i, (fetch, mbps)) in fetches.enumerate() {
+                        out.push_str(&format!(
+                            "{},{},{},{fetch:.3},{mbps:.3}\n",
+                            result.size,
+                            i + 1,
+                            result.hash,
+                        ));
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// Renders a summary as Markdown, for pasting into GitHub issues.
+    ///
+    /// Bandwidth runs get one table row per payload size and direction, all other runs one row
+    /// per headline metric, and soak runs another table of their path changes.
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::new();
+        let title = self.name.as_deref().unwrap_or(self.benchmark.mode());
+        out.push_str(&format!("### iroh benchmark: {title}\n\n"));
+        out.push_str(&format!(
+            "Run `{}`, iroh {} on {} {}, server `{}`",
+            self.run.run_id,
+            self.run.iroh_version,
+            self.run.os,
+            self.run.arch,
+            &self.server_node_id[..self.server_node_id.len().min(10)],
+        ));
+        if !self.run.hostname.is_empty() {
+            out.push_str(&format!(", client host `{}`", self.run.hostname));
+        }
+        if let Some(label) = &self.run.label {
+            out.push_str(&format!(", label \"{label}\""));
+        }
+        if self.relay_only {
+            out.push_str(", relay only");
+        }
+        if self.congestion != Congestion::Cubic {
+            out.push_str(&format!(", {} congestion control", self.congestion.name()));
+        }
+        if let Benchmark::Bandwidth { bitrate_mbps: Some(mbps), .. }
+        | Benchmark::Timed { bitrate_mbps: Some(mbps), .. }
+        | Benchmark::Soak { bitrate_mbps: Some(mbps), .. }
+        | Benchmark::Bursts { bitrate_mbps: Some(mbps), .. } = &self.benchmark
+        {
+            out.push_str(&format!(", paced to {mbps} Mbit/s"));
+        }
+        if let Benchmark::Bandwidth { target_duration_secs: Some(secs), .. } = &self.benchmark {
+            out.push_str(&format!(", payloads sized to take {secs}s"));
+        }
+        if let Benchmark::Bandwidth { knee_size: Some(size), .. } = &self.benchmark {
+            out.push_str(&format!(", bandwidth levels off from {}", format_size(*size)));
+        }
+        if let Some(ceiling) = &self.run.machine_ceiling {
+            out.push_str(&format!(", machine ceiling {}", ceiling.describe()));
+        }
+        if self.interrupted {
+            out.push_str(", interrupted before completing");
+        }
+        out.push_str("\n\n");
+
+        match &self.benchmark {
+            Benchmark::Bandwidth { results, .. } => {
+                out.push_str("| Size | Direction | Avg (Mbit/s) | Min | Max | p95 | Connection type |");
+                out.push_str("\n|---:|---|---:|---:|---:|---:|---|\n");
+                for result in results {
+                    let measured = result.iterations.iter().filter(|it| it.error.is_none());
+                    let mut paths: Vec<&str> =
+                        measured.map(|it| it.connection_type.as_str()).collect();
+                    paths.sort_unstable();
+                    paths.dedup();
+                    for download in [false, true] {
+                        let (direction, summary) = match download {
+                            false => ("upload", result.upload),
+                            true => ("download", result.download),
+                        };
+                        let Some(summary) = summary else {
+                            continue;
+                        };
+                        out.push_str(&format!(
+                            "| {} | {direction} | {:.2} | {:.2} | {:.2} | {:.2} | {} |\n",
+                            format_size(result.size),
+                            summary.average,
+                            summary.min,
+                            summary.max,
+                            summary.p95,
+                            paths.join(", "),
+                        ));
+                    }
+                }
+                out.push_str(&two_sided_table(results));
+            }
+            Benchmark::Soak { path_stability, .. } => {
+                out.push_str(&metrics_table(&self.benchmark));
+                out.push_str(&path_stability_table(path_stability));
+            }
+            benchmark => out.push_str(&metrics_table(benchmark)),
+        }
+        if let Some(counters) = &self.iroh_counters {
+            out.push_str(&format!(
+                "\n{:.1}% of the bytes went through the relay.\n",
+                counters.relay_percent
+            ));
+        }
+        out
+    }
+}
+
+/// Markdown table of the headline metrics of a benchmark, followed by its paths.
+fn metrics_table(benchmark: &Benchmark) -> String {
+    let mut out = String::from("| Metric | Value |\n|---|---:|\n");
+    for metric in benchmark.metrics() {
+        out.push_str(&format!("| {} | {:.3} {} |\n", metric.name, metric.value, metric.unit));
+    }
+    if let Some(connection_type) = benchmark.connection_type() {
+        out.push_str(&format!("\nConnection type: {connection_type}\n"));
+    }
+    out
+}
+
+/// Markdown section on how long a soak test stayed on every path and every change between them.
+fn path_stability_table(stability: &PathStability) -> String {
+    if stability.time_on_path_percent.is_empty() {
+        return String::new();
+    }
+    let shares: Vec<String> = stability
+        .time_on_path_percent
+        .iter()
+        .map(|(path, percent)| format!("{path} {percent:.1}%"))
+        .collect();
+    let mut out = format!("\nPath stability: {} of the time", shares.join(", "));
+    if stability.transitions.is_empty() {
+        out.push_str(", no path changes.\n");
+        return out;
+    }
+    out.push_str(":\n\n| Time | Elapsed | From | To | Before (Mbit/s) | After (Mbit/s) |");
+    out.push_str("\n|---|---:|---|---|---:|---:|\n");
+    let mbps = |v: Option<f64>| v.map_or("-".to_string(), |v| format!("{v:.2}"));
+    for transition in &stability.transitions {
+        out.push_str(&format!(
+            "| {} | {} | {} | {} | {} | {} |\n",
+            transition.at,
+            humantime::format_duration(Duration::from_secs(transition.elapsed_secs as u64)),
+            transition.from,
+            transition.to,
+            mbps(transition.before_mbps),
+            mbps(transition.after_mbps),
+        ));
+    }
+    out
+}
+
+/// Markdown table of every iteration as the sender and the receiver saw it, or nothing without
+/// the server's measurements.
+fn two_sided_table(results: &[SizeResult]) -> String {
+    let mut rows = String::new();
+    for result in results {
+        for (i, iteration) in result.iterations.iter().enumerate() {
+            for view in iteration.two_sided() {
+                rows.push_str(&format!(
+                    "| {} | {} | {} | {:.2} | {:.2} | {} / {} | {:.3} / {:.3} | {} |\n",
+                    format_size(result.size),
+                    i + 1,
+                    view.direction,
+                    view.sender_mbps,
+                    view.receiver_mbps,
+                    view.client_lost_packets,
+                    view.server_lost_packets,
+                    view.client_rtt_ms,
+                    view.server_rtt_ms,
+                    view.discrepancies.join("; "),
+                ));
+            }
+        }
+    }
+    if rows.is_empty() {
+        return rows;
+    }
+    let mut out = String::from("\nSender and receiver per iteration, client / server:\n\n");
+    out.push_str("| Size | Iteration | Direction | Sender (Mbit/s) | Receiver (Mbit/s) |");
+    out.push_str(" Lost packets | RTT (ms) | Discrepancies |");
+    out.push_str("\n|---:|---:|---|---:|---:|---:|---:|---|\n");
+    out.push_str(&rows);
+    out
+}
+
+/// Appends CSV rows for latencies measured in a cold and a warm variant.
+fn push_variants(out: &mut String, column: &str, cold_ms: &[f64], warm_ms: &[f64]) {
+    out.push_str(&format!("variant,trial,{column}\n"));
+    for (variant, samples) in [("cold", cold_ms), ("warm", warm_ms)] {
+        for (i, ms) in samples.iter().enumerate() {
+            out.push_str(&format!("{variant},{},{ms:.3}\n", i + 1));
+        }
+    }
+}
+
+/// Interarrival jitter as defined in RFC 3550, a smoothed mean deviation of consecutive
+/// latencies, or `None` with fewer than two.
+///
+/// Only round-trip times are known, so differences between consecutive RTTs take the place of the
+/// transit time differences of the RFC.
+pub fn jitter(rtts: &[Duration]) -> Option<f64> {
+    if rtts.len() < 2 {
+        return None;
+    }
+    let jitter = rtts.windows(2).fold(0.0, |j, pair| {
+        let d = (millis(pair[1]) - millis(pair[0])).abs();
+        j + (d - j) / 16.0
+    });
+    Some(jitter)
+}
+
+/// Jain's fairness index of the throughputs of flows sharing a link, from `1/n` when one flow
+/// gets everything to 1 when all get the same, or `None` without any throughput.
+pub fn fairness(throughputs: &[f64]) -> Option<f64> {
+    let sum: f64 = throughputs.iter().sum();
+    let squares: f64 = throughputs.iter().map(|x| x * x).sum();
+    (squares > 0.0).then(|| sum * sum / (throughputs.len() as f64 * squares))
+}
+
+/// Slope of the least-squares line through `points`, or `None` with fewer than two distinct x
+/// values.
+pub fn trend(points: &[(f64, f64)]) -> Option<f64> {
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let covariance: f64 = points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+    let variance: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+    (variance > 0.0).then(|| covariance / variance)
+}
+
+/// Short label for the path a connection uses: `direct`, `relay`, `mixed` or `none`.
+pub fn path_label(conn_type: &ConnectionType) -> &'static str {
+    match conn_type {
+        ConnectionType::Direct(_) => "direct",
+        ConnectionType::Relay(_) => "relay",
+        ConnectionType::Mixed(..) => "mixed",
+        ConnectionType::None => "none",
+    }
+}
+
+/// Nearest-rank percentile of an already sorted, non-empty slice.
+pub fn percentile<T: Copy>(sorted: &[T], p: f64) -> T {
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Converts a duration to fractional milliseconds.
+pub fn millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+/// Formats a point in time as an RFC 3339 timestamp with millisecond precision.
+pub fn timestamp(time: SystemTime) -> String {
+    humantime::format_rfc3339_millis(time).to_string()
+}
+
+/// Quotes a CSV field per RFC 4180 if it contains a comma, a quote or a line break.
+pub fn csv_field(field: &str) -> Cow<'_, str> {
+    match field.contains([',', '"', '\r', '\n']) {
+        true => Cow::Owned(format!("\"{}\"", field.replace('"', "\"\""))),
+        false => Cow::Borrowed(field),
+    }
+}
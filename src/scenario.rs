@@ -0,0 +1,178 @@
+//! Scenario files describing suites of benchmarks.
+//!
+//! A scenario is a TOML file with an ordered list of `[[test]]` tables. Every key of a test is a
+//! command line option of the client without the leading dashes, and `mode` selects the
+//! benchmark subcommand (`bandwidth` by default). Options in the `[defaults]` table apply to
+//! every test that does not set them itself. Endpoint options such as discovery and relays are
+//! shared by all tests and taken from the command line.
+//!
+//! ```toml
+//! [defaults]
+//! iterations = 5
+//!
+//! [[test]]
+//! name = "upload"
+//! sizes = ["1M", "16M"]
+//! streams = 4
+//!
+//! [[test]]
+//! name = "latency"
+//! mode = "ping"
+//! count = 200
+//! repetitions = 3
+//! ```
+
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use clap::{Args, CommandFactory, FromArgMatches, Parser};
+use serde::Deserialize;
+use toml::{Table, Value};
+
+use crate::client::{BenchmarkConfig, Command};
+use crate::config::EndpointConfig;
+
+/// Name of the mode that runs the bandwidth test rather than a subcommand.
+const BANDWIDTH: &str = "bandwidth";
+
+/// An ordered list of benchmarks.
+#[derive(Debug, Clone)]
+pub struct Scenario {
+    pub tests: Vec<ScenarioTest>,
+}
+
+/// A single benchmark of a [`Scenario`].
+#[derive(Debug, Clone)]
+pub struct ScenarioTest {
+    /// Name from the scenario file, defaults to the mode. Unique within the scenario.
+    pub name: String,
+    /// How often the benchmark is run, every run producing its own report.
+    pub repetitions: usize,
+    /// Options of the benchmark, with the default endpoint options.
+    pub config: BenchmarkConfig,
+    pub command: Option<Command>,
+}
+
+/// Layout of a scenario file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScenarioFile {
+    #[serde(default)]
+    defaults: Table,
+    #[serde(default, rename = "test")]
+    tests: Vec<Table>,
+}
+
+/// The client options a test can set, parsed with the client's own parsers.
+#[derive(Parser, Debug)]
+#[command(name = "test", no_binary_name = true)]
+struct TestArgs {
+    #[command(flatten)]
+    config: BenchmarkConfig,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+impl Scenario {
+    /// Reads and parses the scenario file at `path`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read scenario {}", path.display()))?;
+        Self::parse(&contents).with_context(|| format!("Invalid scenario {}", path.display()))
+    }
+
+    /// Parses a scenario from its TOML representation.
+    pub fn parse(contents: &str) -> Result<Self> {
+        let file: ScenarioFile = toml::from_str(contents)?;
+        anyhow::ensure!(!file.tests.is_empty(), "The scenario has no [[test]] tables");
+        let tests: Vec<ScenarioTest> = file
+            .tests
+            .into_iter()
+            .enumerate()
+            .map(|(i, test)| {
+                let mut options = file.defaults.clone();
+                options.extend(test);
+                ScenarioTest::parse(options).with_context(|| format!("Invalid test {}", i + 1))
+            })
+            .collect::<Result<_>>()?;
+        // Resuming a run finds the completed repetitions of a test by its name
+        for (i, test) in tests.iter().enumerate() {
+            if let Some(j) = tests[..i].iter().position(|other| other.name == test.name) {
+                bail!(
+                    "Tests {} and {} are both named `{}`, give them distinct names",
+                    j + 1,
+                    i + 1,
+                    test.name
+                );
+            }
+        }
+        Ok(Self { tests })
+    }
+}
+
+impl ScenarioTest {
+    fn parse(mut options: Table) -> Result<Self> {
+        let mode = match options.remove("mode") {
+            Some(Value::String(mode)) => mode,
+            Some(value) => bail!("The mode must be a string, not {value}"),
+            None => BANDWIDTH.to_string(),
+        };
+        let name = match options.remove("name") {
+            Some(Value::String(name)) => name,
+            Some(value) => bail!("The name must be a string, not {value}"),
+            None => mode.clone(),
+        };
+        let repetitions = match options.remove("repetitions") {
+            Some(Value::Integer(n)) if n > 0 => n as usize,
+            Some(value) => bail!("The repetitions must be a positive integer, not {value}"),
+            None => 1,
+        };
+
+        // Options of the benchmark subcommand have to follow it, all others precede it
+        let endpoint_options = long_options(EndpointConfig::augment_args(clap::Command::new("")));
+        let global_options = long_options(TestArgs::command());
+        let (mut global, mut local) = (Vec::new(), Vec::new());
+        for (key, value) in options {
+            let option = key.replace('_', "-");
+            if endpoint_options.contains(&option) {
+                bail!("The endpoint option `{key}` can only be set on the command line");
+            }
+            let args = if global_options.contains(&option) { &mut global } else { &mut local };
+            match value {
+                Value::Boolean(true) => args.push(format!("--{option}")),
+                Value::Boolean(false) => {}
+                value => args.extend([format!("--{option}"), option_value(&key, value)?]),
+            }
+        }
+        if mode != BANDWIDTH {
+            global.push(mode);
+        }
+        global.extend(local);
+
+        let matches = TestArgs::command().try_get_matches_from(global)?;
+        let args = TestArgs::from_arg_matches(&matches)?;
+        Ok(Self { name, repetitions, config: args.config, command: args.command })
+    }
+}
+
+/// Long names of the top-level options of `command`.
+fn long_options(command: clap::Command) -> Vec<String> {
+    command.get_arguments().filter_map(|arg| arg.get_long()).map(String::from).collect()
+}
+
+/// Formats an option value the way it is written on the command line, arrays as comma-separated
+/// lists.
+fn option_value(key: &str, value: Value) -> Result<String> {
+    Ok(match value {
+        Value::String(s) => s,
+        Value::Integer(n) => n.to_string(),
+        Value::Float(x) => x.to_string(),
+        Value::Array(values) => values
+            .into_iter()
+            .map(|value| option_value(key, value))
+            .collect::<Result<Vec<_>>>()?
+            .join(","),
+        value => bail!("Unsupported value for `{key}`: {value}"),
+    })
+}
@@ -0,0 +1,151 @@
+//! Self-tests, which benchmark a server running in the same process as the client.
+//!
+//! Without a network in between, a loopback self-test measures what the machine itself can
+//! sustain: the cost of encryption, packet processing and the benchmark. Via the relay it
+//! measures the round trip to the relay server instead. Either way it exercises every benchmark
+//! end to end without setting up a second machine.
+
+use std::{
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use iroh::{NodeAddr, protocol::Router};
+use tokio::time::timeout;
+
+use crate::client::{
+    BenchmarkConfig, Command, ConnectConfig, HolePunchConfig, PingConfig, ResumeConfig,
+    StreamsConfig, Transport,
+};
+use crate::config::EndpointConfig;
+use crate::metrics::ServerMetrics;
+use crate::report::{Benchmark, Sample};
+use crate::scenario::{Scenario, ScenarioTest};
+use crate::server::ServeOptions;
+
+/// How long to wait for the server to connect to its home relay.
+const RELAY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Duration of the timed and datagram tests of the suite.
+const TEST_TIME: Duration = Duration::from_secs(5);
+
+/// Duration of the loopback transfer measuring the machine ceiling.
+const CEILING_TIME: Duration = Duration::from_secs(3);
+
+/// Path between the client and the in-process server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Via {
+    /// Directly over the loopback interface
+    Loopback,
+    /// Only through the relay server
+    Relay,
+}
+
+/// Binds the server's endpoint with the relays of `endpoint` and serves the benchmark protocols
+/// on it, returning its router and the address the client dials it at.
+///
+/// The server does not publish its addresses, the returned address carries the loopback
+/// addresses it is bound to or its home relay.
+pub async fn serve(endpoint: &EndpointConfig, via: Via) -> Result<(Router, NodeAddr)> {
+    let config = EndpointConfig {
+        no_discovery: true,
+        relay_only: via == Via::Relay,
+        ..endpoint.clone()
+    };
+    let endpoint = config.builder()?.bind().await?;
+    let node_id = endpoint.node_id();
+    let addr = match via {
+        Via::Loopback => {
+            let (v4, v6) = endpoint.bound_sockets();
+            let mut addrs = vec![SocketAddr::new(Ipv4Addr::LOCALHOST.into(), v4.port())];
+            addrs.extend(v6.map(|v6| SocketAddr::new(Ipv6Addr::LOCALHOST.into(), v6.port())));
+            NodeAddr::from_parts(node_id, None, addrs)
+        }
+        Via::Relay => {
+            let relay = timeout(RELAY_TIMEOUT, endpoint.home_relay().initialized())
+                .await
+                .context("No relay server is reachable")??;
+            NodeAddr::new(node_id).with_relay_url(relay)
+        }
+    };
+    let metrics = Arc::new(ServerMetrics::default());
+    let options = ServeOptions { quiet: true, ..Default::default() };
+    let router = crate::server::router(endpoint, metrics, options).await?;
+    Ok((router, addr))
+}
+
+/// The suite of benchmarks a self-test runs: the bandwidth test, streaming for a fixed time,
+/// datagrams, pings, connection setup, stream opening, 0-RTT resumption and, over loopback, hole
+/// punching. Discovery is left out as the server does not publish its addresses.
+pub fn suite(via: Via) -> Scenario {
+    let test = |name: &str, config: BenchmarkConfig, command: Option<Command>| ScenarioTest {
+        name: name.to_string(),
+        repetitions: 1,
+        config,
+        command,
+    };
+    let mut tests = vec![
+        test("bandwidth", BenchmarkConfig::default(), None),
+        test("timed", BenchmarkConfig { time: Some(TEST_TIME), ..Default::default() }, None),
+        test(
+            "datagram",
+            BenchmarkConfig {
+                transport: Transport::Datagram,
+                time: Some(TEST_TIME),
+                ..Default::default()
+            },
+            None,
+        ),
+        test("ping", BenchmarkConfig::default(), Some(Command::Ping(PingConfig::default()))),
+        test(
+            "connect",
+            BenchmarkConfig::default(),
+            Some(Command::Connect(ConnectConfig::default())),
+        ),
+        test(
+            "streams",
+            BenchmarkConfig::default(),
+            Some(Command::Streams(StreamsConfig::default())),
+        ),
+        test("resume", BenchmarkConfig::default(), Some(Command::Resume(ResumeConfig::default()))),
+    ];
+    // Relayed connections never become direct
+    if via == Via::Loopback {
+        tests.push(test(
+            "holepunch",
+            BenchmarkConfig::default(),
+            Some(Command::Holepunch(HolePunchConfig::default())),
+        ));
+    }
+    Scenario { tests }
+}
+
+/// Measures the "machine ceiling": the throughput of a quick timed transfer to an in-process
+/// server over loopback, with the direction, streams and chunk size of `config`.
+///
+/// Bandwidths close to it are limited by the CPU and crypto of this machine rather than by the
+/// network.
+pub async fn machine_ceiling(config: &BenchmarkConfig) -> Result<Sample> {
+    let endpoint_config = EndpointConfig { relay_only: false, ..config.endpoint.clone() };
+    let (router, addr) = serve(&endpoint_config, Via::Loopback).await?;
+    let config = BenchmarkConfig {
+        endpoint: EndpointConfig { no_discovery: true, ..endpoint_config },
+        connections: 1,
+        interval: Duration::ZERO,
+        print_results: false,
+        progress: None,
+        interval_sink: None,
+        direct_only: false,
+        ..config.clone()
+    };
+    let endpoint = config.endpoint.builder()?.bind().await?;
+    let benchmark = crate::client::run_timed(&endpoint, addr, &config, CEILING_TIME).await;
+    endpoint.close().await;
+    router.shutdown().await?;
+    match benchmark? {
+        Benchmark::Timed { total, .. } => Ok(total),
+        _ => unreachable!("timed transfers produce timed results"),
+    }
+}
@@ -1,43 +1,309 @@
-//! Simple server that prints received bytes
+//! Protocol handlers serving the workloads requested by the benchmark client.
 //!
-//! ## Usage
-//!
-//!     cargo run --bin server
+//! Every stream starts with a [`Request`]: the server sinks uploads, sources downloads of the
+//! requested size (or until the client stops reading), does both at once, mirrors the payload
+//! back, echoes pings, and if enabled imports a blob into an in-memory store served with
+//! iroh-blobs or joins a gossip topic to bootstrap the client's iroh-gossip swarm.
+//! Datagram benchmarks are counted by a separate handler on [`DATAGRAM_ALPN`].
+//! Both handlers record the bytes and connections of every client in [`ServerMetrics`], and
+//! close connections from nodes that are not on the [`Allowlist`] or exceed the [`Limits`].
+//! [`serve_tcp_baseline`] and [`serve_quic_baseline`] serve the same transfers over plain TCP
+//! and plain QUIC, for comparison.
+
+use std::{
+    collections::BTreeSet,
+    io::ErrorKind,
+    path::Path,
+    pin::pin,
+    sync::{Arc, atomic::Ordering},
+    time::Duration,
+};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use iroh::{
-    endpoint::Connecting,
+    Endpoint, NodeId,
+    endpoint::{
+        Connecting, Connection, ConnectionError, ConnectionStats, ReadExactError, RecvStream,
+        SendStream, WriteError,
+    },
     protocol::{ProtocolHandler, Router},
-    Endpoint
 };
-use n0_future::boxed::BoxFuture;
+use bytes::Bytes;
+use iroh_blobs::{
+    BlobFormat,
+    net_protocol::Blobs,
+    store::{GcConfig, Store as _, mem},
+    util::progress::IgnoreProgressSender,
+};
+use iroh_gossip::{
+    net::{Event, Gossip, GossipEvent},
+    proto::TopicId,
+};
+use n0_future::{Stream, StreamExt, boxed::BoxFuture, task::AbortOnDropHandle};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::{OwnedSemaphorePermit, Semaphore, watch},
+    task::JoinSet,
+    time::{Instant, timeout_at},
+};
+
+use crate::mesh::Agent;
+use crate::metrics::{ClientMetrics, ServerMetrics};
+use crate::pacing::Pacer;
+use crate::payload::{Pattern, Payload};
+use crate::protocol::{
+    ACK, AGENT_ALPN, ALPN, ClockReply, DATAGRAM_ALPN, DatagramHeader, DatagramSummary, Direction,
+    MAX_GOSSIP_MESSAGE, Request, Response, ServerReport, Transfer, read_rpc_message, rpc_message,
+    wall_clock_us,
+};
+use crate::report::TransportStats;
+
+/// How long to keep counting datagrams that are still in flight once the client is done sending.
+const DATAGRAM_GRACE: Duration = Duration::from_millis(500);
+
+/// Size of the chunks blobs are generated and imported in.
+const BLOB_CHUNK_SIZE: u64 = 1024 * 1024;
+
+/// How often blobs that no client holds on to anymore are removed from the blob store.
+const BLOB_GC_PERIOD: Duration = Duration::from_secs(10);
+
+/// Application error code of connections closed because the client is not on the allowlist.
+pub const NOT_ALLOWED: u32 = 403;
+
+/// Application error code of connections closed because they used up their byte limit.
+pub const BYTE_LIMIT_REACHED: u32 = 413;
+
+/// Application error code of connections closed because the server has as many as it accepts.
+pub const TOO_MANY_CONNECTIONS: u32 = 503;
+
+/// How often the bytes of a connection are checked against its limit.
+const BYTE_LIMIT_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Options of [`router`].
+#[derive(Debug, Clone, Default)]
+pub struct ServeOptions {
+    /// Do not log connections and transfers.
+    pub quiet: bool,
+    /// Also run the measurements clients instruct the server to on [`AGENT_ALPN`].
+    pub agent: bool,
+    /// Also serve the blob benchmark within these limits, with iroh-blobs on its own ALPN.
+    pub blobs: Option<BlobLimits>,
+    /// Also serve the gossip benchmark, with iroh-gossip on its own ALPN.
+    pub gossip: bool,
+    /// Nodes allowed to use the benchmark protocols.
+    pub allow: Allowlist,
+    pub limits: Limits,
+}
+
+/// Limits on the blobs the server holds in memory for [`Request::Blob`].
+#[derive(Debug, Clone, Copy)]
+pub struct BlobLimits {
+    /// Largest blob a client may request, at most [`crate::protocol::MAX_BLOB_SIZE`].
+    pub max_size: u64,
+    /// Blobs a client may have the server hold at once, across all its connections.
+    pub per_client: u64,
+}
 
-/// Each protocol is identified by its ALPN string.
+impl Default for BlobLimits {
+    fn default() -> Self {
+        Self { max_size: 128 * 1024 * 1024, per_client: 4 }
+    }
+}
+
+/// Node IDs of the clients allowed to connect, or everyone.
 ///
-/// The ALPN, or application-layer protocol negotiation, is exchanged in the connection handshake,
-/// and the connection is aborted unless both nodes pass the same bytestring.
-const ALPN: &[u8] = b"iroh-example/print/0";
-
-#[tokio::main]
-async fn main() -> Result<()> {
-    let router = accept_side().await?;
-    let node_addr = router.endpoint().node_addr().await?;
-    println!("Listening on {:?}", node_addr.node_id.to_string());
-
-    tokio::signal::ctrl_c().await?;
-    router.shutdown().await?;
-    Ok(())
+/// Only the benchmark protocols check it. The blob and gossip protocols serve just what allowed
+/// clients set up over them, and the TCP and QUIC baselines know no node IDs, which is why the
+/// server binary does not run them together with an allowlist.
+#[derive(Debug, Clone, Default)]
+pub struct Allowlist(Option<Arc<BTreeSet<NodeId>>>);
+
+impl Allowlist {
+    /// Allows only `nodes`.
+    pub fn only(nodes: impl IntoIterator<Item = NodeId>) -> Self {
+        Self(Some(Arc::new(nodes.into_iter().collect())))
+    }
+
+    /// Allows the `nodes` of `--allow` and those listed in the `file` of `--allow-file`, one per
+    /// line, or everyone if neither is given.
+    pub fn load(nodes: &[NodeId], file: Option<&Path>) -> Result<Self> {
+        let Some(path) = file else {
+            return Ok(match nodes.is_empty() {
+                true => Self::default(),
+                false => Self::only(nodes.iter().copied()),
+            });
+        };
+        let mut nodes = nodes.to_vec();
+        let file = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read the allowlist {}", path.display()))?;
+        for line in file.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let node_id = line
+                .parse()
+                .with_context(|| format!("Invalid node ID in {}: {line}", path.display()))?;
+            nodes.push(node_id);
+        }
+        Ok(Self::only(nodes))
+    }
+
+    /// Whether every node is allowed.
+    pub fn is_open(&self) -> bool {
+        self.0.is_none()
+    }
+
+    pub fn allows(&self, node_id: &NodeId) -> bool {
+        self.0.as_ref().is_none_or(|nodes| nodes.contains(node_id))
+    }
+
+    /// Returns the node ID of the client, or closes the connection with [`NOT_ALLOWED`] and
+    /// returns `None` if the client is not allowed.
+    pub fn admit(&self, connection: &Connection, quiet: bool) -> Result<Option<NodeId>> {
+        let node_id = connection.remote_node_id()?;
+        if self.allows(&node_id) {
+            return Ok(Some(node_id));
+        }
+        log(quiet, format!("Rejected connection from {node_id}, which is not allowed"));
+        connection.close(NOT_ALLOWED.into(), b"not allowed");
+        Ok(None)
+    }
+}
+
+/// Limits on the connections of the benchmark protocols, to keep a server exposed to the
+/// internet from being used to exhaust its memory or bandwidth.
+#[derive(Debug, Clone, Default)]
+pub struct Limits {
+    /// Slots for concurrent connections, unlimited if `None`.
+    connections: Option<Arc<Semaphore>>,
+    /// Bytes a connection may send and receive in total, including QUIC overhead.
+    bytes_per_connection: Option<u64>,
+}
+
+/// Held while a connection is open within the [`Limits`].
+#[derive(Debug)]
+pub struct Permit {
+    _slot: Option<OwnedSemaphorePermit>,
+    _byte_limit: Option<AbortOnDropHandle<()>>,
+}
+
+impl Limits {
+    pub fn new(max_connections: Option<usize>, max_bytes_per_connection: Option<u64>) -> Self {
+        Self {
+            connections: max_connections.map(|max| Arc::new(Semaphore::new(max))),
+            bytes_per_connection: max_bytes_per_connection,
+        }
+    }
+
+    /// Whether neither the connections nor their bytes are limited.
+    pub fn is_unlimited(&self) -> bool {
+        self.connections.is_none() && self.bytes_per_connection.is_none()
+    }
+
+    /// Returns the permit to hold while the `connection` of `node_id` is open, or closes the
+    /// connection with [`TOO_MANY_CONNECTIONS`] and returns `None` if there is no free slot.
+    ///
+    /// With a byte limit, the connection is closed with [`BYTE_LIMIT_REACHED`] once it sent and
+    /// received more than that, checked every `BYTE_LIMIT_CHECK_INTERVAL`.
+    pub fn admit(&self, connection: &Connection, node_id: NodeId, quiet: bool) -> Option<Permit> {
+        let slot = match &self.connections {
+            Some(slots) => match slots.clone().try_acquire_owned() {
+                Ok(slot) => Some(slot),
+                Err(_) => {
+                    log(quiet, format!("Rejected connection from {node_id}, too many are open"));
+                    connection.close(TOO_MANY_CONNECTIONS.into(), b"too many connections");
+                    return None;
+                }
+            },
+            None => None,
+        };
+        let byte_limit = self.bytes_per_connection.map(|max| {
+            let connection = connection.clone();
+            let enforce = enforce_byte_limit(connection, node_id, max, quiet);
+            AbortOnDropHandle::new(tokio::spawn(enforce))
+        });
+        Some(Permit { _slot: slot, _byte_limit: byte_limit })
+    }
 }
 
-async fn accept_side() -> Result<Router> {
-    let endpoint = Endpoint::builder().discovery_n0().bind().await?;
-    let router = Router::builder(endpoint).accept(ALPN, PrintBytes).spawn().await?;
+/// Closes the `connection` of `node_id` once it sent and received more than `max` bytes.
+async fn enforce_byte_limit(connection: Connection, node_id: NodeId, max: u64, quiet: bool) {
+    let mut interval = tokio::time::interval(BYTE_LIMIT_CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let stats = connection.stats();
+        if stats.udp_rx.bytes + stats.udp_tx.bytes > max {
+            log(quiet, format!("Closed connection from {node_id} after {max} bytes"));
+            connection.close(BYTE_LIMIT_REACHED.into(), b"byte limit reached");
+            return;
+        }
+    }
+}
 
-    Ok(router)
+/// Spawns a router that serves all benchmark protocols on `endpoint`, recording its traffic in
+/// `metrics`.
+pub async fn router(
+    endpoint: Endpoint,
+    metrics: Arc<ServerMetrics>,
+    options: ServeOptions,
+) -> Result<Router> {
+    let ServeOptions { quiet, agent, blobs, gossip, allow, limits } = options;
+    let mut services = Services::default();
+    let mut builder = Router::builder(endpoint.clone());
+    if let Some(blob_limits) = blobs {
+        let blobs = Blobs::memory().build(&endpoint);
+        blobs.start_gc(GcConfig { period: BLOB_GC_PERIOD, done_callback: None })?;
+        services.blobs = Some(blobs.store().clone());
+        services.blob_limits = blob_limits;
+        builder = builder.accept(iroh_blobs::ALPN, blobs);
+    }
+    if gossip {
+        let gossip = Gossip::builder()
+            .max_message_size(MAX_GOSSIP_MESSAGE)
+            .spawn(endpoint.clone())
+            .await?;
+        services.gossip = Some(gossip.clone());
+        builder = builder.accept(iroh_gossip::ALPN, gossip);
+    }
+    builder = builder
+        .accept(
+            ALPN,
+            PrintBytes {
+                metrics: metrics.clone(),
+                quiet,
+                allow: allow.clone(),
+                limits: limits.clone(),
+                services,
+            },
+        )
+        .accept(DATAGRAM_ALPN, CountDatagrams { metrics, quiet, allow: allow.clone(), limits });
+    if agent {
+        builder = builder.accept(AGENT_ALPN, Agent { endpoint, allow });
+    }
+    builder.spawn().await
 }
 
+/// Serves the transfers and pings of stream benchmarks on [`ALPN`].
 #[derive(Debug, Clone)]
-struct PrintBytes;
+pub struct PrintBytes {
+    pub metrics: Arc<ServerMetrics>,
+    /// Do not log connections and transfers.
+    pub quiet: bool,
+    pub allow: Allowlist,
+    pub limits: Limits,
+    pub services: Services,
+}
+
+/// Protocols served next to the benchmark protocols, which requests set up workloads on.
+#[derive(Debug, Clone, Default)]
+pub struct Services {
+    /// Store that blobs requested with [`Request::Blob`] are imported into.
+    pub blobs: Option<mem::Store>,
+    pub blob_limits: BlobLimits,
+    /// Gossip instance that joins the topics of [`Request::Gossip`].
+    pub gossip: Option<Gossip>,
+}
 
 impl ProtocolHandler for PrintBytes {
     /// The `accept` method is called for each incoming connection for our ALPN.
@@ -45,23 +311,808 @@ impl ProtocolHandler for PrintBytes {
     /// The returned future runs on a newly spawned tokio task, so it can run as long as
     /// the connection lasts.
     fn accept(&self, connecting: Connecting) -> BoxFuture<Result<()>> {
+        let (metrics, quiet, services) = (self.metrics.clone(), self.quiet, self.services.clone());
+        let (allow, limits) = (self.allow.clone(), self.limits.clone());
         Box::pin(async move {
-            let connection = connecting.await?;
-            let node_id = connection.remote_node_id()?;
-            println!("New connection from {node_id}");
+            // Clients resuming a session can have their first ping answered before the handshake
+            // completes, unless the server is restricted, as the client is not known until then
+            let (connection, handshake) = match allow.is_open() && limits.is_unlimited() {
+                true => match connecting.into_0rtt() {
+                    Ok((connection, handshake)) => (connection, Some(handshake)),
+                    Err(_) => anyhow::bail!("Incoming connections always support 0.5-RTT"),
+                },
+                false => (connecting.await?, None),
+            };
+            // Every benchmark stream is handled on its own task, so clients can run several
+            // transfers in parallel on one connection
+            let mut streams = JoinSet::new();
+            let early = Arc::new(ClientMetrics::default());
+            let (admit, admitted) = watch::channel(None);
+            let mut handshake = pin!(handshake);
+            while let Some(pending) = handshake.as_mut().as_pin_mut() {
+                let stream = tokio::select! {
+                    _ = pending => break,
+                    stream = connection.accept_bi() => stream,
+                };
+                let (send, recv) = match stream {
+                    Ok(stream) => stream,
+                    Err(ConnectionError::ApplicationClosed(_)) => return Ok(()),
+                    Err(err) => return Err(err.into()),
+                };
+                let (early, admitted) = (early.clone(), admitted.clone());
+                let services = services.clone();
+                let connection = connection.clone();
+                let stats = move || connection.stats();
+                streams.spawn(handle_early_stream(stats, send, recv, early, admitted, services));
+            }
+
+            let Some(node_id) = allow.admit(&connection, quiet)? else {
+                return Ok(());
+            };
+            let Some(_permit) = limits.admit(&connection, node_id, quiet) else {
+                return Ok(());
+            };
+            log(quiet, format!("New connection from {node_id}"));
+            let guard = metrics.connected(node_id);
+            admit.send_replace(Some(guard.client().clone()));
+            loop {
+                let (send, recv) = match connection.accept_bi().await {
+                    Ok(stream) => stream,
+                    Err(ConnectionError::ApplicationClosed(_)) => break,
+                    Err(err) => return Err(err.into()),
+                };
+                let (client, services) = (guard.client().clone(), services.clone());
+                let connection = connection.clone();
+                let stats = move || connection.stats();
+                streams.spawn(handle_stream(stats, send, recv, client, services));
+            }
+            while let Some(res) = streams.join_next().await {
+                match res? {
+                    Ok(summary) => log(quiet, summary),
+                    Err(err) => log(quiet, format!("Stream from {node_id} failed: {err:#}")),
+                }
+            }
+            guard.client().add_received(early.bytes_received.load(Ordering::Relaxed) as usize);
+            guard.client().add_sent(early.bytes_sent.load(Ordering::Relaxed) as usize);
+            Ok(())
+        })
+    }
+}
 
-            let (mut send, mut recv) = connection.accept_bi().await?;
+/// Counts datagram benchmarks on [`DATAGRAM_ALPN`].
+#[derive(Debug, Clone)]
+pub struct CountDatagrams {
+    pub metrics: Arc<ServerMetrics>,
+    /// Do not log connections and datagram counts.
+    pub quiet: bool,
+    pub allow: Allowlist,
+    pub limits: Limits,
+}
 
-            // Read all data from the stream
-            let data = recv.read_to_end(usize::MAX).await?;
-            println!("Total bytes received: {}", data.len());
-            
-            // Send small acknowledgment
-            send.write_all(b"received").await?;
-            send.finish()?;
+impl ProtocolHandler for CountDatagrams {
+    /// Counts the datagrams of one datagram benchmark and reports them back to the client.
+    fn accept(&self, connecting: Connecting) -> BoxFuture<Result<()>> {
+        let (metrics, quiet) = (self.metrics.clone(), self.quiet);
+        let (allow, limits) = (self.allow.clone(), self.limits.clone());
+        Box::pin(async move {
+            let connection = connecting.await?;
+            let Some(node_id) = allow.admit(&connection, quiet)? else {
+                return Ok(());
+            };
+            let Some(_permit) = limits.admit(&connection, node_id, quiet) else {
+                return Ok(());
+            };
+            log(quiet, format!("New datagram connection from {node_id}"));
+            let guard = metrics.connected(node_id);
 
+            let summary = count_datagrams(&connection, guard.client()).await?;
+            let (received, bytes) = (summary.received, summary.bytes);
+            log(quiet, format!("Total datagrams received: {received} ({bytes} bytes)"));
             connection.closed().await;
             Ok(())
         })
     }
-}
\ No newline at end of file
+}
+
+/// Counts incoming datagrams until the client announces how many it sent, then answers with
+/// the [`DatagramSummary`].
+async fn count_datagrams(
+    connection: &Connection,
+    client: &ClientMetrics,
+) -> Result<DatagramSummary> {
+    let mut stats = DatagramStats::new();
+    let control = async {
+        let (send, mut recv) = connection.accept_bi().await?;
+        let mut buf = [0u8; 8];
+        recv.read_exact(&mut buf).await?;
+        anyhow::Ok((send, u64::from_be_bytes(buf)))
+    };
+    tokio::pin!(control);
+    let (mut send, sent) = loop {
+        tokio::select! {
+            res = &mut control => break res?,
+            datagram = connection.read_datagram() => stats.record(&datagram?, client)?,
+        }
+    };
+
+    // Datagrams sent right before the count may still arrive after it
+    let deadline = Instant::now() + DATAGRAM_GRACE;
+    while (stats.seqs.len() as u64) < sent {
+        match timeout_at(deadline, connection.read_datagram()).await {
+            Ok(datagram) => stats.record(&datagram?, client)?,
+            Err(_) => break,
+        }
+    }
+    let summary = stats.summary(sent);
+    summary.write(&mut send).await?;
+    send.finish()?;
+    Ok(summary)
+}
+
+/// Arrival statistics of the datagrams of one benchmark.
+struct DatagramStats {
+    start: Instant,
+    /// Sequence numbers in order of arrival.
+    seqs: Vec<u64>,
+    highest_seq: Option<u64>,
+    bytes: u64,
+    reordered: u64,
+    /// Transit time of the previous datagram, offset by the difference between both clocks.
+    last_transit_us: Option<i64>,
+    jitter_us: f64,
+}
+
+impl DatagramStats {
+    fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            seqs: Vec::new(),
+            highest_seq: None,
+            bytes: 0,
+            reordered: 0,
+            last_transit_us: None,
+            jitter_us: 0.0,
+        }
+    }
+
+    fn record(&mut self, datagram: &[u8], client: &ClientMetrics) -> Result<()> {
+        let header = DatagramHeader::decode(datagram)?;
+        client.add_received(datagram.len());
+        match self.highest_seq {
+            Some(highest) if highest > header.seq => self.reordered += 1,
+            _ => self.highest_seq = Some(header.seq),
+        }
+        self.seqs.push(header.seq);
+        self.bytes += datagram.len() as u64;
+
+        // The clocks are not synchronized, but their offset cancels out in the transit time
+        // differences the jitter is based on
+        let transit = self.start.elapsed().as_micros() as i64 - header.sent_at_us as i64;
+        if let Some(last) = self.last_transit_us {
+            let d = (transit - last).abs() as f64;
+            self.jitter_us += (d - self.jitter_us) / 16.0;
+        }
+        self.last_transit_us = Some(transit);
+        Ok(())
+    }
+
+    /// Summarizes the arrivals, given that the client sent datagrams `0..sent`.
+    fn summary(mut self, sent: u64) -> DatagramSummary {
+        self.seqs.sort_unstable();
+        self.seqs.dedup();
+        let (mut loss_runs, mut max_loss_run) = (0, 0);
+        let mut expected = 0;
+        for seq in self.seqs.iter().copied().chain([sent]) {
+            if seq > expected {
+                loss_runs += 1;
+                max_loss_run = max_loss_run.max(seq - expected);
+            }
+            expected = seq + 1;
+        }
+        DatagramSummary {
+            received: self.seqs.len() as u64,
+            bytes: self.bytes,
+            reordered: self.reordered,
+            loss_runs,
+            max_loss_run,
+            jitter_us: self.jitter_us as u64,
+        }
+    }
+}
+
+/// Logs a line at info level unless `quiet` is set.
+fn log(quiet: bool, line: String) {
+    if !quiet {
+        tracing::info!("{line}");
+    }
+}
+
+/// Counts a blob in [`ClientMetrics::blobs`] while it is held for the client.
+struct BlobHold(Arc<ClientMetrics>);
+
+impl BlobHold {
+    /// Counts another blob of `client`, or returns `None` if it already holds `max`.
+    fn new(client: &Arc<ClientMetrics>, max: u64) -> Option<Self> {
+        let held = client.blobs.fetch_add(1, Ordering::Relaxed);
+        let hold = Self(client.clone());
+        (held < max).then_some(hold)
+    }
+}
+
+impl Drop for BlobHold {
+    fn drop(&mut self) {
+        self.0.blobs.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Generates the payload of a blob in chunks of [`BLOB_CHUNK_SIZE`], so that the store is the
+/// only place that holds all of it.
+fn blob_chunks(
+    size: u64,
+    pattern: Pattern,
+    seed: u64,
+) -> impl Stream<Item = std::io::Result<Bytes>> + Send + Unpin + 'static {
+    let mut payload = Payload::new(pattern, seed);
+    let chunks = (0..size).step_by(BLOB_CHUNK_SIZE as usize).map(move |offset| {
+        let mut chunk = vec![0u8; (size - offset).min(BLOB_CHUNK_SIZE) as usize];
+        payload.fill(&mut chunk);
+        Ok(Bytes::from(chunk))
+    });
+    n0_future::stream::iter(chunks)
+}
+
+/// Microseconds elapsed since `started`, as reported in a [`ServerReport`].
+fn micros(started: Instant) -> u64 {
+    started.elapsed().as_micros() as u64
+}
+
+/// Serves a single benchmark stream according to the client's [`Request`], returning a line
+/// summarizing what was transferred. Blobs and gossip are rejected unless `services` has them.
+/// `stats` reads the statistics of the connection the stream belongs to.
+async fn handle_stream(
+    stats: impl Fn() -> ConnectionStats,
+    mut send: SendStream,
+    mut recv: RecvStream,
+    client: Arc<ClientMetrics>,
+    services: Services,
+) -> Result<String> {
+    let request = read_request(&mut send, &mut recv, &services).await?;
+    serve_request(request, stats, send, recv, client, services).await
+}
+
+/// Serves a stream the client opened before the handshake completed, like [`handle_stream`].
+///
+/// Pings are answered right away as 0.5-RTT data and counted in `early`, as the client is not
+/// authenticated yet, which is why only unrestricted servers take such streams. Every other
+/// request waits until the client is `admitted`, and fails if it is not.
+async fn handle_early_stream(
+    stats: impl Fn() -> ConnectionStats,
+    mut send: SendStream,
+    mut recv: RecvStream,
+    early: Arc<ClientMetrics>,
+    mut admitted: watch::Receiver<Option<Arc<ClientMetrics>>>,
+    services: Services,
+) -> Result<String> {
+    let request = read_request(&mut send, &mut recv, &services).await?;
+    let client = match request {
+        Request::Ping { .. } => early,
+        _ => {
+            let admitted = admitted.wait_for(Option::is_some).await;
+            admitted.ok().and_then(|client| client.clone()).context("The client was not admitted")?
+        }
+    };
+    serve_request(request, stats, send, recv, client, services).await
+}
+
+/// Reads and validates the [`Request`] of a stream, rejecting it on the stream if it is invalid.
+async fn read_request(
+    send: &mut SendStream,
+    recv: &mut RecvStream,
+    services: &Services,
+) -> Result<Request> {
+    let request = Request::read(recv).await.and_then(|request| {
+        request.validate()?;
+        match request {
+            Request::Blob { .. } if services.blobs.is_none() => {
+                anyhow::bail!("Blobs are not served here, start the server with --blobs")
+            }
+            Request::Blob { size, .. } if size > services.blob_limits.max_size => {
+                anyhow::bail!("Blobs are limited to {} bytes here", services.blob_limits.max_size)
+            }
+            Request::Gossip { .. } if services.gossip.is_none() => {
+                anyhow::bail!("Gossip is not served here, start the server with --gossip")
+            }
+            _ => Ok(request),
+        }
+    });
+    match request {
+        Ok(request) => Ok(request),
+        Err(err) => {
+            Response::Rejected(format!("{err:#}")).write(send).await?;
+            send.finish()?;
+            Err(err)
+        }
+    }
+}
+
+/// Accepts a valid `request` and serves it, see [`handle_stream`].
+async fn serve_request(
+    request: Request,
+    stats: impl Fn() -> ConnectionStats,
+    mut send: SendStream,
+    mut recv: RecvStream,
+    client: Arc<ClientMetrics>,
+    services: Services,
+) -> Result<String> {
+    // Blobs are counted before accepting, so that a client holding too many is rejected
+    let _blob = match request {
+        Request::Blob { .. } => match BlobHold::new(&client, services.blob_limits.per_client) {
+            Some(hold) => Some(hold),
+            None => {
+                let limit = services.blob_limits.per_client;
+                let err = anyhow::anyhow!("At most {limit} blobs are held per client");
+                Response::Rejected(format!("{err:#}")).write(&mut send).await?;
+                send.finish()?;
+                return Err(err);
+            }
+        },
+        _ => None,
+    };
+    Response::Accepted.write(&mut send).await?;
+
+    let summary = match request {
+        Request::Transfer(transfer) => {
+            let Transfer { direction, size, verify, .. } = transfer;
+            let chunk_size = transfer.chunk_size as usize;
+            let before = TransportStats::from_stats(&stats());
+            let mut report = ServerReport { started_us: wall_clock_us(), ..Default::default() };
+            let mut payload = Payload::new(transfer.pattern, transfer.seed);
+            let mut pacer = Pacer::new(transfer.bitrate, chunk_size);
+            // Hashes of the data sent and received, reported in the trailer when verifying
+            let sends = matches!(direction, Direction::Download | Direction::Both);
+            let receives = matches!(direction, Direction::Upload | Direction::Both);
+            let mut sent_hash = (verify && sends).then(blake3::Hasher::new);
+            let mut received_hash = (verify && receives).then(blake3::Hasher::new);
+            let summary = match direction {
+                Direction::Upload => {
+                    let hash = received_hash.as_mut();
+                    let started = Instant::now();
+                    let received = sink_payload(&mut recv, chunk_size, &client, hash).await?;
+                    (report.received, report.receive_us) = (received, micros(started));
+                    // Send small acknowledgment
+                    send.write_all(ACK).await?;
+                    format!("Total bytes received: {received}")
+                }
+                Direction::Download => {
+                    let hash = sent_hash.as_mut();
+                    let started = Instant::now();
+                    let sent = send_payload(
+                        &mut send,
+                        size,
+                        chunk_size,
+                        &mut payload,
+                        &mut pacer,
+                        &client,
+                        hash,
+                    )
+                    .await?;
+                    (report.sent, report.send_us) = (sent, micros(started));
+                    format!("Total bytes sent: {sent}")
+                }
+                Direction::Both => {
+                    // Sink the client's payload while streaming ours back
+                    let started = Instant::now();
+                    let (received, sent) = tokio::try_join!(
+                        async {
+                            let hash = received_hash.as_mut();
+                            let received =
+                                sink_payload(&mut recv, chunk_size, &client, hash).await?;
+                            anyhow::Ok((received, micros(started)))
+                        },
+                        async {
+                            let (payload, pacer, hash) =
+                                (&mut payload, &mut pacer, sent_hash.as_mut());
+                            let sent = send_payload(
+                                &mut send, size, chunk_size, payload, pacer, &client, hash,
+                            )
+                            .await?;
+                            anyhow::Ok((sent, micros(started)))
+                        },
+                    )?;
+                    ((report.received, report.receive_us), (report.sent, report.send_us)) =
+                        (received, sent);
+                    format!("Total bytes received: {}, sent: {}", received.0, sent.0)
+                }
+                Direction::Echo => {
+                    let started = Instant::now();
+                    let echoed = echo_payload(&mut send, &mut recv, chunk_size, &client).await?;
+                    let elapsed = micros(started);
+                    (report.received, report.receive_us) = (echoed, elapsed);
+                    (report.sent, report.send_us) = (echoed, elapsed);
+                    format!("Total bytes echoed: {echoed}")
+                }
+            };
+            report.finished_us = wall_clock_us();
+            for hasher in [sent_hash, received_hash].into_iter().flatten() {
+                send.write_all(hasher.finalize().as_bytes()).await?;
+            }
+            if transfer.report {
+                let stats = TransportStats::from_stats(&stats()).since(&before);
+                report.rtt_us = (stats.rtt_ms * 1000.0) as u64;
+                report.cwnd = stats.cwnd;
+                report.congestion_events = stats.congestion_events;
+                report.sent_packets = stats.sent_packets;
+                report.lost_packets = stats.lost_packets;
+                report.lost_bytes = stats.lost_bytes;
+                report.mtu = stats.mtu.into();
+                send.write_all(&report.encode()).await?;
+            }
+            summary
+        }
+        Request::Ping { size } => {
+            let pings = echo_pings(&mut send, &mut recv, size as usize, &client).await?;
+            format!("Answered {pings} pings")
+        }
+        Request::Rpc { response_size } => {
+            let requests = answer_rpcs(&mut send, &mut recv, response_size, &client).await?;
+            format!("Answered {requests} RPC requests")
+        }
+        Request::Messages => {
+            let messages = count_messages(&mut recv, &client).await?;
+            send.write_all(&messages.to_be_bytes()).await?;
+            format!("Received {messages} messages")
+        }
+        Request::Clock => {
+            let probes = answer_clock_probes(&mut send, &mut recv, &client).await?;
+            format!("Answered {probes} clock probes")
+        }
+        Request::Blob { size, pattern, seed } => {
+            let store = services.blobs.expect("checked before accepting");
+            let chunks = blob_chunks(size, pattern, seed);
+            let progress = IgnoreProgressSender::default();
+            let (tag, _) = store.import_stream(chunks, BlobFormat::Raw, progress).await?;
+            send.write_all(tag.hash().as_bytes()).await?;
+            // The temporary tag keeps the blob from being collected until the client is done
+            recv.read_to_end(0).await?;
+            format!("Served blob {} of {size} bytes", tag.hash().to_hex())
+        }
+        Request::Gossip { topic } => {
+            let gossip = services.gossip.expect("checked before accepting");
+            let topic = TopicId::from_bytes(topic);
+            let mut events = gossip.subscribe(topic, Vec::new())?;
+            // Stay in the swarm, counting the messages passing through, until the client is done
+            let mut done = pin!(recv.read_to_end(0));
+            let mut received = 0u64;
+            loop {
+                tokio::select! {
+                    res = &mut done => {
+                        res?;
+                        break;
+                    }
+                    event = events.next() => match event {
+                        Some(Ok(Event::Gossip(GossipEvent::Received(_)))) => received += 1,
+                        Some(Ok(_)) => {}
+                        Some(Err(err)) => return Err(err.into()),
+                        None => {
+                            done.await?;
+                            break;
+                        }
+                    },
+                }
+            }
+            format!("Left gossip topic {topic} after {received} messages")
+        }
+    };
+    send.finish()?;
+    Ok(summary)
+}
+
+/// Reads the client's payload until it finishes the stream, discarding the data.
+///
+/// Uses a single chunk-sized buffer, so memory use is independent of the payload size. Returns
+/// the number of bytes received.
+async fn sink_payload(
+    recv: &mut RecvStream,
+    chunk_size: usize,
+    client: &ClientMetrics,
+    mut hasher: Option<&mut blake3::Hasher>,
+) -> Result<u64> {
+    let mut buf = vec![0u8; chunk_size];
+    let mut received = 0;
+    while let Some(n) = recv.read(&mut buf).await? {
+        received += n as u64;
+        client.add_received(n);
+        if let Some(hasher) = hasher.as_mut() {
+            hasher.update(&buf[..n]);
+        }
+    }
+    Ok(received)
+}
+
+/// Writes every chunk of the client's payload back until it finishes the stream.
+///
+/// If the client stops reading the echo, the rest of the payload is discarded. Returns the number
+/// of bytes received.
+async fn echo_payload(
+    send: &mut SendStream,
+    recv: &mut RecvStream,
+    chunk_size: usize,
+    client: &ClientMetrics,
+) -> Result<u64> {
+    let mut buf = vec![0u8; chunk_size];
+    let mut received = 0;
+    while let Some(n) = recv.read(&mut buf).await? {
+        received += n as u64;
+        client.add_received(n);
+        match send.write_all(&buf[..n]).await {
+            Ok(()) => client.add_sent(n),
+            Err(WriteError::Stopped(_)) => {
+                return Ok(received + sink_payload(recv, chunk_size, client, None).await?);
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(received)
+}
+
+/// Streams `size` bytes of `payload` to the client in chunks of `chunk_size`.
+///
+/// The client may stop the stream early, which is how unbounded transfers end. Returns the
+/// number of bytes written.
+async fn send_payload(
+    send: &mut SendStream,
+    size: u64,
+    chunk_size: usize,
+    payload: &mut Payload,
+    pacer: &mut Pacer,
+    client: &ClientMetrics,
+    mut hasher: Option<&mut blake3::Hasher>,
+) -> Result<u64> {
+    let mut chunk = vec![0u8; chunk_size];
+    let mut sent = 0;
+    while sent < size {
+        let n = (size - sent).min(chunk_size as u64) as usize;
+        payload.fill(&mut chunk[..n]);
+        pacer.wait(n).await;
+        match send.write_all(&chunk[..n]).await {
+            Ok(()) => {
+                sent += n as u64;
+                client.add_sent(n);
+                if let Some(hasher) = hasher.as_mut() {
+                    hasher.update(&chunk[..n]);
+                }
+            }
+            Err(WriteError::Stopped(_)) => break,
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(sent)
+}
+
+/// Echoes fixed-size ping messages back to the client until it finishes the stream.
+///
+/// Returns the number of messages answered.
+async fn echo_pings(
+    send: &mut SendStream,
+    recv: &mut RecvStream,
+    size: usize,
+    client: &ClientMetrics,
+) -> Result<u64> {
+    let mut buf = vec![0u8; size];
+    let mut pings = 0;
+    loop {
+        match recv.read_exact(&mut buf).await {
+            Ok(()) => {}
+            Err(ReadExactError::FinishedEarly(0)) => return Ok(pings),
+            Err(err) => return Err(err.into()),
+        }
+        client.add_received(size);
+        send.write_all(&buf).await?;
+        client.add_sent(size);
+        pings += 1;
+    }
+}
+
+/// Answers every timestamp probe of the client with a [`ClockReply`] until it finishes the
+/// stream, returning the number of probes answered.
+async fn answer_clock_probes(
+    send: &mut SendStream,
+    recv: &mut RecvStream,
+    client: &ClientMetrics,
+) -> Result<u64> {
+    let mut buf = [0u8; 8];
+    let mut probes = 0;
+    loop {
+        match recv.read_exact(&mut buf).await {
+            Ok(()) => {}
+            Err(ReadExactError::FinishedEarly(0)) => return Ok(probes),
+            Err(err) => return Err(err.into()),
+        }
+        let server_received_us = wall_clock_us();
+        client.add_received(buf.len());
+        let client_sent_us = u64::from_be_bytes(buf);
+        let server_sent_us = wall_clock_us();
+        let reply = ClockReply { client_sent_us, server_received_us, server_sent_us };
+        send.write_all(&reply.encode()).await?;
+        client.add_sent(ClockReply::LEN);
+        probes += 1;
+    }
+}
+
+/// Answers every RPC message of the client with one of `response_size` bytes until it finishes
+/// the stream, returning the number of requests answered.
+async fn answer_rpcs(
+    send: &mut SendStream,
+    recv: &mut RecvStream,
+    response_size: u32,
+    client: &ClientMetrics,
+) -> Result<u64> {
+    let response = rpc_message(response_size);
+    let mut buf = Vec::new();
+    let mut requests = 0;
+    while let Some(len) = read_rpc_message(recv, &mut buf).await? {
+        client.add_received(4 + len as usize);
+        send.write_all(&response).await?;
+        client.add_sent(response.len());
+        requests += 1;
+    }
+    Ok(requests)
+}
+
+/// Reads the client's framed messages one by one until it finishes the stream, returning their
+/// number.
+async fn count_messages(recv: &mut RecvStream, client: &ClientMetrics) -> Result<u64> {
+    let mut buf = Vec::new();
+    let mut messages = 0;
+    while let Some(len) = read_rpc_message(recv, &mut buf).await? {
+        client.add_received(4 + len as usize);
+        messages += 1;
+    }
+    Ok(messages)
+}
+
+/// Serves the transfers of the client's `--baseline tcp` on plain TCP connections accepted from
+/// `listener`, each carrying one stream of a transfer. Logs every transfer unless `quiet` is set.
+///
+/// Only uploads, downloads and transfers in both directions are served, without verification.
+pub async fn serve_tcp_baseline(listener: TcpListener, quiet: bool) -> Result<()> {
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        tokio::spawn(async move {
+            match handle_tcp_stream(stream).await {
+                Ok(summary) => log(quiet, format!("TCP baseline: {summary}")),
+                Err(err) => log(quiet, format!("TCP baseline from {addr} failed: {err:#}")),
+            }
+        });
+    }
+}
+
+/// Serves a single TCP baseline stream, returning a line summarizing what was transferred.
+async fn handle_tcp_stream(stream: TcpStream) -> Result<String> {
+    stream.set_nodelay(true)?;
+    let (mut recv, mut send) = stream.into_split();
+    let transfer = match Request::read(&mut recv).await.and_then(|r| r.validate().map(|()| r)) {
+        Ok(Request::Transfer(transfer))
+            if transfer.direction != Direction::Echo && !transfer.verify =>
+        {
+            transfer
+        }
+        Ok(_) => {
+            let reason = "The TCP baseline only serves uploads, downloads and transfers in both \
+                          directions, without echo or --verify";
+            Response::Rejected(reason.to_string()).write(&mut send).await?;
+            anyhow::bail!("{reason}");
+        }
+        Err(err) => {
+            Response::Rejected(format!("{err:#}")).write(&mut send).await?;
+            return Err(err);
+        }
+    };
+    Response::Accepted.write(&mut send).await?;
+
+    let Transfer { direction, size, .. } = transfer;
+    let chunk_size = transfer.chunk_size as usize;
+    let mut payload = Payload::new(transfer.pattern, transfer.seed);
+    let mut pacer = Pacer::new(transfer.bitrate, chunk_size);
+    let summary = match direction {
+        Direction::Upload => {
+            let received = sink_tcp(&mut recv, chunk_size).await?;
+            send.write_all(ACK).await?;
+            format!("Total bytes received: {received}")
+        }
+        Direction::Download => {
+            let sent = source_tcp(&mut send, size, chunk_size, &mut payload, &mut pacer).await?;
+            format!("Total bytes sent: {sent}")
+        }
+        Direction::Both => {
+            let (received, sent) = tokio::try_join!(
+                sink_tcp(&mut recv, chunk_size),
+                source_tcp(&mut send, size, chunk_size, &mut payload, &mut pacer),
+            )?;
+            format!("Total bytes received: {received}, sent: {sent}")
+        }
+        Direction::Echo => unreachable!("echo transfers are rejected above"),
+    };
+    // The client may already be gone after an unbounded download
+    send.shutdown().await.ok();
+    Ok(summary)
+}
+
+/// Reads from `recv` until the client shuts down its side, returning the number of bytes read.
+async fn sink_tcp(recv: &mut (impl AsyncRead + Unpin), chunk_size: usize) -> Result<u64> {
+    let mut buf = vec![0u8; chunk_size];
+    let mut received = 0;
+    loop {
+        match recv.read(&mut buf).await? {
+            0 => return Ok(received),
+            n => received += n as u64,
+        }
+    }
+}
+
+/// Writes `size` bytes of `payload` to `send`, or until the client closes the connection.
+/// Returns the number of bytes written.
+async fn source_tcp(
+    send: &mut (impl AsyncWrite + Unpin),
+    size: u64,
+    chunk_size: usize,
+    payload: &mut Payload,
+    pacer: &mut Pacer,
+) -> Result<u64> {
+    let mut chunk = vec![0u8; chunk_size];
+    let mut sent = 0;
+    while sent < size {
+        let n = (size - sent).min(chunk_size as u64) as usize;
+        payload.fill(&mut chunk[..n]);
+        pacer.wait(n).await;
+        match send.write_all(&chunk[..n]).await {
+            Ok(()) => sent += n as u64,
+            // The client ends unbounded downloads by closing the connection
+            Err(err) => match err.kind() {
+                ErrorKind::BrokenPipe | ErrorKind::ConnectionReset => break,
+                _ => return Err(err.into()),
+            },
+        }
+    }
+    Ok(sent)
+}
+
+/// Serves the client's `--baseline quic` on a plain quinn endpoint, handling streams exactly
+/// like iroh connections. Logs every connection unless `quiet` is set.
+pub async fn serve_quic_baseline(endpoint: quinn::Endpoint, quiet: bool) {
+    while let Some(incoming) = endpoint.accept().await {
+        tokio::spawn(async move {
+            let addr = incoming.remote_address();
+            if let Err(err) = serve_quic_connection(incoming, quiet).await {
+                log(quiet, format!("QUIC baseline from {addr} failed: {err:#}"));
+            }
+        });
+    }
+}
+
+/// Serves the streams of one QUIC baseline connection until the client closes it.
+async fn serve_quic_connection(incoming: quinn::Incoming, quiet: bool) -> Result<()> {
+    let connection = incoming.await?;
+    let addr = connection.remote_address();
+    log(quiet, format!("New QUIC baseline connection from {addr}"));
+    // Baseline traffic is not attributed to any iroh client
+    let client = Arc::new(ClientMetrics::default());
+    let mut streams = JoinSet::new();
+    loop {
+        let (send, recv) = match connection.accept_bi().await {
+            Ok(stream) => stream,
+            Err(ConnectionError::ApplicationClosed(_)) => break,
+            Err(err) => return Err(err.into()),
+        };
+        let connection = connection.clone();
+        let stats = move || connection.stats();
+        streams.spawn(handle_stream(stats, send, recv, client.clone(), Services::default()));
+    }
+    while let Some(res) = streams.join_next().await {
+        match res? {
+            Ok(summary) => log(quiet, format!("QUIC baseline: {summary}")),
+            Err(err) => log(quiet, format!("QUIC baseline stream from {addr} failed: {err:#}")),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,258 @@
+//! Local SQLite database of benchmark results, appended to by `client --db <path>` and queried
+//! by `client history`.
+//!
+//! Every report is stored as a row of `reports`, keyed by its run ID and the server's node ID,
+//! together with its full JSON. Its headline metrics go into `metrics` and every individual
+//! sample, such as the bandwidth of one iteration, into `samples`.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use rusqlite::{Connection, params};
+
+use crate::report::{Benchmark, BenchmarkReport};
+use crate::units::format_size;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS reports (
+    id INTEGER PRIMARY KEY,
+    run_id TEXT NOT NULL,
+    server_node_id TEXT NOT NULL,
+    client_node_id TEXT NOT NULL,
+    name TEXT,
+    mode TEXT NOT NULL,
+    started_at TEXT NOT NULL,
+    finished_at TEXT NOT NULL,
+    iroh_version TEXT NOT NULL,
+    report TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS reports_by_server ON reports (server_node_id, started_at);
+CREATE TABLE IF NOT EXISTS metrics (
+    report_id INTEGER NOT NULL REFERENCES reports (id),
+    name TEXT NOT NULL,
+    unit TEXT NOT NULL,
+    value REAL NOT NULL
+);
+CREATE TABLE IF NOT EXISTS samples (
+    report_id INTEGER NOT NULL REFERENCES reports (id),
+    name TEXT NOT NULL,
+    unit TEXT NOT NULL,
+    value REAL NOT NULL
+);
+";
+
+/// A results database, created on first use.
+pub struct ResultStore {
+    db: Connection,
+}
+
+/// A headline metric of one past report, as returned by [`ResultStore::history`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub started_at: String,
+    pub run_id: String,
+    pub server_node_id: String,
+    /// Scenario test name, or the mode of the benchmark.
+    pub test: String,
+    pub metric: String,
+    pub unit: String,
+    pub value: f64,
+}
+
+impl ResultStore {
+    /// Opens the database at `path`, creating it and its tables if needed.
+    pub fn open(path: &Path) -> Result<Self> {
+        let db = Connection::open(path)
+            .with_context(|| format!("Failed to open the database {}", path.display()))?;
+        db.execute_batch(SCHEMA)?;
+        Ok(Self { db })
+    }
+
+    /// Appends a report with its metrics and samples.
+    pub fn append(&mut self, report: &BenchmarkReport) -> Result<()> {
+        let tx = self.db.transaction()?;
+        tx.execute(
+            "INSERT INTO reports (run_id, server_node_id, client_node_id, name, mode, started_at,
+                finished_at, iroh_version, report)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                report.run.run_id,
+                report.server_node_id,
+                report.client_node_id,
+                report.name,
+                report.benchmark.mode(),
+                report.started_at,
+                report.finished_at,
+                report.run.iroh_version,
+                serde_json::to_string(report)?,
+            ],
+        )?;
+        let id = tx.last_insert_rowid();
+        {
+            let mut insert = tx.prepare(
+                "INSERT INTO metrics (report_id, name, unit, value) VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            for metric in report.benchmark.metrics() {
+                insert.execute(params![id, metric.name, metric.unit, metric.value])?;
+            }
+            let mut insert = tx.prepare(
+                "INSERT INTO samples (report_id, name, unit, value) VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            for (name, unit, value) in report.benchmark.samples() {
+                insert.execute(params![id, name, unit, value])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The headline metrics of the latest `limit` reports of servers whose node ID starts with
+    /// `node_id`, oldest first, optionally only those whose name contains `metric`.
+    pub fn history(
+        &self,
+        node_id: &str,
+        metric: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<HistoryEntry>> {
+        let mut query = self.db.prepare(
+            "SELECT r.started_at, r.run_id, r.server_node_id, COALESCE(r.name, r.mode),
+                m.name, m.unit, m.value
+            FROM (
+                SELECT * FROM reports WHERE server_node_id LIKE ?1 || '%'
+                ORDER BY started_at DESC, id DESC LIMIT ?3
+            ) r
+            JOIN metrics m ON m.report_id = r.id
+            WHERE ?2 IS NULL OR instr(m.name, ?2) > 0
+            ORDER BY r.started_at, r.id",
+        )?;
+        let rows = query.query_map(params![node_id, metric, limit as i64], |row| {
+            Ok(HistoryEntry {
+                started_at: row.get(0)?,
+                run_id: row.get(1)?,
+                server_node_id: row.get(2)?,
+                test: row.get(3)?,
+                metric: row.get(4)?,
+                unit: row.get(5)?,
+                value: row.get(6)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+}
+
+impl Benchmark {
+    /// Every individual sample of the benchmark as name, unit and value, e.g. the bandwidth of
+    /// every iteration or every round-trip time.
+    pub fn samples(&self) -> Vec<(String, &'static str, f64)> {
+        let mut samples = Vec::new();
+        let mut push = |name: &str, unit: &'static str, values: &[f64]| {
+            samples.extend(values.iter().map(|value| (name.to_string(), unit, *value)));
+        };
+        match self {
+            Benchmark::Bandwidth { results, .. } => {
+                for result in results {
+                    let size = format_size(result.size);
+                    for it in &result.iterations {
+                        let bandwidth = &it.aggregate;
+                        push(&format!("upload {size}"), "Mbit/s", bandwidth.upload.as_slice());
+                        push(&format!("download {size}"), "Mbit/s", bandwidth.download.as_slice());
+                    }
+                }
+            }
+            Benchmark::ChunkSweep { results, .. } => {
+                for result in results {
+                    let chunk_size = format_size(result.chunk_size);
+                    let upload = format!("upload chunk {chunk_size}");
+                    let download = format!("download chunk {chunk_size}");
+                    for sample in &result.iterations {
+                        push(&upload, "Mbit/s", sample.upload.as_slice());
+                        push(&download, "Mbit/s", sample.download.as_slice());
+                    }
+                }
+            }
+            Benchmark::StreamSweep { results, .. } => {
+                for result in results {
+                    let upload = format!("upload {} streams", result.streams);
+                    let download = format!("download {} streams", result.streams);
+                    for sample in &result.iterations {
+                        push(&upload, "Mbit/s", sample.upload.as_slice());
+                        push(&download, "Mbit/s", sample.download.as_slice());
+                    }
+                }
+            }
+            Benchmark::Timed { per_connection, .. } => {
+                for sample in per_connection {
+                    push("upload", "Mbit/s", sample.upload.as_slice());
+                    push("download", "Mbit/s", sample.download.as_slice());
+                }
+            }
+            Benchmark::Datagram { received_mbps, loss_percent, jitter_ms, .. } => {
+                push("received", "Mbit/s", &[*received_mbps]);
+                push("loss", "%", &[*loss_percent]);
+                push("jitter", "ms", &[*jitter_ms]);
+            }
+            Benchmark::Ping { rtts_ms, .. } => push("round trip", "ms", rtts_ms),
+            Benchmark::Discover { discovery_ms, handshake_ms, .. } => {
+                push("discovery", "ms", discovery_ms);
+                push("handshake", "ms", handshake_ms);
+            }
+            Benchmark::Connect { cold_ms, warm_ms, .. }
+            | Benchmark::Resume { cold_ms, warm_ms, .. } => {
+                push("cold", "ms", cold_ms);
+                push("warm", "ms", warm_ms);
+            }
+            Benchmark::Streams { open_ms, first_byte_ms, .. } => {
+                push("open", "ms", open_ms);
+                push("first byte", "ms", first_byte_ms);
+            }
+            Benchmark::HolePunch { trials, .. } => {
+                for trial in trials {
+                    push("time to direct", "ms", trial.time_to_direct_ms.as_slice());
+                }
+            }
+            Benchmark::Soak { intervals, .. } => {
+                for interval in intervals {
+                    push("upload", "Mbit/s", interval.bandwidth.upload.as_slice());
+                    push("download", "Mbit/s", interval.bandwidth.download.as_slice());
+                }
+            }
+            Benchmark::Bursts { bursts, .. } => {
+                for burst in bursts {
+                    push("burst upload", "Mbit/s", burst.bandwidth.upload.as_slice());
+                    push("burst download", "Mbit/s", burst.bandwidth.download.as_slice());
+                }
+            }
+            Benchmark::Gossip { latency_ms, .. } => push("delivery", "ms", latency_ms),
+            Benchmark::Bufferbloat { idle_ms, loaded_ms, .. } => {
+                push("idle", "ms", idle_ms);
+                push("loaded", "ms", loaded_ms);
+            }
+            Benchmark::Rpc { latency_ms, requests_per_sec, .. } => {
+                push("request", "ms", latency_ms);
+                push("requests", "req/s", &[*requests_per_sec]);
+            }
+            Benchmark::Delay { rtts_ms, forward_ms, backward_ms, .. } => {
+                push("round trip", "ms", rtts_ms);
+                push("forward", "ms", forward_ms);
+                push("backward", "ms", backward_ms);
+            }
+            Benchmark::Migrate { throughput, dip_ms, .. } => {
+                let mbps: Vec<f64> = throughput.iter().map(|sample| sample.mbps).collect();
+                push("throughput", "Mbit/s", &mbps);
+                push("dip", "ms", dip_ms.as_slice());
+            }
+            Benchmark::Messages { messages_per_sec, goodput_mbps, .. } => {
+                push("messages", "msg/s", &[*messages_per_sec]);
+                push("goodput", "Mbit/s", &[*goodput_mbps]);
+            }
+            Benchmark::Blobs { results, .. } => {
+                for result in results {
+                    let size = format_size(result.size);
+                    push(&format!("fetch {size}"), "Mbit/s", &result.download_mbps);
+                    push(&format!("fetch time {size}"), "ms", &result.fetch_ms);
+                }
+            }
+        }
+        samples
+    }
+}
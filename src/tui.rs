@@ -0,0 +1,245 @@
+//! Live dashboard of a running benchmark, shown by the client with `--tui`.
+//!
+//! The dashboard samples the [`Progress`] of the benchmark and the endpoint's view of the server
+//! a few times per second, and draws the throughput, the RTT, the connection type, the progress
+//! of every stream and the latest status messages.
+
+use std::{future::Future, sync::Arc, time::Duration};
+
+use anyhow::{Result, bail};
+use iroh::{Endpoint, NodeId};
+use ratatui::{
+    DefaultTerminal, Frame,
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Style},
+    symbols::Marker,
+    text::Line,
+    widgets::{Axis, Block, Chart, Dataset, GraphType, LineGauge, Paragraph, Sparkline},
+};
+use tokio::time::{Instant, interval};
+
+use crate::progress::{Counters, Progress};
+use crate::report::path_label;
+use crate::units::format_size;
+
+/// How often the dashboard samples the counters and redraws.
+const TICK: Duration = Duration::from_millis(250);
+
+/// Seconds of throughput history shown in the chart.
+const WINDOW_SECS: f64 = 60.0;
+
+/// Runs `benchmark` while showing the dashboard, and restores the terminal once it completes.
+///
+/// Pressing `q`, `Esc` or `Ctrl-C` aborts the benchmark.
+pub async fn run<T>(
+    endpoint: &Endpoint,
+    node_id: NodeId,
+    title: &str,
+    progress: Arc<Progress>,
+    benchmark: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let mut terminal = ratatui::try_init()?;
+    let res = show(&mut terminal, endpoint, node_id, title, &progress, benchmark).await;
+    ratatui::restore();
+    res
+}
+
+async fn show<T>(
+    terminal: &mut DefaultTerminal,
+    endpoint: &Endpoint,
+    node_id: NodeId,
+    title: &str,
+    progress: &Progress,
+    benchmark: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    tokio::pin!(benchmark);
+    let mut dashboard = Dashboard::new(title);
+    let mut ticker = interval(TICK);
+    loop {
+        tokio::select! {
+            res = &mut benchmark => return res,
+            _ = ticker.tick() => {
+                dashboard.sample(endpoint, node_id, progress);
+                terminal.draw(|frame| dashboard.draw(frame, progress))?;
+                while event::poll(Duration::ZERO)? {
+                    if let Event::Key(key) = event::read()? {
+                        let ctrl_c = key.code == KeyCode::Char('c')
+                            && key.modifiers.contains(KeyModifiers::CONTROL);
+                        let quit = matches!(key.code, KeyCode::Char('q') | KeyCode::Esc);
+                        if key.kind == KeyEventKind::Press && (ctrl_c || quit) {
+                            bail!("Benchmark aborted");
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// History of the sampled values.
+struct Dashboard {
+    title: String,
+    start: Instant,
+    /// Counters of the transfer sampled last, and their totals at that time.
+    counters: Option<Arc<Counters>>,
+    last: (u64, u64),
+    last_at: Instant,
+    /// Upload and download throughput in Mbit/s over the seconds since the start.
+    upload: Vec<(f64, f64)>,
+    download: Vec<(f64, f64)>,
+    /// Latency of the path to the server in microseconds.
+    rtts_us: Vec<u64>,
+    connection_type: &'static str,
+}
+
+impl Dashboard {
+    fn new(title: &str) -> Self {
+        let now = Instant::now();
+        Self {
+            title: title.to_string(),
+            start: now,
+            counters: None,
+            last: (0, 0),
+            last_at: now,
+            upload: Vec::new(),
+            download: Vec::new(),
+            rtts_us: Vec::new(),
+            connection_type: "none",
+        }
+    }
+
+    fn sample(&mut self, endpoint: &Endpoint, node_id: NodeId, progress: &Progress) {
+        let now = Instant::now();
+        let elapsed = (now - self.start).as_secs_f64();
+        let counters = progress.counters();
+        let same = match (&counters, &self.counters) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        };
+        if let Some(counters) = &counters {
+            let (sent, received) = (counters.sent(), counters.received());
+            // Every transfer starts counting from zero
+            let (last_sent, last_received) = if same { self.last } else { (0, 0) };
+            let secs = (now - self.last_at).as_secs_f64().max(f64::EPSILON);
+            let mbps = |bytes: u64| bytes as f64 * 8.0 / 1e6 / secs;
+            self.upload.push((elapsed, mbps(sent - last_sent)));
+            self.download.push((elapsed, mbps(received - last_received)));
+            self.last = (sent, received);
+        }
+        self.counters = counters;
+        self.last_at = now;
+
+        if let Some(info) = endpoint.remote_info(node_id) {
+            self.connection_type = path_label(&info.conn_type);
+            if let Some(latency) = info.latency {
+                self.rtts_us.push(latency.as_micros() as u64);
+            }
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame, progress: &Progress) {
+        let [header, chart, rtt, bottom] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(10),
+            Constraint::Length(5),
+            Constraint::Length(12),
+        ])
+        .areas(frame.area());
+        let [streams, log] =
+            Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)])
+                .areas(bottom);
+
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let rtt_label = match self.rtts_us.last() {
+            Some(us) => format!("{:.2} ms", *us as f64 / 1000.0),
+            None => "-".to_string(),
+        };
+        let status = format!(
+            "Elapsed {elapsed:.1} s   Path: {}   RTT: {rtt_label}   (q to abort)",
+            self.connection_type
+        );
+        let title = format!(" iroh benchmark: {} ", self.title);
+        frame.render_widget(Paragraph::new(status).block(Block::bordered().title(title)), header);
+
+        self.draw_chart(frame, chart, elapsed);
+
+        let width = rtt.width.saturating_sub(2) as usize;
+        let rtts = &self.rtts_us[self.rtts_us.len().saturating_sub(width)..];
+        let sparkline = Sparkline::default()
+            .block(Block::bordered().title(format!(" RTT ({rtt_label}) ")))
+            .data(rtts)
+            .style(Style::default().fg(Color::Yellow));
+        frame.render_widget(sparkline, rtt);
+
+        self.draw_streams(frame, streams);
+
+        let lines = progress.last_lines(log.height.saturating_sub(2) as usize);
+        let lines: Vec<Line> = lines.iter().map(|line| Line::from(line.trim())).collect();
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(" Log ")), log);
+    }
+
+    fn draw_chart(&self, frame: &mut Frame, area: Rect, elapsed: f64) {
+        let from = (elapsed - WINDOW_SECS).max(0.0);
+        let visible = |series: &[(f64, f64)]| -> Vec<(f64, f64)> {
+            series.iter().copied().filter(|(t, _)| *t >= from).collect()
+        };
+        let (upload, download) = (visible(&self.upload), visible(&self.download));
+        let max = upload.iter().chain(&download).map(|(_, mbps)| *mbps).fold(1.0, f64::max);
+        let mut datasets = Vec::new();
+        for (name, series, color) in
+            [("up", &upload, Color::Green), ("down", &download, Color::Cyan)]
+        {
+            if series.iter().any(|(_, mbps)| *mbps > 0.0) {
+                let current = series.last().map_or(0.0, |(_, mbps)| *mbps);
+                datasets.push(
+                    Dataset::default()
+                        .name(format!("{name} {current:.1} Mbit/s"))
+                        .marker(Marker::Braille)
+                        .graph_type(GraphType::Line)
+                        .style(Style::default().fg(color))
+                        .data(series),
+                );
+            }
+        }
+        let chart = Chart::new(datasets)
+            .block(Block::bordered().title(" Throughput (Mbit/s) "))
+            .x_axis(
+                Axis::default()
+                    .bounds([from, elapsed.max(1.0)])
+                    .labels([format!("{from:.0}s"), format!("{elapsed:.0}s")]),
+            )
+            .y_axis(
+                Axis::default()
+                    .bounds([0.0, max * 1.1])
+                    .labels(["0".to_string(), format!("{:.0}", max * 1.1)]),
+            );
+        frame.render_widget(chart, area);
+    }
+
+    fn draw_streams(&self, frame: &mut Frame, area: Rect) {
+        let block = Block::bordered().title(" Streams ");
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+        let Some(counters) = &self.counters else {
+            return;
+        };
+        let streams = counters.streams();
+        let rows = Layout::vertical(vec![Constraint::Length(1); inner.height as usize]).split(inner);
+        for (i, (stream, row)) in streams.iter().zip(rows.iter()).enumerate() {
+            let (sent, received) = (stream.sent(), stream.received());
+            let moved = format!("up {} down {}", format_size(sent), format_size(received));
+            match stream.size {
+                Some(size) if size > 0 => {
+                    let ratio = (sent.max(received) as f64 / size as f64).min(1.0);
+                    let gauge = LineGauge::default()
+                        .label(format!("#{:<3} {:>3.0}%", i + 1, ratio * 100.0))
+                        .filled_style(Style::default().fg(Color::Green))
+                        .ratio(ratio);
+                    frame.render_widget(gauge, *row);
+                }
+                _ => frame.render_widget(Paragraph::new(format!("#{:<3} {moved}", i + 1)), *row),
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Parsing and formatting of human-readable quantities used on the command line.
+
+/// Parses a byte size such as `512`, `64K`, `16M`, `1G` or `1.5MiB`.
+///
+/// Suffixes are binary multiples (`K` = 1024) and are case-insensitive; a trailing `B` or `iB`
+/// is accepted and ignored.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let digits = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (number, suffix) = s.split_at(digits);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("Invalid size `{s}`"))?;
+
+    let suffix = suffix.trim().to_ascii_uppercase();
+    let unit = suffix
+        .strip_suffix("IB")
+        .or_else(|| suffix.strip_suffix('B'))
+        .unwrap_or(&suffix);
+    let multiplier: u64 = match unit {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(format!("Unknown size suffix in `{s}`")),
+    };
+    Ok((number * multiplier as f64) as u64)
+}
+
+/// Range of byte sizes such as `64K..1G`, two sizes of [`parse_size`] separated by `..`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl SizeRange {
+    /// The sizes doubling from the start of the range, ending with its end.
+    pub fn doublings(&self) -> Vec<u64> {
+        let mut sizes: Vec<u64> =
+            std::iter::successors(Some(self.start), |size| size.checked_mul(2))
+                .take_while(|size| *size < self.end)
+                .collect();
+        sizes.push(self.end);
+        sizes
+    }
+}
+
+impl std::str::FromStr for SizeRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once("..")
+            .ok_or_else(|| format!("Expected a range like `64K..1G`, got `{s}`"))?;
+        let range = SizeRange { start: parse_size(start)?, end: parse_size(end)? };
+        if range.start == 0 || range.start > range.end {
+            return Err(format!("Invalid size range `{s}`, sizes must grow from at least 1 byte"));
+        }
+        Ok(range)
+    }
+}
+
+/// Formats a byte size using the largest binary unit that keeps the value at least 1.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if value.fract() == 0.0 {
+        format!("{value} {}", UNITS[unit])
+    } else {
+        format!("{value:.2} {}", UNITS[unit])
+    }
+}
+
+/// Parses a bandwidth such as `200Mbps`, `1.5Gbit/s` or `800K` into Mbit/s.
+///
+/// Suffixes are decimal multiples of bits per second and are case-insensitive; a plain number is
+/// taken as Mbit/s.
+pub fn parse_bandwidth(s: &str) -> Result<f64, String> {
+    let s = s.trim();
+    let digits = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (number, suffix) = s.split_at(digits);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("Invalid bandwidth `{s}`"))?;
+
+    let suffix = suffix.trim().to_ascii_lowercase();
+    let unit = suffix
+        .strip_suffix("bps")
+        .or_else(|| suffix.strip_suffix("bit/s"))
+        .unwrap_or(&suffix);
+    let mbps = match unit {
+        "" if suffix.is_empty() => number,
+        "" => number / 1e6,
+        "k" => number / 1e3,
+        "m" => number,
+        "g" => number * 1e3,
+        _ => return Err(format!("Unknown bandwidth suffix in `{s}`")),
+    };
+    Ok(mbps)
+}
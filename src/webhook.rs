@@ -0,0 +1,122 @@
+//! Notifications of finished runs, POSTed as JSON to the `client --webhook-url`, e.g. to alert
+//! from a monitor started with `--every`.
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::compare::label_tests;
+use crate::report::{BenchmarkReport, RunMetadata};
+
+/// How long to wait for the webhook to respond.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// When to notify the webhook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum WebhookOn {
+    /// After every run
+    Completion,
+    /// Only after runs that violated a threshold or failed to reach a server
+    Breach,
+}
+
+/// Why the webhook is notified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+    /// The run finished within all thresholds.
+    Completed,
+    /// Results violated a threshold, or benchmarks of some servers failed.
+    ThresholdBreached,
+}
+
+/// Body of a notification: the headline metrics of every report of a run and what went wrong.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunSummary {
+    pub event: Event,
+    pub run: RunMetadata,
+    pub results: Vec<ResultSummary>,
+    /// Threshold violations, as printed on stderr.
+    pub violations: Vec<String>,
+    /// Benchmarks that failed, e.g. because the server was unreachable.
+    pub failures: Vec<String>,
+}
+
+/// Headline metrics of one report.
+#[derive(Debug, Clone, Serialize)]
+pub struct ResultSummary {
+    pub server_node_id: String,
+    /// Scenario test name or mode, numbered if repeated.
+    pub test: String,
+    pub mode: &'static str,
+    pub metrics: Vec<MetricSummary>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricSummary {
+    pub name: String,
+    pub unit: &'static str,
+    pub value: f64,
+}
+
+impl RunSummary {
+    pub fn new(
+        run: &RunMetadata,
+        reports: &[BenchmarkReport],
+        violations: Vec<String>,
+        failures: Vec<String>,
+    ) -> Self {
+        let results = label_tests(reports)
+            .into_iter()
+            .map(|(test, report)| ResultSummary {
+                server_node_id: report.server_node_id.clone(),
+                test,
+                mode: report.benchmark.mode(),
+                metrics: report
+                    .benchmark
+                    .metrics()
+                    .into_iter()
+                    .map(|m| MetricSummary { name: m.name, unit: m.unit, value: m.value })
+                    .collect(),
+            })
+            .collect();
+        let event = match violations.is_empty() && failures.is_empty() {
+            true => Event::Completed,
+            false => Event::ThresholdBreached,
+        };
+        Self { event, run: run.clone(), results, violations, failures }
+    }
+}
+
+/// Posts run summaries to a webhook.
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    url: String,
+    on: WebhookOn,
+    http: reqwest::Client,
+}
+
+impl Webhook {
+    pub fn new(url: String, on: WebhookOn) -> Result<Self> {
+        let http = reqwest::Client::builder().timeout(TIMEOUT).build()?;
+        Ok(Self { url, on, http })
+    }
+
+    /// Posts the summary, unless only breaches are to be notified and there was none.
+    pub async fn notify(&self, summary: &RunSummary) -> Result<()> {
+        if self.on == WebhookOn::Breach && summary.event == Event::Completed {
+            return Ok(());
+        }
+        let response = self
+            .http
+            .post(&self.url)
+            .json(summary)
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach the webhook {}", self.url))?;
+        let status = response.status();
+        anyhow::ensure!(status.is_success(), "The webhook responded with {status}");
+        Ok(())
+    }
+}